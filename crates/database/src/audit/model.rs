@@ -0,0 +1,112 @@
+use mongodb::bson::{DateTime, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+  Signup,
+  Login,
+  Reauthenticate,
+  Logout,
+  Update,
+  ChangePassword,
+  Delete,
+  Restore,
+  Purge,
+  CreateInvitation,
+  DeleteInvitation,
+  CreateOrganization,
+  InviteMember,
+  JoinOrganization,
+  ChangeMemberRole,
+  LeaveOrganization,
+  RevokeToken,
+  RegisterPasskey,
+  PasskeyLogin,
+  RemovePasskey,
+  ImportUsers,
+  ExportUsers,
+  UploadFile,
+  DeleteFile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+  Success,
+  Failure,
+}
+
+/// A security-relevant event, stored in the `AuditEvent` collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  /// Email of the principal performing the action, if known.
+  pub actor: Option<String>,
+  /// Id or email of the user the action was performed on.
+  pub target: Option<String>,
+  pub action: AuditAction,
+  pub ip: Option<String>,
+  pub user_agent: Option<String>,
+  pub outcome: AuditOutcome,
+  /// Short explanation of a failed outcome.
+  pub reason: Option<String>,
+  pub timestamp: DateTime,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+impl AuditEvent {
+  pub fn new(action: AuditAction, outcome: AuditOutcome) -> Self {
+    Self {
+      id: Some(ObjectId::new()),
+      actor: None,
+      target: None,
+      action,
+      ip: None,
+      user_agent: None,
+      outcome,
+      reason: None,
+      timestamp: DateTime::now(),
+      tenant_id: None,
+    }
+  }
+
+  pub fn actor(mut self, actor: impl Into<String>) -> Self {
+    self.actor = Some(actor.into());
+    self
+  }
+
+  pub fn target(mut self, target: impl Into<String>) -> Self {
+    self.target = Some(target.into());
+    self
+  }
+
+  pub fn reason(mut self, reason: impl Into<String>) -> Self {
+    self.reason = Some(reason.into());
+    self
+  }
+
+  /// Sets the caller's IP address and user agent.
+  pub fn client(mut self, ip: Option<String>, user_agent: Option<String>) -> Self {
+    self.ip = ip;
+    self.user_agent = user_agent;
+    self
+  }
+}
+
+/// Criteria used to search the audit log. `None` fields are not filtered on.
+#[derive(Debug, Clone, Default)]
+pub struct AuditEventFilter {
+  pub actor: Option<String>,
+  pub target: Option<String>,
+  pub action: Option<AuditAction>,
+  pub outcome: Option<AuditOutcome>,
+  pub ip: Option<String>,
+  pub from: Option<DateTime>,
+  pub to: Option<DateTime>,
+}
+
+crate::impl_model!(AuditEvent, "AuditEvent");
@@ -0,0 +1,88 @@
+use crate::{
+  Database,
+  audit::model::{AuditEvent, AuditEventFilter},
+  pagination::{Page, Pagination, find_page},
+  tenant::{current_tenant, scoped},
+  transaction::in_session,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{Document, doc, to_bson},
+  results::InsertOneResult,
+};
+use std::sync::Arc;
+use utils::AppResult;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynAuditRepository = Arc<dyn AuditRepositoryTrait>;
+
+#[async_trait]
+pub trait AuditRepositoryTrait: Send + Sync {
+  async fn create_audit_event(&self, event: AuditEvent) -> AppResult<InsertOneResult>;
+
+  /// Returns one page of the events matching `filter`.
+  async fn find_audit_events(
+    &self,
+    filter: &AuditEventFilter,
+    pagination: &Pagination,
+  ) -> AppResult<Page<AuditEvent>>;
+
+  async fn count_audit_events(&self, filter: &AuditEventFilter) -> AppResult<u64>;
+}
+
+fn filter_document(filter: &AuditEventFilter) -> AppResult<Document> {
+  let mut query = doc! {};
+  if let Some(actor) = &filter.actor {
+    query.insert("actor", actor);
+  }
+  if let Some(target) = &filter.target {
+    query.insert("target", target);
+  }
+  if let Some(action) = filter.action {
+    query.insert("action", to_bson(&action)?);
+  }
+  if let Some(outcome) = filter.outcome {
+    query.insert("outcome", to_bson(&outcome)?);
+  }
+  if let Some(ip) = &filter.ip {
+    query.insert("ip", ip);
+  }
+  let mut timestamp = doc! {};
+  if let Some(from) = filter.from {
+    timestamp.insert("$gte", from);
+  }
+  if let Some(to) = filter.to {
+    timestamp.insert("$lte", to);
+  }
+  if !timestamp.is_empty() {
+    query.insert("timestamp", timestamp);
+  }
+  Ok(scoped(query))
+}
+
+#[async_trait]
+impl AuditRepositoryTrait for Database {
+  #[tracing::instrument(name = "Create Audit Event", skip(self, event))]
+  async fn create_audit_event(&self, mut event: AuditEvent) -> AppResult<InsertOneResult> {
+    event.tenant_id = current_tenant();
+    let result = in_session!(self.audit_col().insert_one(event))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Find Audit Events", skip(self))]
+  async fn find_audit_events(
+    &self,
+    filter: &AuditEventFilter,
+    pagination: &Pagination,
+  ) -> AppResult<Page<AuditEvent>> {
+    let query = filter_document(filter)?;
+    find_page(&self.read_collection::<AuditEvent>(), query, pagination).await
+  }
+
+  #[tracing::instrument(name = "Count Audit Events", skip(self))]
+  async fn count_audit_events(&self, filter: &AuditEventFilter) -> AppResult<u64> {
+    let query = filter_document(filter)?;
+    let count = in_session!(self.read_collection::<AuditEvent>().count_documents(query))?;
+    Ok(count)
+  }
+}
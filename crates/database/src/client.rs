@@ -0,0 +1,205 @@
+//! Options of the Mongo clients, from `[db]`.
+use mongodb::options::{
+  AuthMechanism, ClientOptions, Compressor, Credential, ReadPreference, ReadPreferenceOptions,
+  SelectionCriteria, Tls, TlsOptions,
+};
+use std::time::Duration;
+use utils::{
+  AppResult,
+  config::db_config::{
+    DbAuthMechanism, DbCompressor, MongoClientConfig, ReadPreferenceMode, ReadsConfig,
+  },
+};
+
+/// Parses `uri`, then applies the options set in `[db.client]` over those of the uri.
+pub(crate) async fn client_options(
+  uri: &str,
+  client: &MongoClientConfig,
+) -> AppResult<ClientOptions> {
+  let mut options = ClientOptions::parse(uri).await?;
+  if let Some(app_name) = &client.app_name {
+    options.app_name = Some(app_name.clone());
+  }
+  if let Some(max_pool_size) = client.max_pool_size {
+    options.max_pool_size = Some(max_pool_size);
+  }
+  if let Some(min_pool_size) = client.min_pool_size {
+    options.min_pool_size = Some(min_pool_size);
+  }
+  if let Some(max_idle_time) = client.max_idle_time {
+    options.max_idle_time = Some(Duration::from_secs(max_idle_time));
+  }
+  if let Some(connect_timeout) = client.connect_timeout {
+    options.connect_timeout = Some(Duration::from_secs(connect_timeout));
+  }
+  if let Some(server_selection_timeout) = client.server_selection_timeout {
+    options.server_selection_timeout = Some(Duration::from_secs(server_selection_timeout));
+  }
+  if !client.compressors.is_empty() {
+    options.compressors = Some(client.compressors.iter().map(compressor).collect());
+  }
+  if let Some(tls) = &client.tls {
+    options.tls = Some(if tls.enabled {
+      Tls::Enabled(
+        TlsOptions::builder()
+          .ca_file_path(tls.ca_file.clone())
+          .cert_key_file_path(tls.cert_key_file.clone())
+          .allow_invalid_certificates(tls.allow_invalid_certificates)
+          .build(),
+      )
+    } else {
+      Tls::Disabled
+    });
+  }
+  if let Some(auth) = &client.auth {
+    options.credential = Some(
+      Credential::builder()
+        .mechanism(mechanism(auth.mechanism))
+        .source(auth.source.clone())
+        .username(auth.username.clone())
+        .password(auth.password.clone())
+        .build(),
+    );
+  }
+  Ok(options)
+}
+
+fn compressor(compressor: &DbCompressor) -> Compressor {
+  match compressor {
+    DbCompressor::Zstd => Compressor::Zstd { level: None },
+    DbCompressor::Zlib => Compressor::Zlib { level: None },
+    DbCompressor::Snappy => Compressor::Snappy,
+  }
+}
+
+fn mechanism(mechanism: DbAuthMechanism) -> AuthMechanism {
+  match mechanism {
+    DbAuthMechanism::ScramSha1 => AuthMechanism::ScramSha1,
+    DbAuthMechanism::ScramSha256 => AuthMechanism::ScramSha256,
+    DbAuthMechanism::X509 => AuthMechanism::MongoDbX509,
+    DbAuthMechanism::Plain => AuthMechanism::Plain,
+  }
+}
+
+/// Where the reads routed by `Database::read_database` are sent.
+pub(crate) fn read_criteria(reads: &ReadsConfig) -> SelectionCriteria {
+  let options = reads.max_staleness.map(|max_staleness| {
+    ReadPreferenceOptions::builder()
+      .max_staleness(Duration::from_secs(max_staleness))
+      .build()
+  });
+  SelectionCriteria::ReadPreference(match reads.read_preference() {
+    ReadPreferenceMode::Primary => ReadPreference::Primary,
+    ReadPreferenceMode::PrimaryPreferred => ReadPreference::PrimaryPreferred { options },
+    ReadPreferenceMode::Secondary => ReadPreference::Secondary { options },
+    ReadPreferenceMode::SecondaryPreferred => ReadPreference::SecondaryPreferred { options },
+    ReadPreferenceMode::Nearest => ReadPreference::Nearest { options },
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use utils::config::db_config::{MongoAuthConfig, MongoTlsConfig};
+
+  const URI: &str = "mongodb://localhost/?appName=from-uri&maxPoolSize=5&connectTimeoutMS=2000";
+
+  #[tokio::test]
+  async fn unset_options_keep_those_of_the_uri() {
+    let options = client_options(URI, &MongoClientConfig::default())
+      .await
+      .unwrap();
+    assert_eq!(options.app_name.as_deref(), Some("from-uri"));
+    assert_eq!(options.max_pool_size, Some(5));
+    assert_eq!(options.connect_timeout, Some(Duration::from_secs(2)));
+    assert_eq!(options.compressors, None);
+    assert_eq!(options.credential, None);
+  }
+
+  #[tokio::test]
+  async fn configured_options_override_the_uri() {
+    let client = MongoClientConfig {
+      app_name: Some("from-config".to_string()),
+      max_pool_size: Some(50),
+      min_pool_size: Some(2),
+      max_idle_time: Some(30),
+      connect_timeout: Some(5),
+      server_selection_timeout: Some(10),
+      compressors: vec![DbCompressor::Snappy, DbCompressor::Zlib],
+      ..MongoClientConfig::default()
+    };
+    let options = client_options(URI, &client).await.unwrap();
+    assert_eq!(options.app_name.as_deref(), Some("from-config"));
+    assert_eq!(options.max_pool_size, Some(50));
+    assert_eq!(options.min_pool_size, Some(2));
+    assert_eq!(options.max_idle_time, Some(Duration::from_secs(30)));
+    assert_eq!(options.connect_timeout, Some(Duration::from_secs(5)));
+    assert_eq!(
+      options.server_selection_timeout,
+      Some(Duration::from_secs(10))
+    );
+    assert!(matches!(
+      options.compressors.as_deref(),
+      Some([Compressor::Snappy, Compressor::Zlib { level: None }])
+    ));
+  }
+
+  #[tokio::test]
+  async fn tls_and_credentials_are_mapped() {
+    let client = MongoClientConfig {
+      tls: Some(MongoTlsConfig {
+        enabled: false,
+        ca_file: None,
+        cert_key_file: None,
+        allow_invalid_certificates: false,
+      }),
+      auth: Some(MongoAuthConfig {
+        mechanism: DbAuthMechanism::ScramSha256,
+        source: Some("admin".to_string()),
+        username: Some("app".to_string()),
+        password: Some("secret".to_string()),
+      }),
+      ..MongoClientConfig::default()
+    };
+    let options = client_options("mongodb://localhost/?tls=true", &client)
+      .await
+      .unwrap();
+    assert!(matches!(options.tls, Some(Tls::Disabled)));
+    let credential = options.credential.unwrap();
+    assert_eq!(credential.mechanism, Some(AuthMechanism::ScramSha256));
+    assert_eq!(credential.source.as_deref(), Some("admin"));
+    assert_eq!(credential.username.as_deref(), Some("app"));
+    assert_eq!(credential.password.as_deref(), Some("secret"));
+  }
+
+  #[test]
+  fn followers_are_read_from_secondaries_by_default() {
+    let reads = ReadsConfig::default();
+    assert!(matches!(
+      read_criteria(&reads),
+      SelectionCriteria::ReadPreference(ReadPreference::Primary)
+    ));
+
+    let reads = ReadsConfig {
+      follower_uri: Some("mongodb://follower".to_string()),
+      max_staleness: Some(120),
+      ..ReadsConfig::default()
+    };
+    let SelectionCriteria::ReadPreference(ReadPreference::SecondaryPreferred { options }) =
+      read_criteria(&reads)
+    else {
+      panic!("follower reads should prefer secondaries");
+    };
+    let max_staleness = options.and_then(|options| options.max_staleness);
+    assert_eq!(max_staleness, Some(Duration::from_secs(120)));
+
+    let reads = ReadsConfig {
+      read_preference: Some(ReadPreferenceMode::Nearest),
+      ..reads
+    };
+    assert!(matches!(
+      read_criteria(&reads),
+      SelectionCriteria::ReadPreference(ReadPreference::Nearest { .. })
+    ));
+  }
+}
@@ -0,0 +1,325 @@
+//! Domain events read from the change stream of the user collection.
+//!
+//! The `ChangeStreamWatcher` turns every insert, update and delete of a user into a `UserEvent`
+//! published to the subscribers of `UserEvents`, whoever made the change. The position in the
+//! stream is saved after each event, so that a restarted server resumes where it stopped as long
+//! as the oplog still holds that position.
+pub mod model;
+
+use crate::{Database, tenant_of, user::model::User, user::repository::escape_regex};
+use model::{ChangeStreamToken, UserEvent, UserEventKind, UserSnapshot};
+use mongodb::{
+  Collection,
+  bson::{DateTime, Document, doc, from_document},
+  change_stream::event::{ChangeStreamEvent, OperationType, ResumeToken},
+  error::{Error, ErrorKind, Result},
+  options::FullDocumentType,
+};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
+use tracing::{error, info, warn};
+use utils::config;
+
+/// Delay before reopening a change stream that failed.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Server error codes meaning the saved position cannot be resumed from.
+const CHANGE_STREAM_FATAL_ERROR: i32 = 280;
+const CHANGE_STREAM_HISTORY_LOST: i32 = 286;
+
+/// The in-process subscriber API of user events.
+///
+/// Every subscriber receives every event published after it subscribed. One falling more than
+/// `events.capacity` events behind loses the oldest ones, see `broadcast::Receiver::recv`.
+#[derive(Clone, Debug)]
+pub struct UserEvents {
+  sender: broadcast::Sender<UserEvent>,
+}
+
+impl UserEvents {
+  pub fn new(capacity: usize) -> Self {
+    let (sender, _) = broadcast::channel(capacity.max(1));
+    Self { sender }
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<UserEvent> {
+    self.sender.subscribe()
+  }
+
+  /// Sends `event` to the current subscribers, if any.
+  pub fn publish(&self, event: UserEvent) {
+    let _ = self.sender.send(event);
+  }
+}
+
+pub struct ChangeStreamWatcher {
+  database: Database,
+  events: UserEvents,
+  /// See `events.stream`.
+  stream: String,
+}
+
+impl ChangeStreamWatcher {
+  pub fn new(database: Database, events: UserEvents, stream: impl Into<String>) -> Self {
+    Self {
+      database,
+      events,
+      stream: stream.into(),
+    }
+  }
+
+  /// Publishes user events until the process exits, reopening the stream after errors.
+  pub async fn run(self) {
+    info!("watching user changes as stream {}", self.stream);
+    loop {
+      if let Err(e) = self.watch().await {
+        if cannot_resume(&e) {
+          error!("cannot resume user changes, the changes made meanwhile are lost: {e}");
+          if let Err(e) = self.forget_token().await {
+            error!("failed to forget the user change stream position: {e}");
+          }
+          continue;
+        }
+        error!("user change stream failed, retrying: {e}");
+      }
+      tokio::time::sleep(RETRY_DELAY).await;
+    }
+  }
+
+  fn token_col(&self) -> Collection<ChangeStreamToken> {
+    self.database.db.collection("_change_stream_tokens")
+  }
+
+  async fn saved_token(&self) -> Result<Option<ResumeToken>> {
+    let saved = self
+      .token_col()
+      .find_one(doc! {"_id": &self.stream})
+      .await?;
+    Ok(saved.map(|saved| saved.token))
+  }
+
+  async fn save_token(&self, token: ResumeToken) -> Result<()> {
+    let saved = ChangeStreamToken {
+      stream: self.stream.clone(),
+      token,
+      updated_at: DateTime::now(),
+    };
+    self
+      .token_col()
+      .replace_one(doc! {"_id": &self.stream}, saved)
+      .upsert(true)
+      .await?;
+    Ok(())
+  }
+
+  async fn forget_token(&self) -> Result<()> {
+    self
+      .token_col()
+      .delete_one(doc! {"_id": &self.stream})
+      .await?;
+    Ok(())
+  }
+
+  /// Publishes the events of one change stream, until it fails.
+  async fn watch(&self) -> Result<()> {
+    let cfg = config::get();
+    let collection = cfg.db.collection_name("User");
+    let mut filter = doc! {
+      "ns.coll": collection,
+      "operationType": { "$in": ["insert", "update", "replace", "delete"] },
+    };
+    let users = self.database.db.collection::<Document>(collection);
+    // Tenant databases are created on first use, so the whole deployment is watched for them.
+    let mut watch = if cfg.db.database_per_tenant {
+      let databases = format!("^{}(-|$)", escape_regex(&cfg.db.database));
+      filter.insert("ns.db", doc! { "$regex": databases });
+      self.database.client.watch()
+    } else {
+      users.watch()
+    };
+    if let Some(token) = self.saved_token().await? {
+      watch = watch.start_after(token);
+    }
+    let mut stream = watch
+      .pipeline([doc! { "$match": filter }])
+      .full_document(FullDocumentType::UpdateLookup)
+      .await?;
+    while let Some(change) = stream.next().await {
+      let change = change?;
+      let token = change.id.clone();
+      if let Some(event) = user_event(change) {
+        self.events.publish(event);
+      }
+      self.save_token(token).await?;
+    }
+    Ok(())
+  }
+}
+
+/// Whether `error` means the saved position is gone, so the stream must start over.
+fn cannot_resume(error: &Error) -> bool {
+  matches!(
+    error.kind.as_ref(),
+    ErrorKind::Command(command)
+      if matches!(command.code, CHANGE_STREAM_FATAL_ERROR | CHANGE_STREAM_HISTORY_LOST)
+  )
+}
+
+/// Converts a change of the user collection into an event, see `UserEventKind`.
+fn user_event(change: ChangeStreamEvent<Document>) -> Option<UserEvent> {
+  let user_id = change.document_key?.get_object_id("_id").ok()?;
+  let user = change
+    .full_document
+    .and_then(|document| match from_document::<User>(document) {
+      Ok(user) => Some(user),
+      Err(e) => {
+        warn!("ignoring the invalid user {user_id} of a change event: {e}");
+        None
+      }
+    });
+  let tenant_id = change
+    .ns
+    .and_then(|ns| tenant_of(&ns.db))
+    .or_else(|| user.as_ref().and_then(|user| user.tenant_id.clone()));
+  let user = user.map(UserSnapshot::from);
+  let kind = match change.operation_type {
+    OperationType::Insert => UserEventKind::Created { user: user? },
+    OperationType::Update | OperationType::Replace => {
+      let (updated_fields, removed_fields) = change
+        .update_description
+        .map(|description| {
+          let updated: Vec<String> = description.updated_fields.keys().cloned().collect();
+          (updated, description.removed_fields)
+        })
+        .unwrap_or_default();
+      let deleted = "deleted_at".to_string();
+      if updated_fields.contains(&deleted) {
+        UserEventKind::SoftDeleted { user }
+      } else if removed_fields.contains(&deleted) {
+        UserEventKind::Restored { user }
+      } else {
+        UserEventKind::Updated {
+          user,
+          updated_fields,
+          removed_fields,
+        }
+      }
+    }
+    OperationType::Delete => UserEventKind::Deleted,
+    _ => return None,
+  };
+  Some(UserEvent {
+    user_id,
+    tenant_id,
+    occurred_at: change.wall_time,
+    kind,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mongodb::bson::{Bson, from_bson, oid::ObjectId, to_document};
+
+  fn command_error(code: i32) -> Error {
+    let command = from_document(doc! { "code": code, "errmsg": "failed" }).unwrap();
+    Error::from(ErrorKind::Command(command))
+  }
+
+  fn change(operation: &str, id: ObjectId, extra: Document) -> ChangeStreamEvent<Document> {
+    let mut change = doc! {
+      "_id": { "_data": "8263" },
+      "operationType": operation,
+      "ns": { "db": "rust-axum-boilerplate-db-acme", "coll": "users" },
+      "documentKey": { "_id": id },
+    };
+    change.extend(extra);
+    from_document(change).unwrap()
+  }
+
+  fn user(id: ObjectId) -> Document {
+    doc! {
+      "_id": id,
+      "name": "Ada",
+      "email": "ada@example.com",
+      "password": "hash",
+      "created_at": DateTime::now(),
+      "updated_at": DateTime::now(),
+    }
+  }
+
+  #[test]
+  fn lost_positions_start_the_stream_over() {
+    assert!(cannot_resume(&command_error(CHANGE_STREAM_FATAL_ERROR)));
+    assert!(cannot_resume(&command_error(CHANGE_STREAM_HISTORY_LOST)));
+    // Other failures are retried from the saved position.
+    assert!(!cannot_resume(&command_error(11601)));
+    assert!(!cannot_resume(&Error::from(std::io::Error::other("reset"))));
+  }
+
+  #[test]
+  fn saved_positions_round_trip() {
+    let token: ResumeToken = from_bson(Bson::Document(doc! { "_data": "82635019A0" })).unwrap();
+    let saved = ChangeStreamToken {
+      stream: "users".to_string(),
+      token: token.clone(),
+      updated_at: DateTime::now(),
+    };
+    let document = to_document(&saved).unwrap();
+    assert_eq!(document.get_str("_id").unwrap(), "users");
+    let loaded: ChangeStreamToken = from_document(document).unwrap();
+    assert_eq!(loaded.token, token);
+  }
+
+  #[test]
+  fn changes_become_user_events() {
+    config::init_for_tests();
+    let id = ObjectId::new();
+
+    let event = user_event(change("insert", id, doc! { "fullDocument": user(id) })).unwrap();
+    assert_eq!(event.user_id, id);
+    assert_eq!(event.tenant_id.as_deref(), Some("acme"));
+    assert!(matches!(&event.kind, UserEventKind::Created { user } if user.name == "Ada"));
+    // Subscribers never see the password hash.
+    let published = to_document(&event).unwrap();
+    assert!(
+      !published
+        .get_document("kind")
+        .unwrap()
+        .get_document("user")
+        .unwrap()
+        .contains_key("password")
+    );
+    assert!(!format!("{event:?}").contains("hash"));
+
+    let updated = |set: Document, removed: Vec<&str>| {
+      let description = doc! { "updatedFields": set, "removedFields": removed };
+      user_event(change(
+        "update",
+        id,
+        doc! { "updateDescription": description },
+      ))
+      .unwrap()
+    };
+    let event = updated(doc! { "deleted_at": DateTime::now() }, vec![]);
+    assert!(matches!(
+      event.kind,
+      UserEventKind::SoftDeleted { user: None }
+    ));
+    let event = updated(doc! {}, vec!["deleted_at"]);
+    assert!(matches!(event.kind, UserEventKind::Restored { .. }));
+    let event = updated(doc! { "name": "Grace" }, vec![]);
+    assert!(matches!(
+      event.kind,
+      UserEventKind::Updated { updated_fields, .. } if updated_fields == ["name"]
+    ));
+
+    let event = user_event(change("delete", id, doc! {})).unwrap();
+    assert!(matches!(event.kind, UserEventKind::Deleted));
+    assert_eq!(event.tenant_id.as_deref(), Some("acme"));
+    // An insert without its document and other operations have no event.
+    assert!(user_event(change("insert", id, doc! {})).is_none());
+    assert!(user_event(change("drop", id, doc! {})).is_none());
+  }
+}
@@ -0,0 +1,83 @@
+use crate::user::model::{User, UserRole};
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  change_stream::event::ResumeToken,
+};
+use serde::{Deserialize, Serialize};
+
+/// A change made to a user, published by the `ChangeStreamWatcher`.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserEvent {
+  pub user_id: ObjectId,
+  /// Tenant of the user. Unknown for `Deleted` events unless tenants have their own databases.
+  pub tenant_id: Option<String>,
+  pub occurred_at: Option<DateTime>,
+  pub kind: UserEventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserEventKind {
+  Created {
+    user: UserSnapshot,
+  },
+  /// The user as it is after the change, `None` if it was deleted since.
+  Updated {
+    user: Option<UserSnapshot>,
+    updated_fields: Vec<String>,
+    removed_fields: Vec<String>,
+  },
+  /// See `User::deleted_at`.
+  SoftDeleted {
+    user: Option<UserSnapshot>,
+  },
+  Restored {
+    user: Option<UserSnapshot>,
+  },
+  /// Permanently removed, see `UserRepositoryTrait::purge_deleted_users`.
+  Deleted,
+}
+
+/// A user as published in events, which leave the password hash out.
+#[derive(Debug, Clone, Serialize)]
+pub struct UserSnapshot {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub name: String,
+  pub email: String,
+  pub role: UserRole,
+  pub version: i64,
+  pub created_at: Option<DateTime>,
+  pub updated_at: Option<DateTime>,
+  pub last_login_at: Option<DateTime>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub deleted_at: Option<DateTime>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+impl From<User> for UserSnapshot {
+  fn from(user: User) -> Self {
+    Self {
+      id: user.id,
+      name: user.name,
+      email: user.email,
+      role: user.role,
+      version: user.version,
+      created_at: user.created_at,
+      updated_at: user.updated_at,
+      last_login_at: user.last_login_at,
+      deleted_at: user.deleted_at,
+      tenant_id: user.tenant_id,
+    }
+  }
+}
+
+/// Where a change stream stopped, in the `_change_stream_tokens` collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeStreamToken {
+  #[serde(rename = "_id")]
+  pub stream: String,
+  pub token: ResumeToken,
+  pub updated_at: DateTime,
+}
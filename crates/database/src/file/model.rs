@@ -0,0 +1,37 @@
+use mongodb::bson::{DateTime, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+use utils::config::storage_config::StorageBackend;
+
+/// An uploaded file, whose content is kept by a `FileStorageTrait` under `key`.
+///
+/// Files are stored with the generic `Repository`, see `repository::Model`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredFile {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  /// The user who uploaded the file.
+  pub owner_id: ObjectId,
+  /// Name given by the uploader, without any directory.
+  pub name: String,
+  pub content_type: String,
+  pub size: u64,
+  /// Hex encoded SHA-256 of the content.
+  pub sha256: String,
+  /// Backend holding the content, which files uploaded before a change of `storage.backend`
+  /// keep.
+  pub storage: StorageBackend,
+  pub key: String,
+  pub created_at: DateTime,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+impl StoredFile {
+  /// The `ETag` of the file, derived from its content.
+  pub fn etag(&self) -> String {
+    format!("\"{}\"", self.sha256)
+  }
+}
+
+crate::impl_model!(StoredFile, "File");
@@ -0,0 +1,152 @@
+//! Index declarations, created when the database is initialized.
+//!
+//! Unique indexes are named after the field they guard, so that duplicate-key errors can be
+//! reported against that field, see `AppError::duplicate_key_field`.
+use crate::Database;
+use mongodb::{
+  IndexModel,
+  bson::doc,
+  error::{Error, ErrorKind},
+  options::{Collation, CollationStrength, IndexOptions},
+};
+use std::time::Duration;
+use tracing::{info, warn};
+use utils::AppResult;
+
+/// Compares emails case-insensitively. Queries on `email` must use it to hit its index.
+pub fn email_collation() -> Collation {
+  Collation::builder()
+    .locale("en")
+    .strength(CollationStrength::Secondary)
+    .build()
+}
+
+fn unique(name: &str) -> IndexOptions {
+  IndexOptions::builder()
+    .name(name.to_string())
+    .unique(true)
+    .build()
+}
+
+/// Removes documents once the date in the indexed field has passed.
+fn expiring() -> IndexOptions {
+  IndexOptions::builder().expire_after(Duration::ZERO).build()
+}
+
+/// The text index of `user::search`. Whole words of the name weigh most, and words are
+/// matched as written rather than stemmed, `search_terms` holding their prefixes.
+fn search() -> IndexOptions {
+  IndexOptions::builder()
+    .name("search".to_string())
+    .weights(doc! { "name": 10, "email": 5, "search_terms": 1 })
+    .default_language("none".to_string())
+    .build()
+}
+
+fn index(keys: mongodb::bson::Document, options: Option<IndexOptions>) -> IndexModel {
+  IndexModel::builder().keys(keys).options(options).build()
+}
+
+/// The unique `email` index of users. Only active users hold on to their email, so that the
+/// address of a soft-deleted user can be used again.
+pub(crate) fn email_index() -> IndexModel {
+  let mut email = unique("email");
+  email.collation = Some(email_collation());
+  email.partial_filter_expression = Some(doc! { "deleted_at": null });
+  index(doc! { "tenant_id": 1, "email": 1 }, Some(email))
+}
+
+/// Whether creating an index failed because one with the same name or keys but other options
+/// exists.
+fn is_index_conflict(error: &Error) -> bool {
+  // IndexOptionsConflict and IndexKeySpecsConflict.
+  matches!(error.kind.as_ref(), ErrorKind::Command(e) if e.code == 85 || e.code == 86)
+}
+
+impl Database {
+  /// Creates the indexes of every collection in the current database. Creating an existing
+  /// index is a no-op.
+  ///
+  /// Unique fields are unique per tenant, as tenants sharing a database are told apart by
+  /// `tenant_id`.
+  pub async fn create_indexes(&self) -> AppResult<()> {
+    info!("creating indexes...");
+    match self.user_col().create_index(email_index()).await {
+      Err(e) if is_index_conflict(&e) => {
+        warn!("the email index covers deleted users, run `migrate up` to replace it");
+      }
+      result => {
+        result?;
+      }
+    }
+    self
+      .user_col()
+      .create_indexes([
+        index(doc! { "created_at": 1 }, None),
+        index(
+          doc! { "deleted_at": 1 },
+          Some(IndexOptions::builder().sparse(true).build()),
+        ),
+        index(
+          doc! { "name": "text", "email": "text", "search_terms": "text" },
+          Some(search()),
+        ),
+      ])
+      .await?;
+    self
+      .invitation_col()
+      .create_index(index(doc! { "code": 1 }, Some(unique("code"))))
+      .await?;
+    self
+      .membership_col()
+      .create_indexes([
+        index(
+          doc! { "organization_id": 1, "user_id": 1 },
+          Some(unique("user_id")),
+        ),
+        index(doc! { "user_id": 1 }, None),
+      ])
+      .await?;
+    self
+      .organization_invitation_col()
+      .create_index(index(doc! { "email": 1 }, None))
+      .await?;
+    self
+      .revoked_token_col()
+      .create_indexes([
+        index(doc! { "jti": 1 }, Some(unique("jti"))),
+        index(doc! { "expires_at": 1 }, Some(expiring())),
+      ])
+      .await?;
+    self
+      .audit_col()
+      .create_indexes([
+        index(doc! { "timestamp": -1 }, None),
+        index(doc! { "actor": 1, "timestamp": -1 }, None),
+      ])
+      .await?;
+    self
+      .outbox_col()
+      .create_index(index(doc! { "status": 1, "available_at": 1 }, None))
+      .await?;
+    self
+      .webauthn_credential_col()
+      .create_indexes([
+        index(doc! { "credential_id": 1 }, Some(unique("credential_id"))),
+        index(doc! { "user_id": 1 }, None),
+      ])
+      .await?;
+    self
+      .webauthn_challenge_col()
+      .create_indexes([
+        index(doc! { "challenge": 1 }, None),
+        index(doc! { "expires_at": 1 }, Some(expiring())),
+      ])
+      .await?;
+    self
+      .file_col()
+      .create_index(index(doc! { "owner_id": 1, "created_at": -1 }, None))
+      .await?;
+    Ok(())
+  }
+}
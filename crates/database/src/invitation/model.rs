@@ -0,0 +1,23 @@
+use mongodb::bson::{DateTime, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+/// A single-use code allowing one account to sign up while signups are invite-only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invitation {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub code: String,
+  /// When set, only this (lowercased) email may redeem the code.
+  pub email: Option<String>,
+  /// Email of the admin who created the invitation.
+  pub created_by: String,
+  pub created_at: DateTime,
+  pub expires_at: DateTime,
+  pub used_at: Option<DateTime>,
+  pub used_by: Option<String>,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+crate::impl_model!(Invitation, "Invitation");
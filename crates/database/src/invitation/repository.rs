@@ -0,0 +1,78 @@
+use crate::{
+  Database,
+  invitation::model::Invitation,
+  tenant::{current_tenant, scoped},
+  transaction::in_session,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, doc, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult},
+};
+use std::{str::FromStr, sync::Arc};
+use tokio_stream::StreamExt;
+use utils::AppResult;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynInvitationRepository = Arc<dyn InvitationRepositoryTrait>;
+
+#[async_trait]
+pub trait InvitationRepositoryTrait: Send + Sync {
+  async fn create_invitation(&self, invitation: Invitation) -> AppResult<InsertOneResult>;
+
+  async fn get_all_invitations(&self) -> AppResult<Vec<Invitation>>;
+
+  /// Atomically marks an unused, unexpired invitation as used by `email`.
+  ///
+  /// Returns `None` when no such invitation exists for `code` and `email`.
+  async fn redeem_invitation(&self, code: &str, email: &str) -> AppResult<Option<Invitation>>;
+
+  async fn delete_invitation(&self, id: &str) -> AppResult<DeleteResult>;
+}
+
+#[async_trait]
+impl InvitationRepositoryTrait for Database {
+  #[tracing::instrument(name = "Create Invitation", skip(self, invitation))]
+  async fn create_invitation(&self, mut invitation: Invitation) -> AppResult<InsertOneResult> {
+    invitation.tenant_id = current_tenant();
+    let result = in_session!(self.invitation_col().insert_one(invitation))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Get All Invitations", skip(self))]
+  async fn get_all_invitations(&self) -> AppResult<Vec<Invitation>> {
+    let mut cursor = self
+      .invitation_col()
+      .find(scoped(doc! {}))
+      .sort(doc! { "created_at": -1 })
+      .await?;
+    let mut invitations: Vec<Invitation> = Vec::new();
+    while let Some(doc) = cursor.next().await {
+      invitations.push(doc?);
+    }
+    Ok(invitations)
+  }
+
+  #[tracing::instrument(name = "Redeem Invitation", skip(self, code, email))]
+  async fn redeem_invitation(&self, code: &str, email: &str) -> AppResult<Option<Invitation>> {
+    let email = email.to_lowercase();
+    let now = DateTime::now();
+    let filter = scoped(doc! {
+      "code": code,
+      "used_at": null,
+      "expires_at": { "$gt": now },
+      "$or": [{ "email": null }, { "email": &email }],
+    });
+    let update = doc! { "$set": { "used_at": now, "used_by": &email } };
+    let invitation = in_session!(self.invitation_col().find_one_and_update(filter, update))?;
+    Ok(invitation)
+  }
+
+  #[tracing::instrument(name = "Delete Invitation", skip(self, id))]
+  async fn delete_invitation(&self, id: &str) -> AppResult<DeleteResult> {
+    let id = ObjectId::from_str(id)?;
+    let filter = scoped(doc! {"_id": id});
+    let result = in_session!(self.invitation_col().delete_one(filter))?;
+    Ok(result)
+  }
+}
@@ -1,13 +1,100 @@
+pub mod audit;
+mod client;
+pub mod events;
+pub mod file;
+pub mod index;
+pub mod invitation;
+pub mod memory;
+pub mod migration;
+pub mod organization;
+pub mod outbox;
+pub mod pagination;
+pub mod repository;
+mod results;
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+pub mod sql;
+pub mod storage;
+pub mod tenant;
+pub mod token;
+pub mod transaction;
 pub mod user;
+pub mod webauthn;
 
-use mongodb::{Client, Collection};
-use tracing::info;
-use user::model::User;
+use audit::{model::AuditEvent, repository::AuditRepositoryTrait};
+use file::model::StoredFile;
+use invitation::{model::Invitation, repository::InvitationRepositoryTrait};
+use mongodb::{
+  Client, Collection,
+  options::{DatabaseOptions, SelectionCriteria},
+};
+use organization::{
+  model::{Membership, Organization, OrganizationInvitation},
+  repository::OrganizationRepositoryTrait,
+};
+use outbox::{model::OutboxMessage, repository::OutboxRepositoryTrait};
+use repository::Repository;
+use std::{
+  collections::HashSet,
+  sync::{Arc, Mutex},
+};
+use token::{model::RevokedToken, repository::TokenRepositoryTrait};
+use tracing::{error, info};
+use transaction::UnitOfWorkTrait;
+use user::{model::User, repository::UserRepositoryTrait, search::UserSearchTrait};
 use utils::{AppResult, config};
+use webauthn::{
+  model::{WebauthnChallenge, WebauthnCredential},
+  repository::WebauthnRepositoryTrait,
+};
+
+/// Every repository trait, implemented by `Database` and `MemoryDatabase`.
+///
+/// New repository traits are added here so that both backends keep providing all of them.
+pub trait Backend:
+  UserRepositoryTrait
+  + UserSearchTrait
+  + AuditRepositoryTrait
+  + InvitationRepositoryTrait
+  + OrganizationRepositoryTrait
+  + OutboxRepositoryTrait
+  + TokenRepositoryTrait
+  + WebauthnRepositoryTrait
+  + Repository<StoredFile>
+  + UnitOfWorkTrait
+  + 'static
+{
+}
+
+impl<T> Backend for T where
+  T: UserRepositoryTrait
+    + UserSearchTrait
+    + AuditRepositoryTrait
+    + InvitationRepositoryTrait
+    + OrganizationRepositoryTrait
+    + OutboxRepositoryTrait
+    + TokenRepositoryTrait
+    + WebauthnRepositoryTrait
+    + Repository<StoredFile>
+    + UnitOfWorkTrait
+    + 'static
+{
+}
+
+/// The tenant owning `database`, named after the configured one when tenants have their own.
+pub(crate) fn tenant_of(database: &str) -> Option<String> {
+  let prefix = format!("{}-", config::get().db.database);
+  database.strip_prefix(&prefix).map(str::to_string)
+}
 
 #[derive(Clone, Debug)]
 pub struct Database {
-  pub user_col: Collection<User>,
+  client: Client,
+  /// Client of `db.reads.follower_uri`, if any.
+  follower: Option<Client>,
+  read_criteria: SelectionCriteria,
+  db: mongodb::Database,
+  /// Tenant databases whose indexes have been created, see `database_per_tenant`.
+  indexed_tenants: Arc<Mutex<HashSet<String>>>,
 }
 
 impl Database {
@@ -23,16 +110,216 @@ impl Database {
   ///
   /// # Errors
   ///
-  /// This function will return an error if the `MongoDB` client cannot be initialized
-  /// or if the specified database or collection cannot be accessed.
+  /// This function will return an error if the `MongoDB` client cannot be initialized, e.g.
+  /// because `[db.client]` holds options the driver rejects, or if the specified database or
+  /// collection cannot be accessed.
   pub async fn new() -> AppResult<Self> {
     let cfg = config::get();
-    let client = Client::with_uri_str(&cfg.db.uri).await?;
+    let client = Client::with_options(client::client_options(&cfg.db.uri, &cfg.db.client).await?)?;
+    let follower = match &cfg.db.reads.follower_uri {
+      Some(uri) => Some(Client::with_options(
+        client::client_options(uri, &cfg.db.client).await?,
+      )?),
+      None => None,
+    };
     let db = client.database(&cfg.db.database);
-    let user_col: Collection<User> = db.collection("User");
 
     info!("initializing database connection...");
 
-    Ok(Database { user_col })
+    let database = Database {
+      client,
+      follower,
+      read_criteria: client::read_criteria(&cfg.db.reads),
+      db,
+      indexed_tenants: Arc::default(),
+    };
+    database.create_indexes().await?;
+    Ok(database)
+  }
+
+  /// Returns the database holding the current tenant's data.
+  ///
+  /// This is the configured database unless `db.database_per_tenant` is set and the current
+  /// request belongs to a tenant. Tenant databases are created on first use, their indexes are
+  /// then created in the background.
+  pub fn database(&self) -> mongodb::Database {
+    let cfg = config::get();
+    match tenant::current_tenant() {
+      Some(tenant) if cfg.db.database_per_tenant => {
+        let database = self
+          .client
+          .database(&format!("{}-{tenant}", cfg.db.database));
+        let first_use = self
+          .indexed_tenants
+          .lock()
+          .is_ok_and(|mut indexed| indexed.insert(tenant.clone()));
+        if first_use {
+          tokio::spawn(self.clone().index_tenant(tenant));
+        }
+        database
+      }
+      _ => self.db.clone(),
+    }
+  }
+
+  /// Creates the indexes of the database of `tenant`, which is forgotten on failure so that its
+  /// next use tries again.
+  async fn index_tenant(self, tenant: String) {
+    let result = utils::tenant::scope(tenant.clone(), self.create_indexes()).await;
+    if let Err(e) = result {
+      error!("failed to create the indexes of tenant {tenant}: {e}");
+      if let Ok(mut indexed) = self.indexed_tenants.lock() {
+        indexed.remove(&tenant);
+      }
+    }
+  }
+
+  /// Lists the tenants having their own database, see `db.database_per_tenant`.
+  ///
+  /// Requests only reach the database of their tenant, so work done outside of a request, such
+  /// as migrations or purges, runs once per tenant in the scope of each of them.
+  pub async fn tenants(&self) -> AppResult<Vec<String>> {
+    if !config::get().db.database_per_tenant {
+      return Ok(Vec::new());
+    }
+    let names = self.client.list_database_names().await?;
+    Ok(
+      names
+        .iter()
+        .filter_map(|name| tenant_of(name))
+        .filter(|tenant| utils::tenant::is_valid(tenant))
+        .collect(),
+    )
+  }
+
+  /// Returns the database to run queries tolerating stale data on, see `db.reads`.
+  ///
+  /// This is the current tenant's database on the follower if there is one, read with the
+  /// configured read preference. Within a unit of work it is the primary, so that the unit of
+  /// work sees its own writes, whether or not it runs in a transaction.
+  pub fn read_database(&self) -> mongodb::Database {
+    let database = self.database();
+    if transaction::in_unit_of_work() {
+      return database;
+    }
+    let options = DatabaseOptions::builder()
+      .selection_criteria(self.read_criteria.clone())
+      .build();
+    self
+      .follower
+      .as_ref()
+      .unwrap_or(&self.client)
+      .database_with_options(database.name(), options)
+  }
+
+  pub fn user_col(&self) -> Collection<User> {
+    self.collection()
+  }
+
+  pub fn audit_col(&self) -> Collection<AuditEvent> {
+    self.collection()
+  }
+
+  pub fn invitation_col(&self) -> Collection<Invitation> {
+    self.collection()
+  }
+
+  pub fn organization_col(&self) -> Collection<Organization> {
+    self.collection()
+  }
+
+  pub fn membership_col(&self) -> Collection<Membership> {
+    self.collection()
+  }
+
+  pub fn organization_invitation_col(&self) -> Collection<OrganizationInvitation> {
+    self.collection()
+  }
+
+  pub fn revoked_token_col(&self) -> Collection<RevokedToken> {
+    self.collection()
+  }
+
+  pub fn webauthn_credential_col(&self) -> Collection<WebauthnCredential> {
+    self.collection()
+  }
+
+  pub fn webauthn_challenge_col(&self) -> Collection<WebauthnChallenge> {
+    self.collection()
+  }
+
+  pub fn file_col(&self) -> Collection<StoredFile> {
+    self.collection()
+  }
+
+  /// Always in the configured database, so that one relay serves every tenant.
+  pub fn outbox_col(&self) -> Collection<OutboxMessage> {
+    self
+      .db
+      .collection(config::get().db.collection_name("Outbox"))
+  }
+}
+
+#[cfg(test)]
+impl Database {
+  /// A database whose server cannot be reached, so that every operation fails quickly.
+  pub(crate) async fn unreachable() -> Self {
+    utils::config::init_for_tests();
+    // Nothing listens there, so operations fail once no server is found.
+    let mut options = mongodb::options::ClientOptions::parse("mongodb://127.0.0.1:1")
+      .await
+      .unwrap();
+    options.server_selection_timeout = Some(std::time::Duration::from_millis(50));
+    let client = Client::with_options(options).unwrap();
+    Self {
+      db: client.database("unreachable"),
+      follower: None,
+      read_criteria: client::read_criteria(&config::get().db.reads),
+      client,
+      indexed_tenants: Arc::default(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mongodb::options::ReadPreference;
+
+  #[tokio::test]
+  async fn failed_tenant_index_builds_are_retried() {
+    let database = Database::unreachable().await;
+    database
+      .indexed_tenants
+      .lock()
+      .unwrap()
+      .insert("acme".to_string());
+
+    database.clone().index_tenant("acme".to_string()).await;
+    assert!(!database.indexed_tenants.lock().unwrap().contains("acme"));
+  }
+
+  #[tokio::test]
+  async fn units_of_work_read_from_the_primary() {
+    let mut database = Database::unreachable().await;
+    database.read_criteria =
+      SelectionCriteria::ReadPreference(ReadPreference::SecondaryPreferred { options: None });
+    let is_secondary_preferred = |database: mongodb::Database| {
+      matches!(
+        database.selection_criteria(),
+        Some(SelectionCriteria::ReadPreference(
+          ReadPreference::SecondaryPreferred { .. }
+        ))
+      )
+    };
+    assert!(is_secondary_preferred(database.read_database()));
+
+    // `db.transactions` is off in the template: best effort units of work read their writes too.
+    let in_unit_of_work = transaction::transaction(&database, || async {
+      Ok(is_secondary_preferred(database.read_database()))
+    })
+    .await
+    .unwrap();
+    assert!(!in_unit_of_work);
   }
 }
@@ -0,0 +1,63 @@
+use super::{MemoryDatabase, in_tenant, lock};
+use crate::{
+  audit::{
+    model::{AuditEvent, AuditEventFilter},
+    repository::AuditRepositoryTrait,
+  },
+  pagination::{Page, Pagination, paginate},
+  results::inserted,
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{bson::oid::ObjectId, results::InsertOneResult};
+use utils::AppResult;
+
+fn matches(event: &AuditEvent, filter: &AuditEventFilter) -> bool {
+  in_tenant(event.tenant_id.as_deref())
+    && filter
+      .actor
+      .as_ref()
+      .is_none_or(|actor| event.actor.as_ref() == Some(actor))
+    && filter
+      .target
+      .as_ref()
+      .is_none_or(|target| event.target.as_ref() == Some(target))
+    && filter.action.is_none_or(|action| event.action == action)
+    && filter
+      .outcome
+      .is_none_or(|outcome| event.outcome == outcome)
+    && filter
+      .ip
+      .as_ref()
+      .is_none_or(|ip| event.ip.as_ref() == Some(ip))
+    && filter.from.is_none_or(|from| event.timestamp >= from)
+    && filter.to.is_none_or(|to| event.timestamp <= to)
+}
+
+#[async_trait]
+impl AuditRepositoryTrait for MemoryDatabase {
+  async fn create_audit_event(&self, mut event: AuditEvent) -> AppResult<InsertOneResult> {
+    let id = *event.id.get_or_insert_with(ObjectId::new);
+    event.tenant_id = current_tenant();
+    lock(&self.audit_events).push(event);
+    Ok(inserted(id))
+  }
+
+  async fn find_audit_events(
+    &self,
+    filter: &AuditEventFilter,
+    pagination: &Pagination,
+  ) -> AppResult<Page<AuditEvent>> {
+    let events = lock(&self.audit_events)
+      .iter()
+      .filter(|event| matches(event, filter))
+      .cloned()
+      .collect();
+    paginate(events, pagination)
+  }
+
+  async fn count_audit_events(&self, filter: &AuditEventFilter) -> AppResult<u64> {
+    let events = lock(&self.audit_events);
+    Ok(events.iter().filter(|event| matches(event, filter)).count() as u64)
+  }
+}
@@ -0,0 +1,137 @@
+use super::{MemoryDatabase, in_tenant, lock, remove_where};
+use crate::{
+  invitation::{model::Invitation, repository::InvitationRepositoryTrait},
+  results::{deleted, inserted},
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult},
+};
+use std::{cmp::Reverse, str::FromStr};
+use utils::{AppError, AppResult};
+
+#[async_trait]
+impl InvitationRepositoryTrait for MemoryDatabase {
+  async fn create_invitation(&self, mut invitation: Invitation) -> AppResult<InsertOneResult> {
+    let mut invitations = lock(&self.invitations);
+    if invitations
+      .iter()
+      .any(|other| other.code == invitation.code)
+    {
+      return Err(AppError::DuplicateKey("code".to_string()));
+    }
+    let id = *invitation.id.get_or_insert_with(ObjectId::new);
+    invitation.tenant_id = current_tenant();
+    invitations.push(invitation);
+    Ok(inserted(id))
+  }
+
+  async fn get_all_invitations(&self) -> AppResult<Vec<Invitation>> {
+    let mut invitations: Vec<Invitation> = lock(&self.invitations)
+      .iter()
+      .filter(|invitation| in_tenant(invitation.tenant_id.as_deref()))
+      .cloned()
+      .collect();
+    invitations.sort_by_key(|invitation| Reverse(invitation.created_at));
+    Ok(invitations)
+  }
+
+  async fn redeem_invitation(&self, code: &str, email: &str) -> AppResult<Option<Invitation>> {
+    let email = email.to_lowercase();
+    let now = DateTime::now();
+    let mut invitations = lock(&self.invitations);
+    let invitation = invitations.iter_mut().find(|invitation| {
+      in_tenant(invitation.tenant_id.as_deref())
+        && invitation.code == code
+        && invitation.used_at.is_none()
+        && invitation.expires_at > now
+        && invitation.email.as_ref().is_none_or(|only| *only == email)
+    });
+    let Some(invitation) = invitation else {
+      return Ok(None);
+    };
+    // Like `find_one_and_update`, return the invitation as it was before.
+    let before = invitation.clone();
+    invitation.used_at = Some(now);
+    invitation.used_by = Some(email);
+    Ok(Some(before))
+  }
+
+  async fn delete_invitation(&self, id: &str) -> AppResult<DeleteResult> {
+    let id = ObjectId::from_str(id)?;
+    let mut invitations = lock(&self.invitations);
+    let count = remove_where(&mut invitations, |invitation| {
+      invitation.id == Some(id) && in_tenant(invitation.tenant_id.as_deref())
+    });
+    Ok(deleted(count))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn invitation(code: &str, email: Option<&str>, expires_at: DateTime) -> Invitation {
+    Invitation {
+      id: None,
+      code: code.to_string(),
+      email: email.map(str::to_string),
+      created_by: "admin@example.com".to_string(),
+      created_at: DateTime::now(),
+      expires_at,
+      used_at: None,
+      used_by: None,
+      tenant_id: None,
+    }
+  }
+
+  #[tokio::test]
+  async fn invitations_are_redeemed_once() {
+    let db = MemoryDatabase::new();
+    db.create_invitation(invitation("open", None, DateTime::MAX))
+      .await
+      .unwrap();
+    let redeemed = db
+      .redeem_invitation("open", "Ada@Example.com")
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(redeemed.used_at.is_none());
+    assert!(
+      db.redeem_invitation("open", "grace@example.com")
+        .await
+        .unwrap()
+        .is_none()
+    );
+    let stored = db.get_all_invitations().await.unwrap();
+    assert_eq!(stored[0].used_by.as_deref(), Some("ada@example.com"));
+  }
+
+  #[tokio::test]
+  async fn invitations_check_their_email_and_expiry() {
+    let db = MemoryDatabase::new();
+    db.create_invitation(invitation("ada", Some("ada@example.com"), DateTime::MAX))
+      .await
+      .unwrap();
+    db.create_invitation(invitation("expired", None, DateTime::MIN))
+      .await
+      .unwrap();
+    assert!(
+      db.create_invitation(invitation("ada", None, DateTime::MAX))
+        .await
+        .is_err()
+    );
+
+    let redeem = |code, email| db.redeem_invitation(code, email);
+    assert!(redeem("ada", "grace@example.com").await.unwrap().is_none());
+    assert!(
+      redeem("expired", "ada@example.com")
+        .await
+        .unwrap()
+        .is_none()
+    );
+    assert!(redeem("ada", "ADA@example.com").await.unwrap().is_some());
+  }
+}
@@ -0,0 +1,83 @@
+//! In-memory implementation of every repository trait, selected with `db.backend = "memory"`.
+//!
+//! Data lives in the process and is lost on restart, which makes it suitable for tests and local
+//! development without a Mongo server. Queries mirror the Mongo repositories, including tenant
+//! scoping and the unique indexes created by `Database::create_indexes`. There are no
+//! transactions: a failing unit of work keeps the changes made before the failure.
+mod audit;
+mod invitation;
+mod organization;
+mod outbox;
+mod repository;
+mod token;
+mod user;
+mod webauthn;
+
+use crate::{
+  audit::model::AuditEvent,
+  invitation::model::Invitation,
+  organization::model::{Membership, Organization, OrganizationInvitation},
+  outbox::model::OutboxMessage,
+  tenant::current_tenant,
+  token::model::RevokedToken,
+  transaction::{BoxFuture, UnitOfWorkTrait},
+  user::model::User,
+  webauthn::model::{WebauthnChallenge, WebauthnCredential},
+};
+use async_trait::async_trait;
+use mongodb::bson::Document;
+use std::{
+  collections::HashMap,
+  sync::{Mutex, MutexGuard, PoisonError},
+};
+use utils::AppResult;
+
+#[derive(Debug, Default)]
+pub struct MemoryDatabase {
+  users: Mutex<Vec<User>>,
+  audit_events: Mutex<Vec<AuditEvent>>,
+  invitations: Mutex<Vec<Invitation>>,
+  organizations: Mutex<Vec<Organization>>,
+  memberships: Mutex<Vec<Membership>>,
+  organization_invitations: Mutex<Vec<OrganizationInvitation>>,
+  revoked_tokens: Mutex<Vec<RevokedToken>>,
+  webauthn_credentials: Mutex<Vec<WebauthnCredential>>,
+  webauthn_challenges: Mutex<Vec<WebauthnChallenge>>,
+  outbox: Mutex<Vec<OutboxMessage>>,
+  /// Documents of the generic repositories by collection, see `repository::Model`.
+  documents: Mutex<HashMap<&'static str, Vec<Document>>>,
+}
+
+impl MemoryDatabase {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+/// Locks a collection. Every change is applied under a single lock, so a panic cannot leave a
+/// document half written and poisoning is ignored.
+fn lock<T>(collection: &Mutex<T>) -> MutexGuard<'_, T> {
+  collection.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether a document belongs to the current tenant, see `tenant::scoped`.
+fn in_tenant(tenant_id: Option<&str>) -> bool {
+  current_tenant().is_none_or(|tenant| tenant_id == Some(tenant.as_str()))
+}
+
+/// Removes the documents matching `predicate` and returns how many there were.
+fn remove_where<T>(documents: &mut Vec<T>, predicate: impl Fn(&T) -> bool) -> u64 {
+  let before = documents.len();
+  documents.retain(|document| !predicate(document));
+  (before - documents.len()) as u64
+}
+
+#[async_trait]
+impl UnitOfWorkTrait for MemoryDatabase {
+  async fn run<'a, 'b>(
+    &'b self,
+    work: &'b mut (dyn FnMut() -> BoxFuture<'a, AppResult<()>> + Send + 'b),
+  ) -> AppResult<()> {
+    work().await
+  }
+}
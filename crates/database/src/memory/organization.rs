@@ -0,0 +1,216 @@
+use super::{MemoryDatabase, in_tenant, lock, remove_where};
+use crate::{
+  organization::{
+    model::{Membership, Organization, OrganizationInvitation, OrganizationRole},
+    repository::OrganizationRepositoryTrait,
+  },
+  results::{deleted, inserted, updated},
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use utils::{AppError, AppResult};
+
+fn is_member(membership: &Membership, organization_id: &ObjectId, user_id: &ObjectId) -> bool {
+  membership.organization_id == *organization_id
+    && membership.user_id == *user_id
+    && in_tenant(membership.tenant_id.as_deref())
+}
+
+fn is_pending(invitation: &OrganizationInvitation, email: &str, now: DateTime) -> bool {
+  in_tenant(invitation.tenant_id.as_deref())
+    && invitation.email == email.to_lowercase()
+    && invitation.accepted_at.is_none()
+    && invitation.expires_at > now
+}
+
+#[async_trait]
+impl OrganizationRepositoryTrait for MemoryDatabase {
+  async fn create_organization(
+    &self,
+    mut organization: Organization,
+  ) -> AppResult<InsertOneResult> {
+    let id = *organization.id.get_or_insert_with(ObjectId::new);
+    organization.tenant_id = current_tenant();
+    lock(&self.organizations).push(organization);
+    Ok(inserted(id))
+  }
+
+  async fn get_organization_by_id(&self, id: &ObjectId) -> AppResult<Option<Organization>> {
+    let organizations = lock(&self.organizations);
+    let organization = organizations.iter().find(|organization| {
+      organization.id.as_ref() == Some(id) && in_tenant(organization.tenant_id.as_deref())
+    });
+    Ok(organization.cloned())
+  }
+
+  async fn get_organizations_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<Organization>> {
+    let organizations = lock(&self.organizations)
+      .iter()
+      .filter(|organization| {
+        organization.id.is_some_and(|id| ids.contains(&id))
+          && in_tenant(organization.tenant_id.as_deref())
+      })
+      .cloned()
+      .collect();
+    Ok(organizations)
+  }
+
+  async fn create_membership(&self, mut membership: Membership) -> AppResult<InsertOneResult> {
+    let mut memberships = lock(&self.memberships);
+    let exists = memberships.iter().any(|other| {
+      other.organization_id == membership.organization_id && other.user_id == membership.user_id
+    });
+    if exists {
+      return Err(AppError::DuplicateKey("user_id".to_string()));
+    }
+    let id = *membership.id.get_or_insert_with(ObjectId::new);
+    membership.tenant_id = current_tenant();
+    memberships.push(membership);
+    Ok(inserted(id))
+  }
+
+  async fn get_membership(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+  ) -> AppResult<Option<Membership>> {
+    let memberships = lock(&self.memberships);
+    let membership = memberships
+      .iter()
+      .find(|membership| is_member(membership, organization_id, user_id));
+    Ok(membership.cloned())
+  }
+
+  async fn get_memberships_by_user(&self, user_id: &ObjectId) -> AppResult<Vec<Membership>> {
+    let memberships = lock(&self.memberships)
+      .iter()
+      .filter(|membership| {
+        membership.user_id == *user_id && in_tenant(membership.tenant_id.as_deref())
+      })
+      .cloned()
+      .collect();
+    Ok(memberships)
+  }
+
+  async fn get_memberships_by_organization(
+    &self,
+    organization_id: &ObjectId,
+  ) -> AppResult<Vec<Membership>> {
+    let memberships = lock(&self.memberships)
+      .iter()
+      .filter(|membership| {
+        membership.organization_id == *organization_id && in_tenant(membership.tenant_id.as_deref())
+      })
+      .cloned()
+      .collect();
+    Ok(memberships)
+  }
+
+  async fn count_members_with_role(
+    &self,
+    organization_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<u64> {
+    let memberships = lock(&self.memberships);
+    let count = memberships
+      .iter()
+      .filter(|membership| {
+        membership.organization_id == *organization_id
+          && membership.role == role
+          && in_tenant(membership.tenant_id.as_deref())
+      })
+      .count();
+    Ok(count as u64)
+  }
+
+  async fn adjust_owners(&self, organization_id: &ObjectId, delta: i64) -> AppResult<bool> {
+    let mut organizations = lock(&self.organizations);
+    let organization = organizations.iter_mut().find(|organization| {
+      organization.id.as_ref() == Some(organization_id)
+        && in_tenant(organization.tenant_id.as_deref())
+    });
+    match organization {
+      Some(organization) if organization.owners + delta >= 1 => {
+        organization.owners += delta;
+        Ok(true)
+      }
+      _ => Ok(false),
+    }
+  }
+
+  async fn update_membership_role(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<UpdateResult> {
+    let mut memberships = lock(&self.memberships);
+    let membership = memberships
+      .iter_mut()
+      .find(|membership| is_member(membership, organization_id, user_id));
+    let Some(membership) = membership else {
+      return Ok(updated(0));
+    };
+    membership.role = role;
+    Ok(updated(1))
+  }
+
+  async fn delete_membership(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+  ) -> AppResult<DeleteResult> {
+    let mut memberships = lock(&self.memberships);
+    // `delete_one` removes at most one membership, and the unique index allows no more.
+    let count = remove_where(&mut memberships, |membership| {
+      is_member(membership, organization_id, user_id)
+    });
+    Ok(deleted(count))
+  }
+
+  async fn create_organization_invitation(
+    &self,
+    mut invitation: OrganizationInvitation,
+  ) -> AppResult<InsertOneResult> {
+    let id = *invitation.id.get_or_insert_with(ObjectId::new);
+    invitation.tenant_id = current_tenant();
+    lock(&self.organization_invitations).push(invitation);
+    Ok(inserted(id))
+  }
+
+  async fn get_pending_invitations_by_email(
+    &self,
+    email: &str,
+  ) -> AppResult<Vec<OrganizationInvitation>> {
+    let now = DateTime::now();
+    let invitations = lock(&self.organization_invitations)
+      .iter()
+      .filter(|invitation| is_pending(invitation, email, now))
+      .cloned()
+      .collect();
+    Ok(invitations)
+  }
+
+  async fn accept_organization_invitation(
+    &self,
+    id: &ObjectId,
+    email: &str,
+  ) -> AppResult<Option<OrganizationInvitation>> {
+    let now = DateTime::now();
+    let mut invitations = lock(&self.organization_invitations);
+    let invitation = invitations
+      .iter_mut()
+      .find(|invitation| invitation.id.as_ref() == Some(id) && is_pending(invitation, email, now));
+    let Some(invitation) = invitation else {
+      return Ok(None);
+    };
+    // Like `find_one_and_update`, return the invitation as it was before.
+    let before = invitation.clone();
+    invitation.accepted_at = Some(now);
+    Ok(Some(before))
+  }
+}
@@ -0,0 +1,168 @@
+use super::{MemoryDatabase, lock};
+use crate::{
+  outbox::{
+    model::{OutboxMessage, OutboxStatus},
+    repository::{OutboxRepositoryTrait, later},
+  },
+  results::{inserted, updated},
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{InsertOneResult, UpdateResult},
+};
+use std::time::Duration;
+use utils::AppResult;
+
+#[async_trait]
+impl OutboxRepositoryTrait for MemoryDatabase {
+  async fn enqueue_message(&self, mut message: OutboxMessage) -> AppResult<InsertOneResult> {
+    let id = *message.id.get_or_insert_with(ObjectId::new);
+    message.tenant_id = current_tenant();
+    lock(&self.outbox).push(message);
+    Ok(inserted(id))
+  }
+
+  async fn claim_messages(&self, limit: usize, lease: Duration) -> AppResult<Vec<OutboxMessage>> {
+    let now = DateTime::now();
+    let mut outbox = lock(&self.outbox);
+    let mut due: Vec<&mut OutboxMessage> = outbox
+      .iter_mut()
+      .filter(|message| message.status == OutboxStatus::Pending && message.available_at <= now)
+      .collect();
+    due.sort_by_key(|message| message.available_at);
+    Ok(
+      due
+        .into_iter()
+        .take(limit)
+        .map(|message| {
+          message.available_at = later(now, lease);
+          message.attempts += 1;
+          message.clone()
+        })
+        .collect(),
+    )
+  }
+
+  async fn renew_lease(&self, ids: &[ObjectId], lease: Duration) -> AppResult<UpdateResult> {
+    let available_at = later(DateTime::now(), lease);
+    let mut count = 0;
+    for message in lock(&self.outbox).iter_mut() {
+      if message.id.is_some_and(|id| ids.contains(&id)) && message.status == OutboxStatus::Pending {
+        message.available_at = available_at;
+        count += 1;
+      }
+    }
+    Ok(updated(count))
+  }
+
+  async fn complete_message(&self, id: &ObjectId) -> AppResult<UpdateResult> {
+    let mut outbox = lock(&self.outbox);
+    let Some(message) = outbox.iter_mut().find(|message| message.id == Some(*id)) else {
+      return Ok(updated(0));
+    };
+    message.status = OutboxStatus::Delivered;
+    message.delivered_at = Some(DateTime::now());
+    message.last_error = None;
+    Ok(updated(1))
+  }
+
+  async fn fail_message(
+    &self,
+    id: &ObjectId,
+    error: &str,
+    delivered_to: &[String],
+    retry_at: Option<DateTime>,
+  ) -> AppResult<UpdateResult> {
+    let mut outbox = lock(&self.outbox);
+    let Some(message) = outbox.iter_mut().find(|message| message.id == Some(*id)) else {
+      return Ok(updated(0));
+    };
+    match retry_at {
+      Some(retry_at) => message.available_at = retry_at,
+      None => message.status = OutboxStatus::Failed,
+    }
+    message.last_error = Some(error.to_string());
+    message.delivered_to = delivered_to.to_vec();
+    Ok(updated(1))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mongodb::bson::doc;
+
+  const LEASE: Duration = Duration::from_secs(60);
+
+  #[tokio::test]
+  async fn claimed_messages_are_leased() {
+    let db = MemoryDatabase::new();
+    for topic in ["user.created", "user.updated"] {
+      db.enqueue_message(OutboxMessage::new(topic, doc! {}))
+        .await
+        .unwrap();
+    }
+    let claimed = db.claim_messages(1, LEASE).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].attempts, 1);
+    // The first message is leased, only the second one is left.
+    let rest = db.claim_messages(10, LEASE).await.unwrap();
+    assert_eq!(rest.len(), 1);
+    assert_ne!(rest[0].id, claimed[0].id);
+    assert!(db.claim_messages(10, LEASE).await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn leases_of_pending_messages_are_renewed() {
+    let db = MemoryDatabase::new();
+    for topic in ["user.created", "user.updated"] {
+      db.enqueue_message(OutboxMessage::new(topic, doc! {}))
+        .await
+        .unwrap();
+    }
+    let ids: Vec<ObjectId> = db
+      .claim_messages(10, Duration::ZERO)
+      .await
+      .unwrap()
+      .iter()
+      .map(|message| message.id.unwrap())
+      .collect();
+    db.complete_message(&ids[0]).await.unwrap();
+    let renewed = db.renew_lease(&ids, LEASE).await.unwrap();
+    assert_eq!(renewed.modified_count, 1);
+    // Without the renewal, the zero lease would have made the message due again.
+    assert!(db.claim_messages(10, LEASE).await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn failed_messages_are_retried_until_given_up() {
+    let db = MemoryDatabase::new();
+    db.enqueue_message(OutboxMessage::new("user.created", doc! {}))
+      .await
+      .unwrap();
+    let id = db.claim_messages(1, LEASE).await.unwrap()[0].id.unwrap();
+
+    let delivered_to = ["log".to_string()];
+    db.fail_message(&id, "timeout", &delivered_to, Some(DateTime::MIN))
+      .await
+      .unwrap();
+    let retried = db.claim_messages(1, LEASE).await.unwrap();
+    assert_eq!(retried[0].attempts, 2);
+    assert_eq!(retried[0].last_error.as_deref(), Some("timeout"));
+    assert_eq!(retried[0].delivered_to, delivered_to);
+
+    db.fail_message(&id, "timeout", &delivered_to, None)
+      .await
+      .unwrap();
+    assert_eq!(lock(&db.outbox)[0].status, OutboxStatus::Failed);
+    assert_eq!(
+      db.complete_message(&ObjectId::new())
+        .await
+        .unwrap()
+        .matched_count,
+      0
+    );
+  }
+}
@@ -0,0 +1,222 @@
+//! Generic repositories kept as documents, so that filters are evaluated on the stored fields.
+//!
+//! These documents are apart from those of the dedicated repositories: a `Repository<User>` does
+//! not see the users of `UserRepositoryTrait`. Unique indexes are not enforced.
+use super::{MemoryDatabase, in_tenant, lock, remove_where};
+use crate::{
+  pagination::{Page, Pagination, compare_bson, paginate},
+  repository::{Model, Repository, id_of},
+  results::{deleted, inserted, updated},
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{Bson, Document, doc, from_document, oid::ObjectId, to_document},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use std::cmp::Ordering;
+use utils::{AppError, AppResult};
+
+/// Whether `document` has every value of `filter`, see the `repository` module documentation.
+fn matches(document: &Document, filter: &Document) -> AppResult<bool> {
+  for (key, expected) in filter {
+    let operator = key.starts_with('$')
+      || matches!(expected, Bson::Document(value) if value.keys().any(|key| key.starts_with('$')));
+    if operator {
+      return Err(AppError::InternalServerErrorWithContext(format!(
+        "the memory backend only matches filters for equality, got {key}"
+      )));
+    }
+    let found = document
+      .get(key)
+      .is_some_and(|value| compare_bson(value, expected) == Ordering::Equal);
+    if !found {
+      return Ok(false);
+    }
+  }
+  Ok(true)
+}
+
+fn is_visible(document: &Document) -> bool {
+  in_tenant(document.get_str("tenant_id").ok())
+}
+
+impl MemoryDatabase {
+  /// The documents of `T` matching `filter` in the current tenant.
+  fn matching<T: Model>(&self, filter: &Document) -> AppResult<Vec<T>> {
+    let documents = lock(&self.documents);
+    let mut models = Vec::new();
+    for document in documents.get(T::COLLECTION).into_iter().flatten() {
+      if is_visible(document) && matches(document, filter)? {
+        models.push(from_document(document.clone())?);
+      }
+    }
+    Ok(models)
+  }
+}
+
+#[async_trait]
+impl<T: Model> Repository<T> for MemoryDatabase {
+  async fn insert(&self, mut document: T) -> AppResult<InsertOneResult> {
+    let id = *document.id_mut().get_or_insert_with(ObjectId::new);
+    *document.tenant_id_mut() = current_tenant();
+    let document = to_document(&document)?;
+    lock(&self.documents)
+      .entry(T::COLLECTION)
+      .or_default()
+      .push(document);
+    Ok(inserted(id))
+  }
+
+  async fn find_by_id(&self, id: &ObjectId) -> AppResult<Option<T>> {
+    self.find_one(doc! {"_id": id}).await
+  }
+
+  async fn find_one(&self, filter: Document) -> AppResult<Option<T>> {
+    Ok(self.matching(&filter)?.into_iter().next())
+  }
+
+  async fn find(&self, filter: Document) -> AppResult<Vec<T>> {
+    self.matching(&filter)
+  }
+
+  async fn find_page(&self, filter: Document, pagination: &Pagination) -> AppResult<Page<T>> {
+    paginate(self.matching(&filter)?, pagination)
+  }
+
+  async fn count(&self, filter: Document) -> AppResult<u64> {
+    Ok(self.matching::<T>(&filter)?.len() as u64)
+  }
+
+  async fn exists(&self, filter: Document) -> AppResult<bool> {
+    Ok(!self.matching::<T>(&filter)?.is_empty())
+  }
+
+  async fn update(&self, mut document: T) -> AppResult<UpdateResult> {
+    let id = id_of(&mut document)?;
+    *document.tenant_id_mut() = current_tenant();
+    let replacement = to_document(&document)?;
+    let mut documents = lock(&self.documents);
+    let stored = documents
+      .get_mut(T::COLLECTION)
+      .into_iter()
+      .flatten()
+      .find(|stored| {
+        stored.get_object_id("_id").is_ok_and(|stored| stored == id) && is_visible(stored)
+      });
+    match stored {
+      Some(stored) => {
+        *stored = replacement;
+        Ok(updated(1))
+      }
+      None => Ok(updated(0)),
+    }
+  }
+
+  async fn delete_by_id(&self, id: &ObjectId) -> AppResult<DeleteResult> {
+    let mut documents = lock(&self.documents);
+    let count = documents.get_mut(T::COLLECTION).map_or(0, |stored| {
+      remove_where(stored, |document| {
+        document
+          .get_object_id("_id")
+          .is_ok_and(|stored| stored == *id)
+          && is_visible(document)
+      })
+    });
+    Ok(deleted(count))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::{Deserialize, Serialize};
+  use utils::tenant;
+
+  #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+  struct Note {
+    #[serde(rename = "_id")]
+    id: Option<ObjectId>,
+    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tenant_id: Option<String>,
+  }
+
+  crate::impl_model!(Note, "Note");
+
+  fn note(text: &str) -> Note {
+    Note {
+      id: None,
+      text: text.to_string(),
+      tenant_id: None,
+    }
+  }
+
+  async fn insert(db: &MemoryDatabase, document: Note) -> ObjectId {
+    let result = db.insert(document).await.unwrap();
+    result.inserted_id.as_object_id().unwrap()
+  }
+
+  /// The note with `id` as seen from `tenant`.
+  async fn get(db: &MemoryDatabase, tenant: &str, id: ObjectId) -> Option<Note> {
+    let found = tenant::scope(tenant.to_string(), db.find_by_id(&id)).await;
+    found.unwrap()
+  }
+
+  #[tokio::test]
+  async fn documents_stay_in_their_tenant() {
+    let db = MemoryDatabase::new();
+    let id = tenant::scope("acme".to_string(), insert(&db, note("hello"))).await;
+
+    tenant::scope("globex".to_string(), async {
+      let repository: &dyn Repository<Note> = &db;
+      assert!(repository.find_by_id(&id).await.unwrap().is_none());
+      assert!(repository.find(doc! {}).await.unwrap().is_empty());
+      assert_eq!(repository.count(doc! {}).await.unwrap(), 0);
+      assert!(!repository.exists(doc! { "text": "hello" }).await.unwrap());
+      let mut stolen = note("stolen");
+      stolen.id = Some(id);
+      assert_eq!(repository.update(stolen).await.unwrap().matched_count, 0);
+      assert_eq!(repository.delete_by_id(&id).await.unwrap().deleted_count, 0);
+    })
+    .await;
+
+    let stored = get(&db, "acme", id).await.unwrap();
+    assert_eq!(stored.text, "hello");
+    assert_eq!(stored.tenant_id.as_deref(), Some("acme"));
+  }
+
+  #[tokio::test]
+  async fn documents_cannot_pick_their_tenant() {
+    let db = MemoryDatabase::new();
+    let mut spoofed = note("hello");
+    spoofed.tenant_id = Some("globex".to_string());
+    let id = tenant::scope("acme".to_string(), insert(&db, spoofed)).await;
+
+    assert!(get(&db, "globex", id).await.is_none());
+
+    let mut moved = get(&db, "acme", id).await.unwrap();
+    moved.tenant_id = Some("globex".to_string());
+    tenant::scope("acme".to_string(), db.update(moved))
+      .await
+      .unwrap();
+    assert!(get(&db, "globex", id).await.is_none());
+    let stored = get(&db, "acme", id).await.unwrap();
+    assert_eq!(stored.tenant_id.as_deref(), Some("acme"));
+  }
+
+  #[tokio::test]
+  async fn filters_match_fields_for_equality_only() {
+    let db = MemoryDatabase::new();
+    insert(&db, note("hello")).await;
+    insert(&db, note("world")).await;
+
+    let found: Vec<Note> = db.find(doc! { "text": "world" }).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].text, "world");
+    let error = Repository::<Note>::count(&db, doc! { "text": { "$ne": "world" } }).await;
+    assert!(error.is_err());
+    // Replacing needs to know which document.
+    assert!(db.update(note("no id")).await.is_err());
+  }
+}
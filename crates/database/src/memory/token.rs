@@ -0,0 +1,40 @@
+use super::{MemoryDatabase, in_tenant, lock};
+use crate::{
+  results::updated,
+  tenant::current_tenant,
+  token::{model::RevokedToken, repository::TokenRepositoryTrait},
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{Bson, oid::ObjectId},
+  results::UpdateResult,
+};
+use utils::AppResult;
+
+#[async_trait]
+impl TokenRepositoryTrait for MemoryDatabase {
+  async fn revoke_token(&self, mut token: RevokedToken) -> AppResult<UpdateResult> {
+    let mut tokens = lock(&self.revoked_tokens);
+    let revoked = tokens
+      .iter()
+      .any(|other| other.jti == token.jti && in_tenant(other.tenant_id.as_deref()));
+    if revoked {
+      return Ok(updated(1));
+    }
+    let id = *token.id.get_or_insert_with(ObjectId::new);
+    token.tenant_id = current_tenant();
+    tokens.push(token);
+    let mut result = updated(0);
+    result.upserted_id = Some(Bson::ObjectId(id));
+    Ok(result)
+  }
+
+  async fn is_token_revoked(&self, jti: &str) -> AppResult<bool> {
+    let tokens = lock(&self.revoked_tokens);
+    Ok(
+      tokens
+        .iter()
+        .any(|token| token.jti == jti && in_tenant(token.tenant_id.as_deref())),
+    )
+  }
+}
@@ -0,0 +1,491 @@
+use super::{MemoryDatabase, in_tenant, lock, remove_where};
+use crate::{
+  pagination::{Page, Pagination, paginate},
+  results::{deleted, inserted, updated},
+  tenant::current_tenant,
+  user::{
+    model::{User, UserFilter},
+    repository::UserRepositoryTrait,
+    search::{UserSearchHit, UserSearchQuery, UserSearchResults, UserSearchTrait, score},
+  },
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use std::str::FromStr;
+use utils::{AppError, AppResult};
+
+/// Emails compare case-insensitively, like the collation of the `email` index.
+fn same_email(a: &str, b: &str) -> bool {
+  a.to_lowercase() == b.to_lowercase()
+}
+
+fn contains(value: &str, part: &str) -> bool {
+  value.to_lowercase().contains(&part.to_lowercase())
+}
+
+fn matches(user: &User, filter: &UserFilter) -> bool {
+  in_tenant(user.tenant_id.as_deref())
+    && user.deleted_at.is_some() == filter.deleted
+    && filter
+      .name
+      .as_ref()
+      .is_none_or(|name| contains(&user.name, name))
+    && filter
+      .email
+      .as_ref()
+      .is_none_or(|email| contains(&user.email, email))
+    && filter.role.is_none_or(|role| user.role == role)
+    && filter
+      .created_from
+      .is_none_or(|from| user.created_at.is_some_and(|created| created >= from))
+    && filter
+      .created_to
+      .is_none_or(|to| user.created_at.is_some_and(|created| created <= to))
+}
+
+/// The user with `id` unless it is soft deleted, see `active` in the Mongo repository.
+fn active<'a>(users: &'a mut [User], id: &ObjectId) -> Option<&'a mut User> {
+  users.iter_mut().find(|user| {
+    user.id.as_ref() == Some(id)
+      && user.deleted_at.is_none()
+      && in_tenant(user.tenant_id.as_deref())
+  })
+}
+
+/// Fails when another active user of `tenant_id` already has `email`. Like the partial `email`
+/// index, soft-deleted users do not hold on to their email.
+fn ensure_unique_email(
+  users: &[User],
+  tenant_id: Option<&str>,
+  email: &str,
+  id: Option<&ObjectId>,
+) -> AppResult<()> {
+  let taken = users.iter().any(|user| {
+    user.tenant_id.as_deref() == tenant_id
+      && user.deleted_at.is_none()
+      && same_email(&user.email, email)
+      && user.id.as_ref() != id
+  });
+  if taken {
+    return Err(AppError::DuplicateKey("email".to_string()));
+  }
+  Ok(())
+}
+
+#[async_trait]
+impl UserRepositoryTrait for MemoryDatabase {
+  async fn create_user(
+    &self,
+    name: &str,
+    email: &str,
+    password: &str,
+  ) -> AppResult<InsertOneResult> {
+    let mut users = lock(&self.users);
+    let tenant_id = current_tenant();
+    ensure_unique_email(&users, tenant_id.as_deref(), email, None)?;
+    let now = DateTime::now();
+    let id = ObjectId::new();
+    users.push(User {
+      id: Some(id),
+      name: name.to_string(),
+      email: email.to_string(),
+      password: password.to_string(),
+      created_at: Some(now),
+      updated_at: Some(now),
+      tenant_id,
+      ..Default::default()
+    });
+    Ok(inserted(id))
+  }
+
+  async fn find_users(
+    &self,
+    filter: &UserFilter,
+    pagination: &Pagination,
+  ) -> AppResult<Page<User>> {
+    let users = lock(&self.users)
+      .iter()
+      .filter(|user| matches(user, filter))
+      .cloned()
+      .collect();
+    paginate(users, pagination)
+  }
+
+  async fn count_users(&self, filter: &UserFilter) -> AppResult<u64> {
+    let users = lock(&self.users);
+    Ok(users.iter().filter(|user| matches(user, filter)).count() as u64)
+  }
+
+  async fn get_user_by_id(&self, id: &str) -> AppResult<Option<User>> {
+    let id = ObjectId::from_str(id)?;
+    let mut users = lock(&self.users);
+    Ok(active(&mut users, &id).map(|user| user.clone()))
+  }
+
+  async fn get_users_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<User>> {
+    let users = lock(&self.users)
+      .iter()
+      .filter(|user| {
+        user.id.is_some_and(|id| ids.contains(&id))
+          && user.deleted_at.is_none()
+          && in_tenant(user.tenant_id.as_deref())
+      })
+      .cloned()
+      .collect();
+    Ok(users)
+  }
+
+  async fn get_user_by_email(&self, email: &str) -> AppResult<Option<User>> {
+    let users = lock(&self.users);
+    let user = users.iter().find(|user| {
+      same_email(&user.email, email)
+        && user.deleted_at.is_none()
+        && in_tenant(user.tenant_id.as_deref())
+    });
+    Ok(user.cloned())
+  }
+
+  async fn update_user(
+    &self,
+    id: &str,
+    name: &str,
+    email: &str,
+    expected_version: Option<i64>,
+  ) -> AppResult<Option<User>> {
+    let id = ObjectId::from_str(id)?;
+    let mut users = lock(&self.users);
+    let tenant_id = match active(&mut users, &id) {
+      Some(user) if expected_version.is_none_or(|version| user.version == version) => {
+        user.tenant_id.clone()
+      }
+      _ => return Ok(None),
+    };
+    ensure_unique_email(&users, tenant_id.as_deref(), email, Some(&id))?;
+    let Some(user) = active(&mut users, &id) else {
+      return Ok(None);
+    };
+    user.name = name.to_string();
+    user.email = email.to_string();
+    user.updated_at = Some(DateTime::now());
+    user.version += 1;
+    Ok(Some(user.clone()))
+  }
+
+  async fn change_password(&self, id: &str, password: &str) -> AppResult<UpdateResult> {
+    let id = ObjectId::from_str(id)?;
+    let mut users = lock(&self.users);
+    let Some(user) = active(&mut users, &id) else {
+      return Ok(updated(0));
+    };
+    user.password = password.to_string();
+    user.updated_at = Some(DateTime::now());
+    user.version += 1;
+    Ok(updated(1))
+  }
+
+  async fn delete_user(&self, id: &str, expected_version: Option<i64>) -> AppResult<UpdateResult> {
+    let id = ObjectId::from_str(id)?;
+    let mut users = lock(&self.users);
+    match active(&mut users, &id) {
+      Some(user) if expected_version.is_none_or(|version| user.version == version) => {
+        user.deleted_at = Some(DateTime::now());
+        user.version += 1;
+        Ok(updated(1))
+      }
+      _ => Ok(updated(0)),
+    }
+  }
+
+  async fn restore_user(&self, id: &str) -> AppResult<UpdateResult> {
+    let id = ObjectId::from_str(id)?;
+    let mut users = lock(&self.users);
+    let index = users.iter().position(|user| {
+      user.id == Some(id) && user.deleted_at.is_some() && in_tenant(user.tenant_id.as_deref())
+    });
+    let Some(index) = index else {
+      return Ok(updated(0));
+    };
+    let user = &users[index];
+    ensure_unique_email(&users, user.tenant_id.as_deref(), &user.email, Some(&id))?;
+    let user = &mut users[index];
+    user.deleted_at = None;
+    user.updated_at = Some(DateTime::now());
+    user.version += 1;
+    Ok(updated(1))
+  }
+
+  async fn purge_deleted_users(&self, deleted_before: DateTime) -> AppResult<DeleteResult> {
+    let mut users = lock(&self.users);
+    let count = remove_where(&mut users, |user| {
+      in_tenant(user.tenant_id.as_deref())
+        && user
+          .deleted_at
+          .is_some_and(|deleted_at| deleted_at < deleted_before)
+    });
+    Ok(deleted(count))
+  }
+
+  async fn record_login(&self, id: &ObjectId) -> AppResult<UpdateResult> {
+    let mut users = lock(&self.users);
+    let Some(user) = active(&mut users, id) else {
+      return Ok(updated(0));
+    };
+    user.last_login_at = Some(DateTime::now());
+    Ok(updated(1))
+  }
+}
+
+#[async_trait]
+impl UserSearchTrait for MemoryDatabase {
+  async fn search_users(&self, query: &UserSearchQuery) -> AppResult<UserSearchResults> {
+    let mut hits: Vec<UserSearchHit> = lock(&self.users)
+      .iter()
+      .filter(|user| user.deleted_at.is_none() && in_tenant(user.tenant_id.as_deref()))
+      .map(|user| UserSearchHit {
+        score: score(user, &query.terms),
+        user: user.clone(),
+      })
+      .filter(|hit| hit.score > 0.0)
+      .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.user.id.cmp(&b.user.id)));
+    let total = hits.len() as u64;
+    let skip = usize::try_from(query.skip).unwrap_or(usize::MAX);
+    let limit = usize::try_from(query.limit).unwrap_or_default();
+    let has_more = hits.len() > skip.saturating_add(limit);
+    let hits = hits.into_iter().skip(skip).take(limit).collect();
+    Ok(UserSearchResults {
+      hits,
+      has_more,
+      total: query.include_total.then_some(total),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{pagination::SortDirection, user::search::query_terms};
+  use utils::tenant;
+
+  async fn create(db: &MemoryDatabase, name: &str, email: &str) -> String {
+    let result = db.create_user(name, email, "hash").await.unwrap();
+    result.inserted_id.as_object_id().unwrap().to_hex()
+  }
+
+  #[tokio::test]
+  async fn tenants_only_reach_their_own_users() {
+    let db = MemoryDatabase::new();
+    let id = tenant::scope("acme".to_string(), create(&db, "Ada", "ada@example.com")).await;
+    tenant::scope("globex".to_string(), async {
+      assert!(db.get_user_by_id(&id).await.unwrap().is_none());
+      assert!(
+        db.get_user_by_email("ada@example.com")
+          .await
+          .unwrap()
+          .is_none()
+      );
+      assert_eq!(db.delete_user(&id, None).await.unwrap().matched_count, 0);
+      // Emails are only unique within a tenant.
+      create(&db, "Ada", "ada@example.com").await;
+    })
+    .await;
+    let user = tenant::scope("acme".to_string(), db.get_user_by_id(&id)).await;
+    assert_eq!(user.unwrap().unwrap().tenant_id.as_deref(), Some("acme"));
+  }
+
+  #[tokio::test]
+  async fn updates_check_the_expected_version() {
+    let db = MemoryDatabase::new();
+    let id = create(&db, "Ada", "ada@example.com").await;
+    let user = db
+      .update_user(&id, "Ada L.", "ada@example.com", Some(0))
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(user.version, 1);
+    let stale = db
+      .update_user(&id, "Ada K.", "ada@example.com", Some(0))
+      .await
+      .unwrap();
+    assert!(stale.is_none());
+    assert_eq!(db.delete_user(&id, Some(0)).await.unwrap().matched_count, 0);
+    assert_eq!(db.delete_user(&id, Some(1)).await.unwrap().matched_count, 1);
+  }
+
+  #[tokio::test]
+  async fn writes_maintain_the_timestamps() {
+    let db = MemoryDatabase::new();
+    let id = create(&db, "Ada", "ada@example.com").await;
+    let user = db.get_user_by_id(&id).await.unwrap().unwrap();
+    assert!(user.created_at.is_some());
+    assert_eq!(user.updated_at, user.created_at);
+    assert_eq!(user.last_login_at, None);
+
+    tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+    db.record_login(&user.id.unwrap()).await.unwrap();
+    let logged_in = db.get_user_by_id(&id).await.unwrap().unwrap();
+    assert!(logged_in.last_login_at > user.created_at);
+    // Logging in is not a change of the user.
+    assert_eq!(logged_in.updated_at, user.updated_at);
+    assert_eq!(logged_in.version, user.version);
+
+    tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+    let updated = db
+      .update_user(&id, "Ada L.", "ada@example.com", None)
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(updated.updated_at > logged_in.last_login_at);
+    assert_eq!(updated.created_at, user.created_at);
+  }
+
+  #[tokio::test]
+  async fn sorts_and_filters_by_timestamps() {
+    let db = MemoryDatabase::new();
+    let ada = create(&db, "Ada", "ada@example.com").await;
+    tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+    let grace = create(&db, "Grace", "grace@example.com").await;
+    let grace_id = ObjectId::from_str(&grace).unwrap();
+    db.record_login(&grace_id).await.unwrap();
+    let created = db.get_user_by_id(&grace).await.unwrap().unwrap().created_at;
+
+    let pagination = Pagination {
+      sort: "last_login_at".to_string(),
+      direction: SortDirection::Descending,
+      limit: 10,
+      skip: 0,
+      after: None,
+    };
+    let page = db
+      .find_users(&UserFilter::default(), &pagination)
+      .await
+      .unwrap();
+    let ids: Vec<String> = page
+      .items
+      .iter()
+      .map(|user| user.id.unwrap().to_hex())
+      .collect();
+    assert_eq!(ids, [grace.clone(), ada]);
+
+    let filter = UserFilter {
+      created_from: created,
+      ..Default::default()
+    };
+    let page = db.find_users(&filter, &pagination).await.unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].id, Some(grace_id));
+  }
+
+  #[tokio::test]
+  async fn finds_active_or_deleted_users() {
+    let db = MemoryDatabase::new();
+    create(&db, "Ada", "ada@example.com").await;
+    create(&db, "Grace", "grace@example.com").await;
+    let deleted = create(&db, "Alan", "alan@example.com").await;
+    db.delete_user(&deleted, None).await.unwrap();
+
+    let pagination = Pagination {
+      sort: "name".to_string(),
+      direction: SortDirection::Ascending,
+      limit: 10,
+      skip: 0,
+      after: None,
+    };
+    let names =
+      |page: Page<User>| -> Vec<String> { page.items.into_iter().map(|user| user.name).collect() };
+    let active = db
+      .find_users(&UserFilter::default(), &pagination)
+      .await
+      .unwrap();
+    assert_eq!(names(active), ["Ada", "Grace"]);
+    let filter = UserFilter {
+      name: Some("A".to_string()),
+      deleted: true,
+      ..Default::default()
+    };
+    assert_eq!(
+      names(db.find_users(&filter, &pagination).await.unwrap()),
+      ["Alan"]
+    );
+    assert_eq!(db.count_users(&UserFilter::default()).await.unwrap(), 2);
+  }
+
+  #[tokio::test]
+  async fn purges_users_deleted_before_the_cutoff() {
+    let db = MemoryDatabase::new();
+    let id = create(&db, "Ada", "ada@example.com").await;
+    db.delete_user(&id, None).await.unwrap();
+    let result = db.purge_deleted_users(DateTime::MIN).await.unwrap();
+    assert_eq!(result.deleted_count, 0);
+    let later = DateTime::from_millis(DateTime::now().timestamp_millis() + 1);
+    let result = db.purge_deleted_users(later).await.unwrap();
+    assert_eq!(result.deleted_count, 1);
+    assert_eq!(db.restore_user(&id).await.unwrap().matched_count, 0);
+  }
+
+  #[tokio::test]
+  async fn deleted_users_give_their_email_up() {
+    let db = MemoryDatabase::new();
+    let deleted = db
+      .create_user("Ada", "ada@example.com", "hash")
+      .await
+      .unwrap();
+    let deleted = deleted.inserted_id.as_object_id().unwrap().to_hex();
+    assert!(
+      db.create_user("Ada", "ADA@example.com", "hash")
+        .await
+        .unwrap_err()
+        .duplicate_key_field()
+        .is_some_and(|field| field == "email")
+    );
+
+    db.delete_user(&deleted, None).await.unwrap();
+    db.create_user("Ada", "ADA@example.com", "hash")
+      .await
+      .unwrap();
+    assert!(
+      db.restore_user(&deleted)
+        .await
+        .unwrap_err()
+        .duplicate_key_field()
+        .is_some()
+    );
+  }
+
+  #[tokio::test]
+  async fn search_ranks_users_by_relevance() {
+    let db = MemoryDatabase::new();
+    let exact = create(&db, "Ann Lee", "lee@example.com").await;
+    let prefix = create(&db, "Anne Smith", "smith@example.com").await;
+    let email = create(&db, "Bob", "annie@example.com").await;
+    create(&db, "Carl", "carl@example.com").await;
+    let deleted = create(&db, "Ann", "ann@example.com").await;
+    db.delete_user(&deleted, None).await.unwrap();
+
+    let mut query = UserSearchQuery {
+      terms: query_terms("ann"),
+      skip: 0,
+      limit: 2,
+      include_total: true,
+    };
+    let ids = |results: &UserSearchResults| -> Vec<String> {
+      results
+        .hits
+        .iter()
+        .map(|hit| hit.user.id.unwrap().to_hex())
+        .collect()
+    };
+    let results = db.search_users(&query).await.unwrap();
+    assert_eq!(ids(&results), [exact, prefix]);
+    assert!(results.has_more);
+    assert_eq!(results.total, Some(3));
+
+    query.skip = 2;
+    let results = db.search_users(&query).await.unwrap();
+    assert_eq!(ids(&results), [email]);
+    assert!(!results.has_more);
+  }
+}
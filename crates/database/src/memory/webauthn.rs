@@ -0,0 +1,130 @@
+use super::{MemoryDatabase, in_tenant, lock, remove_where};
+use crate::{
+  results::{deleted, inserted, updated},
+  tenant::current_tenant,
+  webauthn::{
+    model::{WebauthnChallenge, WebauthnChallengeKind, WebauthnCredential},
+    repository::WebauthnRepositoryTrait,
+  },
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use utils::{AppError, AppResult};
+
+fn is_owned(credential: &WebauthnCredential, id: &ObjectId, user_id: &ObjectId) -> bool {
+  credential.id.as_ref() == Some(id)
+    && credential.user_id == *user_id
+    && in_tenant(credential.tenant_id.as_deref())
+}
+
+#[async_trait]
+impl WebauthnRepositoryTrait for MemoryDatabase {
+  async fn create_credential(
+    &self,
+    mut credential: WebauthnCredential,
+  ) -> AppResult<InsertOneResult> {
+    let mut credentials = lock(&self.webauthn_credentials);
+    if credentials
+      .iter()
+      .any(|other| other.credential_id == credential.credential_id)
+    {
+      return Err(AppError::DuplicateKey("credential_id".to_string()));
+    }
+    let id = *credential.id.get_or_insert_with(ObjectId::new);
+    credential.tenant_id = current_tenant();
+    credentials.push(credential);
+    Ok(inserted(id))
+  }
+
+  async fn get_credentials_by_user(
+    &self,
+    user_id: &ObjectId,
+  ) -> AppResult<Vec<WebauthnCredential>> {
+    let credentials = lock(&self.webauthn_credentials)
+      .iter()
+      .filter(|credential| {
+        credential.user_id == *user_id && in_tenant(credential.tenant_id.as_deref())
+      })
+      .cloned()
+      .collect();
+    Ok(credentials)
+  }
+
+  async fn get_credential_by_credential_id(
+    &self,
+    credential_id: &str,
+  ) -> AppResult<Option<WebauthnCredential>> {
+    let credentials = lock(&self.webauthn_credentials);
+    let credential = credentials.iter().find(|credential| {
+      credential.credential_id == credential_id && in_tenant(credential.tenant_id.as_deref())
+    });
+    Ok(credential.cloned())
+  }
+
+  async fn update_credential_usage(
+    &self,
+    id: &ObjectId,
+    sign_count: u32,
+  ) -> AppResult<UpdateResult> {
+    let mut credentials = lock(&self.webauthn_credentials);
+    let credential = credentials.iter_mut().find(|credential| {
+      credential.id.as_ref() == Some(id) && in_tenant(credential.tenant_id.as_deref())
+    });
+    let Some(credential) = credential else {
+      return Ok(updated(0));
+    };
+    credential.sign_count = sign_count;
+    credential.last_used_at = Some(DateTime::now());
+    Ok(updated(1))
+  }
+
+  async fn rename_credential(
+    &self,
+    id: &ObjectId,
+    user_id: &ObjectId,
+    name: &str,
+  ) -> AppResult<UpdateResult> {
+    let mut credentials = lock(&self.webauthn_credentials);
+    let credential = credentials
+      .iter_mut()
+      .find(|credential| is_owned(credential, id, user_id));
+    let Some(credential) = credential else {
+      return Ok(updated(0));
+    };
+    credential.name = name.to_string();
+    Ok(updated(1))
+  }
+
+  async fn delete_credential(&self, id: &ObjectId, user_id: &ObjectId) -> AppResult<DeleteResult> {
+    let mut credentials = lock(&self.webauthn_credentials);
+    let count = remove_where(&mut credentials, |credential| {
+      is_owned(credential, id, user_id)
+    });
+    Ok(deleted(count))
+  }
+
+  async fn create_challenge(&self, mut challenge: WebauthnChallenge) -> AppResult<InsertOneResult> {
+    let id = *challenge.id.get_or_insert_with(ObjectId::new);
+    challenge.tenant_id = current_tenant();
+    lock(&self.webauthn_challenges).push(challenge);
+    Ok(inserted(id))
+  }
+
+  async fn take_challenge(
+    &self,
+    challenge: &str,
+    kind: WebauthnChallengeKind,
+  ) -> AppResult<Option<WebauthnChallenge>> {
+    let now = DateTime::now();
+    let mut challenges = lock(&self.webauthn_challenges);
+    // Expired challenges would be removed by the TTL index.
+    challenges.retain(|other| other.expires_at > now);
+    let position = challenges.iter().position(|other| {
+      other.challenge == challenge && other.kind == kind && in_tenant(other.tenant_id.as_deref())
+    });
+    Ok(position.map(|position| challenges.remove(position)))
+  }
+}
@@ -0,0 +1,35 @@
+use super::users;
+use crate::migration::Migration;
+use async_trait::async_trait;
+use mongodb::bson::doc;
+use utils::AppResult;
+
+/// Stores the `user` role on users created before roles existed. They already deserialize with
+/// the default role, this makes them visible to role filters.
+pub struct DefaultUserRole;
+
+#[async_trait]
+impl Migration for DefaultUserRole {
+  fn version(&self) -> i64 {
+    1
+  }
+
+  fn name(&self) -> &'static str {
+    "default_user_role"
+  }
+
+  async fn up(&self, db: &mongodb::Database) -> AppResult<()> {
+    users(db)
+      .update_many(
+        doc! { "role": { "$exists": false } },
+        doc! { "$set": { "role": "user" } },
+      )
+      .await?;
+    Ok(())
+  }
+
+  async fn down(&self, _db: &mongodb::Database) -> AppResult<()> {
+    // Users without a role read as `user`, so there is nothing to undo.
+    Ok(())
+  }
+}
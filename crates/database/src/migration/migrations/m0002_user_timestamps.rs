@@ -0,0 +1,43 @@
+use super::users;
+use crate::migration::Migration;
+use async_trait::async_trait;
+use mongodb::bson::doc;
+use utils::AppResult;
+
+/// Backfills `created_at` and `updated_at` of existing users from the creation time embedded in
+/// their id.
+pub struct UserTimestamps;
+
+#[async_trait]
+impl Migration for UserTimestamps {
+  fn version(&self) -> i64 {
+    2
+  }
+
+  fn name(&self) -> &'static str {
+    "user_timestamps"
+  }
+
+  async fn up(&self, db: &mongodb::Database) -> AppResult<()> {
+    users(db)
+      .update_many(
+        doc! { "created_at": { "$exists": false } },
+        vec![doc! { "$set": {
+          "created_at": { "$toDate": "$_id" },
+          "updated_at": { "$toDate": "$_id" },
+        } }],
+      )
+      .await?;
+    Ok(())
+  }
+
+  async fn down(&self, db: &mongodb::Database) -> AppResult<()> {
+    users(db)
+      .update_many(
+        doc! {},
+        doc! { "$unset": { "created_at": "", "updated_at": "", "last_login_at": "" } },
+      )
+      .await?;
+    Ok(())
+  }
+}
@@ -0,0 +1,36 @@
+use super::users;
+use crate::migration::Migration;
+use async_trait::async_trait;
+use mongodb::bson::doc;
+use utils::AppResult;
+
+/// Stores version 0 on existing users, so that their `ETag` can be matched by `If-Match`.
+pub struct UserVersion;
+
+#[async_trait]
+impl Migration for UserVersion {
+  fn version(&self) -> i64 {
+    3
+  }
+
+  fn name(&self) -> &'static str {
+    "user_version"
+  }
+
+  async fn up(&self, db: &mongodb::Database) -> AppResult<()> {
+    users(db)
+      .update_many(
+        doc! { "version": { "$exists": false } },
+        doc! { "$set": { "version": 0_i64 } },
+      )
+      .await?;
+    Ok(())
+  }
+
+  async fn down(&self, db: &mongodb::Database) -> AppResult<()> {
+    users(db)
+      .update_many(doc! {}, doc! { "$unset": { "version": "" } })
+      .await?;
+    Ok(())
+  }
+}
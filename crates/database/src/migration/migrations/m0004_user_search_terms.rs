@@ -0,0 +1,50 @@
+use super::users;
+use crate::{migration::Migration, user::search::search_terms};
+use async_trait::async_trait;
+use mongodb::bson::doc;
+use tokio_stream::StreamExt;
+use utils::AppResult;
+
+/// Stores the word prefixes searched by the `search` text index on existing users, see
+/// `user::search`.
+pub struct UserSearchTerms;
+
+#[async_trait]
+impl Migration for UserSearchTerms {
+  fn version(&self) -> i64 {
+    4
+  }
+
+  fn name(&self) -> &'static str {
+    "user_search_terms"
+  }
+
+  async fn up(&self, db: &mongodb::Database) -> AppResult<()> {
+    let users = users(db);
+    let mut cursor = users
+      .find(doc! { "search_terms": { "$exists": false } })
+      .projection(doc! { "name": 1, "email": 1 })
+      .await?;
+    while let Some(user) = cursor.next().await {
+      let user = user?;
+      let terms = search_terms(
+        user.get_str("name").unwrap_or_default(),
+        user.get_str("email").unwrap_or_default(),
+      );
+      users
+        .update_one(
+          doc! { "_id": user.get("_id") },
+          doc! { "$set": { "search_terms": terms } },
+        )
+        .await?;
+    }
+    Ok(())
+  }
+
+  async fn down(&self, db: &mongodb::Database) -> AppResult<()> {
+    users(db)
+      .update_many(doc! {}, doc! { "$unset": { "search_terms": "" } })
+      .await?;
+    Ok(())
+  }
+}
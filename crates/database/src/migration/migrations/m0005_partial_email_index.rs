@@ -0,0 +1,57 @@
+use super::users;
+use crate::{
+  index::{email_collation, email_index},
+  migration::Migration,
+};
+use async_trait::async_trait;
+use mongodb::{IndexModel, bson::doc, error::ErrorKind, options::IndexOptions};
+use utils::AppResult;
+
+/// Replaces the unique `email` index of users by one ignoring soft-deleted users, see
+/// `index::email_index`.
+pub struct PartialEmailIndex;
+
+/// Drops the `email` index of `db`, if there is one.
+async fn drop_email_index(db: &mongodb::Database) -> AppResult<()> {
+  match users(db).drop_index("email").await {
+    // IndexNotFound.
+    Err(e) if matches!(e.kind.as_ref(), ErrorKind::Command(e) if e.code == 27) => Ok(()),
+    result => Ok(result?),
+  }
+}
+
+#[async_trait]
+impl Migration for PartialEmailIndex {
+  fn version(&self) -> i64 {
+    5
+  }
+
+  fn name(&self) -> &'static str {
+    "partial_email_index"
+  }
+
+  async fn up(&self, db: &mongodb::Database) -> AppResult<()> {
+    drop_email_index(db).await?;
+    users(db).create_index(email_index()).await?;
+    Ok(())
+  }
+
+  /// Fails when a deleted user shares the email of another user.
+  async fn down(&self, db: &mongodb::Database) -> AppResult<()> {
+    drop_email_index(db).await?;
+    let options = IndexOptions::builder()
+      .name("email".to_string())
+      .unique(true)
+      .collation(email_collation())
+      .build();
+    users(db)
+      .create_index(
+        IndexModel::builder()
+          .keys(doc! { "tenant_id": 1, "email": 1 })
+          .options(options)
+          .build(),
+      )
+      .await?;
+    Ok(())
+  }
+}
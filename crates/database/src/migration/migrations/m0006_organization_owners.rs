@@ -0,0 +1,53 @@
+use crate::migration::Migration;
+use async_trait::async_trait;
+use mongodb::bson::{Document, doc};
+use tokio_stream::StreamExt;
+use utils::{AppResult, config};
+
+/// Stores the owner count of existing organizations, see
+/// `OrganizationRepositoryTrait::adjust_owners`.
+pub struct OrganizationOwners;
+
+#[async_trait]
+impl Migration for OrganizationOwners {
+  fn version(&self) -> i64 {
+    6
+  }
+
+  fn name(&self) -> &'static str {
+    "organization_owners"
+  }
+
+  async fn up(&self, db: &mongodb::Database) -> AppResult<()> {
+    let cfg = config::get();
+    let organizations = db.collection::<Document>(cfg.db.collection_name("Organization"));
+    let memberships = db.collection::<Document>(cfg.db.collection_name("Membership"));
+    organizations
+      .update_many(doc! {}, doc! { "$set": { "owners": 0_i64 } })
+      .await?;
+    let mut cursor = memberships
+      .aggregate([
+        doc! { "$match": { "role": "owner" } },
+        doc! { "$group": { "_id": "$organization_id", "owners": { "$sum": 1_i64 } } },
+      ])
+      .await?;
+    while let Some(count) = cursor.next().await {
+      let count = count?;
+      organizations
+        .update_one(
+          doc! { "_id": count.get("_id") },
+          doc! { "$set": { "owners": count.get_i64("owners").unwrap_or_default() } },
+        )
+        .await?;
+    }
+    Ok(())
+  }
+
+  async fn down(&self, db: &mongodb::Database) -> AppResult<()> {
+    let organizations = db.collection::<Document>(config::get().db.collection_name("Organization"));
+    organizations
+      .update_many(doc! {}, doc! { "$unset": { "owners": "" } })
+      .await?;
+    Ok(())
+  }
+}
@@ -0,0 +1,27 @@
+mod m0001_default_user_role;
+mod m0002_user_timestamps;
+mod m0003_user_version;
+mod m0004_user_search_terms;
+mod m0005_partial_email_index;
+mod m0006_organization_owners;
+
+use super::Migration;
+use mongodb::{Collection, bson::Document};
+use utils::config;
+
+/// Every migration, in any order. New migrations must be added here.
+pub fn all() -> Vec<Box<dyn Migration>> {
+  vec![
+    Box::new(m0001_default_user_role::DefaultUserRole),
+    Box::new(m0002_user_timestamps::UserTimestamps),
+    Box::new(m0003_user_version::UserVersion),
+    Box::new(m0004_user_search_terms::UserSearchTerms),
+    Box::new(m0005_partial_email_index::PartialEmailIndex),
+    Box::new(m0006_organization_owners::OrganizationOwners),
+  ]
+}
+
+/// The user collection, named as configured with `db.collections`.
+fn users(db: &mongodb::Database) -> Collection<Document> {
+  db.collection(config::get().db.collection_name("User"))
+}
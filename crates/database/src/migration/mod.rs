@@ -0,0 +1,370 @@
+//! Versioned migrations of the stored documents.
+//!
+//! Migrations are Rust types implementing [`Migration`], listed in order in
+//! [`migrations::all`]. Applied versions are recorded in the `_migrations` collection, and a
+//! lock document in `_migrations_lock` makes sure only one instance migrates at a time. The lock
+//! is renewed while migrations run and its ownership is checked before each of them.
+pub mod migrations;
+pub mod model;
+
+use crate::Database;
+use async_trait::async_trait;
+use model::{AppliedMigration, MigrationLock};
+use mongodb::{
+  Collection,
+  bson::{DateTime, doc, oid::ObjectId},
+};
+use std::{collections::HashMap, time::Duration};
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use utils::{AppError, AppResult};
+
+const LOCK_ID: &str = "migrations";
+
+/// A lock that was not renewed for this long is considered abandoned by a crashed instance.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How often the owner of the lock pushes back its expiry.
+const LOCK_HEARTBEAT: Duration = Duration::from_secs(60);
+
+#[async_trait]
+pub trait Migration: Send + Sync {
+  /// Unique version, migrations are applied in ascending order.
+  fn version(&self) -> i64;
+
+  fn name(&self) -> &'static str;
+
+  async fn up(&self, db: &mongodb::Database) -> AppResult<()>;
+
+  async fn down(&self, db: &mongodb::Database) -> AppResult<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+  pub version: i64,
+  pub name: &'static str,
+  pub applied_at: Option<DateTime>,
+}
+
+pub struct Migrator {
+  db: mongodb::Database,
+  migrations: Vec<Box<dyn Migration>>,
+}
+
+impl Migrator {
+  /// Creates a migrator for the current database with every known migration.
+  pub fn new(database: &Database) -> Self {
+    Self::with_migrations(database, migrations::all())
+  }
+
+  pub fn with_migrations(database: &Database, mut migrations: Vec<Box<dyn Migration>>) -> Self {
+    migrations.sort_by_key(|migration| migration.version());
+    Self {
+      db: database.database(),
+      migrations,
+    }
+  }
+
+  fn applied_col(&self) -> Collection<AppliedMigration> {
+    self.db.collection("_migrations")
+  }
+
+  fn lock_col(&self) -> Collection<MigrationLock> {
+    self.db.collection("_migrations_lock")
+  }
+
+  async fn applied(&self) -> AppResult<HashMap<i64, AppliedMigration>> {
+    let mut cursor = self.applied_col().find(doc! {}).await?;
+    let mut applied = HashMap::new();
+    while let Some(doc) = cursor.next().await {
+      let migration = doc?;
+      applied.insert(migration.version, migration);
+    }
+    Ok(applied)
+  }
+
+  /// Lists every known migration and when it was applied.
+  pub async fn status(&self) -> AppResult<Vec<MigrationStatus>> {
+    let applied = self.applied().await?;
+    Ok(
+      self
+        .migrations
+        .iter()
+        .map(|migration| MigrationStatus {
+          version: migration.version(),
+          name: migration.name(),
+          applied_at: applied
+            .get(&migration.version())
+            .map(|applied| applied.applied_at),
+        })
+        .collect(),
+    )
+  }
+
+  /// Applies pending migrations up to and including `target`, or all of them.
+  ///
+  /// Returns the migrations that were applied, or would be with `dry_run`.
+  pub async fn up(&self, target: Option<i64>, dry_run: bool) -> AppResult<Vec<MigrationStatus>> {
+    self
+      .locked(dry_run, |owner| async move {
+        let applied = self.applied().await?;
+        let mut done = Vec::new();
+        for migration in &self.migrations {
+          if target.is_some_and(|target| migration.version() > target)
+            || applied.contains_key(&migration.version())
+          {
+            continue;
+          }
+          if let Some(owner) = owner {
+            self.renew_lock(owner).await?;
+            info!(
+              "applying migration {} {}",
+              migration.version(),
+              migration.name()
+            );
+            migration.up(&self.db).await?;
+            self
+              .applied_col()
+              .insert_one(AppliedMigration {
+                version: migration.version(),
+                name: migration.name().to_string(),
+                applied_at: DateTime::now(),
+              })
+              .await?;
+          }
+          done.push(MigrationStatus {
+            version: migration.version(),
+            name: migration.name(),
+            applied_at: None,
+          });
+        }
+        Ok(done)
+      })
+      .await
+  }
+
+  /// Reverts applied migrations newer than `target`, newest first. Reverts only the latest
+  /// migration when `target` is `None`.
+  ///
+  /// Returns the migrations that were reverted, or would be with `dry_run`.
+  pub async fn down(&self, target: Option<i64>, dry_run: bool) -> AppResult<Vec<MigrationStatus>> {
+    self
+      .locked(dry_run, |owner| async move {
+        let applied = self.applied().await?;
+        let mut reverting: Vec<&Box<dyn Migration>> = self
+          .migrations
+          .iter()
+          .rev()
+          .filter(|migration| applied.contains_key(&migration.version()))
+          .collect();
+        match target {
+          Some(target) => reverting.retain(|migration| migration.version() > target),
+          None => reverting.truncate(1),
+        }
+
+        let mut done = Vec::new();
+        for migration in reverting {
+          if let Some(owner) = owner {
+            self.renew_lock(owner).await?;
+            info!(
+              "reverting migration {} {}",
+              migration.version(),
+              migration.name()
+            );
+            migration.down(&self.db).await?;
+            self
+              .applied_col()
+              .delete_one(doc! { "_id": migration.version() })
+              .await?;
+          }
+          done.push(MigrationStatus {
+            version: migration.version(),
+            name: migration.name(),
+            applied_at: applied
+              .get(&migration.version())
+              .map(|applied| applied.applied_at),
+          });
+        }
+        Ok(done)
+      })
+      .await
+  }
+
+  /// Runs `f` while holding the migration lock, renewing it in the background. `f` receives the
+  /// lock owner to check before each step. Dry runs do not take the lock and receive `None`.
+  async fn locked<'a, T, F>(
+    &'a self,
+    dry_run: bool,
+    f: impl FnOnce(Option<ObjectId>) -> F,
+  ) -> AppResult<T>
+  where
+    F: Future<Output = AppResult<T>> + Send + 'a,
+  {
+    if dry_run {
+      return f(None).await;
+    }
+    let owner = ObjectId::new();
+    self.acquire_lock(owner).await?;
+    let heartbeat = tokio::spawn(Self::heartbeat(self.lock_col(), owner));
+    let result = f(Some(owner)).await;
+    heartbeat.abort();
+    self
+      .lock_col()
+      .delete_one(doc! { "_id": LOCK_ID, "owner": owner })
+      .await?;
+    result
+  }
+
+  async fn acquire_lock(&self, owner: ObjectId) -> AppResult<()> {
+    let now = DateTime::now();
+    let expires_at = lock_expiry(now);
+    // Takes over an expired lock, or creates it when there is none. A lock held by another
+    // instance makes the upsert collide on `_id`.
+    let result = self
+      .lock_col()
+      .update_one(
+        doc! { "_id": LOCK_ID, "expires_at": { "$lt": now } },
+        doc! { "$set": { "owner": owner, "locked_at": now, "expires_at": expires_at } },
+      )
+      .upsert(true)
+      .await;
+    result.map(|_| ()).map_err(|e| held_elsewhere(e.into()))
+  }
+
+  /// Pushes back the expiry of the lock, failing when `owner` no longer holds it, e.g. after
+  /// another instance took over a lock that expired.
+  async fn renew_lock(&self, owner: ObjectId) -> AppResult<()> {
+    renew_lock(&self.lock_col(), owner).await
+  }
+
+  /// Renews the lock every [`LOCK_HEARTBEAT`] until aborted.
+  async fn heartbeat(locks: Collection<MigrationLock>, owner: ObjectId) {
+    let mut interval = tokio::time::interval(LOCK_HEARTBEAT);
+    // The first tick completes immediately, right after the lock was taken.
+    interval.tick().await;
+    loop {
+      interval.tick().await;
+      if let Err(e) = renew_lock(&locks, owner).await {
+        // The next step checks ownership again and stops the migration.
+        warn!("failed to renew the migration lock: {e}");
+      }
+    }
+  }
+}
+
+/// Reports the upsert colliding with a lock held by another instance as a conflict.
+fn held_elsewhere(error: AppError) -> AppError {
+  if error.duplicate_key_field().is_some() {
+    return AppError::Conflict("migrations are being run by another instance".to_string());
+  }
+  error
+}
+
+fn lock_expiry(now: DateTime) -> DateTime {
+  DateTime::from_system_time(now.to_system_time() + LOCK_TIMEOUT)
+}
+
+async fn renew_lock(locks: &Collection<MigrationLock>, owner: ObjectId) -> AppResult<()> {
+  let result = locks
+    .update_one(
+      doc! { "_id": LOCK_ID, "owner": owner },
+      doc! { "$set": { "expires_at": lock_expiry(DateTime::now()) } },
+    )
+    .await?;
+  if result.matched_count == 0 {
+    return Err(AppError::Conflict(
+      "the migration lock was taken over by another instance".to_string(),
+    ));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mongodb::{
+    bson,
+    error::{ErrorKind, WriteFailure},
+  };
+  use std::sync::atomic::{AtomicBool, Ordering};
+
+  struct Noop(i64);
+
+  #[async_trait]
+  impl Migration for Noop {
+    fn version(&self) -> i64 {
+      self.0
+    }
+
+    fn name(&self) -> &'static str {
+      "noop"
+    }
+
+    async fn up(&self, _db: &mongodb::Database) -> AppResult<()> {
+      Ok(())
+    }
+
+    async fn down(&self, _db: &mongodb::Database) -> AppResult<()> {
+      Ok(())
+    }
+  }
+
+  #[tokio::test]
+  async fn migrations_are_sorted_by_version() {
+    let database = Database::unreachable().await;
+    let migrator = Migrator::with_migrations(&database, vec![Box::new(Noop(3)), Box::new(Noop(1))]);
+    let versions: Vec<i64> = migrator.migrations.iter().map(|m| m.version()).collect();
+    assert_eq!(versions, [1, 3]);
+
+    let versions: Vec<i64> = migrations::all().iter().map(|m| m.version()).collect();
+    assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+  }
+
+  #[tokio::test]
+  async fn only_dry_runs_go_without_the_lock() {
+    let database = Database::unreachable().await;
+    let migrator = Migrator::with_migrations(&database, Vec::new());
+    let owner = migrator
+      .locked(true, |owner| async move { Ok(owner) })
+      .await
+      .unwrap();
+    assert_eq!(owner, None);
+
+    // The lock cannot be taken without a server, so nothing runs.
+    let ran = AtomicBool::new(false);
+    let result = migrator
+      .locked(false, |_| async {
+        ran.store(true, Ordering::SeqCst);
+        Ok(())
+      })
+      .await;
+    assert!(result.is_err());
+    assert!(!ran.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn locks_held_elsewhere_are_conflicts() {
+    let error = bson::from_document(bson::doc! {
+      "code": 11000,
+      "errmsg": "E11000 duplicate key error collection: db._migrations_lock index: _id_ dup key: {}",
+    })
+    .unwrap();
+    let error = ErrorKind::Write(WriteFailure::WriteError(error));
+    let error = held_elsewhere(AppError::MongoError(error.into()));
+    assert!(matches!(error, AppError::Conflict(_)));
+
+    let error = held_elsewhere(AppError::InternalServerError);
+    assert!(matches!(error, AppError::InternalServerError));
+  }
+
+  #[test]
+  fn locks_expire_after_the_timeout() {
+    let now = DateTime::now();
+    let expiry = lock_expiry(now);
+    assert_eq!(
+      expiry.timestamp_millis() - now.timestamp_millis(),
+      LOCK_TIMEOUT.as_millis() as i64
+    );
+    assert!(LOCK_HEARTBEAT < LOCK_TIMEOUT);
+  }
+}
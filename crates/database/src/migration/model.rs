@@ -0,0 +1,22 @@
+use mongodb::bson::{DateTime, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+/// A migration recorded in the `_migrations` collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedMigration {
+  #[serde(rename = "_id")]
+  pub version: i64,
+  pub name: String,
+  pub applied_at: DateTime,
+}
+
+/// Held while an instance runs migrations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationLock {
+  #[serde(rename = "_id")]
+  pub id: String,
+  /// Random id of the migrating instance, only the owner releases the lock.
+  pub owner: ObjectId,
+  pub locked_at: DateTime,
+  pub expires_at: DateTime,
+}
@@ -0,0 +1,62 @@
+use mongodb::bson::{DateTime, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub name: String,
+  pub created_by: ObjectId,
+  pub created_at: DateTime,
+  /// Number of members whose role is `Owner`, see `OrganizationRepositoryTrait::adjust_owners`.
+  #[serde(default)]
+  pub owners: i64,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+/// Roles within an organization, ordered from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationRole {
+  Member,
+  Admin,
+  Owner,
+}
+
+/// Links a `User` to an `Organization`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Membership {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub organization_id: ObjectId,
+  pub user_id: ObjectId,
+  pub role: OrganizationRole,
+  pub created_at: DateTime,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+/// An invitation for an email address to join an organization with a given role.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrganizationInvitation {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub organization_id: ObjectId,
+  /// Lowercased email of the invitee.
+  pub email: String,
+  pub role: OrganizationRole,
+  pub invited_by: ObjectId,
+  pub created_at: DateTime,
+  pub expires_at: DateTime,
+  pub accepted_at: Option<DateTime>,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+crate::impl_model!(Organization, "Organization");
+crate::impl_model!(Membership, "Membership");
+crate::impl_model!(OrganizationInvitation, "OrganizationInvitation");
@@ -0,0 +1,253 @@
+use crate::{
+  Database,
+  organization::model::{Membership, Organization, OrganizationInvitation, OrganizationRole},
+  tenant::{current_tenant, scoped},
+  transaction::in_session,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, doc, oid::ObjectId, to_bson},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use utils::AppResult;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynOrganizationRepository = Arc<dyn OrganizationRepositoryTrait>;
+
+#[async_trait]
+pub trait OrganizationRepositoryTrait: Send + Sync {
+  async fn create_organization(&self, organization: Organization) -> AppResult<InsertOneResult>;
+
+  async fn get_organization_by_id(&self, id: &ObjectId) -> AppResult<Option<Organization>>;
+
+  async fn get_organizations_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<Organization>>;
+
+  async fn create_membership(&self, membership: Membership) -> AppResult<InsertOneResult>;
+
+  async fn get_membership(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+  ) -> AppResult<Option<Membership>>;
+
+  async fn get_memberships_by_user(&self, user_id: &ObjectId) -> AppResult<Vec<Membership>>;
+
+  async fn get_memberships_by_organization(
+    &self,
+    organization_id: &ObjectId,
+  ) -> AppResult<Vec<Membership>>;
+
+  async fn count_members_with_role(
+    &self,
+    organization_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<u64>;
+
+  /// Adds `delta` to the owner count of the organization, unless that leaves it without owners.
+  ///
+  /// Returns whether the count changed. The check and the write are one atomic operation, so
+  /// that of concurrent attempts to remove the last owners, only those leaving one succeed.
+  async fn adjust_owners(&self, organization_id: &ObjectId, delta: i64) -> AppResult<bool>;
+
+  async fn update_membership_role(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<UpdateResult>;
+
+  async fn delete_membership(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+  ) -> AppResult<DeleteResult>;
+
+  async fn create_organization_invitation(
+    &self,
+    invitation: OrganizationInvitation,
+  ) -> AppResult<InsertOneResult>;
+
+  /// Returns the unaccepted, unexpired invitations for `email`.
+  async fn get_pending_invitations_by_email(
+    &self,
+    email: &str,
+  ) -> AppResult<Vec<OrganizationInvitation>>;
+
+  /// Atomically marks a pending invitation for `email` as accepted.
+  ///
+  /// Returns `None` when no such invitation exists.
+  async fn accept_organization_invitation(
+    &self,
+    id: &ObjectId,
+    email: &str,
+  ) -> AppResult<Option<OrganizationInvitation>>;
+}
+
+#[async_trait]
+impl OrganizationRepositoryTrait for Database {
+  #[tracing::instrument(name = "Create Organization", skip(self, organization))]
+  async fn create_organization(
+    &self,
+    mut organization: Organization,
+  ) -> AppResult<InsertOneResult> {
+    organization.tenant_id = current_tenant();
+    let result = in_session!(self.organization_col().insert_one(organization))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Get Organization By Id", skip(self))]
+  async fn get_organization_by_id(&self, id: &ObjectId) -> AppResult<Option<Organization>> {
+    let filter = scoped(doc! {"_id": id});
+    let organization = in_session!(self.organization_col().find_one(filter))?;
+    Ok(organization)
+  }
+
+  #[tracing::instrument(name = "Get Organizations By Ids", skip(self, ids))]
+  async fn get_organizations_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<Organization>> {
+    let filter = scoped(doc! {"_id": { "$in": ids }});
+    let mut cursor = self.organization_col().find(filter).await?;
+    let mut organizations: Vec<Organization> = Vec::new();
+    while let Some(doc) = cursor.next().await {
+      organizations.push(doc?);
+    }
+    Ok(organizations)
+  }
+
+  #[tracing::instrument(name = "Create Membership", skip(self, membership))]
+  async fn create_membership(&self, mut membership: Membership) -> AppResult<InsertOneResult> {
+    membership.tenant_id = current_tenant();
+    let result = in_session!(self.membership_col().insert_one(membership))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Get Membership", skip(self))]
+  async fn get_membership(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+  ) -> AppResult<Option<Membership>> {
+    let filter = scoped(doc! {"organization_id": organization_id, "user_id": user_id});
+    let membership = in_session!(self.membership_col().find_one(filter))?;
+    Ok(membership)
+  }
+
+  #[tracing::instrument(name = "Get Memberships By User", skip(self))]
+  async fn get_memberships_by_user(&self, user_id: &ObjectId) -> AppResult<Vec<Membership>> {
+    let filter = scoped(doc! {"user_id": user_id});
+    let mut cursor = self.membership_col().find(filter).await?;
+    let mut memberships: Vec<Membership> = Vec::new();
+    while let Some(doc) = cursor.next().await {
+      memberships.push(doc?);
+    }
+    Ok(memberships)
+  }
+
+  #[tracing::instrument(name = "Get Memberships By Organization", skip(self))]
+  async fn get_memberships_by_organization(
+    &self,
+    organization_id: &ObjectId,
+  ) -> AppResult<Vec<Membership>> {
+    let filter = scoped(doc! {"organization_id": organization_id});
+    let mut cursor = self.membership_col().find(filter).await?;
+    let mut memberships: Vec<Membership> = Vec::new();
+    while let Some(doc) = cursor.next().await {
+      memberships.push(doc?);
+    }
+    Ok(memberships)
+  }
+
+  #[tracing::instrument(name = "Count Members With Role", skip(self))]
+  async fn count_members_with_role(
+    &self,
+    organization_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<u64> {
+    let filter = scoped(doc! {"organization_id": organization_id, "role": to_bson(&role)?});
+    let count = in_session!(self.membership_col().count_documents(filter))?;
+    Ok(count)
+  }
+
+  #[tracing::instrument(name = "Adjust Organization Owners", skip(self))]
+  async fn adjust_owners(&self, organization_id: &ObjectId, delta: i64) -> AppResult<bool> {
+    let filter = scoped(doc! {"_id": organization_id, "owners": { "$gte": 1 - delta }});
+    let update = doc! { "$inc": { "owners": delta } };
+    let result = in_session!(self.organization_col().update_one(filter, update))?;
+    Ok(result.modified_count == 1)
+  }
+
+  #[tracing::instrument(name = "Update Membership Role", skip(self))]
+  async fn update_membership_role(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<UpdateResult> {
+    let filter = scoped(doc! {"organization_id": organization_id, "user_id": user_id});
+    let new_doc = doc! { "$set": { "role": to_bson(&role)? } };
+    let result = in_session!(self.membership_col().update_one(filter, new_doc))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Delete Membership", skip(self))]
+  async fn delete_membership(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+  ) -> AppResult<DeleteResult> {
+    let filter = scoped(doc! {"organization_id": organization_id, "user_id": user_id});
+    let result = in_session!(self.membership_col().delete_one(filter))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Create Organization Invitation", skip(self, invitation))]
+  async fn create_organization_invitation(
+    &self,
+    mut invitation: OrganizationInvitation,
+  ) -> AppResult<InsertOneResult> {
+    invitation.tenant_id = current_tenant();
+    let result = in_session!(self.organization_invitation_col().insert_one(invitation))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Get Pending Organization Invitations", skip(self, email))]
+  async fn get_pending_invitations_by_email(
+    &self,
+    email: &str,
+  ) -> AppResult<Vec<OrganizationInvitation>> {
+    let filter = scoped(doc! {
+      "email": email.to_lowercase(),
+      "accepted_at": null,
+      "expires_at": { "$gt": DateTime::now() },
+    });
+    let mut cursor = self.organization_invitation_col().find(filter).await?;
+    let mut invitations: Vec<OrganizationInvitation> = Vec::new();
+    while let Some(doc) = cursor.next().await {
+      invitations.push(doc?);
+    }
+    Ok(invitations)
+  }
+
+  #[tracing::instrument(name = "Accept Organization Invitation", skip(self, email))]
+  async fn accept_organization_invitation(
+    &self,
+    id: &ObjectId,
+    email: &str,
+  ) -> AppResult<Option<OrganizationInvitation>> {
+    let now = DateTime::now();
+    let filter = scoped(doc! {
+      "_id": id,
+      "email": email.to_lowercase(),
+      "accepted_at": null,
+      "expires_at": { "$gt": now },
+    });
+    let update = doc! { "$set": { "accepted_at": now } };
+    let invitation = in_session!(
+      self
+        .organization_invitation_col()
+        .find_one_and_update(filter, update)
+    )?;
+    Ok(invitation)
+  }
+}
@@ -0,0 +1,54 @@
+use mongodb::bson::{DateTime, Document, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+/// A notification recorded with the change it describes, delivered later by the outbox relay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxMessage {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  /// What happened, e.g. `user.created`.
+  pub topic: String,
+  pub payload: Document,
+  pub status: OutboxStatus,
+  /// Delivery attempts so far, including the one in progress.
+  pub attempts: u32,
+  pub created_at: DateTime,
+  /// When the message is next handed to the relay. Claiming a message pushes it back by the
+  /// lease, so that another relay retries it if this one never reports back.
+  pub available_at: DateTime,
+  pub delivered_at: Option<DateTime>,
+  pub last_error: Option<String>,
+  /// Names of the sinks that accepted the message, which later attempts skip.
+  #[serde(default)]
+  pub delivered_to: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+  Pending,
+  Delivered,
+  /// Given up on after `outbox.max_attempts`, kept for inspection.
+  Failed,
+}
+
+impl OutboxMessage {
+  pub fn new(topic: impl Into<String>, payload: Document) -> Self {
+    let now = DateTime::now();
+    Self {
+      id: None,
+      topic: topic.into(),
+      payload,
+      status: OutboxStatus::Pending,
+      attempts: 0,
+      created_at: now,
+      available_at: now,
+      delivered_at: None,
+      last_error: None,
+      delivered_to: Vec::new(),
+      tenant_id: None,
+    }
+  }
+}
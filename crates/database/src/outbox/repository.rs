@@ -0,0 +1,140 @@
+use crate::{
+  Database,
+  outbox::model::{OutboxMessage, OutboxStatus},
+  tenant::current_tenant,
+  transaction::in_session,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, doc, oid::ObjectId, to_bson},
+  options::ReturnDocument,
+  results::{InsertOneResult, UpdateResult},
+};
+use std::{sync::Arc, time::Duration};
+use utils::AppResult;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynOutboxRepository = Arc<dyn OutboxRepositoryTrait>;
+
+#[async_trait]
+pub trait OutboxRepositoryTrait: Send + Sync {
+  /// Records `message` for the current tenant, within the current unit of work if any.
+  async fn enqueue_message(&self, message: OutboxMessage) -> AppResult<InsertOneResult>;
+
+  /// Claims up to `limit` pending messages that are due, oldest first, and returns them as
+  /// updated: counting the attempt and hidden from other relays for `lease`. The messages of
+  /// every tenant are claimed.
+  async fn claim_messages(&self, limit: usize, lease: Duration) -> AppResult<Vec<OutboxMessage>>;
+
+  /// Hides the pending messages among `ids` from other relays for `lease` again, for deliveries
+  /// outlasting the lease they were claimed with.
+  async fn renew_lease(&self, ids: &[ObjectId], lease: Duration) -> AppResult<UpdateResult>;
+
+  async fn complete_message(&self, id: &ObjectId) -> AppResult<UpdateResult>;
+
+  /// Records a failed delivery, to be retried at `retry_at`, or given up on when it is `None`.
+  /// `delivered_to` lists the sinks that accepted the message so far, see
+  /// `OutboxMessage::delivered_to`.
+  async fn fail_message(
+    &self,
+    id: &ObjectId,
+    error: &str,
+    delivered_to: &[String],
+    retry_at: Option<DateTime>,
+  ) -> AppResult<UpdateResult>;
+}
+
+/// `now` pushed back by `duration`.
+pub(crate) fn later(now: DateTime, duration: Duration) -> DateTime {
+  DateTime::from_system_time(now.to_system_time() + duration)
+}
+
+#[async_trait]
+impl OutboxRepositoryTrait for Database {
+  #[tracing::instrument(name = "Enqueue Outbox Message", skip(self, message))]
+  async fn enqueue_message(&self, mut message: OutboxMessage) -> AppResult<InsertOneResult> {
+    message.id.get_or_insert_with(ObjectId::new);
+    message.tenant_id = current_tenant();
+    let result = in_session!(self.outbox_col().insert_one(message))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Claim Outbox Messages", skip(self))]
+  async fn claim_messages(&self, limit: usize, lease: Duration) -> AppResult<Vec<OutboxMessage>> {
+    let mut messages = Vec::new();
+    while messages.len() < limit {
+      let now = DateTime::now();
+      let filter = doc! {
+        "status": to_bson(&OutboxStatus::Pending)?,
+        "available_at": { "$lte": now },
+      };
+      let update = doc! {
+        "$set": { "available_at": later(now, lease) },
+        "$inc": { "attempts": 1 },
+      };
+      let claimed = self
+        .outbox_col()
+        .find_one_and_update(filter, update)
+        .sort(doc! { "available_at": 1 })
+        .return_document(ReturnDocument::After)
+        .await?;
+      match claimed {
+        Some(message) => messages.push(message),
+        None => break,
+      }
+    }
+    Ok(messages)
+  }
+
+  #[tracing::instrument(name = "Renew Outbox Lease", skip(self, ids))]
+  async fn renew_lease(&self, ids: &[ObjectId], lease: Duration) -> AppResult<UpdateResult> {
+    let filter = doc! {
+      "_id": { "$in": ids },
+      "status": to_bson(&OutboxStatus::Pending)?,
+    };
+    let update = doc! { "$set": { "available_at": later(DateTime::now(), lease) } };
+    let result = self.outbox_col().update_many(filter, update).await?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Complete Outbox Message", skip(self))]
+  async fn complete_message(&self, id: &ObjectId) -> AppResult<UpdateResult> {
+    let update = doc! { "$set": {
+      "status": to_bson(&OutboxStatus::Delivered)?,
+      "delivered_at": DateTime::now(),
+      "last_error": null,
+    } };
+    let result = self
+      .outbox_col()
+      .update_one(doc! {"_id": id}, update)
+      .await?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Fail Outbox Message", skip(self))]
+  async fn fail_message(
+    &self,
+    id: &ObjectId,
+    error: &str,
+    delivered_to: &[String],
+    retry_at: Option<DateTime>,
+  ) -> AppResult<UpdateResult> {
+    let update = match retry_at {
+      Some(retry_at) => doc! { "$set": {
+        "available_at": retry_at,
+        "last_error": error,
+        "delivered_to": delivered_to,
+      } },
+      None => doc! { "$set": {
+        "status": to_bson(&OutboxStatus::Failed)?,
+        "last_error": error,
+        "delivered_to": delivered_to,
+      } },
+    };
+    let result = self
+      .outbox_col()
+      .update_one(doc! {"_id": id}, update)
+      .await?;
+    Ok(result)
+  }
+}
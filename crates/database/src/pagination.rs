@@ -0,0 +1,366 @@
+//! Offset and cursor (keyset) pagination shared by the repositories.
+//!
+//! Every listing is sorted by one field plus `_id` as a tie breaker, so a cursor only needs the
+//! sort value and the id of the last item it returned.
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use mongodb::{
+  Collection,
+  bson::{Bson, Document, doc, oid::ObjectId, to_document},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::cmp::Ordering;
+use tokio_stream::StreamExt;
+use utils::{AppError, AppResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+  Ascending,
+  Descending,
+}
+
+impl SortDirection {
+  fn order(self) -> i32 {
+    match self {
+      Self::Ascending => 1,
+      Self::Descending => -1,
+    }
+  }
+
+  fn operator(self) -> &'static str {
+    match self {
+      Self::Ascending => "$gt",
+      Self::Descending => "$lt",
+    }
+  }
+}
+
+/// Position after the last item of a page.
+#[derive(Debug, Clone)]
+pub struct Cursor {
+  pub value: Bson,
+  pub id: ObjectId,
+}
+
+impl Cursor {
+  pub fn encode(&self) -> String {
+    let mut bytes = Vec::new();
+    // Serializing a document to an in-memory buffer cannot fail.
+    let _ = doc! { "v": self.value.clone(), "id": self.id }.to_writer(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+  }
+
+  pub fn decode(value: &str) -> AppResult<Self> {
+    let invalid = || AppError::BadRequest("invalid cursor".to_string());
+    let bytes = URL_SAFE_NO_PAD.decode(value).map_err(|_| invalid())?;
+    let document = Document::from_reader(bytes.as_slice()).map_err(|_| invalid())?;
+    Ok(Self {
+      value: document.get("v").cloned().ok_or_else(invalid)?,
+      id: document.get_object_id("id").map_err(|_| invalid())?,
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pagination {
+  /// Document field to sort by.
+  pub sort: String,
+  pub direction: SortDirection,
+  pub limit: i64,
+  /// Items to skip, ignored when `after` is set.
+  pub skip: u64,
+  pub after: Option<Cursor>,
+}
+
+/// One page of results.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+  pub items: Vec<T>,
+  /// Cursor of the next page, `None` on the last page.
+  pub next_cursor: Option<String>,
+}
+
+impl Pagination {
+  fn sort_document(&self) -> Document {
+    let order = self.direction.order();
+    if self.sort == "_id" {
+      doc! { "_id": order }
+    } else {
+      doc! { self.sort.as_str(): order, "_id": order }
+    }
+  }
+
+  /// Restricts `filter` to the items after the cursor.
+  fn filter(&self, filter: Document) -> Document {
+    let Some(after) = &self.after else {
+      return filter;
+    };
+    let operator = self.direction.operator();
+    let field = self.sort.as_str();
+    // Comparison operators never match null or missing values, which sort before every other
+    // value, so they are handled separately.
+    let keyset = if field == "_id" {
+      doc! { "_id": { operator: after.id } }
+    } else if after.value == Bson::Null {
+      match self.direction {
+        SortDirection::Ascending => doc! { "$or": [
+          { field: { "$ne": null } },
+          { field: null, "_id": { operator: after.id } },
+        ] },
+        SortDirection::Descending => doc! { field: null, "_id": { operator: after.id } },
+      }
+    } else {
+      let mut keyset = vec![
+        doc! { field: { operator: after.value.clone() } },
+        doc! { field: after.value.clone(), "_id": { operator: after.id } },
+      ];
+      if self.direction == SortDirection::Descending {
+        keyset.push(doc! { field: null });
+      }
+      doc! { "$or": keyset }
+    };
+    doc! { "$and": [filter, keyset] }
+  }
+
+  /// Orders two positions the way `sort_document` does.
+  fn compare(&self, a: &Cursor, b: &Cursor) -> Ordering {
+    let order = compare_bson(&a.value, &b.value).then_with(|| a.id.cmp(&b.id));
+    match self.direction {
+      SortDirection::Ascending => order,
+      SortDirection::Descending => order.reverse(),
+    }
+  }
+
+  pub(crate) fn cursor_of<T: Serialize>(&self, item: &T) -> AppResult<Cursor> {
+    let document = to_document(item)?;
+    let id = document
+      .get_object_id("_id")
+      .map_err(|_| AppError::InternalServerErrorWithContext("item has no id".to_string()))?;
+    let value = document.get(&self.sort).cloned().unwrap_or(Bson::Null);
+    Ok(Cursor { value, id })
+  }
+}
+
+/// Runs `filter` against `collection` and returns the page described by `pagination`.
+pub async fn find_page<T>(
+  collection: &Collection<T>,
+  filter: Document,
+  pagination: &Pagination,
+) -> AppResult<Page<T>>
+where
+  T: Serialize + DeserializeOwned + Send + Sync,
+{
+  let skip = if pagination.after.is_some() {
+    0
+  } else {
+    pagination.skip
+  };
+  // One extra item tells whether there is a next page.
+  let mut cursor = collection
+    .find(pagination.filter(filter))
+    .sort(pagination.sort_document())
+    .skip(skip)
+    .limit(pagination.limit + 1)
+    .await?;
+  let mut items: Vec<T> = Vec::new();
+  while let Some(doc) = cursor.next().await {
+    items.push(doc?);
+  }
+
+  let limit = usize::try_from(pagination.limit).unwrap_or_default();
+  let has_more = items.len() > limit;
+  items.truncate(limit);
+  let next_cursor = match items.last() {
+    Some(last) if has_more => Some(pagination.cursor_of(last)?.encode()),
+    _ => None,
+  };
+  Ok(Page { items, next_cursor })
+}
+
+/// Returns the page of `items` described by `pagination`, the way `find_page` does for a
+/// collection. Used by backends that filter documents themselves.
+pub(crate) fn paginate<T: Serialize>(items: Vec<T>, pagination: &Pagination) -> AppResult<Page<T>> {
+  let mut items = items
+    .into_iter()
+    .map(|item| Ok((pagination.cursor_of(&item)?, item)))
+    .collect::<AppResult<Vec<_>>>()?;
+  items.sort_by(|(a, _), (b, _)| pagination.compare(a, b));
+  let limit = usize::try_from(pagination.limit).unwrap_or_default();
+  let skip = match &pagination.after {
+    Some(after) => items
+      .iter()
+      .take_while(|(cursor, _)| pagination.compare(cursor, after) != Ordering::Greater)
+      .count(),
+    None => usize::try_from(pagination.skip).unwrap_or(usize::MAX),
+  };
+  let mut items = items.into_iter().skip(skip);
+  let page: Vec<(Cursor, T)> = items.by_ref().take(limit).collect();
+  let next_cursor = match page.last() {
+    Some((last, _)) if items.next().is_some() => Some(last.encode()),
+    _ => None,
+  };
+  Ok(Page {
+    items: page.into_iter().map(|(_, item)| item).collect(),
+    next_cursor,
+  })
+}
+
+/// Compares two values following Mongo's sort order for the types documents hold.
+pub(crate) fn compare_bson(a: &Bson, b: &Bson) -> Ordering {
+  fn number(value: &Bson) -> Option<f64> {
+    match value {
+      Bson::Int32(n) => Some(f64::from(*n)),
+      #[allow(clippy::cast_precision_loss)]
+      Bson::Int64(n) => Some(*n as f64),
+      Bson::Double(n) => Some(*n),
+      _ => None,
+    }
+  }
+  fn rank(value: &Bson) -> u8 {
+    match value {
+      Bson::Null | Bson::Undefined => 0,
+      Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_) => 1,
+      Bson::String(_) | Bson::Symbol(_) => 2,
+      Bson::Document(_) => 3,
+      Bson::Array(_) => 4,
+      Bson::Binary(_) => 5,
+      Bson::ObjectId(_) => 6,
+      Bson::Boolean(_) => 7,
+      Bson::DateTime(_) => 8,
+      Bson::Timestamp(_) => 9,
+      _ => 10,
+    }
+  }
+  match (a, b) {
+    (Bson::String(a), Bson::String(b)) => a.cmp(b),
+    (Bson::ObjectId(a), Bson::ObjectId(b)) => a.cmp(b),
+    (Bson::Boolean(a), Bson::Boolean(b)) => a.cmp(b),
+    (Bson::DateTime(a), Bson::DateTime(b)) => a.cmp(b),
+    _ => match (number(a), number(b)) {
+      (Some(a), Some(b)) => a.total_cmp(&b),
+      _ => rank(a).cmp(&rank(b)),
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::Serialize;
+
+  #[derive(Debug, Clone, Serialize)]
+  struct Item {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    last_login_at: Option<i64>,
+  }
+
+  fn pagination(direction: SortDirection, after: Option<Cursor>) -> Pagination {
+    Pagination {
+      sort: "last_login_at".to_string(),
+      direction,
+      limit: 2,
+      skip: 0,
+      after,
+    }
+  }
+
+  fn items() -> Vec<Item> {
+    [None, Some(3), None, Some(1), Some(3), None, Some(2)]
+      .into_iter()
+      .map(|last_login_at| Item {
+        id: ObjectId::new(),
+        last_login_at,
+      })
+      .collect()
+  }
+
+  /// Walks every page with cursors and returns the sort values in the order they were listed.
+  fn walk(direction: SortDirection) -> Vec<Option<i64>> {
+    let items = items();
+    let ids: Vec<(ObjectId, Option<i64>)> = items
+      .iter()
+      .map(|item| (item.id, item.last_login_at))
+      .collect();
+    let mut after = None;
+    let mut listed = Vec::new();
+    loop {
+      let page = paginate(items.clone(), &pagination(direction, after)).unwrap();
+      listed.extend(page.items.iter().map(|item| item.id));
+      match page.next_cursor {
+        Some(cursor) => after = Some(Cursor::decode(&cursor).unwrap()),
+        None => break,
+      }
+    }
+    assert_eq!(listed.len(), ids.len());
+    listed
+      .iter()
+      .map(|id| ids.iter().find(|(other, _)| other == id).unwrap().1)
+      .collect()
+  }
+
+  #[test]
+  fn cursors_walk_past_null_sort_values() {
+    assert_eq!(
+      walk(SortDirection::Ascending),
+      [None, None, None, Some(1), Some(2), Some(3), Some(3)]
+    );
+    assert_eq!(
+      walk(SortDirection::Descending),
+      [Some(3), Some(3), Some(2), Some(1), None, None, None]
+    );
+  }
+
+  #[test]
+  fn null_cursor_filter_matches_the_remaining_items() {
+    let id = ObjectId::new();
+    let after = || Cursor {
+      value: Bson::Null,
+      id,
+    };
+
+    let filter = pagination(SortDirection::Ascending, Some(after())).filter(doc! {});
+    assert_eq!(
+      filter,
+      doc! { "$and": [{}, { "$or": [
+        { "last_login_at": { "$ne": null } },
+        { "last_login_at": null, "_id": { "$gt": id } },
+      ] }] }
+    );
+
+    let filter = pagination(SortDirection::Descending, Some(after())).filter(doc! {});
+    assert_eq!(
+      filter,
+      doc! { "$and": [{}, { "last_login_at": null, "_id": { "$lt": id } }] }
+    );
+  }
+
+  #[test]
+  fn descending_filter_keeps_null_values_after_the_cursor() {
+    let id = ObjectId::new();
+    let after = Cursor {
+      value: Bson::Int64(5),
+      id,
+    };
+    let filter = pagination(SortDirection::Descending, Some(after)).filter(doc! {});
+    assert_eq!(
+      filter,
+      doc! { "$and": [{}, { "$or": [
+        { "last_login_at": { "$lt": 5_i64 } },
+        { "last_login_at": 5_i64, "_id": { "$lt": id } },
+        { "last_login_at": null },
+      ] }] }
+    );
+  }
+
+  #[test]
+  fn cursors_round_trip() {
+    let cursor = Cursor {
+      value: Bson::Null,
+      id: ObjectId::new(),
+    };
+    let decoded = Cursor::decode(&cursor.encode()).unwrap();
+    assert_eq!(decoded.value, Bson::Null);
+    assert_eq!(decoded.id, cursor.id);
+    assert!(Cursor::decode("not a cursor").is_err());
+  }
+}
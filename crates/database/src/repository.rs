@@ -0,0 +1,176 @@
+//! Standard persistence for documents that need nothing beyond CRUD.
+//!
+//! A model only has to say where it is stored:
+//!
+//! ```ignore
+//! #[derive(Debug, Clone, Serialize, Deserialize)]
+//! pub struct Note {
+//!   #[serde(rename = "_id")]
+//!   pub id: Option<ObjectId>,
+//!   pub text: String,
+//!   #[serde(default, skip_serializing_if = "Option::is_none")]
+//!   pub tenant_id: Option<String>,
+//! }
+//!
+//! impl_model!(Note, "Note");
+//! ```
+//!
+//! after which `Database` and `MemoryDatabase` are both a `Repository<Note>`. Filters are matched
+//! for equality field by field, the subset every backend evaluates the same way. Queries needing
+//! more keep getting a dedicated repository trait.
+//!
+//! A service needing the repository takes a `DynRepository<Note>`, `Repository<Note>` being added
+//! to the supertraits of `Backend` so that `Services::new` can hand it out.
+use crate::{
+  Database,
+  pagination::{Page, Pagination, find_page},
+  tenant::{current_tenant, scoped},
+  transaction::in_session,
+};
+use async_trait::async_trait;
+use mongodb::{
+  Collection,
+  bson::{Document, doc, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use utils::{AppResult, config};
+
+/// A document stored in its own collection, see `impl_model!`.
+pub trait Model: Serialize + DeserializeOwned + Clone + Send + Sync + Unpin + 'static {
+  /// Name of the collection holding the documents.
+  const COLLECTION: &'static str;
+
+  fn id_mut(&mut self) -> &mut Option<ObjectId>;
+
+  /// Set by the repository on insert, see `tenant::scoped`.
+  fn tenant_id_mut(&mut self) -> &mut Option<String>;
+}
+
+/// Implements `Model` for a struct with `id: Option<ObjectId>` and `tenant_id: Option<String>`
+/// fields, stored in `collection`.
+#[macro_export]
+macro_rules! impl_model {
+  ($model:ty, $collection:literal) => {
+    impl $crate::repository::Model for $model {
+      const COLLECTION: &'static str = $collection;
+
+      fn id_mut(&mut self) -> &mut Option<::mongodb::bson::oid::ObjectId> {
+        &mut self.id
+      }
+
+      fn tenant_id_mut(&mut self) -> &mut Option<String> {
+        &mut self.tenant_id
+      }
+    }
+  };
+}
+
+pub type DynRepository<T> = Arc<dyn Repository<T>>;
+
+#[async_trait]
+pub trait Repository<T: Model>: Send + Sync {
+  /// Inserts `document`, giving it an id unless it has one.
+  async fn insert(&self, document: T) -> AppResult<InsertOneResult>;
+
+  async fn find_by_id(&self, id: &ObjectId) -> AppResult<Option<T>>;
+
+  async fn find_one(&self, filter: Document) -> AppResult<Option<T>>;
+
+  async fn find(&self, filter: Document) -> AppResult<Vec<T>>;
+
+  /// Returns one page of the documents matching `filter`.
+  async fn find_page(&self, filter: Document, pagination: &Pagination) -> AppResult<Page<T>>;
+
+  async fn count(&self, filter: Document) -> AppResult<u64>;
+
+  async fn exists(&self, filter: Document) -> AppResult<bool>;
+
+  /// Replaces the stored document having the id of `document`.
+  async fn update(&self, document: T) -> AppResult<UpdateResult>;
+
+  async fn delete_by_id(&self, id: &ObjectId) -> AppResult<DeleteResult>;
+}
+
+impl Database {
+  /// The collection of `T` in the current tenant's database, named as configured with
+  /// `db.collections`.
+  pub fn collection<T: Model>(&self) -> Collection<T> {
+    let name = config::get().db.collection_name(T::COLLECTION);
+    self.database().collection(name)
+  }
+
+  /// The collection of `T` to run queries tolerating stale data on, see `read_database`.
+  pub fn read_collection<T: Model>(&self) -> Collection<T> {
+    let name = config::get().db.collection_name(T::COLLECTION);
+    self.read_database().collection(name)
+  }
+}
+
+/// The id of a document about to be replaced.
+pub(crate) fn id_of<T: Model>(document: &mut T) -> AppResult<ObjectId> {
+  (*document.id_mut()).ok_or_else(|| utils::AppError::BadRequest("document has no id".to_string()))
+}
+
+#[async_trait]
+impl<T: Model> Repository<T> for Database {
+  async fn insert(&self, mut document: T) -> AppResult<InsertOneResult> {
+    document.id_mut().get_or_insert_with(ObjectId::new);
+    *document.tenant_id_mut() = current_tenant();
+    let result = in_session!(self.collection::<T>().insert_one(document))?;
+    Ok(result)
+  }
+
+  async fn find_by_id(&self, id: &ObjectId) -> AppResult<Option<T>> {
+    self.find_one(doc! {"_id": id}).await
+  }
+
+  async fn find_one(&self, filter: Document) -> AppResult<Option<T>> {
+    let document = in_session!(self.collection::<T>().find_one(scoped(filter)))?;
+    Ok(document)
+  }
+
+  async fn find(&self, filter: Document) -> AppResult<Vec<T>> {
+    let mut cursor = self.read_collection::<T>().find(scoped(filter)).await?;
+    let mut documents: Vec<T> = Vec::new();
+    while let Some(doc) = cursor.next().await {
+      documents.push(doc?);
+    }
+    Ok(documents)
+  }
+
+  async fn find_page(&self, filter: Document, pagination: &Pagination) -> AppResult<Page<T>> {
+    find_page(&self.read_collection::<T>(), scoped(filter), pagination).await
+  }
+
+  async fn count(&self, filter: Document) -> AppResult<u64> {
+    let count = in_session!(self.read_collection::<T>().count_documents(scoped(filter)))?;
+    Ok(count)
+  }
+
+  async fn exists(&self, filter: Document) -> AppResult<bool> {
+    let count = in_session!(
+      self
+        .collection::<T>()
+        .count_documents(scoped(filter))
+        .limit(1)
+    )?;
+    Ok(count > 0)
+  }
+
+  async fn update(&self, mut document: T) -> AppResult<UpdateResult> {
+    let id = id_of(&mut document)?;
+    *document.tenant_id_mut() = current_tenant();
+    let filter = scoped(doc! {"_id": id});
+    let result = in_session!(self.collection::<T>().replace_one(filter, document))?;
+    Ok(result)
+  }
+
+  async fn delete_by_id(&self, id: &ObjectId) -> AppResult<DeleteResult> {
+    let filter = scoped(doc! {"_id": id});
+    let result = in_session!(self.collection::<T>().delete_one(filter))?;
+    Ok(result)
+  }
+}
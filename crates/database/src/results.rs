@@ -0,0 +1,24 @@
+//! Driver results for backends that do not get them from the Mongo driver.
+use mongodb::{
+  bson::{Bson, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+
+pub(crate) fn inserted(id: ObjectId) -> InsertOneResult {
+  let mut result = InsertOneResult::default();
+  result.inserted_id = Bson::ObjectId(id);
+  result
+}
+
+pub(crate) fn updated(count: u64) -> UpdateResult {
+  let mut result = UpdateResult::default();
+  result.matched_count = count;
+  result.modified_count = count;
+  result
+}
+
+pub(crate) fn deleted(count: u64) -> DeleteResult {
+  let mut result = DeleteResult::default();
+  result.deleted_count = count;
+  result
+}
@@ -0,0 +1,131 @@
+use super::{
+  SortKey, SqlDatabase, Statement, enum_text, get, get_date, get_enum, get_id, sql_error,
+  where_scoped,
+};
+use crate::{
+  audit::{
+    model::{AuditEvent, AuditEventFilter},
+    repository::AuditRepositoryTrait,
+  },
+  pagination::{Page, Pagination},
+  results::inserted,
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::results::InsertOneResult;
+use sqlx::any::AnyRow;
+use utils::{AppError, AppResult};
+
+const COLUMNS: &str = "id, tenant_id, actor, target, action, ip, user_agent, outcome, reason, \
+                       timestamp";
+
+fn event_from_row(row: &AnyRow) -> AppResult<AuditEvent> {
+  Ok(AuditEvent {
+    id: Some(get_id(row, "id")?),
+    actor: get(row, "actor")?,
+    target: get(row, "target")?,
+    action: get_enum(row, "action")?,
+    ip: get(row, "ip")?,
+    user_agent: get(row, "user_agent")?,
+    outcome: get_enum(row, "outcome")?,
+    reason: get(row, "reason")?,
+    timestamp: get_date(row, "timestamp")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+fn filter_statement(statement: &mut Statement, filter: &AuditEventFilter) -> AppResult<()> {
+  where_scoped(statement);
+  if let Some(actor) = &filter.actor {
+    statement.push(" AND actor = ").bind(actor.as_str());
+  }
+  if let Some(target) = &filter.target {
+    statement.push(" AND target = ").bind(target.as_str());
+  }
+  if let Some(action) = &filter.action {
+    statement.push(" AND action = ").bind(enum_text(action)?);
+  }
+  if let Some(outcome) = &filter.outcome {
+    statement.push(" AND outcome = ").bind(enum_text(outcome)?);
+  }
+  if let Some(ip) = &filter.ip {
+    statement.push(" AND ip = ").bind(ip.as_str());
+  }
+  if let Some(from) = filter.from {
+    statement.push(" AND timestamp >= ").bind(from);
+  }
+  if let Some(to) = filter.to {
+    statement.push(" AND timestamp <= ").bind(to);
+  }
+  Ok(())
+}
+
+/// The expression a listing is sorted by.
+fn sort_key(field: &str) -> AppResult<SortKey> {
+  match field {
+    "_id" => Ok(SortKey::Text("id")),
+    "timestamp" => Ok(SortKey::Int("timestamp")),
+    "action" => Ok(SortKey::Text("action")),
+    "actor" => Ok(SortKey::Text("COALESCE(actor, '')")),
+    _ => Err(AppError::BadRequest(format!(
+      "cannot sort audit events by {field}"
+    ))),
+  }
+}
+
+#[async_trait]
+impl AuditRepositoryTrait for SqlDatabase {
+  #[tracing::instrument(name = "Create Audit Event", skip(self, event))]
+  async fn create_audit_event(&self, event: AuditEvent) -> AppResult<InsertOneResult> {
+    let id = event.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!("INSERT INTO audit_events ({COLUMNS}) VALUES ("));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(event.actor)
+      .push(", ")
+      .bind(event.target)
+      .push(", ")
+      .bind(enum_text(&event.action)?)
+      .push(", ")
+      .bind(event.ip)
+      .push(", ")
+      .bind(event.user_agent)
+      .push(", ")
+      .bind(enum_text(&event.outcome)?)
+      .push(", ")
+      .bind(event.reason)
+      .push(", ")
+      .bind(event.timestamp)
+      .push(")");
+    self.execute(&statement).await.map_err(sql_error)?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Find Audit Events", skip(self))]
+  async fn find_audit_events(
+    &self,
+    filter: &AuditEventFilter,
+    pagination: &Pagination,
+  ) -> AppResult<Page<AuditEvent>> {
+    let mut statement = Statement::new(&format!("SELECT {COLUMNS} FROM audit_events"));
+    filter_statement(&mut statement, filter)?;
+    self
+      .fetch_page(
+        statement,
+        sort_key(&pagination.sort)?,
+        pagination,
+        event_from_row,
+      )
+      .await
+  }
+
+  #[tracing::instrument(name = "Count Audit Events", skip(self))]
+  async fn count_audit_events(&self, filter: &AuditEventFilter) -> AppResult<u64> {
+    let mut statement = Statement::new("SELECT COUNT(*) AS count FROM audit_events");
+    filter_statement(&mut statement, filter)?;
+    self.count(&statement).await
+  }
+}
@@ -0,0 +1,211 @@
+use super::{
+  SortKey, SqlDatabase, Statement, Value, enum_text, get, get_date, get_enum, get_id, scoped,
+  sql_error, where_scoped,
+};
+use crate::{
+  file::model::StoredFile,
+  pagination::{Page, Pagination},
+  repository::{Repository, id_of},
+  results::{deleted, inserted, updated},
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{Bson, Document, doc, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use sqlx::any::AnyRow;
+use utils::{AppError, AppResult};
+
+const COLUMNS: &str = "id, tenant_id, owner_id, name, content_type, size, sha256, storage, key, \
+                       created_at";
+
+fn file_from_row(row: &AnyRow) -> AppResult<StoredFile> {
+  Ok(StoredFile {
+    id: Some(get_id(row, "id")?),
+    owner_id: get_id(row, "owner_id")?,
+    name: get(row, "name")?,
+    content_type: get(row, "content_type")?,
+    size: u64::try_from(get::<i64>(row, "size")?).unwrap_or_default(),
+    sha256: get(row, "sha256")?,
+    storage: get_enum(row, "storage")?,
+    key: get(row, "key")?,
+    created_at: get_date(row, "created_at")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+/// Appends the conditions of `filter`, which may only test fields of `StoredFile` for equality.
+fn filter_statement(statement: &mut Statement, filter: &Document) -> AppResult<()> {
+  where_scoped(statement);
+  for (field, value) in filter {
+    let column = match field.as_str() {
+      "_id" => "id",
+      "owner_id" | "name" | "content_type" | "size" | "sha256" | "storage" | "key" => {
+        field.as_str()
+      }
+      _ => {
+        return Err(AppError::InternalServerErrorWithContext(format!(
+          "cannot filter files by {field}"
+        )));
+      }
+    };
+    let value = match value {
+      Bson::ObjectId(id) => Value::from(*id),
+      Bson::String(text) => Value::from(text.as_str()),
+      Bson::Int64(number) => Value::from(*number),
+      Bson::Int32(number) => Value::from(i64::from(*number)),
+      _ => {
+        return Err(AppError::InternalServerErrorWithContext(format!(
+          "cannot filter files by {field} = {value}"
+        )));
+      }
+    };
+    statement.push(&format!(" AND {column} = ")).bind(value);
+  }
+  Ok(())
+}
+
+/// The expression a listing is sorted by.
+fn sort_key(field: &str) -> AppResult<SortKey> {
+  match field {
+    "_id" => Ok(SortKey::Text("id")),
+    "name" => Ok(SortKey::Text("name")),
+    "size" => Ok(SortKey::Int("size")),
+    "created_at" => Ok(SortKey::Int("created_at")),
+    _ => Err(AppError::BadRequest(format!(
+      "cannot sort files by {field}"
+    ))),
+  }
+}
+
+impl SqlDatabase {
+  async fn find_files(&self, filter: &Document, limit: Option<i64>) -> AppResult<Vec<StoredFile>> {
+    let mut statement = Statement::new(&format!("SELECT {COLUMNS} FROM files"));
+    filter_statement(&mut statement, filter)?;
+    if let Some(limit) = limit {
+      statement.push(" LIMIT ").bind(limit);
+    }
+    self
+      .fetch_all(&statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(file_from_row)
+      .collect()
+  }
+
+  async fn count_files(&self, filter: &Document) -> AppResult<u64> {
+    let mut statement = Statement::new("SELECT COUNT(*) AS count FROM files");
+    filter_statement(&mut statement, filter)?;
+    self.count(&statement).await
+  }
+}
+
+#[async_trait]
+impl Repository<StoredFile> for SqlDatabase {
+  #[tracing::instrument(name = "Insert File", skip(self, document))]
+  async fn insert(&self, document: StoredFile) -> AppResult<InsertOneResult> {
+    let id = document.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!("INSERT INTO files ({COLUMNS}) VALUES ("));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(document.owner_id)
+      .push(", ")
+      .bind(document.name)
+      .push(", ")
+      .bind(document.content_type)
+      .push(", ")
+      .bind(i64::try_from(document.size).unwrap_or(i64::MAX))
+      .push(", ")
+      .bind(document.sha256)
+      .push(", ")
+      .bind(enum_text(&document.storage)?)
+      .push(", ")
+      .bind(document.key)
+      .push(", ")
+      .bind(document.created_at)
+      .push(")");
+    self.execute(&statement).await.map_err(sql_error)?;
+    Ok(inserted(id))
+  }
+
+  async fn find_by_id(&self, id: &ObjectId) -> AppResult<Option<StoredFile>> {
+    let mut files = self.find_files(&doc! {"_id": id}, Some(1)).await?;
+    Ok(files.pop())
+  }
+
+  async fn find_one(&self, filter: Document) -> AppResult<Option<StoredFile>> {
+    let mut files = self.find_files(&filter, Some(1)).await?;
+    Ok(files.pop())
+  }
+
+  async fn find(&self, filter: Document) -> AppResult<Vec<StoredFile>> {
+    self.find_files(&filter, None).await
+  }
+
+  #[tracing::instrument(name = "Find Files", skip(self))]
+  async fn find_page(
+    &self,
+    filter: Document,
+    pagination: &Pagination,
+  ) -> AppResult<Page<StoredFile>> {
+    let mut statement = Statement::new(&format!("SELECT {COLUMNS} FROM files"));
+    filter_statement(&mut statement, &filter)?;
+    self
+      .fetch_page(
+        statement,
+        sort_key(&pagination.sort)?,
+        pagination,
+        file_from_row,
+      )
+      .await
+  }
+
+  async fn count(&self, filter: Document) -> AppResult<u64> {
+    self.count_files(&filter).await
+  }
+
+  async fn exists(&self, filter: Document) -> AppResult<bool> {
+    Ok(self.find_one(filter).await?.is_some())
+  }
+
+  #[tracing::instrument(name = "Update File", skip(self, document))]
+  async fn update(&self, mut document: StoredFile) -> AppResult<UpdateResult> {
+    let id = id_of(&mut document)?;
+    let mut statement = Statement::new("UPDATE files SET owner_id = ");
+    statement
+      .bind(document.owner_id)
+      .push(", name = ")
+      .bind(document.name)
+      .push(", content_type = ")
+      .bind(document.content_type)
+      .push(", size = ")
+      .bind(i64::try_from(document.size).unwrap_or(i64::MAX))
+      .push(", sha256 = ")
+      .bind(document.sha256)
+      .push(", storage = ")
+      .bind(enum_text(&document.storage)?)
+      .push(", key = ")
+      .bind(document.key)
+      .push(", created_at = ")
+      .bind(document.created_at)
+      .push(" WHERE id = ")
+      .bind(id);
+    scoped(&mut statement);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Delete File", skip(self))]
+  async fn delete_by_id(&self, id: &ObjectId) -> AppResult<DeleteResult> {
+    let mut statement = Statement::new("DELETE FROM files WHERE id = ");
+    statement.bind(*id);
+    scoped(&mut statement);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(deleted(result.rows_affected()))
+  }
+}
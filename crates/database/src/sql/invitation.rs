@@ -0,0 +1,120 @@
+use super::{
+  SqlDatabase, Statement, duplicate_key, get, get_date, get_id, get_optional_date, scoped,
+  sql_error, where_scoped,
+};
+use crate::{
+  invitation::{model::Invitation, repository::InvitationRepositoryTrait},
+  results::{deleted, inserted},
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult},
+};
+use sqlx::any::AnyRow;
+use std::str::FromStr;
+use utils::AppResult;
+
+const COLUMNS: &str = "id, tenant_id, code, email, created_by, created_at, expires_at, used_at, \
+                       used_by";
+
+fn invitation_from_row(row: &AnyRow) -> AppResult<Invitation> {
+  Ok(Invitation {
+    id: Some(get_id(row, "id")?),
+    code: get(row, "code")?,
+    email: get(row, "email")?,
+    created_by: get(row, "created_by")?,
+    created_at: get_date(row, "created_at")?,
+    expires_at: get_date(row, "expires_at")?,
+    used_at: get_optional_date(row, "used_at")?,
+    used_by: get(row, "used_by")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+#[async_trait]
+impl InvitationRepositoryTrait for SqlDatabase {
+  #[tracing::instrument(name = "Create Invitation", skip(self, invitation))]
+  async fn create_invitation(&self, invitation: Invitation) -> AppResult<InsertOneResult> {
+    let id = invitation.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!("INSERT INTO invitations ({COLUMNS}) VALUES ("));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(invitation.code)
+      .push(", ")
+      .bind(invitation.email)
+      .push(", ")
+      .bind(invitation.created_by)
+      .push(", ")
+      .bind(invitation.created_at)
+      .push(", ")
+      .bind(invitation.expires_at)
+      .push(", ")
+      .bind(invitation.used_at)
+      .push(", ")
+      .bind(invitation.used_by)
+      .push(")");
+    self
+      .execute(&statement)
+      .await
+      .map_err(|e| duplicate_key(e, "code"))?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Get All Invitations", skip(self))]
+  async fn get_all_invitations(&self) -> AppResult<Vec<Invitation>> {
+    let mut statement = Statement::new(&format!("SELECT {COLUMNS} FROM invitations"));
+    where_scoped(&mut statement).push(" ORDER BY created_at DESC");
+    self
+      .fetch_all(&statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(invitation_from_row)
+      .collect()
+  }
+
+  #[tracing::instrument(name = "Redeem Invitation", skip(self, code, email))]
+  async fn redeem_invitation(&self, code: &str, email: &str) -> AppResult<Option<Invitation>> {
+    let email = email.to_lowercase();
+    let now = DateTime::now();
+    let mut statement = Statement::new("UPDATE invitations SET used_at = ");
+    statement
+      .bind(now)
+      .push(", used_by = ")
+      .bind(email.as_str())
+      .push(" WHERE code = ")
+      .bind(code)
+      .push(" AND used_at IS NULL AND expires_at > ")
+      .bind(now)
+      .push(" AND (email IS NULL OR email = ")
+      .bind(email.as_str())
+      .push(")");
+    scoped(&mut statement).push(&format!(" RETURNING {COLUMNS}"));
+    let row = self.fetch_optional(&statement).await.map_err(sql_error)?;
+    // Like `find_one_and_update`, return the invitation as it was before, which was unused.
+    row
+      .as_ref()
+      .map(|row| {
+        let mut invitation = invitation_from_row(row)?;
+        invitation.used_at = None;
+        invitation.used_by = None;
+        Ok(invitation)
+      })
+      .transpose()
+  }
+
+  #[tracing::instrument(name = "Delete Invitation", skip(self, id))]
+  async fn delete_invitation(&self, id: &str) -> AppResult<DeleteResult> {
+    let id = ObjectId::from_str(id)?;
+    let mut statement = Statement::new("DELETE FROM invitations WHERE id = ");
+    statement.bind(id);
+    scoped(&mut statement);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(deleted(result.rows_affected()))
+  }
+}
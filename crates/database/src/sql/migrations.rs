@@ -0,0 +1,229 @@
+//! Schema migrations of the SQL backend, applied in order when connecting.
+//!
+//! Unlike the Mongo `Migrator` they only go forward: each migration is a list of statements run
+//! in one transaction together with its `schema_migrations` row.
+use super::sql_error;
+use mongodb::bson::DateTime;
+use sqlx::{AnyPool, Row};
+use utils::AppResult;
+
+struct SqlMigration {
+  version: i64,
+  name: &'static str,
+  statements: &'static [&'static str],
+}
+
+const MIGRATIONS: &[SqlMigration] = &[
+  SqlMigration {
+    version: 1,
+    name: "create users",
+    statements: &[
+      "CREATE TABLE users (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        name TEXT NOT NULL,
+        email TEXT NOT NULL,
+        password TEXT NOT NULL,
+        role TEXT NOT NULL,
+        created_at BIGINT,
+        updated_at BIGINT,
+        last_login_at BIGINT,
+        version BIGINT NOT NULL DEFAULT 0,
+        deleted_at BIGINT
+      )",
+      // Emails are unique per tenant regardless of case, like the Mongo `email` index.
+      "CREATE UNIQUE INDEX users_email ON users (COALESCE(tenant_id, ''), LOWER(email))",
+      "CREATE INDEX users_created_at ON users (created_at)",
+      "CREATE INDEX users_deleted_at ON users (deleted_at)",
+    ],
+  },
+  SqlMigration {
+    version: 2,
+    name: "partial users email index",
+    statements: &[
+      // Soft-deleted users give their email up, like with the Mongo `email` index.
+      "DROP INDEX users_email",
+      "CREATE UNIQUE INDEX users_email ON users (COALESCE(tenant_id, ''), LOWER(email)) \
+       WHERE deleted_at IS NULL",
+    ],
+  },
+  SqlMigration {
+    version: 3,
+    name: "create the other repositories",
+    statements: &[
+      "CREATE TABLE audit_events (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        actor TEXT,
+        target TEXT,
+        action TEXT NOT NULL,
+        ip TEXT,
+        user_agent TEXT,
+        outcome TEXT NOT NULL,
+        reason TEXT,
+        timestamp BIGINT NOT NULL
+      )",
+      "CREATE INDEX audit_events_timestamp ON audit_events (timestamp)",
+      "CREATE INDEX audit_events_actor ON audit_events (actor, timestamp)",
+      "CREATE TABLE invitations (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        code TEXT NOT NULL,
+        email TEXT,
+        created_by TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        expires_at BIGINT NOT NULL,
+        used_at BIGINT,
+        used_by TEXT
+      )",
+      "CREATE UNIQUE INDEX invitations_code ON invitations (code)",
+      "CREATE TABLE organizations (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        name TEXT NOT NULL,
+        created_by TEXT NOT NULL,
+        created_at BIGINT NOT NULL
+      )",
+      "CREATE TABLE memberships (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        organization_id TEXT NOT NULL,
+        user_id TEXT NOT NULL,
+        role TEXT NOT NULL,
+        created_at BIGINT NOT NULL
+      )",
+      "CREATE UNIQUE INDEX memberships_user_id ON memberships (organization_id, user_id)",
+      "CREATE INDEX memberships_user ON memberships (user_id)",
+      "CREATE TABLE organization_invitations (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        organization_id TEXT NOT NULL,
+        email TEXT NOT NULL,
+        role TEXT NOT NULL,
+        invited_by TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        expires_at BIGINT NOT NULL,
+        accepted_at BIGINT
+      )",
+      "CREATE INDEX organization_invitations_email ON organization_invitations (email)",
+      "CREATE TABLE revoked_tokens (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        jti TEXT NOT NULL,
+        subject TEXT NOT NULL,
+        revoked_at BIGINT NOT NULL,
+        expires_at BIGINT NOT NULL
+      )",
+      "CREATE UNIQUE INDEX revoked_tokens_jti ON revoked_tokens (jti)",
+      "CREATE INDEX revoked_tokens_expires_at ON revoked_tokens (expires_at)",
+      "CREATE TABLE webauthn_credentials (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        user_id TEXT NOT NULL,
+        credential_id TEXT NOT NULL,
+        public_key TEXT NOT NULL,
+        sign_count BIGINT NOT NULL,
+        name TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        last_used_at BIGINT
+      )",
+      "CREATE UNIQUE INDEX webauthn_credentials_credential_id \
+       ON webauthn_credentials (credential_id)",
+      "CREATE INDEX webauthn_credentials_user_id ON webauthn_credentials (user_id)",
+      "CREATE TABLE webauthn_challenges (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        challenge TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        user_id TEXT,
+        expires_at BIGINT NOT NULL
+      )",
+      "CREATE INDEX webauthn_challenges_challenge ON webauthn_challenges (challenge)",
+      "CREATE TABLE outbox (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        topic TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts BIGINT NOT NULL,
+        created_at BIGINT NOT NULL,
+        available_at BIGINT NOT NULL,
+        delivered_at BIGINT,
+        last_error TEXT
+      )",
+      "CREATE INDEX outbox_status ON outbox (status, available_at)",
+      "CREATE TABLE files (
+        id TEXT PRIMARY KEY,
+        tenant_id TEXT,
+        owner_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        content_type TEXT NOT NULL,
+        size BIGINT NOT NULL,
+        sha256 TEXT NOT NULL,
+        storage TEXT NOT NULL,
+        key TEXT NOT NULL,
+        created_at BIGINT NOT NULL
+      )",
+      "CREATE INDEX files_owner_id ON files (owner_id, created_at)",
+    ],
+  },
+  SqlMigration {
+    version: 4,
+    name: "organization owners",
+    statements: &[
+      "ALTER TABLE organizations ADD COLUMN owners BIGINT NOT NULL DEFAULT 0",
+      "UPDATE organizations SET owners = (SELECT COUNT(*) FROM memberships \
+       WHERE memberships.organization_id = organizations.id AND memberships.role = 'owner')",
+    ],
+  },
+  SqlMigration {
+    version: 5,
+    name: "outbox delivered sinks",
+    statements: &["ALTER TABLE outbox ADD COLUMN delivered_to TEXT NOT NULL DEFAULT '[]'"],
+  },
+];
+
+/// Applies the pending migrations and returns how many there were.
+pub(super) async fn run(pool: &AnyPool) -> AppResult<usize> {
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS schema_migrations (
+      version BIGINT PRIMARY KEY,
+      name TEXT NOT NULL,
+      applied_at BIGINT NOT NULL
+    )",
+  )
+  .execute(pool)
+  .await
+  .map_err(sql_error)?;
+  let applied: Vec<i64> = sqlx::query("SELECT version FROM schema_migrations")
+    .fetch_all(pool)
+    .await
+    .map_err(sql_error)?
+    .iter()
+    .map(|row| row.try_get("version"))
+    .collect::<Result<_, _>>()
+    .map_err(sql_error)?;
+
+  let pending: Vec<&SqlMigration> = MIGRATIONS
+    .iter()
+    .filter(|migration| !applied.contains(&migration.version))
+    .collect();
+  for migration in &pending {
+    let mut transaction = pool.begin().await.map_err(sql_error)?;
+    for statement in migration.statements {
+      sqlx::query(statement)
+        .execute(&mut *transaction)
+        .await
+        .map_err(sql_error)?;
+    }
+    sqlx::query("INSERT INTO schema_migrations (version, name, applied_at) VALUES ($1, $2, $3)")
+      .bind(migration.version)
+      .bind(migration.name)
+      .bind(DateTime::now().timestamp_millis())
+      .execute(&mut *transaction)
+      .await
+      .map_err(sql_error)?;
+    transaction.commit().await.map_err(sql_error)?;
+  }
+  Ok(pending.len())
+}
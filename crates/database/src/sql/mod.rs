@@ -0,0 +1,690 @@
+//! SQL storage on SQLite or Postgres, through sqlx's `Any` driver.
+//!
+//! Built with the `sqlite` and/or `postgres` features and selected with `db.backend = "sql"`,
+//! `db.uri` naming the database, e.g. `sqlite://app.db?mode=rwc` or `postgres://...`. The
+//! statements are written once for both: `$n` placeholders are understood by both databases.
+//!
+//! `SqlDatabase` implements every repository trait of `Backend`, each model in its own table.
+//! Units of work run in a transaction kept in a task-local, which the queries of the current task
+//! join like Mongo's do with `in_session!`. Of the generic repositories, only `Repository<StoredFile>`
+//! is provided, on the `files` table.
+//!
+//! Ids remain `ObjectId`s stored as hex strings, so a user keeps the same id on every backend
+//! and documents elsewhere can still refer to it. Dates are stored as milliseconds since the
+//! epoch, since `Any` has no date type common to both databases, and documents such as outbox
+//! payloads as canonical extended JSON.
+mod audit;
+mod file;
+mod invitation;
+mod migrations;
+mod organization;
+mod outbox;
+mod token;
+mod user;
+mod webauthn;
+
+use crate::{
+  pagination::{Page, Pagination, SortDirection},
+  tenant::current_tenant,
+  transaction::{BoxFuture, UnitOfWorkTrait},
+};
+use async_trait::async_trait;
+use mongodb::bson::{Bson, DateTime, from_bson, oid::ObjectId, to_bson};
+use serde::{Serialize, de::DeserializeOwned};
+use sqlx::{
+  Any, AnyPool, Row, Transaction,
+  any::{AnyArguments, AnyPoolOptions, AnyQueryResult, AnyRow, install_default_drivers},
+  query::Query,
+};
+use std::{fmt::Write, str::FromStr, sync::Arc};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use utils::{AppError, AppResult, config};
+
+tokio::task_local! {
+  /// The transaction of the unit of work the current task runs in, `None` once it is over.
+  static TRANSACTION: Arc<Mutex<Option<Transaction<'static, Any>>>>;
+}
+
+#[derive(Clone, Debug)]
+pub struct SqlDatabase {
+  pool: AnyPool,
+}
+
+impl SqlDatabase {
+  /// Connects to `db.uri` and applies pending migrations.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the database cannot be reached or migrated.
+  pub async fn connect() -> AppResult<Self> {
+    Self::open(&config::get().db.uri).await
+  }
+
+  /// Connects to the database at `uri` and applies pending migrations.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the database cannot be reached or migrated.
+  pub async fn open(uri: &str) -> AppResult<Self> {
+    install_default_drivers();
+    info!("initializing sql database connection...");
+    let pool = AnyPoolOptions::new()
+      .connect(uri)
+      .await
+      .map_err(sql_error)?;
+    let applied = migrations::run(&pool).await?;
+    if applied > 0 {
+      info!("applied {applied} sql migrations");
+    }
+    Ok(Self { pool })
+  }
+}
+
+/// Runs `$method` on the query of `$statement`, within the current unit of work's transaction if
+/// there is one, like `in_session!` does for Mongo.
+macro_rules! run {
+  ($self:ident, $statement:ident, $method:ident) => {
+    match TRANSACTION.try_with(Clone::clone).ok() {
+      Some(transaction) => match transaction.lock().await.as_deref_mut() {
+        Some(connection) => $statement.query().$method(connection).await,
+        None => Err(sqlx::Error::Protocol(
+          "the unit of work is over".to_string(),
+        )),
+      },
+      None => $statement.query().$method(&$self.pool).await,
+    }
+  };
+}
+
+impl SqlDatabase {
+  async fn execute(&self, statement: &Statement) -> Result<AnyQueryResult, sqlx::Error> {
+    run!(self, statement, execute)
+  }
+
+  async fn fetch_one(&self, statement: &Statement) -> Result<AnyRow, sqlx::Error> {
+    run!(self, statement, fetch_one)
+  }
+
+  async fn fetch_optional(&self, statement: &Statement) -> Result<Option<AnyRow>, sqlx::Error> {
+    run!(self, statement, fetch_optional)
+  }
+
+  async fn fetch_all(&self, statement: &Statement) -> Result<Vec<AnyRow>, sqlx::Error> {
+    run!(self, statement, fetch_all)
+  }
+
+  /// Runs `statement`, a `SELECT COUNT(*) AS count`.
+  async fn count(&self, statement: &Statement) -> AppResult<u64> {
+    let row = self.fetch_one(statement).await.map_err(sql_error)?;
+    let count: i64 = get(&row, "count")?;
+    Ok(u64::try_from(count).unwrap_or_default())
+  }
+
+  /// Completes `statement`, a `SELECT` ending with its `WHERE` conditions, to fetch the page
+  /// described by `pagination` sorted by `sort`, and maps its rows with `from_row`.
+  async fn fetch_page<T: Serialize>(
+    &self,
+    mut statement: Statement,
+    sort: SortKey,
+    pagination: &Pagination,
+    from_row: impl Fn(&AnyRow) -> AppResult<T>,
+  ) -> AppResult<Page<T>> {
+    let expression = sort.expression();
+    let (order, operator) = match pagination.direction {
+      SortDirection::Ascending => ("ASC", " > "),
+      SortDirection::Descending => ("DESC", " < "),
+    };
+    if let Some(after) = &pagination.after {
+      if expression == "id" {
+        statement.push(" AND id").push(operator).bind(after.id);
+      } else {
+        let value = sort.value(&after.value)?;
+        statement
+          .push(&format!(" AND ({expression}{operator}"))
+          .bind(value.clone())
+          .push(&format!(" OR ({expression} = "))
+          .bind(value)
+          .push(" AND id")
+          .push(operator)
+          .bind(after.id)
+          .push("))");
+      }
+    }
+    let skip = if pagination.after.is_some() {
+      0
+    } else {
+      i64::try_from(pagination.skip).unwrap_or(i64::MAX)
+    };
+    // One extra row tells whether there is a next page.
+    statement
+      .push(&format!(
+        " ORDER BY {expression} {order}, id {order} LIMIT "
+      ))
+      .bind(pagination.limit + 1)
+      .push(" OFFSET ")
+      .bind(skip);
+    let mut items = self
+      .fetch_all(&statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(from_row)
+      .collect::<AppResult<Vec<T>>>()?;
+
+    let limit = usize::try_from(pagination.limit).unwrap_or_default();
+    let has_more = items.len() > limit;
+    items.truncate(limit);
+    let next_cursor = match items.last() {
+      Some(last) if has_more => Some(pagination.cursor_of(last)?.encode()),
+      _ => None,
+    };
+    Ok(Page { items, next_cursor })
+  }
+}
+
+#[async_trait]
+impl UnitOfWorkTrait for SqlDatabase {
+  async fn run<'a, 'b>(
+    &'b self,
+    work: &'b mut (dyn FnMut() -> BoxFuture<'a, AppResult<()>> + Send + 'b),
+  ) -> AppResult<()> {
+    if TRANSACTION.try_with(|_| ()).is_ok() {
+      return work().await;
+    }
+
+    let transaction = self.pool.begin().await.map_err(sql_error)?;
+    let transaction = Arc::new(Mutex::new(Some(transaction)));
+    let result = TRANSACTION.scope(transaction.clone(), work()).await;
+    let Some(transaction) = transaction.lock().await.take() else {
+      return result;
+    };
+    match result {
+      Ok(()) => transaction.commit().await.map_err(sql_error),
+      Err(e) => {
+        if let Err(rollback) = transaction.rollback().await {
+          warn!("failed to roll back a unit of work: {rollback}");
+        }
+        Err(e)
+      }
+    }
+  }
+}
+
+fn sql_error(error: sqlx::Error) -> AppError {
+  AppError::InternalServerErrorWithContext(format!("sql error: {error}"))
+}
+
+/// Maps a failed write, reporting a unique constraint violation against `field` like the Mongo
+/// unique index named after it does.
+fn duplicate_key(error: sqlx::Error, field: &str) -> AppError {
+  match &error {
+    sqlx::Error::Database(e) if e.is_unique_violation() => {
+      AppError::DuplicateKey(field.to_string())
+    }
+    _ => sql_error(error),
+  }
+}
+
+/// Parses an id read from a row, see the module documentation.
+fn parse_id(id: &str) -> AppResult<ObjectId> {
+  Ok(ObjectId::from_str(id)?)
+}
+
+fn to_millis(date: DateTime) -> i64 {
+  date.timestamp_millis()
+}
+
+/// Reads the column `name` of `row`.
+fn get<'r, T>(row: &'r AnyRow, name: &str) -> AppResult<T>
+where
+  T: sqlx::Decode<'r, Any> + sqlx::Type<Any>,
+{
+  row.try_get(name).map_err(sql_error)
+}
+
+fn get_id(row: &AnyRow, name: &str) -> AppResult<ObjectId> {
+  parse_id(&get::<String>(row, name)?)
+}
+
+fn get_optional_id(row: &AnyRow, name: &str) -> AppResult<Option<ObjectId>> {
+  get::<Option<String>>(row, name)?
+    .as_deref()
+    .map(parse_id)
+    .transpose()
+}
+
+fn get_date(row: &AnyRow, name: &str) -> AppResult<DateTime> {
+  Ok(DateTime::from_millis(get(row, name)?))
+}
+
+fn get_optional_date(row: &AnyRow, name: &str) -> AppResult<Option<DateTime>> {
+  Ok(get::<Option<i64>>(row, name)?.map(DateTime::from_millis))
+}
+
+/// Reads an enum stored with `enum_text`.
+fn get_enum<T: DeserializeOwned>(row: &AnyRow, name: &str) -> AppResult<T> {
+  Ok(from_bson(Bson::String(get(row, name)?))?)
+}
+
+/// The text an enum serializes to, e.g. `admin` for `UserRole::Admin`.
+fn enum_text<T: Serialize>(value: &T) -> AppResult<String> {
+  match to_bson(value)? {
+    Bson::String(text) => Ok(text),
+    _ => Err(AppError::InternalServerErrorWithContext(
+      "enum is not a string".to_string(),
+    )),
+  }
+}
+
+/// Restricts the statement to the current tenant, like `tenant::scoped`.
+fn scoped(statement: &mut Statement) -> &mut Statement {
+  if let Some(tenant) = current_tenant() {
+    statement.push(" AND tenant_id = ").bind(tenant);
+  }
+  statement
+}
+
+/// Starts the `WHERE` conditions of the statement with the current tenant, see `scoped`.
+fn where_scoped(statement: &mut Statement) -> &mut Statement {
+  statement.push(" WHERE TRUE");
+  scoped(statement)
+}
+
+/// An expression listings are sorted by. Nulls are coalesced to a value sorting first, as `null`
+/// does in Mongo.
+#[derive(Debug, Clone, Copy)]
+enum SortKey {
+  Text(&'static str),
+  Int(&'static str),
+}
+
+impl SortKey {
+  fn expression(self) -> &'static str {
+    match self {
+      Self::Text(expression) | Self::Int(expression) => expression,
+    }
+  }
+
+  /// Converts a cursor value back to what the expression is compared with.
+  fn value(self, value: &Bson) -> AppResult<Value> {
+    match (self, value) {
+      (Self::Text(_), Bson::Null) => Ok(Value::Text(String::new())),
+      (Self::Int(_), Bson::Null) => Ok(Value::Int(0)),
+      (Self::Text(_), Bson::String(text)) => Ok(Value::Text(text.clone())),
+      (Self::Text(_), Bson::ObjectId(id)) => Ok(Value::from(*id)),
+      (Self::Int(_), Bson::DateTime(date)) => Ok(Value::from(*date)),
+      (Self::Int(_), Bson::Int64(int)) => Ok(Value::Int(*int)),
+      (Self::Int(_), Bson::Int32(int)) => Ok(Value::Int(i64::from(*int))),
+      _ => Err(AppError::BadRequest("invalid cursor".to_string())),
+    }
+  }
+}
+
+/// A value bound to a statement placeholder.
+#[derive(Debug, Clone)]
+enum Value {
+  Text(String),
+  OptionalText(Option<String>),
+  Int(i64),
+  OptionalInt(Option<i64>),
+}
+
+impl From<&str> for Value {
+  fn from(value: &str) -> Self {
+    Self::Text(value.to_string())
+  }
+}
+
+impl From<String> for Value {
+  fn from(value: String) -> Self {
+    Self::Text(value)
+  }
+}
+
+impl From<Option<String>> for Value {
+  fn from(value: Option<String>) -> Self {
+    Self::OptionalText(value)
+  }
+}
+
+impl From<i64> for Value {
+  fn from(value: i64) -> Self {
+    Self::Int(value)
+  }
+}
+
+impl From<Option<i64>> for Value {
+  fn from(value: Option<i64>) -> Self {
+    Self::OptionalInt(value)
+  }
+}
+
+impl From<DateTime> for Value {
+  fn from(value: DateTime) -> Self {
+    Self::Int(to_millis(value))
+  }
+}
+
+impl From<Option<DateTime>> for Value {
+  fn from(value: Option<DateTime>) -> Self {
+    Self::OptionalInt(value.map(to_millis))
+  }
+}
+
+impl From<ObjectId> for Value {
+  fn from(value: ObjectId) -> Self {
+    Self::Text(value.to_hex())
+  }
+}
+
+impl From<Option<ObjectId>> for Value {
+  fn from(value: Option<ObjectId>) -> Self {
+    Self::OptionalText(value.map(|id| id.to_hex()))
+  }
+}
+
+/// A statement built piece by piece, with its placeholders numbered as values are bound.
+#[derive(Debug, Default)]
+struct Statement {
+  sql: String,
+  values: Vec<Value>,
+}
+
+impl Statement {
+  fn new(sql: &str) -> Self {
+    Self {
+      sql: sql.to_string(),
+      values: Vec::new(),
+    }
+  }
+
+  fn push(&mut self, sql: &str) -> &mut Self {
+    self.sql.push_str(sql);
+    self
+  }
+
+  fn bind(&mut self, value: impl Into<Value>) -> &mut Self {
+    self.values.push(value.into());
+    let _ = write!(self.sql, "${}", self.values.len());
+    self
+  }
+
+  fn query(&self) -> Query<'_, Any, AnyArguments<'_>> {
+    self
+      .values
+      .iter()
+      .fold(sqlx::query(&self.sql), |query, value| match value {
+        Value::Text(text) => query.bind(text.clone()),
+        Value::OptionalText(text) => query.bind(text.clone()),
+        Value::Int(int) => query.bind(*int),
+        Value::OptionalInt(int) => query.bind(*int),
+      })
+  }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+  use super::*;
+  use crate::{
+    invitation::{model::Invitation, repository::InvitationRepositoryTrait},
+    organization::{model::Organization, repository::OrganizationRepositoryTrait},
+    outbox::{model::OutboxMessage, repository::OutboxRepositoryTrait},
+    token::{model::RevokedToken, repository::TokenRepositoryTrait},
+    transaction::transaction,
+    user::{
+      repository::UserRepositoryTrait,
+      search::{UserSearchQuery, UserSearchTrait, query_terms},
+    },
+  };
+  use mongodb::bson::doc;
+  use std::time::Duration;
+  use utils::tenant;
+
+  /// A database in a new file, so that every connection of the pool sees the same data.
+  async fn database() -> SqlDatabase {
+    let path = std::env::temp_dir().join(format!("{}.db", ObjectId::new()));
+    SqlDatabase::open(&format!("sqlite://{}?mode=rwc", path.display()))
+      .await
+      .unwrap()
+  }
+
+  fn revoked(jti: &str) -> RevokedToken {
+    let now = DateTime::now();
+    RevokedToken {
+      id: None,
+      jti: jti.to_string(),
+      subject: "subject".to_string(),
+      revoked_at: now,
+      expires_at: DateTime::from_millis(now.timestamp_millis() + 60_000),
+      tenant_id: None,
+    }
+  }
+
+  #[tokio::test]
+  async fn failed_units_of_work_are_rolled_back() {
+    let db = database().await;
+    let result: AppResult<()> = transaction(&db, || async {
+      db.revoke_token(revoked("rolled-back")).await?;
+      Err(AppError::BadRequest("abort".to_string()))
+    })
+    .await;
+    assert!(result.is_err());
+    assert!(!db.is_token_revoked("rolled-back").await.unwrap());
+
+    transaction(&db, || async {
+      db.revoke_token(revoked("committed")).await?;
+      Ok(())
+    })
+    .await
+    .unwrap();
+    assert!(db.is_token_revoked("committed").await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn revoking_twice_is_a_no_op() {
+    let db = database().await;
+    let first = db.revoke_token(revoked("jti")).await.unwrap();
+    assert!(first.upserted_id.is_some());
+    let second = db.revoke_token(revoked("jti")).await.unwrap();
+    assert!(second.upserted_id.is_none());
+    assert_eq!(second.matched_count, 1);
+  }
+
+  #[tokio::test]
+  async fn outbox_payloads_keep_their_types() {
+    let db = database().await;
+    let id = ObjectId::new();
+    let payload = doc! { "id": id, "at": DateTime::from_millis(1), "count": 2_i64 };
+    db.enqueue_message(OutboxMessage::new("user.created", payload.clone()))
+      .await
+      .unwrap();
+    let lease = Duration::from_secs(60);
+    let claimed = db.claim_messages(10, lease).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].payload, payload);
+    assert_eq!(claimed[0].attempts, 1);
+    // The message is leased until it is completed or failed.
+    assert!(db.claim_messages(10, lease).await.unwrap().is_empty());
+
+    let delivered_to = ["log".to_string()];
+    let id = claimed[0].id.unwrap();
+    db.fail_message(&id, "timeout", &delivered_to, Some(DateTime::MIN))
+      .await
+      .unwrap();
+    let retried = db.claim_messages(10, Duration::ZERO).await.unwrap();
+    assert_eq!(retried[0].delivered_to, delivered_to);
+    // Renewed, the lease hides the message again.
+    let renewed = db.renew_lease(&[id], lease).await.unwrap();
+    assert_eq!(renewed.modified_count, 1);
+    assert!(db.claim_messages(10, lease).await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn search_ranks_users_like_the_other_backends() {
+    let db = database().await;
+    let mut ids = Vec::new();
+    for (name, email) in [
+      ("Carl", "carl@example.com"),
+      ("Bob", "annie@example.com"),
+      ("Anne Smith", "smith@example.com"),
+      ("Ann Lee", "lee@example.com"),
+    ] {
+      let result = db.create_user(name, email, "hash").await.unwrap();
+      ids.push(result.inserted_id.as_object_id().unwrap());
+    }
+    let query = UserSearchQuery {
+      terms: query_terms("ann"),
+      skip: 0,
+      limit: 10,
+      include_total: true,
+    };
+    let results = db.search_users(&query).await.unwrap();
+    let found: Vec<ObjectId> = results
+      .hits
+      .iter()
+      .map(|hit| hit.user.id.unwrap())
+      .collect();
+    assert_eq!(found, [ids[3], ids[2], ids[1]]);
+    assert_eq!(results.total, Some(3));
+  }
+
+  #[tokio::test]
+  async fn users_are_fetched_by_ids() {
+    let db = database().await;
+    let mut ids = Vec::new();
+    for email in ["ada@example.com", "grace@example.com", "alan@example.com"] {
+      let result = db.create_user("User", email, "hash").await.unwrap();
+      ids.push(result.inserted_id.as_object_id().unwrap());
+    }
+    db.delete_user(&ids[2].to_hex(), None).await.unwrap();
+    let mut found: Vec<ObjectId> = db
+      .get_users_by_ids(&[ids[0], ids[2], ObjectId::new()])
+      .await
+      .unwrap()
+      .iter()
+      .map(|user| user.id.unwrap())
+      .collect();
+    found.sort_unstable();
+    assert_eq!(found, [ids[0]]);
+    assert!(db.get_users_by_ids(&[]).await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn organizations_keep_an_owner() {
+    let db = database().await;
+    let id = ObjectId::new();
+    let organization = Organization {
+      id: Some(id),
+      name: "Acme".to_string(),
+      created_by: ObjectId::new(),
+      created_at: DateTime::now(),
+      owners: 1,
+      tenant_id: None,
+    };
+    db.create_organization(organization).await.unwrap();
+    assert!(!db.adjust_owners(&id, -1).await.unwrap());
+    assert!(db.adjust_owners(&id, 1).await.unwrap());
+    assert!(db.adjust_owners(&id, -1).await.unwrap());
+    assert!(!db.adjust_owners(&id, -1).await.unwrap());
+    let organization = db.get_organization_by_id(&id).await.unwrap().unwrap();
+    assert_eq!(organization.owners, 1);
+  }
+
+  #[tokio::test]
+  async fn tenants_only_reach_their_own_rows() {
+    let db = database().await;
+    let id = tenant::scope("acme".to_string(), async {
+      db.revoke_token(revoked("acme-jti")).await.unwrap();
+      let result = db.create_user("Ada", "ada@example.com", "hash").await;
+      result.unwrap().inserted_id.as_object_id().unwrap().to_hex()
+    })
+    .await;
+
+    tenant::scope("globex".to_string(), async {
+      assert!(db.get_user_by_id(&id).await.unwrap().is_none());
+      assert!(
+        db.get_user_by_email("ada@example.com")
+          .await
+          .unwrap()
+          .is_none()
+      );
+      assert!(!db.is_token_revoked("acme-jti").await.unwrap());
+      assert_eq!(db.delete_user(&id, None).await.unwrap().matched_count, 0);
+      let query = UserSearchQuery {
+        terms: query_terms("ada"),
+        skip: 0,
+        limit: 10,
+        include_total: true,
+      };
+      assert_eq!(db.search_users(&query).await.unwrap().total, Some(0));
+      // Emails are only unique within a tenant.
+      db.create_user("Ada", "ada@example.com", "hash")
+        .await
+        .unwrap();
+    })
+    .await;
+
+    let user = tenant::scope("acme".to_string(), db.get_user_by_id(&id)).await;
+    assert_eq!(user.unwrap().unwrap().tenant_id.as_deref(), Some("acme"));
+  }
+
+  #[tokio::test]
+  async fn unique_violations_name_their_field() {
+    let db = database().await;
+    let result = db.create_user("Ada", "ada@example.com", "hash").await;
+    let id = result.unwrap().inserted_id.as_object_id().unwrap().to_hex();
+    let error = db
+      .create_user("Ada", "ada@example.com", "hash")
+      .await
+      .unwrap_err();
+    assert_eq!(error.duplicate_key_field().as_deref(), Some("email"));
+    // Deleted users give their email up.
+    db.delete_user(&id, None).await.unwrap();
+    db.create_user("Ada", "ada@example.com", "hash")
+      .await
+      .unwrap();
+
+    let invitation = || Invitation {
+      id: Some(ObjectId::new()),
+      code: "code".to_string(),
+      email: None,
+      created_by: "admin@example.com".to_string(),
+      created_at: DateTime::now(),
+      expires_at: DateTime::MAX,
+      used_at: None,
+      used_by: None,
+      tenant_id: None,
+    };
+    db.create_invitation(invitation()).await.unwrap();
+    let error = db.create_invitation(invitation()).await.unwrap_err();
+    assert_eq!(error.duplicate_key_field().as_deref(), Some("code"));
+  }
+
+  #[tokio::test]
+  async fn writes_maintain_the_timestamps() {
+    let db = database().await;
+    let result = db.create_user("Ada", "ada@example.com", "hash").await;
+    let id = result.unwrap().inserted_id.as_object_id().unwrap();
+    let user = db.get_user_by_id(&id.to_hex()).await.unwrap().unwrap();
+    assert!(user.created_at.is_some());
+    assert_eq!(user.updated_at, user.created_at);
+    assert_eq!(user.last_login_at, None);
+
+    tokio::time::sleep(Duration::from_millis(2)).await;
+    db.record_login(&id).await.unwrap();
+    let logged_in = db.get_user_by_id(&id.to_hex()).await.unwrap().unwrap();
+    assert!(logged_in.last_login_at > user.created_at);
+    assert_eq!(logged_in.updated_at, user.updated_at);
+    assert_eq!(logged_in.version, user.version);
+
+    tokio::time::sleep(Duration::from_millis(2)).await;
+    let updated = db
+      .update_user(&id.to_hex(), "Ada L.", "ada@example.com", None)
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(updated.updated_at > logged_in.last_login_at);
+    assert_eq!(updated.created_at, user.created_at);
+  }
+}
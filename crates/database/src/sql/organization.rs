@@ -0,0 +1,351 @@
+use super::{
+  SqlDatabase, Statement, duplicate_key, enum_text, get, get_date, get_enum, get_id,
+  get_optional_date, scoped, sql_error, where_scoped,
+};
+use crate::{
+  organization::{
+    model::{Membership, Organization, OrganizationInvitation, OrganizationRole},
+    repository::OrganizationRepositoryTrait,
+  },
+  results::{deleted, inserted, updated},
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use sqlx::any::AnyRow;
+use utils::AppResult;
+
+const ORGANIZATION_COLUMNS: &str = "id, tenant_id, name, created_by, created_at, owners";
+
+const MEMBERSHIP_COLUMNS: &str = "id, tenant_id, organization_id, user_id, role, created_at";
+
+const INVITATION_COLUMNS: &str = "id, tenant_id, organization_id, email, role, invited_by, \
+                                  created_at, expires_at, accepted_at";
+
+fn organization_from_row(row: &AnyRow) -> AppResult<Organization> {
+  Ok(Organization {
+    id: Some(get_id(row, "id")?),
+    name: get(row, "name")?,
+    created_by: get_id(row, "created_by")?,
+    created_at: get_date(row, "created_at")?,
+    owners: get(row, "owners")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+fn membership_from_row(row: &AnyRow) -> AppResult<Membership> {
+  Ok(Membership {
+    id: Some(get_id(row, "id")?),
+    organization_id: get_id(row, "organization_id")?,
+    user_id: get_id(row, "user_id")?,
+    role: get_enum(row, "role")?,
+    created_at: get_date(row, "created_at")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+fn invitation_from_row(row: &AnyRow) -> AppResult<OrganizationInvitation> {
+  Ok(OrganizationInvitation {
+    id: Some(get_id(row, "id")?),
+    organization_id: get_id(row, "organization_id")?,
+    email: get(row, "email")?,
+    role: get_enum(row, "role")?,
+    invited_by: get_id(row, "invited_by")?,
+    created_at: get_date(row, "created_at")?,
+    expires_at: get_date(row, "expires_at")?,
+    accepted_at: get_optional_date(row, "accepted_at")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+/// Selects the membership of `user_id` in `organization_id`.
+fn membership_filter<'s>(
+  statement: &'s mut Statement,
+  organization_id: &ObjectId,
+  user_id: &ObjectId,
+) -> &'s mut Statement {
+  statement
+    .push(" WHERE organization_id = ")
+    .bind(*organization_id)
+    .push(" AND user_id = ")
+    .bind(*user_id);
+  scoped(statement)
+}
+
+impl SqlDatabase {
+  async fn fetch_memberships(&self, statement: &Statement) -> AppResult<Vec<Membership>> {
+    self
+      .fetch_all(statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(membership_from_row)
+      .collect()
+  }
+}
+
+#[async_trait]
+impl OrganizationRepositoryTrait for SqlDatabase {
+  #[tracing::instrument(name = "Create Organization", skip(self, organization))]
+  async fn create_organization(&self, organization: Organization) -> AppResult<InsertOneResult> {
+    let id = organization.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!(
+      "INSERT INTO organizations ({ORGANIZATION_COLUMNS}) VALUES ("
+    ));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(organization.name)
+      .push(", ")
+      .bind(organization.created_by)
+      .push(", ")
+      .bind(organization.created_at)
+      .push(", ")
+      .bind(organization.owners)
+      .push(")");
+    self.execute(&statement).await.map_err(sql_error)?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Get Organization By Id", skip(self))]
+  async fn get_organization_by_id(&self, id: &ObjectId) -> AppResult<Option<Organization>> {
+    let mut statement = Statement::new(&format!(
+      "SELECT {ORGANIZATION_COLUMNS} FROM organizations WHERE id = "
+    ));
+    statement.bind(*id);
+    scoped(&mut statement);
+    let row = self.fetch_optional(&statement).await.map_err(sql_error)?;
+    row.as_ref().map(organization_from_row).transpose()
+  }
+
+  #[tracing::instrument(name = "Get Organizations By Ids", skip(self, ids))]
+  async fn get_organizations_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<Organization>> {
+    if ids.is_empty() {
+      return Ok(Vec::new());
+    }
+    let mut statement = Statement::new(&format!(
+      "SELECT {ORGANIZATION_COLUMNS} FROM organizations WHERE id IN ("
+    ));
+    for (i, id) in ids.iter().enumerate() {
+      if i > 0 {
+        statement.push(", ");
+      }
+      statement.bind(*id);
+    }
+    statement.push(")");
+    scoped(&mut statement);
+    self
+      .fetch_all(&statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(organization_from_row)
+      .collect()
+  }
+
+  #[tracing::instrument(name = "Create Membership", skip(self, membership))]
+  async fn create_membership(&self, membership: Membership) -> AppResult<InsertOneResult> {
+    let id = membership.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!(
+      "INSERT INTO memberships ({MEMBERSHIP_COLUMNS}) VALUES ("
+    ));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(membership.organization_id)
+      .push(", ")
+      .bind(membership.user_id)
+      .push(", ")
+      .bind(enum_text(&membership.role)?)
+      .push(", ")
+      .bind(membership.created_at)
+      .push(")");
+    self
+      .execute(&statement)
+      .await
+      .map_err(|e| duplicate_key(e, "user_id"))?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Get Membership", skip(self))]
+  async fn get_membership(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+  ) -> AppResult<Option<Membership>> {
+    let mut statement = Statement::new(&format!("SELECT {MEMBERSHIP_COLUMNS} FROM memberships"));
+    membership_filter(&mut statement, organization_id, user_id);
+    let row = self.fetch_optional(&statement).await.map_err(sql_error)?;
+    row.as_ref().map(membership_from_row).transpose()
+  }
+
+  #[tracing::instrument(name = "Get Memberships By User", skip(self))]
+  async fn get_memberships_by_user(&self, user_id: &ObjectId) -> AppResult<Vec<Membership>> {
+    let mut statement = Statement::new(&format!(
+      "SELECT {MEMBERSHIP_COLUMNS} FROM memberships WHERE user_id = "
+    ));
+    statement.bind(*user_id);
+    scoped(&mut statement);
+    self.fetch_memberships(&statement).await
+  }
+
+  #[tracing::instrument(name = "Get Memberships By Organization", skip(self))]
+  async fn get_memberships_by_organization(
+    &self,
+    organization_id: &ObjectId,
+  ) -> AppResult<Vec<Membership>> {
+    let mut statement = Statement::new(&format!(
+      "SELECT {MEMBERSHIP_COLUMNS} FROM memberships WHERE organization_id = "
+    ));
+    statement.bind(*organization_id);
+    scoped(&mut statement);
+    self.fetch_memberships(&statement).await
+  }
+
+  #[tracing::instrument(name = "Count Members With Role", skip(self))]
+  async fn count_members_with_role(
+    &self,
+    organization_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<u64> {
+    let mut statement =
+      Statement::new("SELECT COUNT(*) AS count FROM memberships WHERE organization_id = ");
+    statement
+      .bind(*organization_id)
+      .push(" AND role = ")
+      .bind(enum_text(&role)?);
+    scoped(&mut statement);
+    self.count(&statement).await
+  }
+
+  #[tracing::instrument(name = "Adjust Organization Owners", skip(self))]
+  async fn adjust_owners(&self, organization_id: &ObjectId, delta: i64) -> AppResult<bool> {
+    // The condition is checked again on the locked row, so concurrent updates cannot both pass.
+    let mut statement = Statement::new("UPDATE organizations SET owners = owners + ");
+    statement
+      .bind(delta)
+      .push(" WHERE id = ")
+      .bind(*organization_id)
+      .push(" AND owners >= ")
+      .bind(1 - delta);
+    scoped(&mut statement);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(result.rows_affected() == 1)
+  }
+
+  #[tracing::instrument(name = "Update Membership Role", skip(self))]
+  async fn update_membership_role(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<UpdateResult> {
+    let mut statement = Statement::new("UPDATE memberships SET role = ");
+    statement.bind(enum_text(&role)?);
+    membership_filter(&mut statement, organization_id, user_id);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Delete Membership", skip(self))]
+  async fn delete_membership(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+  ) -> AppResult<DeleteResult> {
+    let mut statement = Statement::new("DELETE FROM memberships");
+    membership_filter(&mut statement, organization_id, user_id);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(deleted(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Create Organization Invitation", skip(self, invitation))]
+  async fn create_organization_invitation(
+    &self,
+    invitation: OrganizationInvitation,
+  ) -> AppResult<InsertOneResult> {
+    let id = invitation.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!(
+      "INSERT INTO organization_invitations ({INVITATION_COLUMNS}) VALUES ("
+    ));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(invitation.organization_id)
+      .push(", ")
+      .bind(invitation.email)
+      .push(", ")
+      .bind(enum_text(&invitation.role)?)
+      .push(", ")
+      .bind(invitation.invited_by)
+      .push(", ")
+      .bind(invitation.created_at)
+      .push(", ")
+      .bind(invitation.expires_at)
+      .push(", ")
+      .bind(invitation.accepted_at)
+      .push(")");
+    self.execute(&statement).await.map_err(sql_error)?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Get Pending Organization Invitations", skip(self, email))]
+  async fn get_pending_invitations_by_email(
+    &self,
+    email: &str,
+  ) -> AppResult<Vec<OrganizationInvitation>> {
+    let mut statement = Statement::new(&format!(
+      "SELECT {INVITATION_COLUMNS} FROM organization_invitations"
+    ));
+    where_scoped(&mut statement)
+      .push(" AND email = ")
+      .bind(email.to_lowercase())
+      .push(" AND accepted_at IS NULL AND expires_at > ")
+      .bind(DateTime::now());
+    self
+      .fetch_all(&statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(invitation_from_row)
+      .collect()
+  }
+
+  #[tracing::instrument(name = "Accept Organization Invitation", skip(self, email))]
+  async fn accept_organization_invitation(
+    &self,
+    id: &ObjectId,
+    email: &str,
+  ) -> AppResult<Option<OrganizationInvitation>> {
+    let now = DateTime::now();
+    let mut statement = Statement::new("UPDATE organization_invitations SET accepted_at = ");
+    statement
+      .bind(now)
+      .push(" WHERE id = ")
+      .bind(*id)
+      .push(" AND email = ")
+      .bind(email.to_lowercase())
+      .push(" AND accepted_at IS NULL AND expires_at > ")
+      .bind(now);
+    scoped(&mut statement).push(&format!(" RETURNING {INVITATION_COLUMNS}"));
+    let row = self.fetch_optional(&statement).await.map_err(sql_error)?;
+    // Like `find_one_and_update`, return the invitation as it was before, which was pending.
+    row
+      .as_ref()
+      .map(|row| {
+        let mut invitation = invitation_from_row(row)?;
+        invitation.accepted_at = None;
+        Ok(invitation)
+      })
+      .transpose()
+  }
+}
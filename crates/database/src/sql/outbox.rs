@@ -0,0 +1,189 @@
+use super::{
+  SqlDatabase, Statement, enum_text, get, get_date, get_enum, get_id, get_optional_date, sql_error,
+};
+use crate::{
+  outbox::{
+    model::{OutboxMessage, OutboxStatus},
+    repository::{OutboxRepositoryTrait, later},
+  },
+  results::{inserted, updated},
+  tenant::current_tenant,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{Bson, DateTime, Document, oid::ObjectId},
+  results::{InsertOneResult, UpdateResult},
+};
+use sqlx::any::AnyRow;
+use std::time::Duration;
+use utils::{AppError, AppResult};
+
+const COLUMNS: &str = "id, tenant_id, topic, payload, status, attempts, created_at, available_at, \
+                       delivered_at, last_error, delivered_to";
+
+/// Payloads are stored as canonical extended JSON, which keeps their BSON types.
+fn payload_text(payload: Document) -> AppResult<String> {
+  serde_json::to_string(&Bson::Document(payload).into_canonical_extjson())
+    .map_err(|e| AppError::InternalServerErrorWithContext(format!("outbox payload: {e}")))
+}
+
+fn payload_from_text(text: &str) -> AppResult<Document> {
+  let value: serde_json::Value = serde_json::from_str(text)
+    .map_err(|e| AppError::InternalServerErrorWithContext(format!("outbox payload: {e}")))?;
+  match Bson::try_from(value) {
+    Ok(Bson::Document(payload)) => Ok(payload),
+    _ => Err(AppError::InternalServerErrorWithContext(
+      "outbox payload is not a document".to_string(),
+    )),
+  }
+}
+
+/// Sinks are stored as a JSON array.
+fn sinks_text(sinks: &[String]) -> AppResult<String> {
+  serde_json::to_string(sinks)
+    .map_err(|e| AppError::InternalServerErrorWithContext(format!("outbox sinks: {e}")))
+}
+
+fn sinks_from_text(text: &str) -> AppResult<Vec<String>> {
+  serde_json::from_str(text)
+    .map_err(|e| AppError::InternalServerErrorWithContext(format!("outbox sinks: {e}")))
+}
+
+fn message_from_row(row: &AnyRow) -> AppResult<OutboxMessage> {
+  Ok(OutboxMessage {
+    id: Some(get_id(row, "id")?),
+    topic: get(row, "topic")?,
+    payload: payload_from_text(&get::<String>(row, "payload")?)?,
+    status: get_enum(row, "status")?,
+    attempts: u32::try_from(get::<i64>(row, "attempts")?).unwrap_or(u32::MAX),
+    created_at: get_date(row, "created_at")?,
+    available_at: get_date(row, "available_at")?,
+    delivered_at: get_optional_date(row, "delivered_at")?,
+    last_error: get(row, "last_error")?,
+    delivered_to: sinks_from_text(&get::<String>(row, "delivered_to")?)?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+#[async_trait]
+impl OutboxRepositoryTrait for SqlDatabase {
+  #[tracing::instrument(name = "Enqueue Outbox Message", skip(self, message))]
+  async fn enqueue_message(&self, message: OutboxMessage) -> AppResult<InsertOneResult> {
+    let id = message.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!("INSERT INTO outbox ({COLUMNS}) VALUES ("));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(message.topic)
+      .push(", ")
+      .bind(payload_text(message.payload)?)
+      .push(", ")
+      .bind(enum_text(&message.status)?)
+      .push(", ")
+      .bind(i64::from(message.attempts))
+      .push(", ")
+      .bind(message.created_at)
+      .push(", ")
+      .bind(message.available_at)
+      .push(", ")
+      .bind(message.delivered_at)
+      .push(", ")
+      .bind(message.last_error)
+      .push(", ")
+      .bind(sinks_text(&message.delivered_to)?)
+      .push(")");
+    self.execute(&statement).await.map_err(sql_error)?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Claim Outbox Messages", skip(self))]
+  async fn claim_messages(&self, limit: usize, lease: Duration) -> AppResult<Vec<OutboxMessage>> {
+    let pending = enum_text(&OutboxStatus::Pending)?;
+    let mut messages = Vec::new();
+    // One message at a time like the Mongo backend, the conditions being checked again by the
+    // update so that a message claimed meanwhile by another relay is skipped.
+    while messages.len() < limit {
+      let now = DateTime::now();
+      let mut statement = Statement::new("UPDATE outbox SET available_at = ");
+      statement
+        .bind(later(now, lease))
+        .push(", attempts = attempts + 1 WHERE id = (SELECT id FROM outbox WHERE status = ")
+        .bind(pending.as_str())
+        .push(" AND available_at <= ")
+        .bind(now)
+        .push(" ORDER BY available_at LIMIT 1) AND status = ")
+        .bind(pending.as_str())
+        .push(" AND available_at <= ")
+        .bind(now)
+        .push(&format!(" RETURNING {COLUMNS}"));
+      match self.fetch_optional(&statement).await.map_err(sql_error)? {
+        Some(row) => messages.push(message_from_row(&row)?),
+        None => break,
+      }
+    }
+    Ok(messages)
+  }
+
+  #[tracing::instrument(name = "Renew Outbox Lease", skip(self, ids))]
+  async fn renew_lease(&self, ids: &[ObjectId], lease: Duration) -> AppResult<UpdateResult> {
+    if ids.is_empty() {
+      return Ok(updated(0));
+    }
+    let mut statement = Statement::new("UPDATE outbox SET available_at = ");
+    statement
+      .bind(later(DateTime::now(), lease))
+      .push(" WHERE status = ")
+      .bind(enum_text(&OutboxStatus::Pending)?)
+      .push(" AND id IN (");
+    for (i, id) in ids.iter().enumerate() {
+      if i > 0 {
+        statement.push(", ");
+      }
+      statement.bind(*id);
+    }
+    statement.push(")");
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Complete Outbox Message", skip(self))]
+  async fn complete_message(&self, id: &ObjectId) -> AppResult<UpdateResult> {
+    let mut statement = Statement::new("UPDATE outbox SET status = ");
+    statement
+      .bind(enum_text(&OutboxStatus::Delivered)?)
+      .push(", delivered_at = ")
+      .bind(DateTime::now())
+      .push(", last_error = NULL WHERE id = ")
+      .bind(*id);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Fail Outbox Message", skip(self))]
+  async fn fail_message(
+    &self,
+    id: &ObjectId,
+    error: &str,
+    delivered_to: &[String],
+    retry_at: Option<DateTime>,
+  ) -> AppResult<UpdateResult> {
+    let mut statement = Statement::new("UPDATE outbox SET ");
+    match retry_at {
+      Some(retry_at) => statement.push("available_at = ").bind(retry_at),
+      None => statement
+        .push("status = ")
+        .bind(enum_text(&OutboxStatus::Failed)?),
+    };
+    statement
+      .push(", last_error = ")
+      .bind(error)
+      .push(", delivered_to = ")
+      .bind(sinks_text(delivered_to)?)
+      .push(" WHERE id = ")
+      .bind(*id);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+}
@@ -0,0 +1,57 @@
+use super::{SqlDatabase, Statement, scoped, sql_error};
+use crate::{
+  results::updated,
+  tenant::current_tenant,
+  token::{model::RevokedToken, repository::TokenRepositoryTrait},
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{Bson, DateTime},
+  results::UpdateResult,
+};
+use utils::AppResult;
+
+#[async_trait]
+impl TokenRepositoryTrait for SqlDatabase {
+  #[tracing::instrument(name = "Revoke Token", skip(self, token))]
+  async fn revoke_token(&self, token: RevokedToken) -> AppResult<UpdateResult> {
+    // Nothing else removes expired tokens, unlike the TTL index of the Mongo backend.
+    let mut expired = Statement::new("DELETE FROM revoked_tokens WHERE expires_at <= ");
+    expired.bind(DateTime::now());
+    self.execute(&expired).await.map_err(sql_error)?;
+
+    let id = token.id.unwrap_or_default();
+    let mut statement = Statement::new(
+      "INSERT INTO revoked_tokens (id, tenant_id, jti, subject, revoked_at, expires_at) VALUES (",
+    );
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(token.jti)
+      .push(", ")
+      .bind(token.subject)
+      .push(", ")
+      .bind(token.revoked_at)
+      .push(", ")
+      .bind(token.expires_at)
+      .push(") ON CONFLICT (jti) DO NOTHING");
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    // Report it like the upsert of the Mongo backend does.
+    if result.rows_affected() == 0 {
+      return Ok(updated(1));
+    }
+    let mut result = updated(0);
+    result.upserted_id = Some(Bson::ObjectId(id));
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Is Token Revoked", skip(self, jti))]
+  async fn is_token_revoked(&self, jti: &str) -> AppResult<bool> {
+    let mut statement = Statement::new("SELECT COUNT(*) AS count FROM revoked_tokens WHERE jti = ");
+    statement.bind(jti);
+    scoped(&mut statement);
+    Ok(self.count(&statement).await? > 0)
+  }
+}
@@ -0,0 +1,395 @@
+use super::{
+  SortKey, SqlDatabase, Statement, duplicate_key, enum_text, get, get_enum, get_id,
+  get_optional_date, scoped, sql_error,
+};
+use crate::{
+  pagination::{Page, Pagination},
+  results::{deleted, inserted, updated},
+  tenant::current_tenant,
+  user::{
+    model::{User, UserFilter, UserRole},
+    repository::UserRepositoryTrait,
+    search::{UserSearchHit, UserSearchQuery, UserSearchResults, UserSearchTrait},
+  },
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use sqlx::any::AnyRow;
+use std::str::FromStr;
+use utils::{AppError, AppResult};
+
+const COLUMNS: &str = "id, tenant_id, name, email, password, role, created_at, updated_at, \
+                       last_login_at, version, deleted_at";
+
+/// Maps a failed write, reporting a taken email like the Mongo `email` index does.
+fn write_error(error: sqlx::Error) -> AppError {
+  duplicate_key(error, "email")
+}
+
+fn role_text(role: UserRole) -> AppResult<String> {
+  enum_text(&role)
+}
+
+fn user_from_row(row: &AnyRow) -> AppResult<User> {
+  Ok(User {
+    id: Some(get_id(row, "id")?),
+    name: get(row, "name")?,
+    email: get(row, "email")?,
+    password: get(row, "password")?,
+    role: get_enum(row, "role")?,
+    created_at: get_optional_date(row, "created_at")?,
+    updated_at: get_optional_date(row, "updated_at")?,
+    last_login_at: get_optional_date(row, "last_login_at")?,
+    version: get(row, "version")?,
+    deleted_at: get_optional_date(row, "deleted_at")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+/// Appends `WHERE` conditions restricting the statement to the active user `id`.
+fn active(statement: &mut Statement, id: &ObjectId) {
+  statement
+    .push(" WHERE id = ")
+    .bind(id.to_hex())
+    .push(" AND deleted_at IS NULL");
+  scoped(statement);
+}
+
+/// `LIKE` pattern matching the lowercase `value` between `before` and `after`.
+fn pattern(before: &str, value: &str, after: &str) -> String {
+  let mut pattern = String::from(before);
+  for c in value.to_lowercase().chars() {
+    if matches!(c, '\\' | '%' | '_') {
+      pattern.push('\\');
+    }
+    pattern.push(c);
+  }
+  pattern.push_str(after);
+  pattern
+}
+
+/// Case-insensitive `LIKE` pattern matching `value` anywhere.
+fn contains(value: &str) -> String {
+  pattern("%", value, "%")
+}
+
+/// Pushes the relevance of a user for `terms`, following `search::score` except that words are
+/// only told apart by spaces in names and by `.`, `@` and `-` in emails.
+fn push_score(statement: &mut Statement, terms: &[String]) {
+  statement.push("(0");
+  for term in terms {
+    statement
+      .push(" + CASE WHEN (' ' || LOWER(name) || ' ') LIKE ")
+      .bind(pattern("% ", term, " %"))
+      .push(" ESCAPE '\\' THEN 3 WHEN (' ' || LOWER(name)) LIKE ")
+      .bind(pattern("% ", term, "%"))
+      .push(" ESCAPE '\\' THEN 2")
+      .push(" WHEN ('.' || REPLACE(REPLACE(LOWER(email), '@', '.'), '-', '.')) LIKE ")
+      .bind(pattern("%.", term, "%"))
+      .push(" ESCAPE '\\' THEN 1 ELSE 0 END");
+  }
+  statement.push(")");
+}
+
+/// The active users of the current tenant with their relevance for `terms`, as a subquery.
+fn search_statement(select: &str, terms: &[String]) -> Statement {
+  let mut statement = Statement::new(&format!("{select} FROM (SELECT {COLUMNS}, "));
+  push_score(&mut statement, terms);
+  statement.push(" AS score FROM users WHERE deleted_at IS NULL");
+  scoped(&mut statement);
+  statement.push(") AS hits WHERE score > 0");
+  statement
+}
+
+fn filter_statement(statement: &mut Statement, filter: &UserFilter) -> AppResult<()> {
+  statement.push(if filter.deleted {
+    " WHERE deleted_at IS NOT NULL"
+  } else {
+    " WHERE deleted_at IS NULL"
+  });
+  if let Some(name) = &filter.name {
+    statement
+      .push(" AND LOWER(name) LIKE ")
+      .bind(contains(name))
+      .push(" ESCAPE '\\'");
+  }
+  if let Some(email) = &filter.email {
+    statement
+      .push(" AND LOWER(email) LIKE ")
+      .bind(contains(email))
+      .push(" ESCAPE '\\'");
+  }
+  if let Some(role) = filter.role {
+    statement.push(" AND role = ").bind(role_text(role)?);
+  }
+  if let Some(from) = filter.created_from {
+    statement.push(" AND created_at >= ").bind(from);
+  }
+  if let Some(to) = filter.created_to {
+    statement.push(" AND created_at <= ").bind(to);
+  }
+  scoped(statement);
+  Ok(())
+}
+
+/// The expression a listing is sorted by.
+fn sort_key(field: &str) -> AppResult<SortKey> {
+  match field {
+    "_id" => Ok(SortKey::Text("id")),
+    "name" => Ok(SortKey::Text("name")),
+    "email" => Ok(SortKey::Text("email")),
+    "role" => Ok(SortKey::Text("role")),
+    "created_at" => Ok(SortKey::Int("COALESCE(created_at, 0)")),
+    "updated_at" => Ok(SortKey::Int("COALESCE(updated_at, 0)")),
+    "last_login_at" => Ok(SortKey::Int("COALESCE(last_login_at, 0)")),
+    _ => Err(AppError::BadRequest(format!(
+      "cannot sort users by {field}"
+    ))),
+  }
+}
+
+impl SqlDatabase {
+  /// Runs an `UPDATE users SET <set>` on the active user `id` and returns how many rows changed.
+  async fn update_active(
+    &self,
+    mut statement: Statement,
+    id: &ObjectId,
+    expected_version: Option<i64>,
+  ) -> AppResult<UpdateResult> {
+    active(&mut statement, id);
+    if let Some(version) = expected_version {
+      statement.push(" AND version = ").bind(version);
+    }
+    let result = self.execute(&statement).await.map_err(write_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+}
+
+#[async_trait]
+impl UserRepositoryTrait for SqlDatabase {
+  #[tracing::instrument(name = "Create User", skip(self, name, email, password))]
+  async fn create_user(
+    &self,
+    name: &str,
+    email: &str,
+    password: &str,
+  ) -> AppResult<InsertOneResult> {
+    let id = ObjectId::new();
+    let now = DateTime::now();
+    let mut statement = Statement::new(
+      "INSERT INTO users (id, tenant_id, name, email, password, role, created_at, updated_at, \
+       version) VALUES (",
+    );
+    statement
+      .bind(id.to_hex())
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(name)
+      .push(", ")
+      .bind(email)
+      .push(", ")
+      .bind(password)
+      .push(", ")
+      .bind(role_text(UserRole::default())?)
+      .push(", ")
+      .bind(now)
+      .push(", ")
+      .bind(now)
+      .push(", 0)");
+    self.execute(&statement).await.map_err(write_error)?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Find Users", skip(self))]
+  async fn find_users(
+    &self,
+    filter: &UserFilter,
+    pagination: &Pagination,
+  ) -> AppResult<Page<User>> {
+    let mut statement = Statement::new(&format!("SELECT {COLUMNS} FROM users"));
+    filter_statement(&mut statement, filter)?;
+    self
+      .fetch_page(
+        statement,
+        sort_key(&pagination.sort)?,
+        pagination,
+        user_from_row,
+      )
+      .await
+  }
+
+  #[tracing::instrument(name = "Count Users", skip(self))]
+  async fn count_users(&self, filter: &UserFilter) -> AppResult<u64> {
+    let mut statement = Statement::new("SELECT COUNT(*) AS count FROM users");
+    filter_statement(&mut statement, filter)?;
+    self.count(&statement).await
+  }
+
+  #[tracing::instrument(name = "Get User By Id", skip(self, id))]
+  async fn get_user_by_id(&self, id: &str) -> AppResult<Option<User>> {
+    let id = ObjectId::from_str(id)?;
+    let mut statement = Statement::new(&format!("SELECT {COLUMNS} FROM users"));
+    active(&mut statement, &id);
+    let row = self.fetch_optional(&statement).await.map_err(sql_error)?;
+    row.as_ref().map(user_from_row).transpose()
+  }
+
+  #[tracing::instrument(name = "Get Users By Ids", skip(self, ids))]
+  async fn get_users_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<User>> {
+    if ids.is_empty() {
+      return Ok(Vec::new());
+    }
+    let mut statement = Statement::new(&format!("SELECT {COLUMNS} FROM users WHERE id IN ("));
+    for (i, id) in ids.iter().enumerate() {
+      if i > 0 {
+        statement.push(", ");
+      }
+      statement.bind(id.to_hex());
+    }
+    statement.push(") AND deleted_at IS NULL");
+    scoped(&mut statement);
+    self
+      .fetch_all(&statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(user_from_row)
+      .collect()
+  }
+
+  #[tracing::instrument(name = "Get User By Email", skip(self, email))]
+  async fn get_user_by_email(&self, email: &str) -> AppResult<Option<User>> {
+    let mut statement = Statement::new(&format!(
+      "SELECT {COLUMNS} FROM users WHERE LOWER(email) = LOWER("
+    ));
+    statement.bind(email).push(") AND deleted_at IS NULL");
+    scoped(&mut statement);
+    let row = self.fetch_optional(&statement).await.map_err(sql_error)?;
+    row.as_ref().map(user_from_row).transpose()
+  }
+
+  #[tracing::instrument(name = "Update User", skip(self, id, name, email))]
+  async fn update_user(
+    &self,
+    id: &str,
+    name: &str,
+    email: &str,
+    expected_version: Option<i64>,
+  ) -> AppResult<Option<User>> {
+    let id = ObjectId::from_str(id)?;
+    let mut statement = Statement::new("UPDATE users SET name = ");
+    statement
+      .bind(name)
+      .push(", email = ")
+      .bind(email)
+      .push(", updated_at = ")
+      .bind(DateTime::now())
+      .push(", version = version + 1");
+    active(&mut statement, &id);
+    if let Some(version) = expected_version {
+      statement.push(" AND version = ").bind(version);
+    }
+    statement.push(&format!(" RETURNING {COLUMNS}"));
+    let row = self.fetch_optional(&statement).await.map_err(write_error)?;
+    row.as_ref().map(user_from_row).transpose()
+  }
+
+  #[tracing::instrument(name = "Change Password", skip(self, id, password))]
+  async fn change_password(&self, id: &str, password: &str) -> AppResult<UpdateResult> {
+    let id = ObjectId::from_str(id)?;
+    let mut statement = Statement::new("UPDATE users SET password = ");
+    statement
+      .bind(password)
+      .push(", updated_at = ")
+      .bind(DateTime::now())
+      .push(", version = version + 1");
+    self.update_active(statement, &id, None).await
+  }
+
+  #[tracing::instrument(name = "Delete User", skip(self, id))]
+  async fn delete_user(&self, id: &str, expected_version: Option<i64>) -> AppResult<UpdateResult> {
+    let id = ObjectId::from_str(id)?;
+    let mut statement = Statement::new("UPDATE users SET deleted_at = ");
+    statement
+      .bind(DateTime::now())
+      .push(", version = version + 1");
+    self.update_active(statement, &id, expected_version).await
+  }
+
+  #[tracing::instrument(name = "Restore User", skip(self, id))]
+  async fn restore_user(&self, id: &str) -> AppResult<UpdateResult> {
+    let id = ObjectId::from_str(id)?;
+    let mut statement = Statement::new("UPDATE users SET deleted_at = NULL, updated_at = ");
+    statement
+      .bind(DateTime::now())
+      .push(", version = version + 1 WHERE id = ")
+      .bind(id.to_hex())
+      .push(" AND deleted_at IS NOT NULL");
+    scoped(&mut statement);
+    let result = self.execute(&statement).await.map_err(write_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Purge Deleted Users", skip(self))]
+  async fn purge_deleted_users(&self, deleted_before: DateTime) -> AppResult<DeleteResult> {
+    let mut statement = Statement::new("DELETE FROM users WHERE deleted_at < ");
+    statement.bind(deleted_before);
+    scoped(&mut statement);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(deleted(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Record Login", skip(self))]
+  async fn record_login(&self, id: &ObjectId) -> AppResult<UpdateResult> {
+    // Logging in does not change the user's data, so the version is left alone.
+    let mut statement = Statement::new("UPDATE users SET last_login_at = ");
+    statement.bind(DateTime::now());
+    self.update_active(statement, id, None).await
+  }
+}
+
+#[async_trait]
+impl UserSearchTrait for SqlDatabase {
+  #[tracing::instrument(name = "Search Users", skip(self))]
+  async fn search_users(&self, query: &UserSearchQuery) -> AppResult<UserSearchResults> {
+    let mut statement = search_statement("SELECT *", &query.terms);
+    // One extra row tells whether there are more hits.
+    statement
+      .push(" ORDER BY score DESC, id LIMIT ")
+      .bind(query.limit + 1)
+      .push(" OFFSET ")
+      .bind(i64::try_from(query.skip).unwrap_or(i64::MAX));
+    let mut hits = self
+      .fetch_all(&statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(|row| {
+        let score: i64 = get(row, "score")?;
+        Ok(UserSearchHit {
+          user: user_from_row(row)?,
+          #[allow(clippy::cast_precision_loss)]
+          score: score as f64,
+        })
+      })
+      .collect::<AppResult<Vec<UserSearchHit>>>()?;
+    let limit = usize::try_from(query.limit).unwrap_or_default();
+    let has_more = hits.len() > limit;
+    hits.truncate(limit);
+    let total = if query.include_total {
+      let statement = search_statement("SELECT COUNT(*) AS count", &query.terms);
+      Some(self.count(&statement).await?)
+    } else {
+      None
+    };
+    Ok(UserSearchResults {
+      hits,
+      has_more,
+      total,
+    })
+  }
+}
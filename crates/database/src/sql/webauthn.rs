@@ -0,0 +1,218 @@
+use super::{
+  SqlDatabase, Statement, duplicate_key, enum_text, get, get_date, get_enum, get_id,
+  get_optional_date, get_optional_id, scoped, sql_error,
+};
+use crate::{
+  results::{deleted, inserted, updated},
+  tenant::current_tenant,
+  webauthn::{
+    model::{WebauthnChallenge, WebauthnChallengeKind, WebauthnCredential},
+    repository::WebauthnRepositoryTrait,
+  },
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use sqlx::any::AnyRow;
+use utils::AppResult;
+
+const CREDENTIAL_COLUMNS: &str = "id, tenant_id, user_id, credential_id, public_key, sign_count, \
+                                  name, created_at, last_used_at";
+
+const CHALLENGE_COLUMNS: &str = "id, tenant_id, challenge, kind, user_id, expires_at";
+
+fn credential_from_row(row: &AnyRow) -> AppResult<WebauthnCredential> {
+  Ok(WebauthnCredential {
+    id: Some(get_id(row, "id")?),
+    user_id: get_id(row, "user_id")?,
+    credential_id: get(row, "credential_id")?,
+    public_key: get(row, "public_key")?,
+    sign_count: u32::try_from(get::<i64>(row, "sign_count")?).unwrap_or(u32::MAX),
+    name: get(row, "name")?,
+    created_at: get_date(row, "created_at")?,
+    last_used_at: get_optional_date(row, "last_used_at")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+fn challenge_from_row(row: &AnyRow) -> AppResult<WebauthnChallenge> {
+  Ok(WebauthnChallenge {
+    id: Some(get_id(row, "id")?),
+    challenge: get(row, "challenge")?,
+    kind: get_enum(row, "kind")?,
+    user_id: get_optional_id(row, "user_id")?,
+    expires_at: get_date(row, "expires_at")?,
+    tenant_id: get(row, "tenant_id")?,
+  })
+}
+
+/// Selects the credential `id` of `user_id`.
+fn credential_filter<'s>(
+  statement: &'s mut Statement,
+  id: &ObjectId,
+  user_id: &ObjectId,
+) -> &'s mut Statement {
+  statement
+    .push(" WHERE id = ")
+    .bind(*id)
+    .push(" AND user_id = ")
+    .bind(*user_id);
+  scoped(statement)
+}
+
+#[async_trait]
+impl WebauthnRepositoryTrait for SqlDatabase {
+  #[tracing::instrument(name = "Create Webauthn Credential", skip(self, credential))]
+  async fn create_credential(&self, credential: WebauthnCredential) -> AppResult<InsertOneResult> {
+    let id = credential.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!(
+      "INSERT INTO webauthn_credentials ({CREDENTIAL_COLUMNS}) VALUES ("
+    ));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(credential.user_id)
+      .push(", ")
+      .bind(credential.credential_id)
+      .push(", ")
+      .bind(credential.public_key)
+      .push(", ")
+      .bind(i64::from(credential.sign_count))
+      .push(", ")
+      .bind(credential.name)
+      .push(", ")
+      .bind(credential.created_at)
+      .push(", ")
+      .bind(credential.last_used_at)
+      .push(")");
+    self
+      .execute(&statement)
+      .await
+      .map_err(|e| duplicate_key(e, "credential_id"))?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Get Webauthn Credentials By User", skip(self))]
+  async fn get_credentials_by_user(
+    &self,
+    user_id: &ObjectId,
+  ) -> AppResult<Vec<WebauthnCredential>> {
+    let mut statement = Statement::new(&format!(
+      "SELECT {CREDENTIAL_COLUMNS} FROM webauthn_credentials WHERE user_id = "
+    ));
+    statement.bind(*user_id);
+    scoped(&mut statement);
+    self
+      .fetch_all(&statement)
+      .await
+      .map_err(sql_error)?
+      .iter()
+      .map(credential_from_row)
+      .collect()
+  }
+
+  #[tracing::instrument(name = "Get Webauthn Credential By Credential Id", skip(self))]
+  async fn get_credential_by_credential_id(
+    &self,
+    credential_id: &str,
+  ) -> AppResult<Option<WebauthnCredential>> {
+    let mut statement = Statement::new(&format!(
+      "SELECT {CREDENTIAL_COLUMNS} FROM webauthn_credentials WHERE credential_id = "
+    ));
+    statement.bind(credential_id);
+    scoped(&mut statement);
+    let row = self.fetch_optional(&statement).await.map_err(sql_error)?;
+    row.as_ref().map(credential_from_row).transpose()
+  }
+
+  #[tracing::instrument(name = "Update Webauthn Credential Usage", skip(self))]
+  async fn update_credential_usage(
+    &self,
+    id: &ObjectId,
+    sign_count: u32,
+  ) -> AppResult<UpdateResult> {
+    let mut statement = Statement::new("UPDATE webauthn_credentials SET sign_count = ");
+    statement
+      .bind(i64::from(sign_count))
+      .push(", last_used_at = ")
+      .bind(DateTime::now())
+      .push(" WHERE id = ")
+      .bind(*id);
+    scoped(&mut statement);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Rename Webauthn Credential", skip(self))]
+  async fn rename_credential(
+    &self,
+    id: &ObjectId,
+    user_id: &ObjectId,
+    name: &str,
+  ) -> AppResult<UpdateResult> {
+    let mut statement = Statement::new("UPDATE webauthn_credentials SET name = ");
+    statement.bind(name);
+    credential_filter(&mut statement, id, user_id);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(updated(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Delete Webauthn Credential", skip(self))]
+  async fn delete_credential(&self, id: &ObjectId, user_id: &ObjectId) -> AppResult<DeleteResult> {
+    let mut statement = Statement::new("DELETE FROM webauthn_credentials");
+    credential_filter(&mut statement, id, user_id);
+    let result = self.execute(&statement).await.map_err(sql_error)?;
+    Ok(deleted(result.rows_affected()))
+  }
+
+  #[tracing::instrument(name = "Create Webauthn Challenge", skip(self, challenge))]
+  async fn create_challenge(&self, challenge: WebauthnChallenge) -> AppResult<InsertOneResult> {
+    let id = challenge.id.unwrap_or_default();
+    let mut statement = Statement::new(&format!(
+      "INSERT INTO webauthn_challenges ({CHALLENGE_COLUMNS}) VALUES ("
+    ));
+    statement
+      .bind(id)
+      .push(", ")
+      .bind(current_tenant())
+      .push(", ")
+      .bind(challenge.challenge)
+      .push(", ")
+      .bind(enum_text(&challenge.kind)?)
+      .push(", ")
+      .bind(challenge.user_id)
+      .push(", ")
+      .bind(challenge.expires_at)
+      .push(")");
+    self.execute(&statement).await.map_err(sql_error)?;
+    Ok(inserted(id))
+  }
+
+  #[tracing::instrument(name = "Take Webauthn Challenge", skip(self, challenge))]
+  async fn take_challenge(
+    &self,
+    challenge: &str,
+    kind: WebauthnChallengeKind,
+  ) -> AppResult<Option<WebauthnChallenge>> {
+    let now = DateTime::now();
+    // Nothing else removes expired challenges, unlike the TTL index of the Mongo backend.
+    let mut expired = Statement::new("DELETE FROM webauthn_challenges WHERE expires_at <= ");
+    expired.bind(now);
+    self.execute(&expired).await.map_err(sql_error)?;
+
+    let mut statement = Statement::new("DELETE FROM webauthn_challenges WHERE challenge = ");
+    statement
+      .bind(challenge)
+      .push(" AND kind = ")
+      .bind(enum_text(&kind)?)
+      .push(" AND expires_at > ")
+      .bind(now);
+    scoped(&mut statement).push(&format!(" RETURNING {CHALLENGE_COLUMNS}"));
+    let row = self.fetch_optional(&statement).await.map_err(sql_error)?;
+    row.as_ref().map(challenge_from_row).transpose()
+  }
+}
@@ -0,0 +1,130 @@
+use super::{ByteStream, FileStorageTrait};
+use crate::Database;
+use async_trait::async_trait;
+use bytes::Bytes;
+use mongodb::{
+  Collection,
+  bson::{Bson, Document, doc},
+  error::{ErrorKind, GridFsErrorKind},
+  gridfs::GridFsBucket,
+  options::GridFsBucketOptions,
+};
+use std::ops::Range;
+use tokio::io::AsyncWriteExt;
+use tokio_stream::StreamExt;
+use tokio_util::compat::FuturesAsyncWriteCompatExt;
+use utils::{AppError, AppResult};
+
+/// Keeps each file in a GridFS bucket of the current tenant's database, with `key` as its id.
+///
+/// Ranges are read from the chunks holding them rather than from the start of the file.
+pub struct GridFsStorage {
+  database: Database,
+  bucket: String,
+}
+
+impl GridFsStorage {
+  /// Stores files in the `bucket.files` and `bucket.chunks` collections.
+  pub fn new(database: Database, bucket: impl Into<String>) -> Self {
+    Self {
+      database,
+      bucket: bucket.into(),
+    }
+  }
+
+  fn bucket(&self) -> GridFsBucket {
+    let options = GridFsBucketOptions::builder()
+      .bucket_name(self.bucket.clone())
+      .build();
+    self.database.database().gridfs_bucket(options)
+  }
+
+  fn collection(&self, suffix: &str) -> Collection<Document> {
+    self
+      .database
+      .database()
+      .collection(&format!("{}.{suffix}", self.bucket))
+  }
+}
+
+/// Reads a length stored as any integer type, as drivers differ.
+fn integer(document: &Document, key: &str) -> AppResult<u64> {
+  let value = match document.get(key) {
+    Some(Bson::Int32(value)) => i64::from(*value),
+    Some(Bson::Int64(value)) => *value,
+    Some(Bson::Double(value)) => *value as i64,
+    _ => {
+      return Err(AppError::InternalServerErrorWithContext(format!(
+        "gridfs document has no {key}"
+      )));
+    }
+  };
+  u64::try_from(value)
+    .map_err(|_| AppError::InternalServerErrorWithContext(format!("gridfs {key} is negative")))
+}
+
+#[async_trait]
+impl FileStorageTrait for GridFsStorage {
+  async fn put(&self, key: &str, content: Bytes, content_type: &str) -> AppResult<()> {
+    let upload = self
+      .bucket()
+      .open_upload_stream(key)
+      .id(Bson::String(key.to_string()))
+      .metadata(doc! { "contentType": content_type })
+      .await?;
+    // The driver removes the chunks written so far if the upload is dropped before closing.
+    let mut upload = upload.compat_write();
+    upload.write_all(&content).await?;
+    upload.shutdown().await?;
+    Ok(())
+  }
+
+  async fn get(&self, key: &str, range: Option<Range<u64>>) -> AppResult<ByteStream> {
+    let file = self
+      .collection("files")
+      .find_one(doc! { "_id": key })
+      .await?
+      .ok_or_else(|| AppError::NotFound("file content not found".to_string()))?;
+    let chunk_size = integer(&file, "chunkSize")?;
+    let range = match range {
+      Some(range) => range,
+      None => 0..integer(&file, "length")?,
+    };
+    if range.is_empty() {
+      return Ok(Box::pin(tokio_stream::empty()));
+    }
+    let first = range.start / chunk_size;
+    let last = (range.end - 1) / chunk_size;
+    let chunks = self
+      .collection("chunks")
+      .find(doc! {
+        "files_id": key,
+        "n": { "$gte": first as i64, "$lte": last as i64 },
+      })
+      .sort(doc! { "n": 1 })
+      .await?;
+    let stream = chunks.map(move |chunk| {
+      let chunk = chunk?;
+      let offset = integer(&chunk, "n")? * chunk_size;
+      let data = chunk.get_binary_generic("data")?;
+      let end = (range.end - offset).min(data.len() as u64);
+      let start = range.start.saturating_sub(offset).min(end);
+      #[allow(clippy::cast_possible_truncation)]
+      Ok(Bytes::copy_from_slice(&data[start as usize..end as usize]))
+    });
+    Ok(Box::pin(stream))
+  }
+
+  async fn delete(&self, key: &str) -> AppResult<()> {
+    if let Err(e) = self.bucket().delete(Bson::String(key.to_string())).await {
+      let missing = matches!(
+        *e.kind,
+        ErrorKind::GridFs(GridFsErrorKind::FileNotFound { .. })
+      );
+      if !missing {
+        return Err(e.into());
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,78 @@
+use super::{ByteStream, FileStorageTrait};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::{
+  io::{ErrorKind, SeekFrom},
+  ops::Range,
+  path::{Component, Path, PathBuf},
+};
+use tokio::{
+  fs::{self, File},
+  io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_stream::StreamExt;
+use tokio_util::io::ReaderStream;
+use utils::{AppError, AppResult};
+
+/// Keeps each file at `root/key`, see `storage.local.path`.
+pub struct LocalStorage {
+  root: PathBuf,
+}
+
+impl LocalStorage {
+  pub fn new(root: impl Into<PathBuf>) -> Self {
+    Self { root: root.into() }
+  }
+
+  /// Where `key` is stored. Keys are relative paths that cannot leave the root.
+  fn path(&self, key: &str) -> AppResult<PathBuf> {
+    let relative = Path::new(key);
+    let valid = relative
+      .components()
+      .all(|component| matches!(component, Component::Normal(_)));
+    if key.is_empty() || !valid {
+      return Err(AppError::BadRequest(format!("invalid storage key {key}")));
+    }
+    Ok(self.root.join(relative))
+  }
+}
+
+#[async_trait]
+impl FileStorageTrait for LocalStorage {
+  async fn put(&self, key: &str, content: Bytes, _content_type: &str) -> AppResult<()> {
+    let path = self.path(key)?;
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).await?;
+    }
+    // Written aside then renamed, so that a failed write leaves no truncated file behind.
+    let partial = path.with_extension("partial");
+    fs::write(&partial, &content).await?;
+    fs::rename(&partial, &path).await?;
+    Ok(())
+  }
+
+  async fn get(&self, key: &str, range: Option<Range<u64>>) -> AppResult<ByteStream> {
+    let mut file = File::open(self.path(key)?).await.map_err(|e| {
+      if e.kind() == ErrorKind::NotFound {
+        AppError::NotFound("file content not found".to_string())
+      } else {
+        e.into()
+      }
+    })?;
+    let (start, length) = range.map_or((0, u64::MAX), |range| {
+      (range.start, range.end - range.start)
+    });
+    if start > 0 {
+      file.seek(SeekFrom::Start(start)).await?;
+    }
+    let stream = ReaderStream::new(file.take(length)).map(|chunk| chunk.map_err(AppError::from));
+    Ok(Box::pin(stream))
+  }
+
+  async fn delete(&self, key: &str) -> AppResult<()> {
+    match fs::remove_file(self.path(key)?).await {
+      Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+      _ => Ok(()),
+    }
+  }
+}
@@ -0,0 +1,41 @@
+//! Content of uploaded files, kept apart from their metadata, see `file::model::StoredFile`.
+//!
+//! `storage.backend` selects the implementation of `FileStorageTrait`: a directory of the
+//! server (`LocalStorage`), GridFS buckets of the Mongo database (`GridFsStorage`) or a bucket
+//! of S3 or of a compatible service such as MinIO (`S3Storage`). Another store can be used by
+//! implementing the trait and handing it to `Services::new`.
+mod gridfs;
+mod local;
+mod s3;
+
+pub use gridfs::GridFsStorage;
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::{ops::Range, pin::Pin, sync::Arc};
+use tokio_stream::Stream;
+use utils::AppResult;
+
+/// The content of a file, read as it is sent.
+pub type ByteStream = Pin<Box<dyn Stream<Item = AppResult<Bytes>> + Send>>;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynFileStorage = Arc<dyn FileStorageTrait>;
+
+#[async_trait]
+pub trait FileStorageTrait: Send + Sync {
+  /// Stores `content` under `key`. Keys are never reused.
+  async fn put(&self, key: &str, content: Bytes, content_type: &str) -> AppResult<()>;
+
+  /// Streams the content stored under `key`, or the bytes of `range` only, which must lie
+  /// within it.
+  ///
+  /// The stream does not depend on the current tenant, so that it can be read once the request
+  /// handler has returned.
+  async fn get(&self, key: &str, range: Option<Range<u64>>) -> AppResult<ByteStream>;
+
+  /// Deletes the content stored under `key`, succeeding when there is none.
+  async fn delete(&self, key: &str) -> AppResult<()>;
+}
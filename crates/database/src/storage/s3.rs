@@ -0,0 +1,202 @@
+use super::{ByteStream, FileStorageTrait};
+use async_trait::async_trait;
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use mongodb::bson::DateTime;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode, Url, header::RANGE};
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, ops::Range, time::Duration};
+use tokio_stream::StreamExt;
+use utils::{AppError, AppResult, config::storage_config::S3StorageConfig};
+
+/// The only service requests are signed for.
+const SERVICE: &str = "s3";
+
+/// Keeps each file as an object of an S3 bucket, named `key`.
+///
+/// Requests are signed with AWS Signature Version 4, which S3 and the compatible services such
+/// as MinIO accept.
+pub struct S3Storage {
+  client: Client,
+  endpoint: Url,
+  bucket: String,
+  region: String,
+  access_key: String,
+  secret_key: String,
+  path_style: bool,
+}
+
+impl S3Storage {
+  /// # Errors
+  ///
+  /// Returns an error if `config.endpoint` is not a URL.
+  pub fn new(config: &S3StorageConfig) -> AppResult<Self> {
+    let endpoint = Url::parse(&config.endpoint)
+      .map_err(|e| AppError::BadRequest(format!("invalid s3 endpoint: {e}")))?;
+    let client = Client::builder()
+      .connect_timeout(Duration::from_secs(10))
+      .build()
+      .map_err(|e| AppError::InternalServerErrorWithContext(e.to_string()))?;
+    Ok(Self {
+      client,
+      endpoint,
+      bucket: config.bucket.clone(),
+      region: config.region.clone(),
+      access_key: config.access_key.clone(),
+      secret_key: config.secret_key.clone(),
+      path_style: config.path_style,
+    })
+  }
+
+  /// The URL of the object named `key`.
+  fn url(&self, key: &str) -> AppResult<Url> {
+    let mut url = self.endpoint.clone();
+    let base = url.path().trim_end_matches('/').to_string();
+    if self.path_style {
+      url.set_path(&format!("{base}/{}/{}", encode(&self.bucket), encode(key)));
+    } else {
+      let host = url.host_str().unwrap_or_default().to_string();
+      url
+        .set_host(Some(&format!("{}.{host}", self.bucket)))
+        .map_err(|e| AppError::BadRequest(format!("invalid s3 bucket: {e}")))?;
+      url.set_path(&format!("{base}/{}", encode(key)));
+    }
+    Ok(url)
+  }
+
+  /// A request for the object named `key`, signed for a payload hashing to `payload_sha256`.
+  fn request(&self, method: Method, key: &str, payload_sha256: &str) -> AppResult<RequestBuilder> {
+    let url = self.url(key)?;
+    let date = amz_date(DateTime::now())?;
+    let authorization = self.authorization(&method, &url, payload_sha256, &date);
+    Ok(
+      self
+        .client
+        .request(method, url)
+        .header("x-amz-content-sha256", payload_sha256)
+        .header("x-amz-date", date)
+        .header("authorization", authorization),
+    )
+  }
+
+  /// The `Authorization` header of a request, signing its host, payload hash and date.
+  fn authorization(&self, method: &Method, url: &Url, payload_sha256: &str, date: &str) -> String {
+    let host = match url.port() {
+      Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+      None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+      "{method}\n{}\n{}\nhost:{host}\nx-amz-content-sha256:{payload_sha256}\nx-amz-date:{date}\n\n{signed_headers}\n{payload_sha256}",
+      url.path(),
+      url.query().unwrap_or_default(),
+    );
+    let day = &date[..8];
+    let scope = format!("{day}/{}/{SERVICE}/aws4_request", self.region);
+    let string_to_sign = format!(
+      "AWS4-HMAC-SHA256\n{date}\n{scope}\n{}",
+      hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = [day, &self.region, SERVICE, "aws4_request"].iter().fold(
+      format!("AWS4{}", self.secret_key).into_bytes(),
+      |key, part| hmac(&key, part),
+    );
+    format!(
+      "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+      self.access_key,
+      hex(&hmac(&key, &string_to_sign))
+    )
+  }
+}
+
+/// Percent-encodes `path` as in the canonical requests of Signature Version 4, keeping `/`.
+fn encode(path: &str) -> String {
+  path.bytes().fold(String::new(), |mut encoded, byte| {
+    if byte.is_ascii_alphanumeric() || b"-_.~/".contains(&byte) {
+      encoded.push(char::from(byte));
+    } else {
+      let _ = write!(encoded, "%{byte:02X}");
+    }
+    encoded
+  })
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().fold(String::new(), |mut hex, byte| {
+    let _ = write!(hex, "{byte:02x}");
+    hex
+  })
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+  let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+  mac.update(data.as_bytes());
+  mac.finalize().into_bytes().to_vec()
+}
+
+/// `date` in the basic ISO 8601 format of `X-Amz-Date`, e.g. `20240131T235959Z`.
+fn amz_date(date: DateTime) -> AppResult<String> {
+  let rfc3339 = date
+    .try_to_rfc3339_string()
+    .map_err(|e| AppError::InternalServerErrorWithContext(e.to_string()))?;
+  let seconds: String = rfc3339
+    .chars()
+    .take(19)
+    .filter(|c| c.is_ascii_digit() || *c == 'T')
+    .collect();
+  Ok(format!("{seconds}Z"))
+}
+
+/// Fails unless `response` is a success, keeping the start of the error S3 answered with.
+async fn check(response: reqwest::Result<Response>) -> AppResult<Response> {
+  let response =
+    response.map_err(|e| AppError::InternalServerErrorWithContext(format!("s3 failed: {e}")))?;
+  let status = response.status();
+  if status.is_success() {
+    return Ok(response);
+  }
+  if status == StatusCode::NOT_FOUND {
+    return Err(AppError::NotFound("file content not found".to_string()));
+  }
+  let body = response.text().await.unwrap_or_default();
+  Err(AppError::InternalServerErrorWithContext(format!(
+    "s3 answered {status}: {}",
+    body.chars().take(200).collect::<String>()
+  )))
+}
+
+#[async_trait]
+impl FileStorageTrait for S3Storage {
+  async fn put(&self, key: &str, content: Bytes, content_type: &str) -> AppResult<()> {
+    let payload_sha256 = hex(&Sha256::digest(&content));
+    let request = self
+      .request(Method::PUT, key, &payload_sha256)?
+      .header("content-type", content_type)
+      .body(content);
+    check(request.send().await).await?;
+    Ok(())
+  }
+
+  async fn get(&self, key: &str, range: Option<Range<u64>>) -> AppResult<ByteStream> {
+    let mut request = self.request(Method::GET, key, &hex(&Sha256::digest([])))?;
+    if let Some(range) = range {
+      if range.is_empty() {
+        return Ok(Box::pin(tokio_stream::empty()));
+      }
+      request = request.header(RANGE, format!("bytes={}-{}", range.start, range.end - 1));
+    }
+    let response = check(request.send().await).await?;
+    let stream = response.bytes_stream().map(|chunk| {
+      chunk.map_err(|e| AppError::InternalServerErrorWithContext(format!("s3 failed: {e}")))
+    });
+    Ok(Box::pin(stream))
+  }
+
+  async fn delete(&self, key: &str) -> AppResult<()> {
+    let request = self.request(Method::DELETE, key, &hex(&Sha256::digest([])))?;
+    match check(request.send().await).await {
+      Err(AppError::NotFound(_)) | Ok(_) => Ok(()),
+      Err(e) => Err(e),
+    }
+  }
+}
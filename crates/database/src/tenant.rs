@@ -0,0 +1,38 @@
+use mongodb::bson::Document;
+
+pub use utils::tenant::current as current_tenant;
+
+/// Restricts `filter` to documents of the current tenant.
+///
+/// Every repository query goes through this, so that a request can never read or modify
+/// another tenant's documents. Without a current tenant the filter is returned unchanged.
+pub fn scoped(mut filter: Document) -> Document {
+  if let Some(tenant) = current_tenant() {
+    filter.insert("tenant_id", tenant);
+  }
+  filter
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mongodb::bson::doc;
+  use utils::tenant;
+
+  #[tokio::test]
+  async fn filters_are_scoped_to_the_current_tenant() {
+    assert_eq!(
+      scoped(doc! { "email": "ada@example.com" }),
+      doc! { "email": "ada@example.com" }
+    );
+    let filter = tenant::scope("acme".to_string(), async {
+      // A tenant in the filter itself cannot widen the scope.
+      scoped(doc! { "email": "ada@example.com", "tenant_id": "globex" })
+    })
+    .await;
+    assert_eq!(
+      filter,
+      doc! { "email": "ada@example.com", "tenant_id": "acme" }
+    );
+  }
+}
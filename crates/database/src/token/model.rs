@@ -0,0 +1,18 @@
+use mongodb::bson::{DateTime, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+/// A token revoked before its expiry. It can be removed once `expires_at` has passed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevokedToken {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub jti: String,
+  pub subject: String,
+  pub revoked_at: DateTime,
+  pub expires_at: DateTime,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+crate::impl_model!(RevokedToken, "RevokedToken");
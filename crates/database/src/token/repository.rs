@@ -0,0 +1,48 @@
+use crate::{
+  Database,
+  tenant::{current_tenant, scoped},
+  token::model::RevokedToken,
+  transaction::in_session,
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{doc, to_document},
+  results::UpdateResult,
+};
+use std::sync::Arc;
+use utils::AppResult;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynTokenRepository = Arc<dyn TokenRepositoryTrait>;
+
+#[async_trait]
+pub trait TokenRepositoryTrait: Send + Sync {
+  /// Records `token` as revoked. Revoking the same `jti` twice is a no-op.
+  async fn revoke_token(&self, token: RevokedToken) -> AppResult<UpdateResult>;
+
+  async fn is_token_revoked(&self, jti: &str) -> AppResult<bool>;
+}
+
+#[async_trait]
+impl TokenRepositoryTrait for Database {
+  #[tracing::instrument(name = "Revoke Token", skip(self, token))]
+  async fn revoke_token(&self, mut token: RevokedToken) -> AppResult<UpdateResult> {
+    token.tenant_id = current_tenant();
+    let filter = scoped(doc! {"jti": &token.jti});
+    let update = doc! { "$setOnInsert": to_document(&token)? };
+    let result = in_session!(
+      self
+        .revoked_token_col()
+        .update_one(filter, update)
+        .upsert(true)
+    )?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Is Token Revoked", skip(self, jti))]
+  async fn is_token_revoked(&self, jti: &str) -> AppResult<bool> {
+    let filter = scoped(doc! {"jti": jti});
+    let count = in_session!(self.revoked_token_col().count_documents(filter).limit(1))?;
+    Ok(count > 0)
+  }
+}
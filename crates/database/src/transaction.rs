@@ -0,0 +1,307 @@
+//! Units of work spanning several repository calls.
+//!
+//! A unit of work runs a closure inside a Mongo transaction. Like the current tenant, the session
+//! is kept in a task-local so that repositories join the transaction without taking it as an
+//! argument: their writes and single document reads go through [`in_session!`]. Listings read
+//! through a cursor and stay outside of it. Whatever must wait for the commit, such as evicting
+//! a cached user, is registered with [`after_commit`].
+//!
+//! Transactions need a replica set. Unless `db.transactions` is set, units of work run their
+//! closure as is and are best effort: a failure midway keeps the writes made before it.
+use crate::Database;
+use async_trait::async_trait;
+use mongodb::{
+  ClientSession,
+  error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT},
+};
+use std::{
+  pin::Pin,
+  sync::{Arc, PoisonError},
+  time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::warn;
+use utils::{AppError, AppResult, config};
+
+/// How long a transaction is retried after transient errors, as recommended by the drivers spec.
+const RETRY_TIMEOUT: Duration = Duration::from_secs(120);
+
+tokio::task_local! {
+  static SESSION: Arc<Mutex<ClientSession>>;
+  /// What to run once the unit of work the current task runs in has committed.
+  static AFTER_COMMIT: Arc<std::sync::Mutex<Vec<BoxFuture<'static, ()>>>>;
+}
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynUnitOfWork = Arc<dyn UnitOfWorkTrait>;
+
+#[async_trait]
+pub trait UnitOfWorkTrait: Send + Sync {
+  /// Runs `work` in a transaction, committing when it succeeds and aborting when it fails.
+  ///
+  /// `work` is called again when the transaction fails with a transient error. A unit of work
+  /// started inside another one joins the outer transaction.
+  async fn run<'a, 'b>(
+    &'b self,
+    work: &'b mut (dyn FnMut() -> BoxFuture<'a, AppResult<()>> + Send + 'b),
+  ) -> AppResult<()>;
+}
+
+/// Runs `work` in a unit of work and returns its output, see [`UnitOfWorkTrait::run`].
+///
+/// The hooks registered with [`after_commit`] run once the unit of work has committed, and are
+/// dropped when it fails. A unit of work started inside another one leaves them to the outer one.
+pub async fn transaction<'a, T, F, Fut>(
+  unit_of_work: &dyn UnitOfWorkTrait,
+  mut work: F,
+) -> AppResult<T>
+where
+  T: Send + 'a,
+  F: FnMut() -> Fut + Send,
+  Fut: Future<Output = AppResult<T>> + Send + 'a,
+{
+  let output = Arc::new(std::sync::Mutex::new(None));
+  let mut work = || -> BoxFuture<'a, AppResult<()>> {
+    let future = work();
+    let output = output.clone();
+    Box::pin(async move {
+      let value = future.await?;
+      if let Ok(mut output) = output.lock() {
+        *output = Some(value);
+      }
+      Ok(())
+    })
+  };
+  if in_unit_of_work() {
+    unit_of_work.run(&mut work).await?;
+  } else {
+    let hooks = Arc::new(std::sync::Mutex::new(Vec::new()));
+    AFTER_COMMIT
+      .scope(hooks.clone(), unit_of_work.run(&mut work))
+      .await?;
+    let hooks = std::mem::take(&mut *hooks.lock().unwrap_or_else(PoisonError::into_inner));
+    for hook in hooks {
+      hook.await;
+    }
+  }
+  output
+    .lock()
+    .ok()
+    .and_then(|mut output| output.take())
+    .ok_or_else(|| AppError::InternalServerErrorWithContext("unit of work has no output".into()))
+}
+
+/// Whether the current task runs in a unit of work, on any backend.
+pub fn in_unit_of_work() -> bool {
+  AFTER_COMMIT.try_with(|_| ()).is_ok()
+}
+
+/// Runs `hook` once the current unit of work has committed, or right away outside of one.
+///
+/// Hooks registered by an attempt that is retried run as well, so they must be harmless when
+/// nothing was written, such as invalidating a cache.
+pub async fn after_commit(hook: impl Future<Output = ()> + Send + 'static) {
+  match AFTER_COMMIT.try_with(Clone::clone) {
+    Ok(hooks) => hooks
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .push(Box::pin(hook)),
+    Err(_) => hook.await,
+  }
+}
+
+/// The session of the unit of work the current task runs in.
+pub fn current_session() -> Option<Arc<Mutex<ClientSession>>> {
+  SESSION.try_with(Clone::clone).ok()
+}
+
+/// Awaits a driver action, within the current unit of work's transaction if there is one.
+macro_rules! in_session {
+  ($action:expr) => {
+    match $crate::transaction::current_session() {
+      Some(session) => $action.session(&mut *session.lock().await).await,
+      None => $action.await,
+    }
+  };
+}
+pub(crate) use in_session;
+
+fn is_transient(error: &AppError) -> bool {
+  matches!(error, AppError::MongoError(e) if e.contains_label(TRANSIENT_TRANSACTION_ERROR))
+}
+
+/// Commits the session's transaction, retrying while its outcome is unknown.
+async fn commit(session: &Mutex<ClientSession>, started: Instant) -> AppResult<()> {
+  loop {
+    match session.lock().await.commit_transaction().await {
+      Ok(()) => return Ok(()),
+      Err(e)
+        if e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT)
+          && started.elapsed() < RETRY_TIMEOUT =>
+      {
+        warn!("retrying transaction commit: {e}");
+      }
+      Err(e) => return Err(e.into()),
+    }
+  }
+}
+
+#[async_trait]
+impl UnitOfWorkTrait for Database {
+  async fn run<'a, 'b>(
+    &'b self,
+    work: &'b mut (dyn FnMut() -> BoxFuture<'a, AppResult<()>> + Send + 'b),
+  ) -> AppResult<()> {
+    if !config::get().db.transactions || current_session().is_some() {
+      return work().await;
+    }
+
+    let session = Arc::new(Mutex::new(self.client.start_session().await?));
+    let started = Instant::now();
+    loop {
+      session.lock().await.start_transaction().await?;
+      let error = match SESSION.scope(session.clone(), work()).await {
+        Ok(()) => match commit(&session, started).await {
+          Ok(()) => return Ok(()),
+          Err(e) => e,
+        },
+        Err(e) => {
+          // The server may have aborted the transaction already.
+          if let Err(abort) = session.lock().await.abort_transaction().await {
+            warn!("failed to abort transaction: {abort}");
+          }
+          e
+        }
+      };
+      if !is_transient(&error) || started.elapsed() >= RETRY_TIMEOUT {
+        return Err(error);
+      }
+      warn!("retrying transaction: {error}");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::MemoryDatabase;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  /// Counts the hooks that ran.
+  fn hook(ran: &Arc<AtomicUsize>) -> impl Future<Output = ()> + Send + 'static {
+    let ran = ran.clone();
+    async move {
+      ran.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  /// Runs `work` a second time when its first attempt fails, like a transient error would.
+  struct RetryOnce;
+
+  #[async_trait]
+  impl UnitOfWorkTrait for RetryOnce {
+    async fn run<'a, 'b>(
+      &'b self,
+      work: &'b mut (dyn FnMut() -> BoxFuture<'a, AppResult<()>> + Send + 'b),
+    ) -> AppResult<()> {
+      match work().await {
+        Ok(()) => Ok(()),
+        Err(_) => work().await,
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn hooks_run_once_the_unit_of_work_committed() {
+    let db = MemoryDatabase::new();
+    let ran = Arc::new(AtomicUsize::new(0));
+    assert!(!in_unit_of_work());
+
+    let output = transaction(&db, || async {
+      assert!(in_unit_of_work());
+      after_commit(hook(&ran)).await;
+      assert_eq!(ran.load(Ordering::SeqCst), 0);
+      Ok("done")
+    })
+    .await
+    .unwrap();
+    assert_eq!(output, "done");
+    assert_eq!(ran.load(Ordering::SeqCst), 1);
+
+    // Outside of a unit of work, hooks run right away.
+    after_commit(hook(&ran)).await;
+    assert_eq!(ran.load(Ordering::SeqCst), 2);
+  }
+
+  #[tokio::test]
+  async fn failed_units_of_work_drop_their_hooks() {
+    let db = MemoryDatabase::new();
+    let ran = Arc::new(AtomicUsize::new(0));
+    let result: AppResult<()> = transaction(&db, || async {
+      after_commit(hook(&ran)).await;
+      Err(AppError::BadRequest("abort".to_string()))
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(ran.load(Ordering::SeqCst), 0);
+  }
+
+  #[tokio::test]
+  async fn nested_units_of_work_leave_hooks_to_the_outer_one() {
+    let db = MemoryDatabase::new();
+    let ran = Arc::new(AtomicUsize::new(0));
+    let result: AppResult<()> = transaction(&db, || async {
+      transaction(&db, || async {
+        after_commit(hook(&ran)).await;
+        Ok(())
+      })
+      .await?;
+      assert_eq!(ran.load(Ordering::SeqCst), 0);
+      Err(AppError::BadRequest("abort".to_string()))
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(ran.load(Ordering::SeqCst), 0);
+
+    transaction(&db, || async {
+      transaction(&db, || async {
+        after_commit(hook(&ran)).await;
+        Ok(())
+      })
+      .await
+    })
+    .await
+    .unwrap();
+    assert_eq!(ran.load(Ordering::SeqCst), 1);
+  }
+
+  #[tokio::test]
+  async fn retried_units_of_work_return_the_last_attempt() {
+    let attempts = AtomicUsize::new(0);
+    let output = transaction(&RetryOnce, || async {
+      match attempts.fetch_add(1, Ordering::SeqCst) {
+        0 => Err(AppError::InternalServerError),
+        attempt => Ok(attempt),
+      }
+    })
+    .await
+    .unwrap();
+    assert_eq!(output, 1);
+    assert!(!is_transient(&AppError::InternalServerError));
+  }
+
+  #[tokio::test]
+  async fn without_transactions_mongo_runs_the_work_as_is() {
+    // `db.transactions` is off in the template, so no session is started on the server.
+    let db = Database::unreachable().await;
+    let output = transaction(&db, || async {
+      assert!(current_session().is_none());
+      Ok(42)
+    })
+    .await
+    .unwrap();
+    assert_eq!(output, 42);
+  }
+}
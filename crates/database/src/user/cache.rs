@@ -0,0 +1,423 @@
+//! Caching of user lookups, wrapping any `DynUserRepository`.
+//!
+//! `CachedUserRepository` answers `get_user_by_id` and `get_user_by_email` from a
+//! `DynUserCache`, and invalidates a user on every change made through it, once the unit of work
+//! making it has committed. Hits, misses and invalidations are counted in the
+//! `user_cache_*_total` metrics, labelled with the lookup.
+use super::{
+  model::{User, UserFilter},
+  repository::{DynUserRepository, UserRepositoryTrait},
+};
+use crate::{
+  pagination::{Page, Pagination},
+  tenant::current_tenant,
+  transaction::{after_commit, in_unit_of_work},
+};
+use async_trait::async_trait;
+use lru::LruCache;
+use metrics::counter;
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use std::{
+  collections::HashMap,
+  num::NonZeroUsize,
+  sync::{
+    Arc, Mutex, MutexGuard, PoisonError,
+    atomic::{AtomicU64, Ordering},
+  },
+  time::{Duration, Instant},
+};
+use utils::AppResult;
+
+pub type DynUserCache = Arc<dyn UserCacheTrait>;
+
+/// Where `CachedUserRepository` keeps users. Entries belong to the current tenant.
+#[async_trait]
+pub trait UserCacheTrait: Send + Sync {
+  async fn get_by_id(&self, id: &str) -> Option<User>;
+
+  /// Looks a user up by email, ignoring case like the repositories do.
+  async fn get_by_email(&self, email: &str) -> Option<User>;
+
+  async fn put(&self, user: User);
+
+  /// Forgets the user with id `id`, however it was looked up.
+  async fn invalidate(&self, id: &str);
+}
+
+/// A tenant and an id or an email.
+type Key = (Option<String>, String);
+
+struct Entry {
+  user: User,
+  email: String,
+  expires_at: Instant,
+}
+
+struct State {
+  entries: LruCache<Key, Entry>,
+  /// Ids of the cached users by email, kept in sync with `entries`.
+  emails: HashMap<Key, String>,
+}
+
+impl State {
+  fn remove(&mut self, key: &Key) {
+    if let Some(entry) = self.entries.pop(key) {
+      self.emails.remove(&(key.0.clone(), entry.email));
+    }
+  }
+}
+
+/// In-process least recently used cache whose entries expire after a fixed time.
+pub struct LruUserCache {
+  ttl: Duration,
+  state: Mutex<State>,
+}
+
+impl LruUserCache {
+  pub fn new(capacity: NonZeroUsize, ttl: Duration) -> Self {
+    Self {
+      ttl,
+      state: Mutex::new(State {
+        entries: LruCache::new(capacity),
+        emails: HashMap::new(),
+      }),
+    }
+  }
+
+  fn lock(&self) -> MutexGuard<'_, State> {
+    self.state.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+fn get(state: &mut State, key: &Key) -> Option<User> {
+  match state.entries.get(key) {
+    Some(entry) if entry.expires_at > Instant::now() => Some(entry.user.clone()),
+    Some(_) => {
+      state.remove(key);
+      None
+    }
+    None => None,
+  }
+}
+
+#[async_trait]
+impl UserCacheTrait for LruUserCache {
+  async fn get_by_id(&self, id: &str) -> Option<User> {
+    get(
+      &mut self.lock(),
+      &(current_tenant(), id.to_ascii_lowercase()),
+    )
+  }
+
+  async fn get_by_email(&self, email: &str) -> Option<User> {
+    let tenant = current_tenant();
+    let mut state = self.lock();
+    let id = state
+      .emails
+      .get(&(tenant.clone(), email.to_lowercase()))?
+      .clone();
+    get(&mut state, &(tenant, id))
+  }
+
+  async fn put(&self, user: User) {
+    let Some(id) = user.id.map(|id| id.to_hex()) else {
+      return;
+    };
+    let tenant = current_tenant();
+    let email = user.email.to_lowercase();
+    let entry = Entry {
+      user,
+      email: email.clone(),
+      expires_at: Instant::now() + self.ttl,
+    };
+    let mut state = self.lock();
+    // Replacing the user or evicting another one, whose email must then be forgotten.
+    if let Some(((tenant, _), replaced)) = state.entries.push((tenant.clone(), id.clone()), entry) {
+      state.emails.remove(&(tenant, replaced.email));
+    }
+    state.emails.insert((tenant, email), id);
+  }
+
+  async fn invalidate(&self, id: &str) {
+    self
+      .lock()
+      .remove(&(current_tenant(), id.to_ascii_lowercase()));
+  }
+}
+
+/// A user repository whose lookups are cached, see the module documentation.
+///
+/// Lookups within a unit of work skip the cache, so that it never holds uncommitted changes.
+pub struct CachedUserRepository {
+  repository: DynUserRepository,
+  cache: DynUserCache,
+  /// Invalidations so far, telling whether a user read from the repository may be stale.
+  generation: Arc<AtomicU64>,
+}
+
+impl CachedUserRepository {
+  pub fn new(repository: DynUserRepository, cache: DynUserCache) -> Self {
+    Self {
+      repository,
+      cache,
+      generation: Arc::new(AtomicU64::new(0)),
+    }
+  }
+
+  /// Caches `user`, read from the repository when the generation was `generation`.
+  ///
+  /// A change committed meanwhile may have been invalidated before the put, which must then be
+  /// undone. Checking after the put means that either this check or the invalidation comes last.
+  async fn put(&self, user: &User, generation: u64) {
+    self.cache.put(user.clone()).await;
+    if self.generation.load(Ordering::SeqCst) == generation {
+      return;
+    }
+    if let Some(id) = user.id {
+      self.cache.invalidate(&id.to_hex()).await;
+    }
+  }
+
+  /// Invalidates the user `id` once the current unit of work has committed, so that a lookup
+  /// made before cannot cache the previous version again.
+  async fn invalidate(&self, id: &str) {
+    let cache = self.cache.clone();
+    let generation = self.generation.clone();
+    let id = id.to_string();
+    after_commit(async move {
+      counter!("user_cache_invalidations_total").increment(1);
+      generation.fetch_add(1, Ordering::SeqCst);
+      cache.invalidate(&id).await;
+    })
+    .await;
+  }
+}
+
+fn record(lookup: &'static str, user: Option<&User>) {
+  match user {
+    Some(_) => counter!("user_cache_hits_total", "lookup" => lookup).increment(1),
+    None => counter!("user_cache_misses_total", "lookup" => lookup).increment(1),
+  }
+}
+
+#[async_trait]
+impl UserRepositoryTrait for CachedUserRepository {
+  async fn create_user(
+    &self,
+    name: &str,
+    email: &str,
+    password: &str,
+  ) -> AppResult<InsertOneResult> {
+    self.repository.create_user(name, email, password).await
+  }
+
+  async fn find_users(
+    &self,
+    filter: &UserFilter,
+    pagination: &Pagination,
+  ) -> AppResult<Page<User>> {
+    self.repository.find_users(filter, pagination).await
+  }
+
+  async fn count_users(&self, filter: &UserFilter) -> AppResult<u64> {
+    self.repository.count_users(filter).await
+  }
+
+  async fn get_user_by_id(&self, id: &str) -> AppResult<Option<User>> {
+    if in_unit_of_work() {
+      return self.repository.get_user_by_id(id).await;
+    }
+    let cached = self.cache.get_by_id(id).await;
+    record("id", cached.as_ref());
+    if cached.is_some() {
+      return Ok(cached);
+    }
+    let generation = self.generation.load(Ordering::SeqCst);
+    let user = self.repository.get_user_by_id(id).await?;
+    if let Some(user) = &user {
+      self.put(user, generation).await;
+    }
+    Ok(user)
+  }
+
+  async fn get_users_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<User>> {
+    self.repository.get_users_by_ids(ids).await
+  }
+
+  async fn get_user_by_email(&self, email: &str) -> AppResult<Option<User>> {
+    if in_unit_of_work() {
+      return self.repository.get_user_by_email(email).await;
+    }
+    let cached = self.cache.get_by_email(email).await;
+    record("email", cached.as_ref());
+    if cached.is_some() {
+      return Ok(cached);
+    }
+    let generation = self.generation.load(Ordering::SeqCst);
+    let user = self.repository.get_user_by_email(email).await?;
+    if let Some(user) = &user {
+      self.put(user, generation).await;
+    }
+    Ok(user)
+  }
+
+  async fn update_user(
+    &self,
+    id: &str,
+    name: &str,
+    email: &str,
+    expected_version: Option<i64>,
+  ) -> AppResult<Option<User>> {
+    let user = self
+      .repository
+      .update_user(id, name, email, expected_version)
+      .await;
+    self.invalidate(id).await;
+    user
+  }
+
+  async fn change_password(&self, id: &str, password: &str) -> AppResult<UpdateResult> {
+    let result = self.repository.change_password(id, password).await;
+    self.invalidate(id).await;
+    result
+  }
+
+  async fn delete_user(&self, id: &str, expected_version: Option<i64>) -> AppResult<UpdateResult> {
+    let result = self.repository.delete_user(id, expected_version).await;
+    self.invalidate(id).await;
+    result
+  }
+
+  async fn restore_user(&self, id: &str) -> AppResult<UpdateResult> {
+    let result = self.repository.restore_user(id).await;
+    self.invalidate(id).await;
+    result
+  }
+
+  async fn purge_deleted_users(&self, deleted_before: DateTime) -> AppResult<DeleteResult> {
+    // Deleted users are not returned by lookups, so none of them is cached.
+    self.repository.purge_deleted_users(deleted_before).await
+  }
+
+  async fn record_login(&self, id: &ObjectId) -> AppResult<UpdateResult> {
+    let result = self.repository.record_login(id).await;
+    self.invalidate(&id.to_hex()).await;
+    result
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{memory::MemoryDatabase, transaction::transaction};
+
+  fn cached(db: &Arc<MemoryDatabase>) -> (CachedUserRepository, DynUserCache) {
+    let cache: DynUserCache = Arc::new(LruUserCache::new(
+      NonZeroUsize::new(10).unwrap(),
+      Duration::from_secs(60),
+    ));
+    (CachedUserRepository::new(db.clone(), cache.clone()), cache)
+  }
+
+  async fn create(repository: &CachedUserRepository) -> String {
+    let result = repository
+      .create_user("Ada", "ada@example.com", "hash")
+      .await
+      .unwrap();
+    result.inserted_id.as_object_id().unwrap().to_hex()
+  }
+
+  #[tokio::test]
+  async fn reads_after_a_write_are_never_stale() {
+    let db = Arc::new(MemoryDatabase::new());
+    let (repository, cache) = cached(&db);
+    let id = create(&repository).await;
+    assert_eq!(
+      repository.get_user_by_id(&id).await.unwrap().unwrap().name,
+      "Ada"
+    );
+
+    transaction(&*db, || async {
+      repository
+        .update_user(&id, "Grace", "grace@example.com", None)
+        .await?;
+      // Lookups skip the cache until the change is committed, and the cached user is only
+      // invalidated then.
+      assert_eq!(repository.get_user_by_id(&id).await?.unwrap().name, "Grace");
+      assert!(cache.get_by_id(&id).await.is_some());
+      Ok(())
+    })
+    .await
+    .unwrap();
+
+    assert!(cache.get_by_id(&id).await.is_none());
+    let user = repository.get_user_by_id(&id).await.unwrap().unwrap();
+    assert_eq!(user.name, "Grace");
+    let user = repository
+      .get_user_by_email("GRACE@example.com")
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(user.name, "Grace");
+    assert!(
+      repository
+        .get_user_by_email("ada@example.com")
+        .await
+        .unwrap()
+        .is_none()
+    );
+  }
+
+  #[tokio::test]
+  async fn writes_outside_a_unit_of_work_invalidate_right_away() {
+    let db = Arc::new(MemoryDatabase::new());
+    let (repository, cache) = cached(&db);
+    let id = create(&repository).await;
+    repository
+      .get_user_by_email("ada@example.com")
+      .await
+      .unwrap();
+    assert!(cache.get_by_email("ada@example.com").await.is_some());
+
+    repository.delete_user(&id, None).await.unwrap();
+    assert!(cache.get_by_id(&id).await.is_none());
+    assert!(repository.get_user_by_id(&id).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn users_read_before_an_invalidation_are_not_cached() {
+    let db = Arc::new(MemoryDatabase::new());
+    let (repository, cache) = cached(&db);
+    let id = create(&repository).await;
+    let generation = repository.generation.load(Ordering::SeqCst);
+    let stale = db.get_user_by_id(&id).await.unwrap().unwrap();
+
+    repository
+      .update_user(&id, "Grace", "grace@example.com", None)
+      .await
+      .unwrap();
+    repository.put(&stale, generation).await;
+    assert!(cache.get_by_id(&id).await.is_none());
+    assert_eq!(
+      repository.get_user_by_id(&id).await.unwrap().unwrap().name,
+      "Grace"
+    );
+  }
+
+  #[tokio::test]
+  async fn evicted_users_are_forgotten_by_email() {
+    let cache = LruUserCache::new(NonZeroUsize::new(1).unwrap(), Duration::from_secs(60));
+    let user = |email: &str| User {
+      id: Some(ObjectId::new()),
+      email: email.to_string(),
+      ..User::default()
+    };
+    cache.put(user("ada@example.com")).await;
+    cache.put(user("grace@example.com")).await;
+    assert!(cache.get_by_email("ada@example.com").await.is_none());
+    assert!(cache.get_by_email("grace@example.com").await.is_some());
+  }
+}
@@ -1,2 +1,4 @@
+pub mod cache;
 pub mod model;
 pub mod repository;
+pub mod search;
@@ -1,7 +1,25 @@
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, oid::ObjectId};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+  #[default]
+  User,
+  Admin,
+}
+
+impl UserRole {
+  /// Scopes granted to tokens issued for this role.
+  pub fn scope(self) -> &'static str {
+    match self {
+      Self::User => "user",
+      Self::Admin => "user admin",
+    }
+  }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate, Default)]
 pub struct User {
   #[serde(rename = "_id")]
@@ -12,6 +30,40 @@ pub struct User {
   pub email: String,
   #[validate(length(min = 6))]
   pub password: String,
+  #[serde(default)]
+  pub role: UserRole,
+  /// Maintained by the repository.
+  #[serde(default)]
+  pub created_at: Option<DateTime>,
+  /// Maintained by the repository.
+  #[serde(default)]
+  pub updated_at: Option<DateTime>,
+  #[serde(default)]
+  pub last_login_at: Option<DateTime>,
+  /// Incremented on every change to the user's data, for optimistic concurrency control.
+  #[serde(default)]
+  pub version: i64,
+  /// Set when the user is soft deleted. Deleted users are hidden from every query but
+  /// `find_users` with `UserFilter::deleted`, and purged after the retention period.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub deleted_at: Option<DateTime>,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+/// Criteria for listing users. Unset fields match every user.
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+  /// Case-insensitive substring of the name.
+  pub name: Option<String>,
+  /// Case-insensitive substring of the email.
+  pub email: Option<String>,
+  pub role: Option<UserRole>,
+  pub created_from: Option<DateTime>,
+  pub created_to: Option<DateTime>,
+  /// List soft deleted users instead of active ones.
+  pub deleted: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -19,3 +71,5 @@ pub struct LoginResponse {
   pub access_token: String,
   pub refresh_token: String,
 }
+
+crate::impl_model!(User, "User");
@@ -1,7 +1,18 @@
-use crate::{Database, user::model::User};
+use crate::{
+  Database,
+  index::email_collation,
+  pagination::{Page, Pagination, find_page},
+  tenant::{current_tenant, scoped},
+  transaction::in_session,
+  user::{
+    model::{User, UserFilter},
+    search::search_terms,
+  },
+};
 use async_trait::async_trait;
 use mongodb::{
-  bson::{doc, oid::ObjectId},
+  bson::{Bson, DateTime, Document, doc, oid::ObjectId, to_bson, to_document},
+  options::ReturnDocument,
   results::{DeleteResult, InsertOneResult, UpdateResult},
 };
 use std::{str::FromStr, sync::Arc};
@@ -19,17 +30,111 @@ pub trait UserRepositoryTrait: Send + Sync {
     email: &str,
     password: &str,
   ) -> AppResult<InsertOneResult>;
-  async fn get_all_users(&self) -> AppResult<Vec<User>>;
+
+  /// Returns one page of the users matching `filter`. Like `count_users` and `get_user_by_id`,
+  /// may be served by a follower, see `db.reads`.
+  async fn find_users(&self, filter: &UserFilter, pagination: &Pagination)
+  -> AppResult<Page<User>>;
+
+  async fn count_users(&self, filter: &UserFilter) -> AppResult<u64>;
 
   async fn get_user_by_id(&self, id: &str) -> AppResult<Option<User>>;
 
+  /// Returns the active users among `ids`, in no particular order. Like `get_user_by_id`, may be
+  /// served by a follower.
+  async fn get_users_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<User>>;
+
+  /// Always reads from the primary, signup and authentication relying on the result.
   async fn get_user_by_email(&self, email: &str) -> AppResult<Option<User>>;
 
-  async fn update_user(&self, id: &str, name: &str, email: &str) -> AppResult<UpdateResult>;
+  /// Updates the user, provided its version is `expected_version` when set.
+  ///
+  /// Returns the updated user, or `None` when no user matched.
+  async fn update_user(
+    &self,
+    id: &str,
+    name: &str,
+    email: &str,
+    expected_version: Option<i64>,
+  ) -> AppResult<Option<User>>;
 
   async fn change_password(&self, id: &str, password: &str) -> AppResult<UpdateResult>;
 
-  async fn delete_user(&self, id: &str) -> AppResult<DeleteResult>;
+  /// Soft deletes the user, see `User::deleted_at`, provided its version is `expected_version`
+  /// when set.
+  async fn delete_user(&self, id: &str, expected_version: Option<i64>) -> AppResult<UpdateResult>;
+
+  /// Undoes `delete_user`.
+  async fn restore_user(&self, id: &str) -> AppResult<UpdateResult>;
+
+  /// Permanently removes users soft deleted before `deleted_before`, across all tenants of the
+  /// database when called outside of a request.
+  async fn purge_deleted_users(&self, deleted_before: DateTime) -> AppResult<DeleteResult>;
+
+  /// Sets `last_login_at` to now.
+  async fn record_login(&self, id: &ObjectId) -> AppResult<UpdateResult>;
+}
+
+/// Escapes `value` for use as a literal inside a regular expression.
+pub(crate) fn escape_regex(value: &str) -> String {
+  let mut escaped = String::with_capacity(value.len());
+  for c in value.chars() {
+    if "\\.+*?()|[]{}^$".contains(c) {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+/// Restricts `filter` to users that are not soft deleted, on top of `scoped`.
+pub(crate) fn active(mut filter: Document) -> Document {
+  filter.insert("deleted_at", Bson::Null);
+  scoped(filter)
+}
+
+/// Filters on `_id`, and on `version` when one is expected.
+fn versioned(id: ObjectId, expected_version: Option<i64>) -> Document {
+  let mut filter = doc! {"_id": id};
+  if let Some(version) = expected_version {
+    filter.insert("version", version);
+  }
+  active(filter)
+}
+
+fn filter_document(filter: &UserFilter) -> AppResult<Document> {
+  let mut query = doc! {};
+  if filter.deleted {
+    query.insert("deleted_at", doc! { "$ne": Bson::Null });
+  } else {
+    query.insert("deleted_at", Bson::Null);
+  }
+  if let Some(name) = &filter.name {
+    query.insert(
+      "name",
+      doc! { "$regex": escape_regex(name), "$options": "i" },
+    );
+  }
+  if let Some(email) = &filter.email {
+    query.insert(
+      "email",
+      doc! { "$regex": escape_regex(email), "$options": "i" },
+    );
+  }
+  if let Some(role) = filter.role {
+    query.insert("role", to_bson(&role)?);
+  }
+  let mut created_at = doc! {};
+  if let Some(from) = filter.created_from {
+    created_at.insert("$gte", from);
+  }
+  if let Some(to) = filter.created_to {
+    created_at.insert("$lte", to);
+  }
+  if !created_at.is_empty() {
+    query.insert("created_at", created_at);
+  }
+  Ok(scoped(query))
 }
 
 #[async_trait]
@@ -41,65 +146,151 @@ impl UserRepositoryTrait for Database {
     email: &str,
     password: &str,
   ) -> AppResult<InsertOneResult> {
+    let now = DateTime::now();
     let new_doc = User {
       id: Some(ObjectId::new()),
       name: name.to_string(),
       email: email.to_string(),
       password: password.to_string(),
+      created_at: Some(now),
+      updated_at: Some(now),
+      tenant_id: current_tenant(),
+      ..Default::default()
     };
-    let result = self.user_col.insert_one(new_doc).await?;
+    // `search_terms` is only used by the text index, see `user::search`.
+    let mut new_doc = to_document(&new_doc)?;
+    new_doc.insert("search_terms", search_terms(name, email));
+    let users = self.user_col().clone_with_type::<Document>();
+    let result = in_session!(users.insert_one(new_doc))?;
     Ok(result)
   }
 
-  #[tracing::instrument(name = "Get All Users", skip(self))]
-  async fn get_all_users(&self) -> AppResult<Vec<User>> {
-    let filter = doc! {};
-    let mut cursor = self.user_col.find(filter).await?;
-    let mut users: Vec<User> = Vec::new();
-    while let Some(doc) = cursor.next().await {
-      users.push(doc?);
-    }
-    Ok(users)
+  #[tracing::instrument(name = "Find Users", skip(self))]
+  async fn find_users(
+    &self,
+    filter: &UserFilter,
+    pagination: &Pagination,
+  ) -> AppResult<Page<User>> {
+    let query = filter_document(filter)?;
+    find_page(&self.read_collection::<User>(), query, pagination).await
+  }
+
+  #[tracing::instrument(name = "Count Users", skip(self))]
+  async fn count_users(&self, filter: &UserFilter) -> AppResult<u64> {
+    let query = filter_document(filter)?;
+    let count = in_session!(self.read_collection::<User>().count_documents(query))?;
+    Ok(count)
   }
 
   #[tracing::instrument(name = "Get User By Id", skip(self, id))]
   async fn get_user_by_id(&self, id: &str) -> AppResult<Option<User>> {
     let id = ObjectId::from_str(id)?;
-    let filter = doc! {"_id": id};
-    let user = self.user_col.find_one(filter).await?;
+    let filter = active(doc! {"_id": id});
+    let user = in_session!(self.read_collection::<User>().find_one(filter))?;
     Ok(user)
   }
 
+  #[tracing::instrument(name = "Get Users By Ids", skip(self, ids))]
+  async fn get_users_by_ids(&self, ids: &[ObjectId]) -> AppResult<Vec<User>> {
+    let filter = active(doc! {"_id": { "$in": ids }});
+    let mut cursor = self.read_collection::<User>().find(filter).await?;
+    let mut users: Vec<User> = Vec::new();
+    while let Some(doc) = cursor.next().await {
+      users.push(doc?);
+    }
+    Ok(users)
+  }
+
   #[tracing::instrument(name = "Get User By Email", skip(self, email))]
   async fn get_user_by_email(&self, email: &str) -> AppResult<Option<User>> {
-    let filter = doc! {"email": email};
-    let user = self.user_col.find_one(filter).await?;
+    let filter = active(doc! {"email": email});
+    let user = in_session!(
+      self
+        .user_col()
+        .find_one(filter)
+        .collation(email_collation())
+    )?;
     Ok(user)
   }
 
   #[tracing::instrument(name = "Update User", skip(self, id, name, email))]
-  async fn update_user(&self, id: &str, name: &str, email: &str) -> AppResult<UpdateResult> {
+  async fn update_user(
+    &self,
+    id: &str,
+    name: &str,
+    email: &str,
+    expected_version: Option<i64>,
+  ) -> AppResult<Option<User>> {
     let id = ObjectId::from_str(id)?;
-    let filter = doc! {"_id": id};
-    let new_doc = doc! { "$set": { "name": name, "email": email } };
-    let result = self.user_col.update_one(filter, new_doc).await?;
-    Ok(result)
+    let filter = versioned(id, expected_version);
+    let new_doc = doc! {
+      "$set": {
+        "name": name,
+        "email": email,
+        "search_terms": search_terms(name, email),
+        "updated_at": DateTime::now(),
+      },
+      "$inc": { "version": 1 },
+    };
+    let user = in_session!(
+      self
+        .user_col()
+        .find_one_and_update(filter, new_doc)
+        .return_document(ReturnDocument::After)
+    )?;
+    Ok(user)
   }
 
   #[tracing::instrument(name = "Change Password", skip(self, id, password))]
   async fn change_password(&self, id: &str, password: &str) -> AppResult<UpdateResult> {
     let id = ObjectId::from_str(id)?;
-    let filter = doc! {"_id": id};
-    let new_doc = doc! { "$set": { "password": password } };
-    let result = self.user_col.update_one(filter, new_doc).await?;
+    let filter = active(doc! {"_id": id});
+    let new_doc = doc! {
+      "$set": { "password": password, "updated_at": DateTime::now() },
+      "$inc": { "version": 1 },
+    };
+    let result = in_session!(self.user_col().update_one(filter, new_doc))?;
     Ok(result)
   }
 
   #[tracing::instrument(name = "Delete User", skip(self, id))]
-  async fn delete_user(&self, id: &str) -> AppResult<DeleteResult> {
+  async fn delete_user(&self, id: &str, expected_version: Option<i64>) -> AppResult<UpdateResult> {
+    let id = ObjectId::from_str(id)?;
+    let filter = versioned(id, expected_version);
+    let new_doc = doc! {
+      "$set": { "deleted_at": DateTime::now() },
+      "$inc": { "version": 1 },
+    };
+    let result = in_session!(self.user_col().update_one(filter, new_doc))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Restore User", skip(self, id))]
+  async fn restore_user(&self, id: &str) -> AppResult<UpdateResult> {
     let id = ObjectId::from_str(id)?;
-    let filter = doc! {"_id": id};
-    let result = self.user_col.delete_one(filter).await?;
+    let filter = scoped(doc! {"_id": id, "deleted_at": { "$ne": Bson::Null }});
+    let new_doc = doc! {
+      "$unset": { "deleted_at": "" },
+      "$set": { "updated_at": DateTime::now() },
+      "$inc": { "version": 1 },
+    };
+    let result = in_session!(self.user_col().update_one(filter, new_doc))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Purge Deleted Users", skip(self))]
+  async fn purge_deleted_users(&self, deleted_before: DateTime) -> AppResult<DeleteResult> {
+    let filter = scoped(doc! {"deleted_at": { "$lt": deleted_before }});
+    let result = in_session!(self.user_col().delete_many(filter))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Record Login", skip(self))]
+  async fn record_login(&self, id: &ObjectId) -> AppResult<UpdateResult> {
+    // Logging in does not change the user's data, so the version is left alone.
+    let filter = active(doc! {"_id": id});
+    let new_doc = doc! { "$set": { "last_login_at": DateTime::now() } };
+    let result = in_session!(self.user_col().update_one(filter, new_doc))?;
     Ok(result)
   }
 }
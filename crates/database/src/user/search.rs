@@ -0,0 +1,209 @@
+//! Full-text search of users by name and email.
+//!
+//! Queries are split into lowercase words, each matching the start of a word of the name or
+//! email: `ann smi` finds "Anne Smith". Results are ordered by relevance, whose scale is
+//! specific to each backend, then by id.
+//!
+//! On Mongo the search runs on the `search` text index of the user collection. Text indexes
+//! only match whole words, so the repository also stores the prefixes of every word in
+//! `search_terms`, see [`search_terms`]. Another search engine can be used by implementing
+//! `UserSearchTrait` and handing it to `Services::with_users`.
+use crate::{
+  Database,
+  user::{model::User, repository::active},
+};
+use async_trait::async_trait;
+use mongodb::bson::{Document, doc, from_document};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use utils::AppResult;
+
+/// Shortest word prefix that is searched for.
+pub const MIN_TERM_LENGTH: usize = 2;
+
+/// Longest word prefix stored in `search_terms`. Longer query words are shortened to it.
+pub const MAX_TERM_LENGTH: usize = 20;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynUserSearch = Arc<dyn UserSearchTrait>;
+
+#[async_trait]
+pub trait UserSearchTrait: Send + Sync {
+  /// Returns the active users of the current tenant matching any of `query.terms`, most
+  /// relevant first. May be served by a follower, see `db.reads`.
+  async fn search_users(&self, query: &UserSearchQuery) -> AppResult<UserSearchResults>;
+}
+
+#[derive(Debug, Clone)]
+pub struct UserSearchQuery {
+  /// Words to search for, see [`query_terms`].
+  pub terms: Vec<String>,
+  pub skip: u64,
+  pub limit: i64,
+  pub include_total: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct UserSearchHit {
+  pub user: User,
+  /// Relevance of the user, higher is better.
+  pub score: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct UserSearchResults {
+  pub hits: Vec<UserSearchHit>,
+  /// Whether more hits follow the returned ones.
+  pub has_more: bool,
+  /// Number of matching users, when requested.
+  pub total: Option<u64>,
+}
+
+/// Lowercase words of `text`, split on anything but letters and digits.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+  text
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .map(str::to_lowercase)
+}
+
+/// The first `length` characters of `word`.
+fn prefix(word: &str, length: usize) -> String {
+  word.chars().take(length).collect()
+}
+
+/// The distinct words of a search query, ignoring those shorter than `MIN_TERM_LENGTH`.
+pub fn query_terms(text: &str) -> Vec<String> {
+  let mut terms: Vec<String> = words(text)
+    .filter(|word| word.chars().count() >= MIN_TERM_LENGTH)
+    .map(|word| prefix(&word, MAX_TERM_LENGTH))
+    .collect();
+  terms.sort();
+  terms.dedup();
+  terms
+}
+
+/// Every prefix of every word of `name` and `email`, stored with Mongo users so that the text
+/// index matches the start of words.
+pub(crate) fn search_terms(name: &str, email: &str) -> Vec<String> {
+  let mut terms = Vec::new();
+  for word in words(name).chain(words(email)) {
+    let length = word.chars().count().min(MAX_TERM_LENGTH);
+    terms.extend((MIN_TERM_LENGTH..=length).map(|length| prefix(&word, length)));
+  }
+  terms.sort();
+  terms.dedup();
+  terms
+}
+
+/// Relevance of `user` for `terms`, for the backends without a search index: a whole word of
+/// the name counts most, then the start of a word of the name, then the start of a word of the
+/// email.
+pub(crate) fn score(user: &User, terms: &[String]) -> f64 {
+  let name: Vec<String> = words(&user.name).collect();
+  let email: Vec<String> = words(&user.email).collect();
+  terms
+    .iter()
+    .map(|term| {
+      if name.contains(term) {
+        3.0
+      } else if name.iter().any(|word| word.starts_with(term.as_str())) {
+        2.0
+      } else if email.iter().any(|word| word.starts_with(term.as_str())) {
+        1.0
+      } else {
+        0.0
+      }
+    })
+    .sum()
+}
+
+#[async_trait]
+impl UserSearchTrait for Database {
+  #[tracing::instrument(name = "Search Users", skip(self))]
+  async fn search_users(&self, query: &UserSearchQuery) -> AppResult<UserSearchResults> {
+    let filter = active(doc! { "$text": { "$search": query.terms.join(" ") } });
+    let users = self.read_collection::<User>().clone_with_type::<Document>();
+    let score = doc! { "$meta": "textScore" };
+    // One extra hit tells whether there are more.
+    let mut cursor = users
+      .find(filter.clone())
+      .projection(doc! { "score": score.clone() })
+      .sort(doc! { "score": score, "_id": 1 })
+      .skip(query.skip)
+      .limit(query.limit + 1)
+      .await?;
+    let mut hits = Vec::new();
+    while let Some(document) = cursor.next().await {
+      let mut document = document?;
+      let score = document.remove("score").and_then(|score| score.as_f64());
+      hits.push(UserSearchHit {
+        user: from_document(document)?,
+        score: score.unwrap_or_default(),
+      });
+    }
+    let limit = usize::try_from(query.limit).unwrap_or_default();
+    let has_more = hits.len() > limit;
+    hits.truncate(limit);
+    let total = if query.include_total {
+      Some(users.count_documents(filter).await?)
+    } else {
+      None
+    };
+    Ok(UserSearchResults {
+      hits,
+      has_more,
+      total,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn user(name: &str, email: &str) -> User {
+    User {
+      name: name.to_string(),
+      email: email.to_string(),
+      ..User::default()
+    }
+  }
+
+  fn terms(text: &str) -> Vec<String> {
+    query_terms(text)
+  }
+
+  #[test]
+  fn query_terms_are_distinct_lowercase_words() {
+    assert_eq!(terms("Ann  ann, a SMI"), ["ann", "smi"]);
+    assert_eq!(terms("x"), Vec::<String>::new());
+    let long = "a".repeat(MAX_TERM_LENGTH + 5);
+    assert_eq!(terms(&long), ["a".repeat(MAX_TERM_LENGTH)]);
+  }
+
+  #[test]
+  fn search_terms_are_the_prefixes_of_every_word() {
+    let stored = search_terms("Anne Li", "anne@ex.io");
+    assert_eq!(stored, ["an", "ann", "anne", "ex", "io", "li"]);
+  }
+
+  #[test]
+  fn whole_name_words_rank_above_prefixes_and_emails() {
+    let ann = terms("ann");
+    let exact = score(&user("Ann Lee", "lee@example.com"), &ann);
+    let prefix = score(&user("Anne Smith", "smith@example.com"), &ann);
+    let email = score(&user("Bob", "annie@example.com"), &ann);
+    let none = score(&user("Carl", "carl@example.com"), &ann);
+    assert!(exact > prefix && prefix > email && email > none);
+    assert!(none.abs() < f64::EPSILON);
+  }
+
+  #[test]
+  fn scores_add_up_over_terms() {
+    let user = user("Anne Smith", "anne@example.com");
+    assert!(score(&user, &terms("anne smi")) > score(&user, &terms("anne")));
+    // Terms only match the start of words.
+    assert!(score(&user, &terms("mith")).abs() < f64::EPSILON);
+  }
+}
@@ -0,0 +1,47 @@
+use mongodb::bson::{DateTime, oid::ObjectId};
+use serde::{Deserialize, Serialize};
+
+/// A passkey registered by a user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebauthnCredential {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub user_id: ObjectId,
+  /// Base64url encoded credential id chosen by the authenticator.
+  pub credential_id: String,
+  /// Base64url encoded SEC1 P-256 public key.
+  pub public_key: String,
+  pub sign_count: u32,
+  pub name: String,
+  pub created_at: DateTime,
+  pub last_used_at: Option<DateTime>,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebauthnChallengeKind {
+  Registration,
+  Authentication,
+}
+
+/// A single-use challenge handed out by a registration or login ceremony.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebauthnChallenge {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  /// Base64url encoded random bytes.
+  pub challenge: String,
+  pub kind: WebauthnChallengeKind,
+  /// The user registering a passkey. Unset for discoverable credential logins.
+  pub user_id: Option<ObjectId>,
+  pub expires_at: DateTime,
+  /// Set by the repository on insert, see `tenant::scoped`.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant_id: Option<String>,
+}
+
+crate::impl_model!(WebauthnCredential, "WebauthnCredential");
+crate::impl_model!(WebauthnChallenge, "WebauthnChallenge");
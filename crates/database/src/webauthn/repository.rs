@@ -0,0 +1,148 @@
+use crate::{
+  Database,
+  tenant::{current_tenant, scoped},
+  transaction::in_session,
+  webauthn::model::{WebauthnChallenge, WebauthnChallengeKind, WebauthnCredential},
+};
+use async_trait::async_trait;
+use mongodb::{
+  bson::{DateTime, doc, oid::ObjectId, to_bson},
+  results::{DeleteResult, InsertOneResult, UpdateResult},
+};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use utils::AppResult;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynWebauthnRepository = Arc<dyn WebauthnRepositoryTrait>;
+
+#[async_trait]
+pub trait WebauthnRepositoryTrait: Send + Sync {
+  async fn create_credential(&self, credential: WebauthnCredential) -> AppResult<InsertOneResult>;
+
+  async fn get_credentials_by_user(&self, user_id: &ObjectId)
+  -> AppResult<Vec<WebauthnCredential>>;
+
+  async fn get_credential_by_credential_id(
+    &self,
+    credential_id: &str,
+  ) -> AppResult<Option<WebauthnCredential>>;
+
+  /// Stores the latest signature counter after a successful login.
+  async fn update_credential_usage(
+    &self,
+    id: &ObjectId,
+    sign_count: u32,
+  ) -> AppResult<UpdateResult>;
+
+  async fn rename_credential(
+    &self,
+    id: &ObjectId,
+    user_id: &ObjectId,
+    name: &str,
+  ) -> AppResult<UpdateResult>;
+
+  async fn delete_credential(&self, id: &ObjectId, user_id: &ObjectId) -> AppResult<DeleteResult>;
+
+  async fn create_challenge(&self, challenge: WebauthnChallenge) -> AppResult<InsertOneResult>;
+
+  /// Atomically removes and returns an unexpired challenge, so it can only be answered once.
+  async fn take_challenge(
+    &self,
+    challenge: &str,
+    kind: WebauthnChallengeKind,
+  ) -> AppResult<Option<WebauthnChallenge>>;
+}
+
+#[async_trait]
+impl WebauthnRepositoryTrait for Database {
+  #[tracing::instrument(name = "Create Webauthn Credential", skip(self, credential))]
+  async fn create_credential(
+    &self,
+    mut credential: WebauthnCredential,
+  ) -> AppResult<InsertOneResult> {
+    credential.tenant_id = current_tenant();
+    let result = in_session!(self.webauthn_credential_col().insert_one(credential))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Get Webauthn Credentials By User", skip(self))]
+  async fn get_credentials_by_user(
+    &self,
+    user_id: &ObjectId,
+  ) -> AppResult<Vec<WebauthnCredential>> {
+    let filter = scoped(doc! {"user_id": user_id});
+    let mut cursor = self.webauthn_credential_col().find(filter).await?;
+    let mut credentials: Vec<WebauthnCredential> = Vec::new();
+    while let Some(doc) = cursor.next().await {
+      credentials.push(doc?);
+    }
+    Ok(credentials)
+  }
+
+  #[tracing::instrument(name = "Get Webauthn Credential By Credential Id", skip(self))]
+  async fn get_credential_by_credential_id(
+    &self,
+    credential_id: &str,
+  ) -> AppResult<Option<WebauthnCredential>> {
+    let filter = scoped(doc! {"credential_id": credential_id});
+    let credential = in_session!(self.webauthn_credential_col().find_one(filter))?;
+    Ok(credential)
+  }
+
+  #[tracing::instrument(name = "Update Webauthn Credential Usage", skip(self))]
+  async fn update_credential_usage(
+    &self,
+    id: &ObjectId,
+    sign_count: u32,
+  ) -> AppResult<UpdateResult> {
+    let filter = scoped(doc! {"_id": id});
+    let new_doc = doc! {
+      "$set": { "sign_count": i64::from(sign_count), "last_used_at": DateTime::now() }
+    };
+    let result = in_session!(self.webauthn_credential_col().update_one(filter, new_doc))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Rename Webauthn Credential", skip(self))]
+  async fn rename_credential(
+    &self,
+    id: &ObjectId,
+    user_id: &ObjectId,
+    name: &str,
+  ) -> AppResult<UpdateResult> {
+    let filter = scoped(doc! {"_id": id, "user_id": user_id});
+    let new_doc = doc! { "$set": { "name": name } };
+    let result = in_session!(self.webauthn_credential_col().update_one(filter, new_doc))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Delete Webauthn Credential", skip(self))]
+  async fn delete_credential(&self, id: &ObjectId, user_id: &ObjectId) -> AppResult<DeleteResult> {
+    let filter = scoped(doc! {"_id": id, "user_id": user_id});
+    let result = in_session!(self.webauthn_credential_col().delete_one(filter))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Create Webauthn Challenge", skip(self, challenge))]
+  async fn create_challenge(&self, mut challenge: WebauthnChallenge) -> AppResult<InsertOneResult> {
+    challenge.tenant_id = current_tenant();
+    let result = in_session!(self.webauthn_challenge_col().insert_one(challenge))?;
+    Ok(result)
+  }
+
+  #[tracing::instrument(name = "Take Webauthn Challenge", skip(self, challenge))]
+  async fn take_challenge(
+    &self,
+    challenge: &str,
+    kind: WebauthnChallengeKind,
+  ) -> AppResult<Option<WebauthnChallenge>> {
+    let filter = scoped(doc! {
+      "challenge": challenge,
+      "kind": to_bson(&kind)?,
+      "expires_at": { "$gt": DateTime::now() },
+    });
+    let challenge = in_session!(self.webauthn_challenge_col().find_one_and_delete(filter))?;
+    Ok(challenge)
+  }
+}
@@ -0,0 +1,33 @@
+use crate::{
+  api::{authenticate_user, require_admin},
+  dtos::audit_dto::{AuditEventQueryDto, AuditEventResponse},
+  extractors::{pagination::PaginationQuery, validation_extractor::QueryValidationExtractor},
+  services::Services,
+};
+use axum::{Extension, Router, body::Body, middleware::from_fn, response::Response, routing::get};
+use utils::AppResult;
+
+pub struct AuditController;
+
+impl AuditController {
+  pub fn app() -> Router {
+    Router::new()
+      .route("/", get(Self::search))
+      .route_layer(from_fn(require_admin))
+      .route_layer(from_fn(authenticate_user::<Body>))
+  }
+
+  pub async fn search(
+    Extension(services): Extension<Services>,
+    page: PaginationQuery,
+    QueryValidationExtractor(req): QueryValidationExtractor<AuditEventQueryDto>,
+  ) -> AppResult<Response> {
+    let (events, total) = services.audit.find_audit_events(req, &page).await?;
+    let data = events
+      .items
+      .into_iter()
+      .map(AuditEventResponse::from)
+      .collect();
+    Ok(page.response::<AuditEventResponse>(data, events.next_cursor, total))
+  }
+}
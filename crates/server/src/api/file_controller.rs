@@ -0,0 +1,194 @@
+use crate::{
+  api::authenticate_user,
+  dtos::file_dto::{FileDownload, FileResponse, FileUpload},
+  extractors::{client_info::ClientInfo, pagination::PaginationQuery},
+  services::Services,
+};
+use axum::{
+  Extension, Json, Router,
+  body::Body,
+  extract::{
+    DefaultBodyLimit, Multipart, Path,
+    multipart::{MultipartError, MultipartRejection},
+  },
+  http::{
+    HeaderMap, HeaderValue, StatusCode,
+    header::{
+      ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+      IF_RANGE, RANGE, X_CONTENT_TYPE_OPTIONS,
+    },
+  },
+  middleware::from_fn,
+  response::{IntoResponse, Response},
+  routing::get,
+};
+use bytes::BytesMut;
+use std::fmt::Write;
+use utils::{AppError, AppResult, config, jwt::Claims};
+
+/// Room left for the multipart boundaries and headers around the file.
+const MULTIPART_OVERHEAD: u64 = 64 * 1024;
+
+/// Content type of parts sent without one.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+pub struct FileController;
+
+fn too_large() -> AppError {
+  AppError::PayloadTooLarge(format!(
+    "files are limited to {} bytes",
+    config::get().storage.max_file_size
+  ))
+}
+
+fn multipart_error(error: MultipartError) -> AppError {
+  if error.status() == StatusCode::PAYLOAD_TOO_LARGE {
+    too_large()
+  } else {
+    AppError::BadRequest(error.body_text())
+  }
+}
+
+/// Offers the file for download as `name`, with an ASCII fallback for older clients.
+fn content_disposition(name: &str) -> String {
+  let fallback: String = name
+    .chars()
+    .map(|c| {
+      if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' {
+        c
+      } else {
+        '_'
+      }
+    })
+    .collect();
+  let encoded = name.bytes().fold(String::new(), |mut encoded, byte| {
+    if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+      encoded.push(char::from(byte));
+    } else {
+      let _ = write!(encoded, "%{byte:02X}");
+    }
+    encoded
+  });
+  format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+impl FileController {
+  pub fn app() -> Router {
+    let body_limit = config::get().storage.max_file_size + MULTIPART_OVERHEAD;
+    Router::new()
+      .route("/", get(Self::get_all).post(Self::upload))
+      .route("/:id", get(Self::get).delete(Self::delete))
+      .route("/:id/content", get(Self::download))
+      .layer(DefaultBodyLimit::max(
+        usize::try_from(body_limit).unwrap_or(usize::MAX),
+      ))
+      .route_layer(from_fn(authenticate_user::<Body>))
+  }
+
+  /// Stores the `file` part of a `multipart/form-data` body.
+  pub async fn upload(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    multipart: Result<Multipart, MultipartRejection>,
+  ) -> AppResult<(StatusCode, Json<FileResponse>)> {
+    let mut multipart = multipart.map_err(|e| AppError::BadRequest(e.body_text()))?;
+    let max_file_size = config::get().storage.max_file_size;
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+      if field.name() != Some("file") {
+        continue;
+      }
+      let name = field.file_name().unwrap_or_default().to_string();
+      let content_type = field
+        .content_type()
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+        .to_string();
+      let mut content = BytesMut::new();
+      while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        if (content.len() + chunk.len()) as u64 > max_file_size {
+          return Err(too_large());
+        }
+        content.extend_from_slice(&chunk);
+      }
+      let upload = FileUpload {
+        name,
+        content_type,
+        content: content.freeze(),
+      };
+      let file = services
+        .file
+        .upload_file(upload, &claims.sub, &client)
+        .await?;
+      return Ok((StatusCode::CREATED, Json(FileResponse::from(file))));
+    }
+    Err(AppError::BadRequest("the file part is missing".to_string()))
+  }
+
+  pub async fn get_all(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    page: PaginationQuery,
+  ) -> AppResult<Response> {
+    let (files, total) = services.file.find_files(&claims.sub, &page).await?;
+    let data = files.items.into_iter().map(FileResponse::from).collect();
+    Ok(page.response::<FileResponse>(data, files.next_cursor, total))
+  }
+
+  pub async fn get(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<String>,
+  ) -> AppResult<Json<FileResponse>> {
+    let file = services.file.get_file(&id, &claims.sub).await?;
+    Ok(Json(FileResponse::from(file)))
+  }
+
+  /// Streams the content of a file, or the single byte range of the `Range` header with
+  /// `206 Partial Content`.
+  pub async fn download(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+  ) -> AppResult<Response> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let FileDownload {
+      file,
+      range,
+      content,
+    } = services
+      .file
+      .download_file(&id, &claims.sub, header(RANGE), header(IF_RANGE))
+      .await?;
+    let (status, length) = match &range {
+      Some(range) => (StatusCode::PARTIAL_CONTENT, range.end - range.start),
+      None => (StatusCode::OK, file.size),
+    };
+    let headers = [
+      (CONTENT_TYPE, file.content_type.clone()),
+      (CONTENT_LENGTH, length.to_string()),
+      (CONTENT_DISPOSITION, content_disposition(&file.name)),
+      (ACCEPT_RANGES, "bytes".to_string()),
+      (ETAG, file.etag()),
+      (X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+    ];
+    let mut response = (status, headers, Body::from_stream(content)).into_response();
+    if let Some(range) = range {
+      let content_range = format!("bytes {}-{}/{}", range.start, range.end - 1, file.size);
+      if let Ok(value) = HeaderValue::from_str(&content_range) {
+        response.headers_mut().insert(CONTENT_RANGE, value);
+      }
+    }
+    Ok(response)
+  }
+
+  pub async fn delete(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    Path(id): Path<String>,
+  ) -> AppResult<StatusCode> {
+    services.file.delete_file(&id, &claims.sub, &client).await?;
+    Ok(StatusCode::NO_CONTENT)
+  }
+}
@@ -0,0 +1,69 @@
+use crate::{
+  api::{authenticate_user, require_admin},
+  dtos::{
+    IdOnlyDto,
+    invitation_dto::{CreateInvitationDto, InvitationResponse},
+  },
+  extractors::{client_info::ClientInfo, validation_extractor::ValidationExtractor},
+  services::Services,
+};
+use axum::{
+  Extension, Json, Router,
+  body::Body,
+  middleware::from_fn,
+  routing::{delete, get, post},
+};
+use mongodb::results::DeleteResult;
+use utils::{AppResult, jwt::Claims};
+
+pub struct InvitationController;
+
+impl InvitationController {
+  pub fn app() -> Router {
+    Router::new()
+      .route("/", get(Self::get_all))
+      .route("/create", post(Self::create))
+      .route("/delete", delete(Self::delete))
+      .route_layer(from_fn(require_admin))
+      .route_layer(from_fn(authenticate_user::<Body>))
+  }
+
+  pub async fn get_all(
+    Extension(services): Extension<Services>,
+  ) -> AppResult<Json<Vec<InvitationResponse>>> {
+    let invitations = services.invitation.get_all_invitations().await?;
+    Ok(Json(
+      invitations
+        .into_iter()
+        .map(InvitationResponse::from)
+        .collect(),
+    ))
+  }
+
+  pub async fn create(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    ValidationExtractor(req): ValidationExtractor<CreateInvitationDto>,
+  ) -> AppResult<Json<InvitationResponse>> {
+    let invitation = services
+      .invitation
+      .create_invitation(req, &claims.sub, &client)
+      .await?;
+    Ok(Json(InvitationResponse::from(invitation)))
+  }
+
+  pub async fn delete(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    ValidationExtractor(req): ValidationExtractor<IdOnlyDto>,
+  ) -> AppResult<Json<DeleteResult>> {
+    let id = req.id.unwrap();
+    let result = services
+      .invitation
+      .delete_invitation(&id, &claims.sub, &client)
+      .await?;
+    Ok(Json(result))
+  }
+}
@@ -1,15 +1,35 @@
+mod audit_controller;
+mod file_controller;
+mod invitation_controller;
+mod oauth_controller;
+mod organization_controller;
 mod user_controller;
+mod webauthn_controller;
 
+use crate::services::Services;
 use axum::{
+  Extension,
   body::Body,
-  http::Request,
-  middleware::Next,
+  http::{HeaderMap, Request, header::HOST},
+  middleware::{Next, from_fn},
   response::Response,
   routing::{Router, get},
 };
-use axum_extra::{TypedHeader, headers::Cookie};
+use axum_extra::{
+  TypedHeader,
+  headers::{Cookie, HeaderMapExt},
+};
+use database::user::model::UserRole;
 use tracing::error;
-use utils::{AppError, config, jwt::decode_token};
+use utils::{
+  AppError,
+  config::{
+    self,
+    tenant_config::{TenantConfig, TenantSource},
+  },
+  jwt::{self, Claims, decode_token},
+  tenant,
+};
 
 pub async fn health() -> &'static str {
   "🚀 Server is running! 🚀"
@@ -19,23 +39,258 @@ pub fn app() -> Router {
   Router::new()
     .route("/", get(health))
     .nest("/users", user_controller::UserController::app())
+    .nest("/audit-events", audit_controller::AuditController::app())
+    .nest(
+      "/invitations",
+      invitation_controller::InvitationController::app(),
+    )
+    .nest(
+      "/organizations",
+      organization_controller::OrganizationController::app(),
+    )
+    .nest("/oauth", oauth_controller::OAuthController::app())
+    .nest("/webauthn", webauthn_controller::WebauthnController::app())
+    .nest("/files", file_controller::FileController::app())
+    .layer(from_fn(resolve_tenant))
+}
+
+/// Resolves the tenant of the request from the configured sources and runs the rest of the
+/// request within its scope, see `utils::tenant`.
+async fn resolve_tenant(request: Request<Body>, next: Next) -> Result<Response, AppError> {
+  let cfg = config::get();
+  if !cfg.tenant.enabled {
+    return Ok(next.run(request).await);
+  }
+  let tenant = tenant_of(request.headers(), &cfg.tenant, &cfg.jwt.access_token_secret)?;
+  Ok(tenant::scope(tenant, next.run(request)).await)
+}
+
+/// The tenant of a request with `headers`, which must be one of `tenant.tenants`.
+///
+/// Unknown tenants are rejected before anything reaches the database, which would otherwise
+/// create a database for each of them with `db.database_per_tenant`.
+fn tenant_of(
+  headers: &HeaderMap,
+  cfg: &TenantConfig,
+  access_token_secret: &str,
+) -> Result<String, AppError> {
+  let resolved = cfg.sources.iter().find_map(|source| match source {
+    TenantSource::Header => headers
+      .get(cfg.header.as_str())
+      .and_then(|value| value.to_str().ok())
+      .map(str::to_string),
+    TenantSource::Subdomain => {
+      let base_domain = cfg.base_domain.as_deref()?;
+      let host = headers.get(HOST)?.to_str().ok()?;
+      let host = host.split(':').next().unwrap_or(host);
+      host
+        .strip_suffix(base_domain)?
+        .strip_suffix('.')
+        .filter(|subdomain| !subdomain.contains('.'))
+        .map(str::to_string)
+    }
+    TenantSource::Claim => {
+      let cookie = headers.typed_get::<Cookie>()?;
+      decode_token(cookie.get("access_token")?, access_token_secret)
+        .ok()?
+        .tenant
+    }
+  });
+
+  let Some(tenant) = resolved.or_else(|| cfg.default_tenant.clone()) else {
+    error!("tenant could not be resolved");
+    return Err(AppError::BadRequest(
+      "tenant could not be resolved".to_string(),
+    ));
+  };
+  let tenant = tenant.to_lowercase();
+  if !tenant::is_valid(&tenant) {
+    error!("invalid tenant {:?}", tenant);
+    return Err(AppError::BadRequest(format!("invalid tenant {tenant}")));
+  }
+  if !cfg.is_known(&tenant) {
+    error!("unknown tenant {:?}", tenant);
+    return Err(AppError::BadRequest(format!("unknown tenant {tenant}")));
+  }
+  Ok(tenant)
 }
 
 async fn authenticate_user<B>(
+  Extension(services): Extension<Services>,
   TypedHeader(cookie): TypedHeader<Cookie>,
-  request: Request<Body>,
+  mut request: Request<Body>,
   next: Next,
 ) -> Result<Response, AppError> {
   let cfg = config::get();
 
   if let Some(access_token) = cookie.get("access_token") {
-    if decode_token(access_token, &cfg.jwt.access_token_secret).is_err() {
+    let Ok(claims) = decode_token(access_token, &cfg.jwt.access_token_secret) else {
       error!("can't decode token");
       return Err(AppError::Unauthorized);
+    };
+    if claims.tenant != tenant::current() {
+      error!("token was issued for tenant {:?}", claims.tenant);
+      return Err(AppError::Unauthorized);
+    }
+    if services.token.is_revoked(&claims).await? {
+      error!("token has been revoked");
+      return Err(AppError::Unauthorized);
     }
+    request.extensions_mut().insert(claims);
     return Ok(next.run(request).await);
   }
 
   error!("token not found in cookie");
   Err(AppError::Unauthorized)
 }
+
+/// Requires the user to have authenticated interactively within `jwt.reauthentication_window`.
+///
+/// Must be layered inside `authenticate_user`, which provides the `Claims`.
+async fn require_recent_authentication(
+  Extension(claims): Extension<Claims>,
+  request: Request<Body>,
+  next: Next,
+) -> Result<Response, AppError> {
+  if !authenticated_recently(&claims, config::get().jwt.reauthentication_window) {
+    error!("user {:?} must re-authenticate", claims.sub);
+    return Err(AppError::ReauthenticationRequired);
+  }
+  Ok(next.run(request).await)
+}
+
+/// Whether the user of `claims` authenticated interactively less than `window` seconds ago.
+fn authenticated_recently(claims: &Claims, window: usize) -> bool {
+  claims.auth_time + window >= jwt::now()
+}
+
+/// Must be layered inside `authenticate_user`, which provides the `Claims`.
+async fn require_admin(
+  Extension(services): Extension<Services>,
+  Extension(claims): Extension<Claims>,
+  request: Request<Body>,
+  next: Next,
+) -> Result<Response, AppError> {
+  let user = services.user.get_user_by_email(&claims.sub).await?;
+  if user.is_some_and(|user| user.role == UserRole::Admin) {
+    return Ok(next.run(request).await);
+  }
+
+  error!("user {:?} is not an admin", claims.sub);
+  Err(AppError::Forbidden)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use axum::http::{HeaderValue, header::COOKIE};
+  use utils::jwt::{TokenType, create_token};
+
+  const SECRET: &str = "secret";
+
+  fn tenants() -> TenantConfig {
+    TenantConfig {
+      enabled: true,
+      base_domain: Some("example.com".to_string()),
+      tenants: vec!["acme".to_string(), "globex".to_string()],
+      ..TenantConfig::default()
+    }
+  }
+
+  fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+      headers.insert(*name, HeaderValue::from_str(value).unwrap());
+    }
+    headers
+  }
+
+  fn resolve(pairs: &[(&'static str, &str)], cfg: &TenantConfig) -> Result<String, AppError> {
+    tenant_of(&headers(pairs), cfg, SECRET)
+  }
+
+  #[test]
+  fn resolves_tenants_from_the_sources_in_order() {
+    let cfg = tenants();
+    assert_eq!(resolve(&[("x-tenant-id", "ACME")], &cfg).unwrap(), "acme");
+    assert_eq!(
+      resolve(&[("host", "globex.example.com:8080")], &cfg).unwrap(),
+      "globex"
+    );
+    // The header comes first, and only the first label below the base domain is a tenant.
+    let both = [("x-tenant-id", "acme"), ("host", "globex.example.com")];
+    assert_eq!(resolve(&both, &cfg).unwrap(), "acme");
+    assert!(resolve(&[("host", "a.globex.example.com")], &cfg).is_err());
+  }
+
+  #[tokio::test]
+  async fn resolves_tenants_from_the_access_token() {
+    let token = tenant::scope("globex".to_string(), async {
+      create_token(SECRET, "ada@example.com", 60, TokenType::Access, "", 0)
+    })
+    .await;
+    let cookie = format!("access_token={token}");
+    assert_eq!(
+      resolve(&[(COOKIE.as_str(), &cookie)], &tenants()).unwrap(),
+      "globex"
+    );
+  }
+
+  #[test]
+  fn falls_back_to_the_default_tenant() {
+    let mut cfg = tenants();
+    assert!(matches!(resolve(&[], &cfg), Err(AppError::BadRequest(_))));
+    cfg.default_tenant = Some("acme".to_string());
+    assert_eq!(resolve(&[], &cfg).unwrap(), "acme");
+  }
+
+  #[test]
+  fn rejects_invalid_and_unknown_tenants() {
+    let cfg = tenants();
+    for tenant in ["initech", "../admin", "-acme", "acme_corp"] {
+      assert!(
+        matches!(
+          resolve(&[("x-tenant-id", tenant)], &cfg),
+          Err(AppError::BadRequest(_))
+        ),
+        "{tenant}"
+      );
+    }
+  }
+
+  #[test]
+  fn validates_the_accepted_tenants() {
+    assert!(tenants().validate().is_ok());
+    assert!(TenantConfig::default().validate().is_ok());
+    let mut cfg = tenants();
+    cfg.tenants.clear();
+    assert!(cfg.validate().is_err());
+    let mut cfg = tenants();
+    cfg.tenants.push("Initech".to_string());
+    assert!(cfg.validate().is_err());
+    let mut cfg = tenants();
+    cfg.default_tenant = Some("initech".to_string());
+    assert!(cfg.validate().is_err());
+  }
+
+  #[test]
+  fn sensitive_routes_need_a_recent_authentication() {
+    let claims = |auth_time| {
+      let token = create_token(
+        SECRET,
+        "ada@example.com",
+        60,
+        TokenType::Access,
+        "user",
+        auth_time,
+      );
+      decode_token(&token, SECRET).unwrap()
+    };
+    let now = jwt::now();
+    assert!(authenticated_recently(&claims(now), 300));
+    assert!(authenticated_recently(&claims(now - 200), 300));
+    assert!(!authenticated_recently(&claims(now - 400), 300));
+    // Tokens issued before `auth_time` existed never count as recent.
+    assert!(!authenticated_recently(&claims(0), 300));
+  }
+}
@@ -0,0 +1,81 @@
+use crate::{
+  dtos::oauth_dto::{IntrospectionResponse, TokenRequestDto},
+  extractors::{client_info::ClientInfo, validation_extractor::FormValidationExtractor},
+  services::Services,
+};
+use axum::{
+  Extension, Json, Router,
+  body::Body,
+  http::{Request, StatusCode},
+  middleware::{Next, from_fn},
+  response::Response,
+  routing::post,
+};
+use axum_extra::{
+  TypedHeader,
+  headers::{Authorization, authorization::Basic},
+};
+use tracing::error;
+use utils::{AppError, AppResult, config, config::oauth_config::OAuthClient};
+
+pub struct OAuthController;
+
+impl OAuthController {
+  pub fn app() -> Router {
+    Router::new()
+      .route("/introspect", post(Self::introspect))
+      .route("/revoke", post(Self::revoke))
+      .route_layer(from_fn(authenticate_client))
+  }
+
+  pub async fn introspect(
+    Extension(services): Extension<Services>,
+    FormValidationExtractor(req): FormValidationExtractor<TokenRequestDto>,
+  ) -> AppResult<Json<IntrospectionResponse>> {
+    let claims = services
+      .token
+      .introspect(&req.token, req.token_type_hint.as_deref())
+      .await?;
+    Ok(Json(claims.map_or_else(
+      IntrospectionResponse::inactive,
+      IntrospectionResponse::active,
+    )))
+  }
+
+  pub async fn revoke(
+    Extension(services): Extension<Services>,
+    Extension(oauth_client): Extension<OAuthClient>,
+    client: ClientInfo,
+    FormValidationExtractor(req): FormValidationExtractor<TokenRequestDto>,
+  ) -> AppResult<StatusCode> {
+    services
+      .token
+      .revoke(
+        &req.token,
+        req.token_type_hint.as_deref(),
+        &oauth_client.client_id,
+        &client,
+      )
+      .await?;
+    Ok(StatusCode::OK)
+  }
+}
+
+/// Authenticates the calling service with HTTP Basic credentials from `oauth.clients`.
+async fn authenticate_client(
+  authorization: Option<TypedHeader<Authorization<Basic>>>,
+  mut request: Request<Body>,
+  next: Next,
+) -> AppResult<Response> {
+  let cfg = config::get();
+  let Some(TypedHeader(Authorization(basic))) = authorization else {
+    error!("client credentials not found");
+    return Err(AppError::Unauthorized);
+  };
+  let Some(oauth_client) = cfg.oauth.authenticate(basic.username(), basic.password()) else {
+    error!("invalid credentials for client {:?}", basic.username());
+    return Err(AppError::Unauthorized);
+  };
+  request.extensions_mut().insert(oauth_client.clone());
+  Ok(next.run(request).await)
+}
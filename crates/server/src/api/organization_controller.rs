@@ -0,0 +1,150 @@
+use crate::{
+  api::authenticate_user,
+  dtos::{
+    IdOnlyDto,
+    organization_dto::{
+      ChangeMemberRoleDto, CreateOrganizationDto, InviteMemberDto, MemberResponse,
+      OrganizationInvitationResponse, OrganizationResponse,
+    },
+  },
+  extractors::{client_info::ClientInfo, validation_extractor::ValidationExtractor},
+  services::Services,
+};
+use axum::{
+  Extension, Json, Router,
+  body::Body,
+  extract::Path,
+  http::StatusCode,
+  middleware::from_fn,
+  routing::{delete, get, post, put},
+};
+use database::organization::model::OrganizationRole;
+use utils::{AppResult, jwt::Claims};
+
+pub struct OrganizationController;
+
+impl OrganizationController {
+  pub fn app() -> Router {
+    Router::new()
+      .route("/", get(Self::get_all))
+      .route("/create", post(Self::create))
+      .route("/invitations", get(Self::get_invitations))
+      .route("/invitations/accept", post(Self::accept_invitation))
+      .route("/:id/members", get(Self::get_members))
+      .route("/:id/members/role", put(Self::change_member_role))
+      .route("/:id/invitations", post(Self::invite_member))
+      .route("/:id/leave", delete(Self::leave))
+      .route_layer(from_fn(authenticate_user::<Body>))
+  }
+
+  pub async fn get_all(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+  ) -> AppResult<Json<Vec<OrganizationResponse>>> {
+    let organizations = services.organization.get_organizations(&claims.sub).await?;
+    Ok(Json(
+      organizations
+        .into_iter()
+        .map(OrganizationResponse::from)
+        .collect(),
+    ))
+  }
+
+  pub async fn create(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    ValidationExtractor(req): ValidationExtractor<CreateOrganizationDto>,
+  ) -> AppResult<Json<OrganizationResponse>> {
+    let organization = services
+      .organization
+      .create_organization(req, &claims.sub, &client)
+      .await?;
+    Ok(Json(OrganizationResponse::from((
+      organization,
+      OrganizationRole::Owner,
+    ))))
+  }
+
+  pub async fn get_members(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<String>,
+  ) -> AppResult<Json<Vec<MemberResponse>>> {
+    let members = services.organization.get_members(&id, &claims.sub).await?;
+    Ok(Json(
+      members.into_iter().map(MemberResponse::from).collect(),
+    ))
+  }
+
+  pub async fn change_member_role(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    Path(id): Path<String>,
+    ValidationExtractor(req): ValidationExtractor<ChangeMemberRoleDto>,
+  ) -> AppResult<StatusCode> {
+    services
+      .organization
+      .change_member_role(&id, req, &claims.sub, &client)
+      .await?;
+    Ok(StatusCode::NO_CONTENT)
+  }
+
+  pub async fn invite_member(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    Path(id): Path<String>,
+    ValidationExtractor(req): ValidationExtractor<InviteMemberDto>,
+  ) -> AppResult<Json<OrganizationInvitationResponse>> {
+    let invitation = services
+      .organization
+      .invite_member(&id, req, &claims.sub, &client)
+      .await?;
+    Ok(Json(OrganizationInvitationResponse::from(invitation)))
+  }
+
+  pub async fn leave(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    Path(id): Path<String>,
+  ) -> AppResult<StatusCode> {
+    services
+      .organization
+      .leave_organization(&id, &claims.sub, &client)
+      .await?;
+    Ok(StatusCode::NO_CONTENT)
+  }
+
+  pub async fn get_invitations(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+  ) -> AppResult<Json<Vec<OrganizationInvitationResponse>>> {
+    let invitations = services
+      .organization
+      .get_pending_invitations(&claims.sub)
+      .await?;
+    Ok(Json(
+      invitations
+        .into_iter()
+        .map(OrganizationInvitationResponse::from)
+        .collect(),
+    ))
+  }
+
+  pub async fn accept_invitation(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    ValidationExtractor(req): ValidationExtractor<IdOnlyDto>,
+  ) -> AppResult<Json<MemberResponse>> {
+    let id = req.id.unwrap();
+    let membership = services
+      .organization
+      .accept_invitation(&id, &claims.sub, &client)
+      .await?;
+    Ok(Json(MemberResponse::from((membership, None))))
+  }
+}
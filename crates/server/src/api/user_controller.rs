@@ -1,29 +1,55 @@
 use crate::{
-  api::authenticate_user,
+  api::{authenticate_user, require_admin, require_recent_authentication},
   dtos::{
     EmailOnlyDto, IdOnlyDto,
-    user_dto::{ChangePasswordDto, LoginInDto, SignUpUserDto, UpdateUserDto, UserResponse},
+    user_dto::{
+      ChangePasswordDto, LoginInDto, ReauthenticateDto, SignUpUserDto, UpdateUserDto,
+      UserExportQueryDto, UserImportQueryDto, UserImportReport, UserQueryDto, UserResponse,
+      UserSearchDto, UserSearchHitResponse, UserTransferFormat,
+    },
+  },
+  extractors::{
+    client_info::ClientInfo,
+    if_match::{IfMatch, etag},
+    pagination::PaginationQuery,
+    validation_extractor::{QueryValidationExtractor, ValidationExtractor},
   },
-  extractors::validation_extractor::ValidationExtractor,
   services::Services,
 };
 use axum::{
   Extension, Json, Router,
   body::Body,
+  extract::Path,
   http::{
     HeaderMap, StatusCode,
-    header::{COOKIE, SET_COOKIE},
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE, COOKIE, ETAG, SET_COOKIE},
   },
   middleware::from_fn,
-  response::IntoResponse,
+  response::{IntoResponse, Response},
   routing::{delete, get, post, put},
 };
+use axum_extra::{TypedHeader, headers};
 use database::user::model::{LoginResponse, User};
-use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
-use utils::{AppResult, cookie::Cookie};
+use mongodb::results::{InsertOneResult, UpdateResult};
+use tokio_stream::wrappers::ReceiverStream;
+use utils::{
+  AppResult, config,
+  cookie::Cookie,
+  jwt::{Claims, decode_token},
+};
 
 pub struct UserController;
 
+/// Responds with `user` and its version as `ETag`.
+fn with_etag(user: Option<User>) -> Response {
+  let version = user.as_ref().map(|user| user.version);
+  let mut response = Json(user).into_response();
+  if let Some(version) = version {
+    response.headers_mut().insert(ETAG, etag(version));
+  }
+  response
+}
+
 impl UserController {
   pub fn app() -> Router {
     let unprotected = Router::new()
@@ -33,29 +59,68 @@ impl UserController {
       .route("/refresh-token", post(Self::refresh_token));
     let protected = Router::new()
       .route("/", get(Self::get_all))
+      .route("/search", get(Self::search))
       .route("/get", get(Self::get_by_id))
       .route("/get/:email", get(Self::get_by_email))
       .route("/update", put(Self::update))
+      .route("/reauthenticate", post(Self::reauthenticate))
+      .route_layer(from_fn(authenticate_user::<Body>));
+    let sensitive = Router::new()
       .route("/change-password", put(Self::change_password))
       .route("/delete", delete(Self::delete))
+      .route_layer(from_fn(require_recent_authentication))
       .route_layer(from_fn(authenticate_user::<Body>));
-    unprotected.merge(protected)
+    let admin = Router::new()
+      .route("/deleted", get(Self::get_deleted))
+      .route("/:id/restore", post(Self::restore))
+      .route("/import", post(Self::import))
+      .route("/export", get(Self::export))
+      .route_layer(from_fn(require_admin))
+      .route_layer(from_fn(authenticate_user::<Body>));
+    unprotected.merge(protected).merge(sensitive).merge(admin)
   }
 
   pub async fn signup(
     Extension(services): Extension<Services>,
+    client: ClientInfo,
     ValidationExtractor(req): ValidationExtractor<SignUpUserDto>,
   ) -> AppResult<Json<InsertOneResult>> {
-    let created_user = services.user.signup_user(req).await?;
+    let created_user = services.user.signup_user(req, &client).await?;
     Ok(Json(created_user))
   }
 
   pub async fn login(
     Extension(services): Extension<Services>,
+    client: ClientInfo,
     ValidationExtractor(req): ValidationExtractor<LoginInDto>,
   ) -> AppResult<impl IntoResponse> {
     let (access_token, access_cookie, refresh_token, refresh_cookie) =
-      services.user.login_user(req).await?;
+      services.user.login_user(req, &client).await?;
+    let login_response = LoginResponse {
+      access_token,
+      refresh_token,
+    };
+    let mut response = Json(login_response).into_response();
+
+    response
+      .headers_mut()
+      .insert(SET_COOKIE, access_cookie.to_string().parse().unwrap());
+    response
+      .headers_mut()
+      .append(SET_COOKIE, refresh_cookie.to_string().parse().unwrap());
+    Ok(response)
+  }
+
+  pub async fn reauthenticate(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    ValidationExtractor(req): ValidationExtractor<ReauthenticateDto>,
+  ) -> AppResult<impl IntoResponse> {
+    let (access_token, access_cookie, refresh_token, refresh_cookie) = services
+      .user
+      .reauthenticate(req, &claims.sub, &client)
+      .await?;
     let login_response = LoginResponse {
       access_token,
       refresh_token,
@@ -71,8 +136,19 @@ impl UserController {
     Ok(response)
   }
 
-  pub async fn logout(Extension(services): Extension<Services>) -> AppResult<impl IntoResponse> {
-    let (access_cookie, refresh_cookie) = services.user.logout_user().await?;
+  pub async fn logout(
+    Extension(services): Extension<Services>,
+    client: ClientInfo,
+    cookie: Option<TypedHeader<headers::Cookie>>,
+  ) -> AppResult<impl IntoResponse> {
+    // Logout does not require a valid session, the actor is only recorded when one is present.
+    let cfg = config::get();
+    let actor = cookie
+      .as_ref()
+      .and_then(|TypedHeader(cookie)| cookie.get("access_token"))
+      .and_then(|token| decode_token(token, &cfg.jwt.access_token_secret).ok())
+      .map(|claims| claims.sub);
+    let (access_cookie, refresh_cookie) = services.user.logout_user(actor, &client).await?;
     let mut response = StatusCode::OK.into_response();
     response
       .headers_mut()
@@ -124,52 +200,148 @@ impl UserController {
 
   pub async fn get_all(
     Extension(services): Extension<Services>,
-  ) -> AppResult<Json<Vec<UserResponse>>> {
-    let users = services.user.get_all_users().await?;
-    let users2: Vec<UserResponse> = users.into_iter().map(|u| UserResponse::from(u)).collect();
-    Ok(Json(users2))
+    page: PaginationQuery,
+    QueryValidationExtractor(req): QueryValidationExtractor<UserQueryDto>,
+  ) -> AppResult<Response> {
+    let (users, total) = services.user.find_users(req, &page).await?;
+    let data = users.items.into_iter().map(UserResponse::from).collect();
+    Ok(page.response::<UserResponse>(data, users.next_cursor, total))
+  }
+
+  pub async fn search(
+    Extension(services): Extension<Services>,
+    page: PaginationQuery,
+    QueryValidationExtractor(req): QueryValidationExtractor<UserSearchDto>,
+  ) -> AppResult<Response> {
+    let results = services.user.search_users(req, &page).await?;
+    let data = results
+      .hits
+      .into_iter()
+      .map(UserSearchHitResponse::from)
+      .collect();
+    Ok(page.numbered_response::<UserSearchHitResponse>(data, results.has_more, results.total))
   }
 
   pub async fn get_by_id(
     Extension(services): Extension<Services>,
     ValidationExtractor(req): ValidationExtractor<IdOnlyDto>,
-  ) -> AppResult<Json<Option<User>>> {
+  ) -> AppResult<impl IntoResponse> {
     let id = req.id.unwrap();
     let user = services.user.get_user_by_id(&id).await?;
-    Ok(Json(user))
+    Ok(with_etag(user))
   }
 
   pub async fn get_by_email(
     Extension(services): Extension<Services>,
     ValidationExtractor(req): ValidationExtractor<EmailOnlyDto>,
-  ) -> AppResult<Json<Option<User>>> {
+  ) -> AppResult<impl IntoResponse> {
     let email = req.email.unwrap();
     let user = services.user.get_user_by_email(&email).await?;
-    Ok(Json(user))
+    Ok(with_etag(user))
   }
 
   pub async fn update(
     Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    IfMatch(expected_version): IfMatch,
     ValidationExtractor(req): ValidationExtractor<UpdateUserDto>,
-  ) -> AppResult<Json<UpdateResult>> {
-    let result = services.user.update_user(req).await?;
-    Ok(Json(result))
+  ) -> AppResult<impl IntoResponse> {
+    let user = services
+      .user
+      .update_user(req, expected_version, &claims.sub, &client)
+      .await?;
+    Ok(([(ETAG, etag(user.version))], Json(UserResponse::from(user))))
   }
 
   pub async fn change_password(
     Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
     ValidationExtractor(req): ValidationExtractor<ChangePasswordDto>,
   ) -> AppResult<Json<UpdateResult>> {
-    let result = services.user.change_password(req).await?;
+    let result = services
+      .user
+      .change_password(req, &claims.sub, &client)
+      .await?;
     Ok(Json(result))
   }
 
   pub async fn delete(
     Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    IfMatch(expected_version): IfMatch,
     ValidationExtractor(req): ValidationExtractor<IdOnlyDto>,
-  ) -> AppResult<Json<DeleteResult>> {
+  ) -> AppResult<StatusCode> {
     let id = req.id.unwrap();
-    let result = services.user.delete_user(&id).await?;
-    Ok(Json(result))
+    services
+      .user
+      .delete_user(&id, expected_version, &claims.sub, &client)
+      .await?;
+    Ok(StatusCode::NO_CONTENT)
+  }
+
+  pub async fn get_deleted(
+    Extension(services): Extension<Services>,
+    page: PaginationQuery,
+    QueryValidationExtractor(req): QueryValidationExtractor<UserQueryDto>,
+  ) -> AppResult<Response> {
+    let (users, total) = services.user.find_deleted_users(req, &page).await?;
+    let data = users.items.into_iter().map(UserResponse::from).collect();
+    Ok(page.response::<UserResponse>(data, users.next_cursor, total))
+  }
+
+  pub async fn restore(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    Path(id): Path<String>,
+  ) -> AppResult<StatusCode> {
+    services
+      .user
+      .restore_user(&id, &claims.sub, &client)
+      .await?;
+    Ok(StatusCode::NO_CONTENT)
+  }
+
+  pub async fn import(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    QueryValidationExtractor(query): QueryValidationExtractor<UserImportQueryDto>,
+    headers: HeaderMap,
+    body: String,
+  ) -> AppResult<Json<UserImportReport>> {
+    let content_type = headers
+      .get(CONTENT_TYPE)
+      .and_then(|value| value.to_str().ok());
+    let report = services
+      .bulk_user
+      .import_users(&body, query, content_type, &claims.sub, &client)
+      .await?;
+    Ok(Json(report))
+  }
+
+  pub async fn export(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    QueryValidationExtractor(query): QueryValidationExtractor<UserExportQueryDto>,
+    QueryValidationExtractor(filter): QueryValidationExtractor<UserQueryDto>,
+  ) -> AppResult<Response> {
+    let format = query.format.unwrap_or(UserTransferFormat::Csv);
+    let batches = services
+      .bulk_user
+      .export_users(filter, format, &claims.sub, &client)
+      .await?;
+    let headers = [
+      (CONTENT_TYPE, format.content_type().to_string()),
+      (
+        CONTENT_DISPOSITION,
+        format!("attachment; filename=\"users.{}\"", format.extension()),
+      ),
+    ];
+    Ok((headers, Body::from_stream(ReceiverStream::new(batches))).into_response())
   }
 }
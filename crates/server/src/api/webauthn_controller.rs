@@ -0,0 +1,128 @@
+use crate::{
+  api::{authenticate_user, require_recent_authentication},
+  dtos::webauthn_dto::{
+    AuthenticationOptions, PasskeyLoginDto, PasskeyResponse, RegisterPasskeyDto,
+    RegistrationOptions, RenamePasskeyDto,
+  },
+  extractors::{client_info::ClientInfo, validation_extractor::ValidationExtractor},
+  services::Services,
+};
+use axum::{
+  Extension, Json, Router,
+  body::Body,
+  extract::Path,
+  http::{StatusCode, header::SET_COOKIE},
+  middleware::from_fn,
+  response::IntoResponse,
+  routing::{delete, get, post, put},
+};
+use database::user::model::LoginResponse;
+use utils::{AppResult, jwt::Claims};
+
+pub struct WebauthnController;
+
+impl WebauthnController {
+  pub fn app() -> Router {
+    let unprotected = Router::new()
+      .route("/login/start", post(Self::start_login))
+      .route("/login/finish", post(Self::finish_login));
+    let protected = Router::new()
+      .route("/credentials", get(Self::get_credentials))
+      .route_layer(from_fn(authenticate_user::<Body>));
+    // A stolen access token must not be enough to enroll or remove a passkey.
+    let sensitive = Router::new()
+      .route("/register/start", post(Self::start_registration))
+      .route("/register/finish", post(Self::finish_registration))
+      .route("/credentials/:id", put(Self::rename_credential))
+      .route("/credentials/:id", delete(Self::remove_credential))
+      .route_layer(from_fn(require_recent_authentication))
+      .route_layer(from_fn(authenticate_user::<Body>));
+    unprotected.merge(protected).merge(sensitive)
+  }
+
+  pub async fn start_registration(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+  ) -> AppResult<Json<RegistrationOptions>> {
+    let options = services.webauthn.start_registration(&claims.sub).await?;
+    Ok(Json(options))
+  }
+
+  pub async fn finish_registration(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    ValidationExtractor(req): ValidationExtractor<RegisterPasskeyDto>,
+  ) -> AppResult<Json<PasskeyResponse>> {
+    let credential = services
+      .webauthn
+      .finish_registration(req, &claims.sub, &client)
+      .await?;
+    Ok(Json(PasskeyResponse::from(credential)))
+  }
+
+  pub async fn start_login(
+    Extension(services): Extension<Services>,
+  ) -> AppResult<Json<AuthenticationOptions>> {
+    let options = services.webauthn.start_login().await?;
+    Ok(Json(options))
+  }
+
+  pub async fn finish_login(
+    Extension(services): Extension<Services>,
+    client: ClientInfo,
+    ValidationExtractor(req): ValidationExtractor<PasskeyLoginDto>,
+  ) -> AppResult<impl IntoResponse> {
+    let (access_token, access_cookie, refresh_token, refresh_cookie) =
+      services.webauthn.finish_login(req, &client).await?;
+    let login_response = LoginResponse {
+      access_token,
+      refresh_token,
+    };
+    let mut response = Json(login_response).into_response();
+
+    response
+      .headers_mut()
+      .insert(SET_COOKIE, access_cookie.to_string().parse().unwrap());
+    response
+      .headers_mut()
+      .append(SET_COOKIE, refresh_cookie.to_string().parse().unwrap());
+    Ok(response)
+  }
+
+  pub async fn get_credentials(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+  ) -> AppResult<Json<Vec<PasskeyResponse>>> {
+    let credentials = services.webauthn.get_credentials(&claims.sub).await?;
+    Ok(Json(
+      credentials.into_iter().map(PasskeyResponse::from).collect(),
+    ))
+  }
+
+  pub async fn rename_credential(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<String>,
+    ValidationExtractor(req): ValidationExtractor<RenamePasskeyDto>,
+  ) -> AppResult<StatusCode> {
+    services
+      .webauthn
+      .rename_credential(&id, &req.name, &claims.sub)
+      .await?;
+    Ok(StatusCode::NO_CONTENT)
+  }
+
+  pub async fn remove_credential(
+    Extension(services): Extension<Services>,
+    Extension(claims): Extension<Claims>,
+    client: ClientInfo,
+    Path(id): Path<String>,
+  ) -> AppResult<StatusCode> {
+    services
+      .webauthn
+      .remove_credential(&id, &claims.sub, &client)
+      .await?;
+    Ok(StatusCode::NO_CONTENT)
+  }
+}
@@ -1,11 +1,20 @@
 use crate::services::Services;
-use crate::{logger::Logger, router::AppRouter};
+use crate::{logger::Logger, router::AppRouter, tasks};
 use anyhow::Context;
 use axum::serve;
-use database::Database;
+use database::{
+  Database,
+  memory::MemoryDatabase,
+  migration::Migrator,
+  storage::{DynFileStorage, GridFsStorage, LocalStorage, S3Storage},
+};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::signal;
-use tracing::info;
-use utils::config;
+use tracing::{info, warn};
+use utils::{
+  config::{self, db_config::DbBackend, storage_config::StorageBackend},
+  tenant,
+};
 
 pub struct ApplicationServer;
 
@@ -25,18 +34,97 @@ impl ApplicationServer {
 
     info!("server has launched on {local_addr} 🚀");
 
-    let db = Database::new().await?;
-    let services = Services::new(db);
+    let (services, database) = match cfg.db.backend {
+      DbBackend::Mongo => {
+        let db = Database::new().await?;
+        if cfg.db.auto_migrate {
+          Self::migrate(&db).await?;
+        }
+        let services = Services::new(Arc::new(db.clone()), Self::file_storage(Some(&db))?);
+        if cfg.events.enabled {
+          tasks::spawn_user_events(db.clone(), &services);
+        }
+        (services, Some(db))
+      }
+      DbBackend::Memory => {
+        warn!("using the in-memory database, data will be lost on shutdown");
+        let services = Services::new(Arc::new(MemoryDatabase::new()), Self::file_storage(None)?);
+        (services, None)
+      }
+      DbBackend::Sql => (Self::sql_services(Self::file_storage(None)?).await?, None),
+    };
+    if cfg.events.enabled && cfg.db.backend != DbBackend::Mongo {
+      warn!("user events need the mongo backend, none will be published");
+    }
+    tasks::spawn_user_purge(services.clone(), database);
+    if cfg.outbox.enabled {
+      tasks::spawn_outbox_relay(services.clone());
+    }
     let router = AppRouter::init(services);
 
-    serve(tcp_listener, router)
-      .with_graceful_shutdown(Self::shutdown_signal())
-      .await
-      .context("Failed to start server")?;
+    serve(
+      tcp_listener,
+      router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(Self::shutdown_signal())
+    .await
+    .context("Failed to start server")?;
 
     Ok(())
   }
 
+  /// Applies the pending migrations to the configured database and to every tenant database.
+  async fn migrate(db: &Database) -> anyhow::Result<()> {
+    let applied = Migrator::new(db).up(None, false).await?;
+    info!("applied {} pending migrations", applied.len());
+    for tenant in db.tenants().await? {
+      // The migrator picks the database of the current tenant when created.
+      let migrate = async { Migrator::new(db).up(None, false).await };
+      let applied = tenant::scope(tenant.clone(), migrate).await?;
+      info!(
+        "applied {} pending migrations of tenant {tenant:?}",
+        applied.len()
+      );
+    }
+    Ok(())
+  }
+
+  #[cfg(any(feature = "sqlite", feature = "postgres"))]
+  async fn sql_services(storage: DynFileStorage) -> anyhow::Result<Services> {
+    let database = database::sql::SqlDatabase::connect().await?;
+    Ok(Services::new(Arc::new(database), storage))
+  }
+
+  #[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+  async fn sql_services(_storage: DynFileStorage) -> anyhow::Result<Services> {
+    anyhow::bail!("the sql backend needs the server built with the sqlite or postgres feature")
+  }
+
+  /// The storage of `storage.backend`. GridFS keeps files in `database`, only set with the
+  /// mongo backend.
+  fn file_storage(database: Option<&Database>) -> anyhow::Result<DynFileStorage> {
+    let cfg = &config::get().storage;
+    let storage: DynFileStorage = match cfg.backend {
+      StorageBackend::Local => Arc::new(LocalStorage::new(cfg.local.path.clone())),
+      StorageBackend::Gridfs => {
+        let database = database.context("the gridfs storage needs the mongo backend")?;
+        Arc::new(GridFsStorage::new(
+          database.clone(),
+          cfg.gridfs.bucket.clone(),
+        ))
+      }
+      StorageBackend::S3 => {
+        let s3 = cfg
+          .s3
+          .as_ref()
+          .context("the s3 storage needs [storage.s3]")?;
+        Arc::new(S3Storage::new(s3)?)
+      }
+    };
+    info!("storing files with the {:?} storage", cfg.backend);
+    Ok(storage)
+  }
+
   async fn shutdown_signal() {
     let ctrl_c = async {
       signal::ctrl_c()
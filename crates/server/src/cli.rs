@@ -0,0 +1,102 @@
+use crate::logger::Logger;
+use clap::{Parser, Subcommand};
+use database::{
+  Database,
+  migration::{MigrationStatus, Migrator},
+};
+use utils::{
+  config::{self, db_config::DbBackend},
+  tenant,
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Start the HTTP server. This is the default.
+  Serve,
+  /// Manage database migrations, of every tenant database too with `db.database_per_tenant`.
+  #[command(subcommand)]
+  Migrate(MigrateCommand),
+}
+
+#[derive(Subcommand, Debug, Clone, Copy)]
+pub enum MigrateCommand {
+  /// List migrations and whether they are applied.
+  Status,
+  /// Apply pending migrations.
+  Up {
+    /// Stop after this version.
+    #[arg(long)]
+    to: Option<i64>,
+    /// Only print the migrations that would be applied.
+    #[arg(long)]
+    dry_run: bool,
+  },
+  /// Revert applied migrations, only the latest one unless `--to` is given.
+  Down {
+    /// Revert every migration newer than this version.
+    #[arg(long)]
+    to: Option<i64>,
+    /// Only print the migrations that would be reverted.
+    #[arg(long)]
+    dry_run: bool,
+  },
+}
+
+fn print(migrations: &[MigrationStatus], verb: &str) {
+  if migrations.is_empty() {
+    println!("nothing to do");
+  }
+  for migration in migrations {
+    println!("{verb} {:>4} {}", migration.version, migration.name);
+  }
+}
+
+pub async fn migrate(command: MigrateCommand) -> anyhow::Result<()> {
+  let _guard = Logger::init();
+  if config::get().db.backend != DbBackend::Mongo {
+    anyhow::bail!("migrations only apply to the mongo backend");
+  }
+  let db = Database::new().await?;
+  run(&db, command).await?;
+  for tenant in db.tenants().await? {
+    println!("\ntenant {tenant}:");
+    tenant::scope(tenant, run(&db, command)).await?;
+  }
+  Ok(())
+}
+
+/// Runs `command` on the database of the current tenant.
+async fn run(db: &Database, command: MigrateCommand) -> anyhow::Result<()> {
+  let migrator = Migrator::new(db);
+
+  match command {
+    MigrateCommand::Status => {
+      for migration in migrator.status().await? {
+        let applied_at = migration
+          .applied_at
+          .and_then(|date| date.try_to_rfc3339_string().ok())
+          .unwrap_or_else(|| "pending".to_string());
+        println!(
+          "{:>4} {:<40} {applied_at}",
+          migration.version, migration.name
+        );
+      }
+    }
+    MigrateCommand::Up { to, dry_run } => {
+      let applied = migrator.up(to, dry_run).await?;
+      print(&applied, if dry_run { "would apply" } else { "applied" });
+    }
+    MigrateCommand::Down { to, dry_run } => {
+      let reverted = migrator.down(to, dry_run).await?;
+      print(&reverted, if dry_run { "would revert" } else { "reverted" });
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,49 @@
+use database::audit::model::{AuditAction, AuditEvent, AuditOutcome};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct AuditEventQueryDto {
+  pub actor: Option<String>,
+  pub target: Option<String>,
+  pub action: Option<AuditAction>,
+  pub outcome: Option<AuditOutcome>,
+  pub ip: Option<String>,
+  /// RFC 3339 lower bound (inclusive) on the event timestamp.
+  pub from: Option<String>,
+  /// RFC 3339 upper bound (inclusive) on the event timestamp.
+  pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct AuditEventResponse {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub actor: Option<String>,
+  pub target: Option<String>,
+  pub action: AuditAction,
+  pub ip: Option<String>,
+  pub user_agent: Option<String>,
+  pub outcome: AuditOutcome,
+  pub reason: Option<String>,
+  pub timestamp: String,
+}
+
+impl From<AuditEvent> for AuditEventResponse {
+  fn from(event: AuditEvent) -> Self {
+    Self {
+      id: event.id,
+      actor: event.actor,
+      target: event.target,
+      action: event.action,
+      ip: event.ip,
+      user_agent: event.user_agent,
+      outcome: event.outcome,
+      reason: event.reason,
+      timestamp: event.timestamp.try_to_rfc3339_string().unwrap_or_default(),
+    }
+  }
+}
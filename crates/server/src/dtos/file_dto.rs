@@ -0,0 +1,48 @@
+use bytes::Bytes;
+use database::{file::model::StoredFile, storage::ByteStream};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use std::ops::Range;
+
+/// A file received by `POST /files`, whose size the controller limited to
+/// `storage.max_file_size` while reading it.
+#[derive(Debug, Clone)]
+pub struct FileUpload {
+  pub name: String,
+  pub content_type: String,
+  pub content: Bytes,
+}
+
+/// A file being downloaded, all of it or the bytes of `range`.
+pub struct FileDownload {
+  pub file: StoredFile,
+  pub range: Option<Range<u64>>,
+  pub content: ByteStream,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct FileResponse {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub owner_id: ObjectId,
+  pub name: String,
+  pub content_type: String,
+  pub size: u64,
+  pub sha256: String,
+  pub created_at: String,
+}
+
+impl From<StoredFile> for FileResponse {
+  fn from(file: StoredFile) -> Self {
+    Self {
+      id: file.id,
+      owner_id: file.owner_id,
+      name: file.name,
+      content_type: file.content_type,
+      size: file.size,
+      sha256: file.sha256,
+      created_at: file.created_at.try_to_rfc3339_string().unwrap_or_default(),
+    }
+  }
+}
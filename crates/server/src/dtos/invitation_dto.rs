@@ -0,0 +1,52 @@
+use database::invitation::model::Invitation;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct CreateInvitationDto {
+  /// Restricts the invitation to a single email address.
+  #[validate(email(message = "email is invalid"))]
+  pub email: Option<String>,
+  /// Lifetime in seconds, defaults to `signup.invitation_expiry`.
+  #[validate(range(min = 60))]
+  pub expires_in: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct InvitationResponse {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub code: String,
+  pub email: Option<String>,
+  pub created_by: String,
+  pub created_at: String,
+  pub expires_at: String,
+  pub used_at: Option<String>,
+  pub used_by: Option<String>,
+}
+
+impl From<Invitation> for InvitationResponse {
+  fn from(invitation: Invitation) -> Self {
+    Self {
+      id: invitation.id,
+      code: invitation.code,
+      email: invitation.email,
+      created_by: invitation.created_by,
+      created_at: invitation
+        .created_at
+        .try_to_rfc3339_string()
+        .unwrap_or_default(),
+      expires_at: invitation
+        .expires_at
+        .try_to_rfc3339_string()
+        .unwrap_or_default(),
+      used_at: invitation
+        .used_at
+        .and_then(|used_at| used_at.try_to_rfc3339_string().ok()),
+      used_by: invitation.used_by,
+    }
+  }
+}
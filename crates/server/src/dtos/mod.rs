@@ -1,7 +1,13 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use validator::Validate;
 
+pub mod audit_dto;
+pub mod file_dto;
+pub mod invitation_dto;
+pub mod oauth_dto;
+pub mod organization_dto;
 pub mod user_dto;
+pub mod webauthn_dto;
 
 #[derive(Clone, Deserialize, Debug, Validate, Default)]
 #[allow(clippy::module_name_repetitions)]
@@ -16,3 +22,16 @@ pub struct EmailOnlyDto {
   #[validate(length(min = 1), email(message = "email is invalid"))]
   pub email: Option<String>,
 }
+
+#[derive(Clone, Serialize, Debug)]
+pub struct PageResponse<T> {
+  pub data: Vec<T>,
+  /// Unset when paging with cursors.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub page: Option<u64>,
+  pub limit: i64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub total: Option<u64>,
+  /// Cursor of the next page, unset on the last page.
+  pub next_cursor: Option<String>,
+}
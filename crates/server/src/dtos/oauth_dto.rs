@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use utils::jwt::{Claims, TokenType};
+use validator::Validate;
+
+/// Body of the introspection (RFC 7662) and revocation (RFC 7009) requests.
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct TokenRequestDto {
+  #[validate(length(min = 1))]
+  pub token: String,
+  /// `access_token` or `refresh_token`, only used to decide which is tried first.
+  pub token_type_hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct IntrospectionResponse {
+  pub active: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub client_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub username: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub token_type: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub exp: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub iat: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sub: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub jti: Option<String>,
+}
+
+impl IntrospectionResponse {
+  /// Describes an active token. `client_id` is the client the token was issued to, absent for
+  /// the tokens of the first-party login.
+  pub fn active(claims: Claims) -> Self {
+    let token_type = match claims.token_type {
+      TokenType::Access => "access_token",
+      TokenType::Refresh => "refresh_token",
+    };
+    Self {
+      active: true,
+      scope: claims.scope,
+      client_id: claims.client_id,
+      username: Some(claims.sub.clone()),
+      token_type: Some(token_type.to_string()),
+      exp: Some(claims.exp),
+      iat: Some(claims.iat),
+      sub: Some(claims.sub),
+      jti: claims.jti,
+    }
+  }
+
+  /// Per RFC 7662, nothing but `active` is disclosed for unusable tokens.
+  pub fn inactive() -> Self {
+    Self::default()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn inactive_tokens_disclose_nothing_else() {
+    let response = serde_json::to_value(IntrospectionResponse::inactive()).unwrap();
+    assert_eq!(response, json!({ "active": false }));
+  }
+}
@@ -0,0 +1,106 @@
+use database::{
+  organization::model::{Membership, Organization, OrganizationInvitation, OrganizationRole},
+  user::model::User,
+};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct CreateOrganizationDto {
+  #[validate(required, length(min = 1, max = 100))]
+  pub name: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, Validate)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ChangeMemberRoleDto {
+  #[validate(length(min = 1))]
+  pub user_id: String,
+  pub role: OrganizationRole,
+}
+
+#[derive(Clone, Deserialize, Debug, Validate)]
+#[allow(clippy::module_name_repetitions)]
+pub struct InviteMemberDto {
+  #[validate(length(min = 1), email(message = "email is invalid"))]
+  pub email: String,
+  pub role: OrganizationRole,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct OrganizationResponse {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub name: String,
+  /// Role of the current user in the organization.
+  pub role: OrganizationRole,
+  pub created_at: String,
+}
+
+impl From<(Organization, OrganizationRole)> for OrganizationResponse {
+  fn from((organization, role): (Organization, OrganizationRole)) -> Self {
+    Self {
+      id: organization.id,
+      name: organization.name,
+      role,
+      created_at: organization
+        .created_at
+        .try_to_rfc3339_string()
+        .unwrap_or_default(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct MemberResponse {
+  pub user_id: ObjectId,
+  pub name: Option<String>,
+  pub email: Option<String>,
+  pub role: OrganizationRole,
+  pub joined_at: String,
+}
+
+impl From<(Membership, Option<User>)> for MemberResponse {
+  fn from((membership, user): (Membership, Option<User>)) -> Self {
+    Self {
+      user_id: membership.user_id,
+      name: user.as_ref().map(|user| user.name.clone()),
+      email: user.map(|user| user.email),
+      role: membership.role,
+      joined_at: membership
+        .created_at
+        .try_to_rfc3339_string()
+        .unwrap_or_default(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct OrganizationInvitationResponse {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub organization_id: ObjectId,
+  pub email: String,
+  pub role: OrganizationRole,
+  pub expires_at: String,
+}
+
+impl From<OrganizationInvitation> for OrganizationInvitationResponse {
+  fn from(invitation: OrganizationInvitation) -> Self {
+    Self {
+      id: invitation.id,
+      organization_id: invitation.organization_id,
+      email: invitation.email,
+      role: invitation.role,
+      expires_at: invitation
+        .expires_at
+        .try_to_rfc3339_string()
+        .unwrap_or_default(),
+    }
+  }
+}
@@ -1,6 +1,10 @@
-use database::user::model::User;
-use mongodb::bson::oid::ObjectId;
+use database::user::{
+  model::{User, UserRole},
+  search::UserSearchHit,
+};
+use mongodb::bson::{DateTime, oid::ObjectId};
 use serde::{Deserialize, Serialize};
+use utils::{AppError, AppResult, ErrorMap};
 use validator::Validate;
 
 #[derive(Clone, Serialize, Deserialize, Debug, Validate, Default)]
@@ -12,6 +16,9 @@ pub struct SignUpUserDto {
   pub email: Option<String>,
   #[validate(required, length(min = 6))]
   pub password: Option<String>,
+  /// Required when `signup.mode` is `invite_only`.
+  #[validate(length(min = 1))]
+  pub invitation_code: Option<String>,
 }
 
 #[derive(Clone, Deserialize, Debug, Validate, Default)]
@@ -23,6 +30,13 @@ pub struct LoginInDto {
   pub password: Option<String>,
 }
 
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ReauthenticateDto {
+  #[validate(required)]
+  pub password: Option<String>,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Validate, Default)]
 #[allow(clippy::module_name_repetitions)]
 pub struct UpdateUserDto {
@@ -52,14 +66,214 @@ pub struct UserResponse {
   pub name: String,
   #[validate(length(min = 1), email(message = "email is invalid"))]
   pub email: String,
+  pub role: UserRole,
+  pub version: i64,
+  pub created_at: Option<String>,
+  pub updated_at: Option<String>,
+  pub last_login_at: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub deleted_at: Option<String>,
 }
 
 impl From<User> for UserResponse {
   fn from(user: User) -> Self {
+    let rfc3339 = |date: Option<DateTime>| date.and_then(|date| date.try_to_rfc3339_string().ok());
     Self {
       id: user.id,
       name: user.name,
       email: user.email,
+      role: user.role,
+      version: user.version,
+      created_at: rfc3339(user.created_at),
+      updated_at: rfc3339(user.updated_at),
+      last_login_at: rfc3339(user.last_login_at),
+      deleted_at: rfc3339(user.deleted_at),
+    }
+  }
+}
+
+/// Filters of `GET /users`, pagination is read by `PaginationQuery`.
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserQueryDto {
+  pub name: Option<String>,
+  pub email: Option<String>,
+  pub role: Option<UserRole>,
+  /// RFC 3339 lower bound (inclusive) on the creation date.
+  pub created_from: Option<String>,
+  /// RFC 3339 upper bound (inclusive) on the creation date.
+  pub created_to: Option<String>,
+}
+
+/// Query of `GET /users/search`, pagination is read by `PaginationQuery`.
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserSearchDto {
+  /// Words matching the start of words of the name or email.
+  #[validate(required, length(min = 2, max = 100))]
+  pub q: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserSearchHitResponse {
+  #[serde(flatten)]
+  pub user: UserResponse,
+  /// Relevance of the user, higher is better. Only comparable within one search.
+  pub score: f64,
+}
+
+impl From<UserSearchHit> for UserSearchHitResponse {
+  fn from(hit: UserSearchHit) -> Self {
+    Self {
+      user: UserResponse::from(hit.user),
+      score: hit.score,
+    }
+  }
+}
+
+/// File format of `POST /users/import` and `GET /users/export`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserTransferFormat {
+  /// Comma separated values with a header row.
+  Csv,
+  /// One JSON object per line.
+  Ndjson,
+}
+
+impl UserTransferFormat {
+  pub fn content_type(self) -> &'static str {
+    match self {
+      Self::Csv => "text/csv; charset=utf-8",
+      Self::Ndjson => "application/x-ndjson",
+    }
+  }
+
+  pub fn extension(self) -> &'static str {
+    match self {
+      Self::Csv => "csv",
+      Self::Ndjson => "ndjson",
+    }
+  }
+
+  /// The format named by `format`, or else the one of `content_type`.
+  pub fn resolve(format: Option<Self>, content_type: Option<&str>) -> AppResult<Self> {
+    if let Some(format) = format {
+      return Ok(format);
+    }
+    let essence = content_type
+      .and_then(|content_type| content_type.split(';').next())
+      .map(str::trim);
+    match essence {
+      Some("text/csv") => Ok(Self::Csv),
+      Some("application/x-ndjson" | "application/ndjson" | "application/jsonl") => Ok(Self::Ndjson),
+      _ => Err(AppError::BadRequest(
+        "format must be csv or ndjson, set with ?format= or the Content-Type".to_string(),
+      )),
+    }
+  }
+}
+
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserImportQueryDto {
+  /// Defaults to the format of the `Content-Type`.
+  pub format: Option<UserTransferFormat>,
+  /// Validates the rows without creating anything.
+  pub dry_run: Option<bool>,
+  /// Invites the users of rows without a password instead of rejecting them.
+  pub invite: Option<bool>,
+}
+
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserExportQueryDto {
+  /// Defaults to `csv`.
+  pub format: Option<UserTransferFormat>,
+}
+
+/// One row of an import, a CSV record or an NDJSON line.
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserImportRecordDto {
+  #[validate(required, length(min = 1))]
+  pub name: Option<String>,
+  #[validate(required, length(min = 1), email(message = "email is invalid"))]
+  pub email: Option<String>,
+  /// When unset, the user is invited if the import allows it.
+  #[validate(length(min = 6))]
+  pub password: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserImportStatus {
+  Created,
+  Invited,
+  /// Valid, the user would be created without `dry_run`.
+  WillCreate,
+  /// Valid, the user would be invited without `dry_run`.
+  WillInvite,
+  Failed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserImportRowReport {
+  /// Line of the row in the file, starting at 1.
+  pub line: u64,
+  pub email: Option<String>,
+  pub status: UserImportStatus,
+  /// Id of the created user.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  /// Code of the invitation sent instead of creating the user.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub invitation_code: Option<String>,
+  /// Error messages by field, like those of a rejected request. Errors about the whole row are
+  /// reported under `row`.
+  #[serde(skip_serializing_if = "ErrorMap::is_empty")]
+  pub errors: ErrorMap,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserImportReport {
+  pub dry_run: bool,
+  pub rows: usize,
+  pub created: usize,
+  pub invited: usize,
+  pub failed: usize,
+  pub results: Vec<UserImportRowReport>,
+}
+
+/// A user as exported, without its password.
+#[derive(Clone, Debug, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct UserExportRow {
+  pub id: String,
+  pub name: String,
+  pub email: String,
+  pub role: UserRole,
+  pub version: i64,
+  pub created_at: Option<String>,
+  pub updated_at: Option<String>,
+  pub last_login_at: Option<String>,
+}
+
+impl From<User> for UserExportRow {
+  fn from(user: User) -> Self {
+    let user = UserResponse::from(user);
+    Self {
+      id: user.id.map(|id| id.to_hex()).unwrap_or_default(),
+      name: user.name,
+      email: user.email,
+      role: user.role,
+      version: user.version,
+      created_at: user.created_at,
+      updated_at: user.updated_at,
+      last_login_at: user.last_login_at,
     }
   }
 }
@@ -0,0 +1,145 @@
+use database::webauthn::model::WebauthnCredential;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Options passed to `navigator.credentials.create()`, binary fields are base64url encoded.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationOptions {
+  pub challenge: String,
+  pub rp: RelyingParty,
+  pub user: UserEntity,
+  pub pub_key_cred_params: Vec<CredentialParameter>,
+  /// Milliseconds.
+  pub timeout: usize,
+  pub attestation: &'static str,
+  pub exclude_credentials: Vec<CredentialDescriptor>,
+  pub authenticator_selection: AuthenticatorSelection,
+}
+
+/// Options passed to `navigator.credentials.get()`, binary fields are base64url encoded.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticationOptions {
+  pub challenge: String,
+  /// Milliseconds.
+  pub timeout: usize,
+  pub rp_id: String,
+  pub allow_credentials: Vec<CredentialDescriptor>,
+  pub user_verification: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RelyingParty {
+  pub id: String,
+  pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+  pub id: String,
+  pub name: String,
+  pub display_name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialParameter {
+  #[serde(rename = "type")]
+  pub type_: &'static str,
+  pub alg: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialDescriptor {
+  #[serde(rename = "type")]
+  pub type_: &'static str,
+  pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+  pub resident_key: &'static str,
+  pub user_verification: &'static str,
+}
+
+#[derive(Clone, Deserialize, Debug, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+  #[serde(rename = "clientDataJSON")]
+  #[validate(length(min = 1))]
+  pub client_data_json: String,
+  #[validate(length(min = 1))]
+  pub attestation_object: String,
+}
+
+#[derive(Clone, Deserialize, Debug, Validate)]
+#[allow(clippy::module_name_repetitions)]
+pub struct RegisterPasskeyDto {
+  /// Base64url encoded credential id.
+  #[validate(length(min = 1))]
+  pub id: String,
+  #[validate(nested)]
+  pub response: AttestationResponse,
+  #[validate(length(min = 1, max = 100))]
+  pub name: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+  #[serde(rename = "clientDataJSON")]
+  #[validate(length(min = 1))]
+  pub client_data_json: String,
+  #[validate(length(min = 1))]
+  pub authenticator_data: String,
+  #[validate(length(min = 1))]
+  pub signature: String,
+}
+
+#[derive(Clone, Deserialize, Debug, Validate)]
+#[allow(clippy::module_name_repetitions)]
+pub struct PasskeyLoginDto {
+  /// Base64url encoded credential id.
+  #[validate(length(min = 1))]
+  pub id: String,
+  #[validate(nested)]
+  pub response: AssertionResponse,
+}
+
+#[derive(Clone, Deserialize, Debug, Validate)]
+#[allow(clippy::module_name_repetitions)]
+pub struct RenamePasskeyDto {
+  #[validate(length(min = 1, max = 100))]
+  pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[allow(clippy::module_name_repetitions)]
+pub struct PasskeyResponse {
+  #[serde(rename = "_id")]
+  pub id: Option<ObjectId>,
+  pub credential_id: String,
+  pub name: String,
+  pub created_at: String,
+  pub last_used_at: Option<String>,
+}
+
+impl From<WebauthnCredential> for PasskeyResponse {
+  fn from(credential: WebauthnCredential) -> Self {
+    Self {
+      id: credential.id,
+      credential_id: credential.credential_id,
+      name: credential.name,
+      created_at: credential
+        .created_at
+        .try_to_rfc3339_string()
+        .unwrap_or_default(),
+      last_used_at: credential
+        .last_used_at
+        .and_then(|date| date.try_to_rfc3339_string().ok()),
+    }
+  }
+}
@@ -0,0 +1,135 @@
+use axum::{
+  async_trait,
+  extract::{ConnectInfo, FromRequestParts},
+  http::{HeaderMap, header::USER_AGENT, request::Parts},
+};
+use std::{
+  convert::Infallible,
+  net::{IpAddr, SocketAddr},
+};
+use utils::config;
+
+/// Network details about the caller, recorded alongside audit events.
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+  pub ip: Option<String>,
+  pub user_agent: Option<String>,
+}
+
+fn header_str(headers: &HeaderMap, name: &str) -> Option<String> {
+  headers
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+    .map(|value| value.trim().to_string())
+    .filter(|value| !value.is_empty())
+}
+
+/// The address of the client that connected from `peer`.
+///
+/// Proxy headers can be forged by anyone, so they are only read from the proxies `trusted`
+/// accepts, see `trusted_proxies`. The client is then the last address of `X-Forwarded-For`
+/// that is not a trusted proxy itself, since each proxy appends the address it received the
+/// request from.
+fn client_ip(headers: &HeaderMap, peer: IpAddr, trusted: impl Fn(&IpAddr) -> bool) -> IpAddr {
+  if !trusted(&peer) {
+    return peer;
+  }
+  if let Some(forwarded) = header_str(headers, "x-forwarded-for") {
+    let mut client = peer;
+    for hop in forwarded.rsplit(',') {
+      let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+        break;
+      };
+      client = hop;
+      if !trusted(&hop) {
+        break;
+      }
+    }
+    return client;
+  }
+  header_str(headers, "x-real-ip")
+    .and_then(|ip| ip.parse().ok())
+    .unwrap_or(peer)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+  S: Send + Sync,
+{
+  type Rejection = Infallible;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    let peer = parts
+      .extensions
+      .get::<ConnectInfo<SocketAddr>>()
+      .map(|ConnectInfo(addr)| addr.ip());
+    let cfg = config::get();
+    let ip =
+      peer.map(|peer| client_ip(&parts.headers, peer, |ip| cfg.is_trusted_proxy(ip)).to_string());
+    let user_agent = header_str(&parts.headers, USER_AGENT.as_str());
+
+    Ok(ClientInfo { ip, user_agent })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use axum::http::HeaderValue;
+
+  /// Proxies are in 10.0.0.0/8.
+  fn trusted(ip: &IpAddr) -> bool {
+    matches!(ip, IpAddr::V4(ip) if ip.octets()[0] == 10)
+  }
+
+  fn client(headers: &[(&'static str, &str)], peer: &str) -> String {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+      map.insert(*name, HeaderValue::from_str(value).unwrap());
+    }
+    client_ip(&map, peer.parse().unwrap(), trusted).to_string()
+  }
+
+  #[test]
+  fn untrusted_peers_cannot_forge_their_address() {
+    let forged = [("x-forwarded-for", "1.2.3.4"), ("x-real-ip", "1.2.3.4")];
+    assert_eq!(client(&forged, "203.0.113.7"), "203.0.113.7");
+  }
+
+  #[test]
+  fn the_client_is_the_last_untrusted_hop() {
+    let peer = "10.0.0.1";
+    let chain = |forwarded| [("x-forwarded-for", forwarded)];
+    assert_eq!(client(&chain("198.51.100.2"), peer), "198.51.100.2");
+    // Addresses before the first untrusted hop from the right may have been made up by it.
+    assert_eq!(
+      client(&chain("1.2.3.4, 198.51.100.2, 10.0.0.2"), peer),
+      "198.51.100.2"
+    );
+    // Through trusted proxies only, the first of them is the best guess.
+    assert_eq!(client(&chain("10.0.0.3, 10.0.0.2"), peer), "10.0.0.3");
+    // Parsing stops at garbage, keeping the last valid hop.
+    assert_eq!(
+      client(&chain("198.51.100.2, bogus, 10.0.0.2"), peer),
+      "10.0.0.2"
+    );
+    assert_eq!(
+      client(&[("x-forwarded-for", " 2001:db8::1 ")], peer),
+      "2001:db8::1"
+    );
+  }
+
+  #[test]
+  fn x_real_ip_is_used_without_x_forwarded_for() {
+    assert_eq!(
+      client(&[("x-real-ip", "198.51.100.2")], "10.0.0.1"),
+      "198.51.100.2"
+    );
+    assert_eq!(
+      client(&[("x-real-ip", "not an ip")], "10.0.0.1"),
+      "10.0.0.1"
+    );
+    assert_eq!(client(&[], "10.0.0.1"), "10.0.0.1");
+  }
+}
@@ -0,0 +1,41 @@
+use axum::{
+  async_trait,
+  extract::FromRequestParts,
+  http::{HeaderValue, header::IF_MATCH, request::Parts},
+};
+use utils::AppError;
+
+/// The version a client expects a resource to be at, read from the `If-Match` header.
+///
+/// `None` when the header is absent or `*`, in which case writes are unconditional. Versions are
+/// sent as `ETag`s, see [`etag`].
+pub struct IfMatch(pub Option<i64>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IfMatch
+where
+  S: Send + Sync,
+{
+  type Rejection = AppError;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    let Some(value) = parts.headers.get(IF_MATCH) else {
+      return Ok(Self(None));
+    };
+    let value = value.to_str().unwrap_or_default().trim();
+    if value == "*" {
+      return Ok(Self(None));
+    }
+    value
+      .trim_start_matches("W/")
+      .trim_matches('"')
+      .parse()
+      .map(|version| Self(Some(version)))
+      .map_err(|_| AppError::PreconditionFailed("If-Match does not match any version".to_string()))
+  }
+}
+
+/// The `ETag` of a resource at `version`.
+pub fn etag(version: i64) -> HeaderValue {
+  HeaderValue::from_str(&format!("\"{version}\"")).expect("a quoted number is a valid header")
+}
@@ -1 +1,4 @@
+pub(crate) mod client_info;
+pub(crate) mod if_match;
+pub(crate) mod pagination;
 pub(crate) mod validation_extractor;
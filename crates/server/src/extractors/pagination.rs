@@ -0,0 +1,258 @@
+use crate::dtos::PageResponse;
+use axum::{
+  Json, async_trait,
+  extract::{FromRequestParts, OriginalUri, Query},
+  http::{HeaderValue, Uri, header::LINK, request::Parts},
+  response::{IntoResponse, Response},
+};
+use database::pagination::{Cursor, Pagination, SortDirection};
+use serde::{Deserialize, Serialize};
+use utils::{AppError, AppResult};
+use validator::Validate;
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 20;
+/// Highest page number accepted, deeper pages have to be reached with cursors.
+pub const MAX_PAGE: u64 = 100_000;
+
+#[derive(Clone, Deserialize, Debug, Validate, Default)]
+struct PaginationQueryDto {
+  #[validate(range(min = 1, max = MAX_PAGE))]
+  page: Option<u64>,
+  #[validate(range(min = 1, max = 100))]
+  limit: Option<i64>,
+  /// Opaque `next_cursor` of the previous page. Cannot be combined with `page`.
+  cursor: Option<String>,
+  /// Field to sort by, prefixed with `-` for descending order.
+  sort: Option<String>,
+  include_total: Option<bool>,
+}
+
+/// Pagination and sorting query parameters shared by every listing endpoint.
+///
+/// Pages are addressed either by number (`page`) or by the cursor returned with the previous
+/// page (`cursor`). Resource specific filters are read separately, e.g. with a
+/// [`QueryValidationExtractor`](super::validation_extractor::QueryValidationExtractor).
+#[derive(Clone, Debug)]
+pub struct PaginationQuery {
+  pub page: u64,
+  pub limit: i64,
+  pub cursor: Option<String>,
+  pub sort: Option<String>,
+  include_total: Option<bool>,
+  uri: Uri,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PaginationQuery
+where
+  S: Send + Sync,
+{
+  type Rejection = AppError;
+
+  async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    let Query(query) = Query::<PaginationQueryDto>::from_request_parts(parts, state)
+      .await
+      .map_err(|e| AppError::BadRequest(e.body_text()))?;
+    query.validate()?;
+    if query.cursor.is_some() && query.page.is_some() {
+      return Err(AppError::BadRequest(
+        "page and cursor cannot be combined".to_string(),
+      ));
+    }
+    // Nested routers only see the rest of the path, links need the full one.
+    let uri = parts
+      .extensions
+      .get::<OriginalUri>()
+      .map_or_else(|| parts.uri.clone(), |OriginalUri(uri)| uri.clone());
+    Ok(Self {
+      page: query.page.unwrap_or(1),
+      limit: query.limit.unwrap_or(DEFAULT_PAGE_LIMIT),
+      cursor: query.cursor,
+      sort: query.sort,
+      include_total: query.include_total,
+      uri,
+    })
+  }
+}
+
+impl PaginationQuery {
+  /// Resolves the query for a resource.
+  ///
+  /// `sortable` maps the accepted `sort` values to document fields and `default_sort` is used
+  /// when the query has none.
+  pub fn pagination(&self, sortable: &[(&str, &str)], default_sort: &str) -> AppResult<Pagination> {
+    let sort = self.sort.as_deref().unwrap_or(default_sort);
+    let (name, direction) = match sort.strip_prefix('-') {
+      Some(name) => (name, SortDirection::Descending),
+      None => (sort, SortDirection::Ascending),
+    };
+    let field = sortable
+      .iter()
+      .find(|(param, _)| *param == name)
+      .map(|(_, field)| (*field).to_string())
+      .ok_or_else(|| {
+        let allowed: Vec<&str> = sortable.iter().map(|(param, _)| *param).collect();
+        AppError::BadRequest(format!("sort must be one of: {}", allowed.join(", ")))
+      })?;
+
+    Ok(Pagination {
+      sort: field,
+      direction,
+      limit: self.limit,
+      skip: self.skip()?,
+      after: self.cursor.as_deref().map(Cursor::decode).transpose()?,
+    })
+  }
+
+  /// Items to skip, for listings only paged by number such as search results ordered by
+  /// relevance.
+  pub fn offset(&self) -> AppResult<u64> {
+    if self.cursor.is_some() || self.sort.is_some() {
+      return Err(AppError::BadRequest(
+        "this listing is paged by number and cannot be sorted".to_string(),
+      ));
+    }
+    self.skip()
+  }
+
+  /// Items before the requested page.
+  fn skip(&self) -> AppResult<u64> {
+    let too_large = || AppError::BadRequest("page is too large".to_string());
+    let limit = u64::try_from(self.limit).map_err(|_| too_large())?;
+    self
+      .page
+      .checked_sub(1)
+      .and_then(|page| page.checked_mul(limit))
+      .ok_or_else(too_large)
+  }
+
+  /// Whether the total count was requested. Defaults to true for numbered pages only, counting
+  /// defeats the purpose of cursors on large collections.
+  pub fn include_total(&self) -> bool {
+    self.include_total.unwrap_or(self.cursor.is_none())
+  }
+
+  /// Builds the JSON page along with a `Link` header pointing at the first, previous and next
+  /// pages.
+  pub fn response<T: Serialize>(
+    &self,
+    data: Vec<T>,
+    next_cursor: Option<String>,
+    total: Option<u64>,
+  ) -> Response {
+    self.page_response(data, next_cursor.is_some(), next_cursor, total)
+  }
+
+  /// Like `response`, for listings read with `offset`.
+  pub fn numbered_response<T: Serialize>(
+    &self,
+    data: Vec<T>,
+    has_next: bool,
+    total: Option<u64>,
+  ) -> Response {
+    self.page_response(data, has_next, None, total)
+  }
+
+  fn page_response<T: Serialize>(
+    &self,
+    data: Vec<T>,
+    has_next: bool,
+    next_cursor: Option<String>,
+    total: Option<u64>,
+  ) -> Response {
+    let mut links = vec![format!("<{}>; rel=\"first\"", self.link(None))];
+    if self.cursor.is_none() {
+      if self.page > 1 {
+        let prev = format!("page={}", self.page - 1);
+        links.push(format!("<{}>; rel=\"prev\"", self.link(Some(&prev))));
+      }
+      if has_next {
+        let next = format!("page={}", self.page + 1);
+        links.push(format!("<{}>; rel=\"next\"", self.link(Some(&next))));
+      }
+    } else if let Some(cursor) = &next_cursor {
+      let next = format!("cursor={cursor}");
+      links.push(format!("<{}>; rel=\"next\"", self.link(Some(&next))));
+    }
+
+    let page = PageResponse {
+      data,
+      page: self.cursor.is_none().then_some(self.page),
+      limit: self.limit,
+      total,
+      next_cursor,
+    };
+    let mut response = Json(page).into_response();
+    if let Ok(value) = HeaderValue::from_str(&links.join(", ")) {
+      response.headers_mut().insert(LINK, value);
+    }
+    response
+  }
+
+  /// The current URI with its `page` and `cursor` parameters replaced by `position`.
+  fn link(&self, position: Option<&str>) -> String {
+    let mut params: Vec<&str> = self
+      .uri
+      .query()
+      .unwrap_or_default()
+      .split('&')
+      .filter(|param| {
+        let key = param.split('=').next().unwrap_or_default();
+        !param.is_empty() && key != "page" && key != "cursor"
+      })
+      .collect();
+    if let Some(position) = position {
+      params.push(position);
+    }
+    if params.is_empty() {
+      self.uri.path().to_string()
+    } else {
+      format!("{}?{}", self.uri.path(), params.join("&"))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use axum::http::Request;
+
+  async fn query(uri: &str) -> AppResult<PaginationQuery> {
+    let (mut parts, ()) = Request::get(uri).body(()).unwrap().into_parts();
+    PaginationQuery::from_request_parts(&mut parts, &()).await
+  }
+
+  #[tokio::test]
+  async fn rejects_pages_past_the_limit() {
+    let uri = format!("/users?page={}", MAX_PAGE + 1);
+    assert!(matches!(
+      query(&uri).await,
+      Err(AppError::ValidationError(_))
+    ));
+    let uri = format!("/users?page={}&limit=100", MAX_PAGE);
+    let page = query(&uri).await.unwrap();
+    assert_eq!(page.offset().unwrap(), (MAX_PAGE - 1) * 100);
+  }
+
+  #[tokio::test]
+  async fn overflowing_offsets_are_bad_requests() {
+    let mut page = query("/users").await.unwrap();
+    page.page = u64::MAX;
+    page.limit = 100;
+    assert!(matches!(page.offset(), Err(AppError::BadRequest(_))));
+    assert!(matches!(
+      page.pagination(&[("name", "name")], "name"),
+      Err(AppError::BadRequest(_))
+    ));
+  }
+
+  #[tokio::test]
+  async fn resolves_sort_fields_and_directions() {
+    let page = query("/users?page=3&limit=10&sort=-name").await.unwrap();
+    let pagination = page.pagination(&[("name", "name")], "name").unwrap();
+    assert_eq!(pagination.sort, "name");
+    assert_eq!(pagination.direction, SortDirection::Descending);
+    assert_eq!(pagination.skip, 20);
+    assert!(page.pagination(&[("email", "email")], "email").is_err());
+  }
+}
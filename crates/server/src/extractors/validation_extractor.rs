@@ -1,6 +1,7 @@
 use axum::{
-  Json, async_trait,
-  extract::{FromRequest, Request, rejection::JsonRejection},
+  Form, Json, async_trait,
+  extract::{FromRequest, FromRequestParts, Query, Request, rejection::JsonRejection},
+  http::request::Parts,
 };
 use serde::de::DeserializeOwned;
 use utils::AppError;
@@ -23,3 +24,43 @@ where
     Ok(ValidationExtractor(value))
   }
 }
+
+/// Same as [`ValidationExtractor`], but reads `T` from the query string.
+pub struct QueryValidationExtractor<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for QueryValidationExtractor<T>
+where
+  T: DeserializeOwned + Validate,
+  S: Send + Sync,
+{
+  type Rejection = AppError;
+
+  async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    let Query(value) = Query::<T>::from_request_parts(parts, state)
+      .await
+      .map_err(|e| AppError::BadRequest(e.body_text()))?;
+    value.validate()?;
+    Ok(QueryValidationExtractor(value))
+  }
+}
+
+/// Same as [`ValidationExtractor`], but reads `T` from an `application/x-www-form-urlencoded` body.
+pub struct FormValidationExtractor<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for FormValidationExtractor<T>
+where
+  T: DeserializeOwned + Validate,
+  S: Send + Sync,
+{
+  type Rejection = AppError;
+
+  async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+    let Form(value) = Form::<T>::from_request(req, state)
+      .await
+      .map_err(|e| AppError::BadRequest(e.body_text()))?;
+    value.validate()?;
+    Ok(FormValidationExtractor(value))
+  }
+}
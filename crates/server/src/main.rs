@@ -1,21 +1,31 @@
 pub(crate) mod api;
 pub(crate) mod app;
+pub(crate) mod cli;
 pub(crate) mod dtos;
 pub(crate) mod extractors;
 pub(crate) mod logger;
 pub(crate) mod router;
 pub(crate) mod services;
+pub(crate) mod tasks;
 
 use anyhow::{Context, Result};
 use app::ApplicationServer;
+use clap::Parser;
+use cli::{Cli, Command};
 use utils::config;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
+  let cli = Cli::parse();
   config::init();
-  ApplicationServer::serve()
-    .await
-    .context("Failed to start server")?;
+  match cli.command.unwrap_or(Command::Serve) {
+    Command::Serve => ApplicationServer::serve()
+      .await
+      .context("Failed to start server")?,
+    Command::Migrate(command) => cli::migrate(command)
+      .await
+      .context("Failed to run migrations")?,
+  }
 
   Ok(())
 }
@@ -6,7 +6,10 @@ use axum::{
   error_handling::HandleErrorLayer,
   http::{
     Method, StatusCode,
-    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+    header::{
+      ACCEPT, ACCEPT_RANGES, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+      IF_MATCH, IF_RANGE, LINK, RANGE,
+    },
   },
   response::IntoResponse,
 };
@@ -41,7 +44,21 @@ impl AppRouter {
         Method::PUT,
         Method::PATCH,
       ])
-      .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE]);
+      .allow_headers([
+        AUTHORIZATION,
+        ACCEPT,
+        CONTENT_TYPE,
+        IF_MATCH,
+        RANGE,
+        IF_RANGE,
+      ])
+      .expose_headers([
+        ETAG,
+        LINK,
+        ACCEPT_RANGES,
+        CONTENT_RANGE,
+        CONTENT_DISPOSITION,
+      ]);
 
     let index = ServeDir::new("dist").not_found_service(ServeFile::new("dist/index.html"));
 
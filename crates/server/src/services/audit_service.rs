@@ -0,0 +1,94 @@
+use crate::{
+  dtos::audit_dto::AuditEventQueryDto,
+  extractors::{client_info::ClientInfo, pagination::PaginationQuery},
+  services::parse_date,
+};
+use async_trait::async_trait;
+use database::{
+  audit::{
+    model::{AuditAction, AuditEvent, AuditEventFilter, AuditOutcome},
+    repository::DynAuditRepository,
+  },
+  pagination::Page,
+};
+use std::sync::Arc;
+use tracing::error;
+use utils::AppResult;
+
+/// Accepted `sort` values of the audit log and the fields they sort by.
+const SORTABLE_FIELDS: &[(&str, &str)] = &[
+  ("timestamp", "timestamp"),
+  ("action", "action"),
+  ("actor", "actor"),
+];
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynAuditService = Arc<dyn AuditServiceTrait + Send + Sync>;
+
+#[async_trait]
+#[allow(clippy::module_name_repetitions)]
+pub trait AuditServiceTrait {
+  /// Persists an audit event. Failures are logged and never surfaced to the caller.
+  async fn record(&self, event: AuditEvent);
+
+  /// Returns one page of matching events, newest first unless sorted otherwise, and the total
+  /// count when requested.
+  async fn find_audit_events(
+    &self,
+    query: AuditEventQueryDto,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<AuditEvent>, Option<u64>)>;
+}
+
+/// Starts an audit event pre-filled with the caller's network details.
+pub fn audit_event(client: &ClientInfo, action: AuditAction, outcome: AuditOutcome) -> AuditEvent {
+  AuditEvent::new(action, outcome).client(client.ip.clone(), client.user_agent.clone())
+}
+
+#[derive(Clone)]
+pub struct AuditService {
+  repository: DynAuditRepository,
+}
+
+impl AuditService {
+  pub fn new(repository: DynAuditRepository) -> Self {
+    Self { repository }
+  }
+}
+
+#[async_trait]
+impl AuditServiceTrait for AuditService {
+  async fn record(&self, event: AuditEvent) {
+    if let Err(e) = self.repository.create_audit_event(event).await {
+      error!("failed to write audit event: {e}");
+    }
+  }
+
+  async fn find_audit_events(
+    &self,
+    query: AuditEventQueryDto,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<AuditEvent>, Option<u64>)> {
+    let filter = AuditEventFilter {
+      actor: query.actor,
+      target: query.target,
+      action: query.action,
+      outcome: query.outcome,
+      ip: query.ip,
+      from: parse_date(query.from, "from")?,
+      to: parse_date(query.to, "to")?,
+    };
+    let pagination = page.pagination(SORTABLE_FIELDS, "-timestamp")?;
+
+    let events = self
+      .repository
+      .find_audit_events(&filter, &pagination)
+      .await?;
+    let total = if page.include_total() {
+      Some(self.repository.count_audit_events(&filter).await?)
+    } else {
+      None
+    };
+    Ok((events, total))
+  }
+}
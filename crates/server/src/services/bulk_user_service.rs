@@ -0,0 +1,638 @@
+use crate::{
+  dtos::{
+    invitation_dto::CreateInvitationDto,
+    user_dto::{
+      UserExportRow, UserImportQueryDto, UserImportRecordDto, UserImportReport,
+      UserImportRowReport, UserImportStatus, UserQueryDto, UserTransferFormat,
+    },
+  },
+  extractors::client_info::ClientInfo,
+  services::{
+    audit_service::{DynAuditService, audit_event},
+    invitation_service::DynInvitationService,
+    outbox_service::enqueue,
+    user_service::{DynUserService, user_filter},
+  },
+};
+use async_trait::async_trait;
+use database::{
+  audit::model::{AuditAction, AuditOutcome},
+  outbox::repository::DynOutboxRepository,
+  pagination::{Cursor, Pagination, SortDirection},
+  tenant::current_tenant,
+  transaction::{DynUnitOfWork, transaction},
+  user::{model::User, repository::DynUserRepository},
+};
+use mongodb::bson::doc;
+use std::{borrow::Cow, collections::HashSet, sync::Arc};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+use utils::{AppError, AppResult, ErrorMap, tenant};
+use validator::Validate;
+
+/// Most rows accepted by one import.
+const MAX_IMPORT_ROWS: usize = 10_000;
+
+/// Users read from the repository at a time while exporting.
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+/// Encoded batches buffered ahead of a slow client while exporting.
+const EXPORT_BUFFER: usize = 4;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynBulkUserService = Arc<dyn BulkUserServiceTrait + Send + Sync>;
+
+/// Options of an import, see `UserImportQueryDto`.
+#[derive(Clone, Copy, Debug)]
+struct UserImportOptions {
+  pub format: UserTransferFormat,
+  pub dry_run: bool,
+  pub invite: bool,
+}
+
+#[async_trait]
+#[allow(clippy::module_name_repetitions)]
+pub trait BulkUserServiceTrait {
+  /// Creates, or invites when they have no password and `query.invite` is set, the users of
+  /// every valid row of `body`, whose format defaults to that of `content_type`. Rows are
+  /// handled one by one: an invalid row is reported and does not prevent the others from being
+  /// imported.
+  async fn import_users(
+    &self,
+    body: &str,
+    query: UserImportQueryDto,
+    content_type: Option<&str>,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<UserImportReport>;
+
+  /// Streams the users matching `filter` in `format`, reading them in batches so that the whole
+  /// collection is never held in memory. An error ends the stream early.
+  async fn export_users(
+    &self,
+    filter: UserQueryDto,
+    format: UserTransferFormat,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<mpsc::Receiver<AppResult<String>>>;
+}
+
+#[derive(Clone)]
+pub struct BulkUserService {
+  user: DynUserService,
+  invitation: DynInvitationService,
+  repository: DynUserRepository,
+  outbox: DynOutboxRepository,
+  unit_of_work: DynUnitOfWork,
+  audit: DynAuditService,
+}
+
+/// A parsed row and the line it was read from.
+type Record = (u64, Result<UserImportRecordDto, String>);
+
+fn too_many_rows(limit: usize) -> AppError {
+  AppError::BadRequest(format!("imports are limited to {limit} rows"))
+}
+
+/// Parses the rows of `body`, failing as soon as there are more than `limit` of them.
+fn csv_records(body: &str, limit: usize) -> AppResult<Vec<Record>> {
+  let mut reader = csv::ReaderBuilder::new()
+    .trim(csv::Trim::All)
+    .from_reader(body.as_bytes());
+  let headers = reader
+    .headers()
+    .map_err(|e| AppError::BadRequest(format!("invalid csv header: {e}")))?
+    .clone();
+  let mut records = Vec::new();
+  for record in reader.records() {
+    if records.len() == limit {
+      return Err(too_many_rows(limit));
+    }
+    let record = match record {
+      Ok(record) => {
+        let line = record.position().map_or(0, csv::Position::line);
+        let parsed = record
+          .deserialize::<UserImportRecordDto>(Some(&headers))
+          .map_err(|e| e.to_string());
+        (line, parsed)
+      }
+      Err(e) => {
+        let line = e.position().map_or(0, csv::Position::line);
+        (line, Err(e.to_string()))
+      }
+    };
+    records.push(record);
+  }
+  Ok(records)
+}
+
+/// Parses the non-blank lines of `body`, failing as soon as there are more than `limit` of them.
+fn ndjson_records(body: &str, limit: usize) -> AppResult<Vec<Record>> {
+  let mut records = Vec::new();
+  for (line, number) in body.lines().zip(1..) {
+    if line.trim().is_empty() {
+      continue;
+    }
+    if records.len() == limit {
+      return Err(too_many_rows(limit));
+    }
+    let parsed = serde_json::from_str::<UserImportRecordDto>(line).map_err(|e| e.to_string());
+    records.push((number, parsed));
+  }
+  Ok(records)
+}
+
+fn add_error(errors: &mut ErrorMap, field: &'static str, message: impl Into<Cow<'static, str>>) {
+  errors
+    .entry(Cow::Borrowed(field))
+    .or_default()
+    .push(message.into());
+}
+
+/// Encodes a batch of exported users, preceded by the CSV header when `header` is set.
+fn encode(format: UserTransferFormat, users: Vec<User>, header: bool) -> AppResult<String> {
+  let rows = users.into_iter().map(UserExportRow::from);
+  match format {
+    UserTransferFormat::Csv => {
+      let mut writer = csv::WriterBuilder::new()
+        .has_headers(header)
+        .from_writer(Vec::new());
+      for row in rows {
+        writer
+          .serialize(row)
+          .map_err(|e| AppError::InternalServerErrorWithContext(e.to_string()))?;
+      }
+      let bytes = writer
+        .into_inner()
+        .map_err(|e| AppError::InternalServerErrorWithContext(e.to_string()))?;
+      String::from_utf8(bytes).map_err(|e| AppError::InternalServerErrorWithContext(e.to_string()))
+    }
+    UserTransferFormat::Ndjson => {
+      let mut lines = String::new();
+      for row in rows {
+        lines.push_str(&serde_json::to_string(&row)?);
+        lines.push('\n');
+      }
+      Ok(lines)
+    }
+  }
+}
+
+impl BulkUserService {
+  pub fn new(
+    user: DynUserService,
+    invitation: DynInvitationService,
+    repository: DynUserRepository,
+    outbox: DynOutboxRepository,
+    unit_of_work: DynUnitOfWork,
+    audit: DynAuditService,
+  ) -> Self {
+    Self {
+      user,
+      invitation,
+      repository,
+      outbox,
+      unit_of_work,
+      audit,
+    }
+  }
+
+  /// Invites `email` and notifies `user.invited`, so that the invitation can be emailed by an
+  /// outbox sink. Returns the invitation code.
+  async fn invite(
+    &self,
+    name: &str,
+    email: &str,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<String> {
+    transaction(&*self.unit_of_work, || async {
+      let request = CreateInvitationDto {
+        email: Some(email.to_string()),
+        expires_in: None,
+      };
+      let invitation = self
+        .invitation
+        .create_invitation(request, actor, client)
+        .await?;
+      let payload = doc! {
+        "name": name,
+        "email": email,
+        "code": &invitation.code,
+        "expires_at": invitation.expires_at,
+      };
+      enqueue(&self.outbox, "user.invited", payload).await?;
+      Ok(invitation.code)
+    })
+    .await
+  }
+
+  /// Validates one row, then imports it unless `options.dry_run` is set.
+  async fn import_row(
+    &self,
+    (line, record): Record,
+    seen: &mut HashSet<String>,
+    options: UserImportOptions,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<UserImportRowReport> {
+    let mut report = UserImportRowReport {
+      line,
+      email: None,
+      status: UserImportStatus::Failed,
+      id: None,
+      invitation_code: None,
+      errors: ErrorMap::new(),
+    };
+    let record = match record {
+      Ok(record) => record,
+      Err(e) => {
+        add_error(&mut report.errors, "row", e);
+        return Ok(report);
+      }
+    };
+    report.email.clone_from(&record.email);
+    if let Err(e) = record.validate() {
+      report.errors = AppError::error_map(e);
+      return Ok(report);
+    }
+    let (Some(name), Some(email)) = (record.name, record.email) else {
+      return Ok(report);
+    };
+    if !seen.insert(email.to_lowercase()) {
+      add_error(
+        &mut report.errors,
+        "email",
+        "email appears on an earlier row",
+      );
+    } else if self.user.get_user_by_email(&email).await?.is_some() {
+      add_error(&mut report.errors, "email", "email is taken");
+    }
+    if record.password.is_none() && !options.invite {
+      add_error(
+        &mut report.errors,
+        "password",
+        "password is required unless invite is set",
+      );
+    }
+    if !report.errors.is_empty() {
+      return Ok(report);
+    }
+
+    let imported = match (record.password, options.dry_run) {
+      (Some(_), true) => Ok(UserImportStatus::WillCreate),
+      (None, true) => Ok(UserImportStatus::WillInvite),
+      (Some(password), false) => {
+        self
+          .user
+          .create_user(&name, &email, &password)
+          .await
+          .map(|result| {
+            report.id = result.inserted_id.as_object_id().map(|id| id.to_hex());
+            UserImportStatus::Created
+          })
+      }
+      (None, false) => self.invite(&name, &email, actor, client).await.map(|code| {
+        report.invitation_code = Some(code);
+        UserImportStatus::Invited
+      }),
+    };
+    match imported {
+      Ok(status) => report.status = status,
+      Err(e) => {
+        error!("failed to import user {:?} on line {line}: {e}", email);
+        add_error(&mut report.errors, "row", e.to_string());
+      }
+    }
+    Ok(report)
+  }
+}
+
+#[async_trait]
+impl BulkUserServiceTrait for BulkUserService {
+  async fn import_users(
+    &self,
+    body: &str,
+    query: UserImportQueryDto,
+    content_type: Option<&str>,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<UserImportReport> {
+    let options = UserImportOptions {
+      format: UserTransferFormat::resolve(query.format, content_type)?,
+      dry_run: query.dry_run.unwrap_or_default(),
+      invite: query.invite.unwrap_or_default(),
+    };
+    let records = match options.format {
+      UserTransferFormat::Csv => csv_records(body, MAX_IMPORT_ROWS)?,
+      UserTransferFormat::Ndjson => ndjson_records(body, MAX_IMPORT_ROWS)?,
+    };
+
+    let mut report = UserImportReport {
+      dry_run: options.dry_run,
+      rows: records.len(),
+      created: 0,
+      invited: 0,
+      failed: 0,
+      results: Vec::with_capacity(records.len()),
+    };
+    let mut seen = HashSet::new();
+    for record in records {
+      let row = self
+        .import_row(record, &mut seen, options, actor, client)
+        .await?;
+      match row.status {
+        UserImportStatus::Created => report.created += 1,
+        UserImportStatus::Invited => report.invited += 1,
+        UserImportStatus::Failed => report.failed += 1,
+        UserImportStatus::WillCreate | UserImportStatus::WillInvite => {}
+      }
+      report.results.push(row);
+    }
+
+    if !options.dry_run {
+      info!(
+        "user {:?} imported {} users, invited {}, {} rows failed",
+        actor, report.created, report.invited, report.failed
+      );
+      self
+        .audit
+        .record(
+          audit_event(client, AuditAction::ImportUsers, AuditOutcome::Success)
+            .actor(actor)
+            .reason(format!(
+              "{} created, {} invited, {} failed",
+              report.created, report.invited, report.failed
+            )),
+        )
+        .await;
+    }
+    Ok(report)
+  }
+
+  async fn export_users(
+    &self,
+    filter: UserQueryDto,
+    format: UserTransferFormat,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<mpsc::Receiver<AppResult<String>>> {
+    let filter = user_filter(filter, false)?;
+    let repository = self.repository.clone();
+    let (sender, receiver) = mpsc::channel(EXPORT_BUFFER);
+    let export = async move {
+      let mut pagination = Pagination {
+        sort: "_id".to_string(),
+        direction: SortDirection::Ascending,
+        limit: EXPORT_BATCH_SIZE,
+        skip: 0,
+        after: None,
+      };
+      let mut header = true;
+      loop {
+        let page = match repository.find_users(&filter, &pagination).await {
+          Ok(page) => page,
+          Err(e) => {
+            error!("failed to export users: {e}");
+            let _ = sender.send(Err(e)).await;
+            return;
+          }
+        };
+        let batch = encode(format, page.items, header);
+        header = false;
+        // The client went away.
+        if sender.send(batch).await.is_err() {
+          return;
+        }
+        match page.next_cursor.as_deref().map(Cursor::decode) {
+          Some(Ok(cursor)) => pagination.after = Some(cursor),
+          Some(Err(e)) => {
+            let _ = sender.send(Err(e)).await;
+            return;
+          }
+          None => return,
+        }
+      }
+    };
+    // The stream outlives the request, whose tenant must still apply.
+    match current_tenant() {
+      Some(current) => tokio::spawn(tenant::scope(current, export)),
+      None => tokio::spawn(export),
+    };
+
+    info!("user {:?} exported users", actor);
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::ExportUsers, AuditOutcome::Success)
+          .actor(actor)
+          .reason(format.extension()),
+      )
+      .await;
+    Ok(receiver)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::{
+    audit_service::AuditService, invitation_service::InvitationService,
+    token_service::TokenService, user_service::UserService,
+  };
+  use database::{
+    memory::MemoryDatabase,
+    user::{model::UserFilter, repository::UserRepositoryTrait},
+  };
+
+  const ACTOR: &str = "admin@example.com";
+
+  /// A service over an empty in-memory database.
+  fn service() -> (BulkUserService, Arc<MemoryDatabase>) {
+    utils::config::init_for_tests();
+    let database = Arc::new(MemoryDatabase::new());
+    let audit = Arc::new(AuditService::new(database.clone()));
+    let tokens = Arc::new(TokenService::new(database.clone(), audit.clone()));
+    let user = Arc::new(UserService::new(
+      database.clone(),
+      database.clone(),
+      database.clone(),
+      database.clone(),
+      database.clone(),
+      tokens,
+      audit.clone(),
+    ));
+    let invitation = Arc::new(InvitationService::new(database.clone(), audit.clone()));
+    let service = BulkUserService::new(
+      user,
+      invitation,
+      database.clone(),
+      database.clone(),
+      database.clone(),
+      audit,
+    );
+    (service, database)
+  }
+
+  async fn import(
+    service: &BulkUserService,
+    body: &str,
+    format: UserTransferFormat,
+    dry_run: bool,
+    invite: bool,
+  ) -> UserImportReport {
+    let query = UserImportQueryDto {
+      format: Some(format),
+      dry_run: Some(dry_run),
+      invite: Some(invite),
+    };
+    service
+      .import_users(body, query, None, ACTOR, &ClientInfo::default())
+      .await
+      .unwrap()
+  }
+
+  fn lines(report: &UserImportReport) -> Vec<u64> {
+    report.results.iter().map(|row| row.line).collect()
+  }
+
+  #[tokio::test]
+  async fn csv_rows_are_reported_with_their_line() {
+    let (service, _) = service();
+    let body = "name,email,password\n\
+                Ada,ada@example.com,secret1\n\
+                \"Grace\nHopper\",grace@example.com,secret1\n\
+                Alan,alan@example.com,secret1,extra\n\
+                Edsger,not-an-email,secret1\n";
+    let report = import(&service, body, UserTransferFormat::Csv, true, false).await;
+    // The quoted name spans two lines, the row after it starts on the fifth.
+    assert_eq!(lines(&report), [2, 3, 5, 6]);
+    let statuses: Vec<UserImportStatus> = report.results.iter().map(|row| row.status).collect();
+    assert_eq!(
+      statuses,
+      [
+        UserImportStatus::WillCreate,
+        UserImportStatus::WillCreate,
+        UserImportStatus::Failed,
+        UserImportStatus::Failed,
+      ]
+    );
+    assert!(report.results[2].errors.contains_key("row"));
+    assert!(report.results[3].errors.contains_key("email"));
+  }
+
+  #[tokio::test]
+  async fn blank_ndjson_lines_are_skipped_but_counted() {
+    let (service, _) = service();
+    let body = "\n{\"name\":\"Ada\",\"email\":\"ada@example.com\",\"password\":\"secret1\"}\n  \n\
+                {\"name\":\"Grace\"\n";
+    let report = import(&service, body, UserTransferFormat::Ndjson, true, false).await;
+    assert_eq!(report.rows, 2);
+    assert_eq!(lines(&report), [2, 4]);
+    assert_eq!(report.results[0].status, UserImportStatus::WillCreate);
+    assert!(report.results[1].errors.contains_key("row"));
+  }
+
+  #[tokio::test]
+  async fn emails_may_only_appear_once() {
+    let (service, database) = service();
+    database
+      .create_user("Taken", "taken@example.com", "hash")
+      .await
+      .unwrap();
+    let body = "name,email,password\n\
+                Ada,ada@example.com,secret1\n\
+                Ada,ADA@example.com,secret1\n\
+                Taken,taken@example.com,secret1\n";
+    let report = import(&service, body, UserTransferFormat::Csv, true, false).await;
+    assert_eq!(report.results[0].status, UserImportStatus::WillCreate);
+    for row in &report.results[1..] {
+      assert_eq!(row.status, UserImportStatus::Failed);
+      assert!(row.errors.contains_key("email"), "{row:?}");
+    }
+  }
+
+  #[tokio::test]
+  async fn rows_without_a_password_need_invite() {
+    let (service, database) = service();
+    let body = "name,email\nAda,ada@example.com\n";
+    let report = import(&service, body, UserTransferFormat::Csv, false, false).await;
+    assert_eq!(report.failed, 1);
+    assert!(report.results[0].errors.contains_key("password"));
+
+    let report = import(&service, body, UserTransferFormat::Csv, false, true).await;
+    assert_eq!(report.invited, 1);
+    assert_eq!(report.results[0].status, UserImportStatus::Invited);
+    assert!(report.results[0].invitation_code.is_some());
+    // Invited users are only created once they accept.
+    assert!(
+      database
+        .get_user_by_email("ada@example.com")
+        .await
+        .unwrap()
+        .is_none()
+    );
+  }
+
+  #[tokio::test]
+  async fn dry_runs_report_what_would_happen() {
+    let (service, database) = service();
+    let body = "name,email,password\n\
+                Ada,ada@example.com,secret1\n\
+                Grace,grace@example.com,\n";
+    let report = import(&service, body, UserTransferFormat::Csv, true, true).await;
+    let statuses: Vec<UserImportStatus> = report.results.iter().map(|row| row.status).collect();
+    assert_eq!(
+      statuses,
+      [UserImportStatus::WillCreate, UserImportStatus::WillInvite]
+    );
+    assert_eq!((report.created, report.invited, report.failed), (0, 0, 0));
+    let everyone = database.count_users(&UserFilter::default()).await.unwrap();
+    assert_eq!(everyone, 0);
+  }
+
+  #[tokio::test]
+  async fn csv_exports_have_one_header() {
+    let (service, database) = service();
+    let users = usize::try_from(EXPORT_BATCH_SIZE).unwrap() + 1;
+    for i in 0..users {
+      database
+        .create_user("User", &format!("user{i}@example.com"), "hash")
+        .await
+        .unwrap();
+    }
+    let mut receiver = service
+      .export_users(
+        UserQueryDto::default(),
+        UserTransferFormat::Csv,
+        ACTOR,
+        &ClientInfo::default(),
+      )
+      .await
+      .unwrap();
+    let mut batches = Vec::new();
+    while let Some(batch) = receiver.recv().await {
+      batches.push(batch.unwrap());
+    }
+    assert!(batches.len() > 1);
+    let csv = batches.concat();
+    let header = csv.lines().next().unwrap();
+    assert!(header.starts_with("id,name,email,"), "{header}");
+    assert_eq!(csv.lines().filter(|line| *line == header).count(), 1);
+    assert_eq!(csv.lines().count(), users + 1);
+  }
+
+  #[test]
+  fn parsing_stops_past_the_row_limit() {
+    let csv = "name,email\nAda,ada@example.com\nGrace,grace@example.com\n";
+    assert_eq!(csv_records(csv, 2).unwrap().len(), 2);
+    assert!(matches!(csv_records(csv, 1), Err(AppError::BadRequest(_))));
+
+    let ndjson = "{\"name\":\"Ada\"}\n\n{\"name\":\"Grace\"}\n";
+    assert_eq!(ndjson_records(ndjson, 2).unwrap().len(), 2);
+    assert!(matches!(
+      ndjson_records(ndjson, 1),
+      Err(AppError::BadRequest(_))
+    ));
+  }
+}
@@ -0,0 +1,393 @@
+use crate::{
+  dtos::file_dto::{FileDownload, FileUpload},
+  extractors::{client_info::ClientInfo, pagination::PaginationQuery},
+  services::audit_service::{DynAuditService, audit_event},
+};
+use async_trait::async_trait;
+use database::{
+  audit::model::{AuditAction, AuditOutcome},
+  file::model::StoredFile,
+  pagination::Page,
+  repository::DynRepository,
+  storage::DynFileStorage,
+  user::{
+    model::{User, UserRole},
+    repository::DynUserRepository,
+  },
+};
+use mongodb::bson::{DateTime, doc, oid::ObjectId};
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, ops::Range, str::FromStr, sync::Arc};
+use tracing::{error, info};
+use utils::{AppError, AppResult, config, tenant};
+
+/// Accepted `sort` values of the file listing and the fields they sort by.
+const SORTABLE_FIELDS: &[(&str, &str)] = &[
+  ("name", "name"),
+  ("size", "size"),
+  ("created_at", "created_at"),
+];
+
+/// Longest file name kept, in characters.
+const MAX_NAME_LENGTH: usize = 255;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynFileService = Arc<dyn FileServiceTrait + Send + Sync>;
+
+#[async_trait]
+#[allow(clippy::module_name_repetitions)]
+pub trait FileServiceTrait {
+  /// Stores an upload of `actor`, provided its type is one of `storage.allowed_content_types`.
+  async fn upload_file(
+    &self,
+    upload: FileUpload,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<StoredFile>;
+
+  /// Returns one page of the files of `actor`, newest first unless sorted otherwise, and the
+  /// total count when requested.
+  async fn find_files(
+    &self,
+    actor: &str,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<StoredFile>, Option<u64>)>;
+
+  /// Returns a file of `actor`, or of anyone when `actor` is an admin.
+  async fn get_file(&self, id: &str, actor: &str) -> AppResult<StoredFile>;
+
+  /// Opens a file readable by `actor`, see `get_file`, at the single range requested by the
+  /// `Range` header if any. The range is ignored when `if_range` does not match the file's
+  /// `ETag`.
+  async fn download_file(
+    &self,
+    id: &str,
+    actor: &str,
+    range: Option<&str>,
+    if_range: Option<&str>,
+  ) -> AppResult<FileDownload>;
+
+  /// Deletes a file of `actor`, or of anyone when `actor` is an admin.
+  async fn delete_file(&self, id: &str, actor: &str, client: &ClientInfo) -> AppResult<()>;
+}
+
+#[derive(Clone)]
+pub struct FileService {
+  repository: DynRepository<StoredFile>,
+  users: DynUserRepository,
+  storage: DynFileStorage,
+  audit: DynAuditService,
+}
+
+impl FileService {
+  pub fn new(
+    repository: DynRepository<StoredFile>,
+    users: DynUserRepository,
+    storage: DynFileStorage,
+    audit: DynAuditService,
+  ) -> Self {
+    Self {
+      repository,
+      users,
+      storage,
+      audit,
+    }
+  }
+
+  /// Returns the user having `email` and its id.
+  async fn user(&self, email: &str) -> AppResult<(User, ObjectId)> {
+    let user = self
+      .users
+      .get_user_by_email(email)
+      .await?
+      .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let id = user.id.ok_or(AppError::InternalServerErrorWithContext(
+      "user has no id".to_string(),
+    ))?;
+    Ok((user, id))
+  }
+}
+
+/// The last segment of the name a client gave a file, without control characters.
+fn file_name(name: &str) -> String {
+  let name: String = name
+    .rsplit(['/', '\\'])
+    .next()
+    .unwrap_or_default()
+    .chars()
+    .filter(|c| !c.is_control())
+    .take(MAX_NAME_LENGTH)
+    .collect();
+  let name = name.trim();
+  if name.is_empty() || name == "." || name == ".." {
+    "file".to_string()
+  } else {
+    name.to_string()
+  }
+}
+
+/// The bytes of a file of `size` bytes requested by a `Range` header, `None` for the whole file.
+///
+/// Only a single range of bytes is served. Other units, several ranges and malformed headers
+/// get the whole file, as RFC 9110 allows.
+fn byte_range(header: &str, size: u64) -> AppResult<Option<Range<u64>>> {
+  let Some((first, last)) = header
+    .trim()
+    .strip_prefix("bytes=")
+    .filter(|ranges| !ranges.contains(','))
+    .and_then(|range| range.split_once('-'))
+  else {
+    return Ok(None);
+  };
+  let (first, last) = (first.trim(), last.trim());
+  if first.is_empty() {
+    // The last `last` bytes.
+    let Ok(length) = last.parse::<u64>() else {
+      return Ok(None);
+    };
+    if length == 0 || size == 0 {
+      return Err(AppError::RangeNotSatisfiable { size });
+    }
+    return Ok(Some(size.saturating_sub(length)..size));
+  }
+  let Ok(first) = first.parse::<u64>() else {
+    return Ok(None);
+  };
+  let end = if last.is_empty() {
+    size
+  } else {
+    match last.parse::<u64>() {
+      Ok(last) if last >= first => last.saturating_add(1).min(size),
+      _ => return Ok(None),
+    }
+  };
+  if first >= size {
+    return Err(AppError::RangeNotSatisfiable { size });
+  }
+  Ok(Some(first..end))
+}
+
+#[async_trait]
+impl FileServiceTrait for FileService {
+  async fn upload_file(
+    &self,
+    upload: FileUpload,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<StoredFile> {
+    let cfg = &config::get().storage;
+    if upload.content.len() as u64 > cfg.max_file_size {
+      return Err(AppError::PayloadTooLarge(format!(
+        "files are limited to {} bytes",
+        cfg.max_file_size
+      )));
+    }
+    if !cfg.allows(&upload.content_type) {
+      error!("rejected a {:?} upload of {:?}", upload.content_type, actor);
+      return Err(AppError::UnsupportedMediaType(format!(
+        "{} files are not accepted",
+        upload.content_type
+      )));
+    }
+    let (_, owner_id) = self.user(actor).await?;
+
+    let id = ObjectId::new();
+    // Tenants get their own prefix, so that their files can be told apart in the storage.
+    let key = match tenant::current() {
+      Some(tenant) => format!("{tenant}/{id}"),
+      None => id.to_hex(),
+    };
+    let sha256 = Sha256::digest(&upload.content)
+      .iter()
+      .fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+      });
+    let file = StoredFile {
+      id: Some(id),
+      owner_id,
+      name: file_name(&upload.name),
+      content_type: upload.content_type.clone(),
+      size: upload.content.len() as u64,
+      sha256,
+      storage: cfg.backend,
+      key,
+      created_at: DateTime::now(),
+      tenant_id: None,
+    };
+    self
+      .storage
+      .put(&file.key, upload.content, &file.content_type)
+      .await?;
+    if let Err(e) = self.repository.insert(file.clone()).await {
+      if let Err(e) = self.storage.delete(&file.key).await {
+        error!("failed to delete the content of {:?}: {e}", file.key);
+      }
+      return Err(e);
+    }
+    info!("user {:?} uploaded file {id}", actor);
+
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::UploadFile, AuditOutcome::Success)
+          .actor(actor)
+          .target(id.to_hex()),
+      )
+      .await;
+    Ok(file)
+  }
+
+  async fn find_files(
+    &self,
+    actor: &str,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<StoredFile>, Option<u64>)> {
+    let (_, owner_id) = self.user(actor).await?;
+    let filter = doc! { "owner_id": owner_id };
+    let pagination = page.pagination(SORTABLE_FIELDS, "-created_at")?;
+    let files = self
+      .repository
+      .find_page(filter.clone(), &pagination)
+      .await?;
+    let total = if page.include_total() {
+      Some(self.repository.count(filter).await?)
+    } else {
+      None
+    };
+    Ok((files, total))
+  }
+
+  async fn get_file(&self, id: &str, actor: &str) -> AppResult<StoredFile> {
+    let not_found = || AppError::NotFound("File not found".to_string());
+    let id = ObjectId::from_str(id).map_err(|_| not_found())?;
+    let file = self
+      .repository
+      .find_by_id(&id)
+      .await?
+      .ok_or_else(not_found)?;
+    let (user, user_id) = self.user(actor).await?;
+    // Files of other users are hidden rather than forbidden, their ids are none of the caller's
+    // business.
+    if user_id != file.owner_id && user.role != UserRole::Admin {
+      return Err(not_found());
+    }
+    Ok(file)
+  }
+
+  async fn download_file(
+    &self,
+    id: &str,
+    actor: &str,
+    range: Option<&str>,
+    if_range: Option<&str>,
+  ) -> AppResult<FileDownload> {
+    let file = self.get_file(id, actor).await?;
+    let range = match range {
+      Some(range) if if_range.is_none_or(|if_range| if_range == file.etag()) => {
+        byte_range(range, file.size)?
+      }
+      _ => None,
+    };
+    if file.storage != config::get().storage.backend {
+      error!(
+        "file {:?} is kept by the {:?} storage, which is not the configured one",
+        file.id, file.storage
+      );
+      return Err(AppError::NotFound("file content not found".to_string()));
+    }
+    let content = self.storage.get(&file.key, range.clone()).await?;
+    Ok(FileDownload {
+      file,
+      range,
+      content,
+    })
+  }
+
+  async fn delete_file(&self, id: &str, actor: &str, client: &ClientInfo) -> AppResult<()> {
+    let file = self.get_file(id, actor).await?;
+    let id = file.id.ok_or(AppError::InternalServerErrorWithContext(
+      "file has no id".to_string(),
+    ))?;
+    self.repository.delete_by_id(&id).await?;
+    // The file is gone once its metadata is, leftover content is only wasted space.
+    if let Err(e) = self.storage.delete(&file.key).await {
+      error!("failed to delete the content of {:?}: {e}", file.key);
+    }
+    info!("user {:?} deleted file {id}", actor);
+
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::DeleteFile, AuditOutcome::Success)
+          .actor(actor)
+          .target(id.to_hex()),
+      )
+      .await;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn range(header: &str, size: u64) -> Option<Range<u64>> {
+    byte_range(header, size).unwrap()
+  }
+
+  fn unsatisfiable(header: &str, size: u64) -> bool {
+    matches!(
+      byte_range(header, size),
+      Err(AppError::RangeNotSatisfiable { size: reported }) if reported == size
+    )
+  }
+
+  #[test]
+  fn parses_single_byte_ranges() {
+    assert_eq!(range("bytes=0-99", 1000), Some(0..100));
+    assert_eq!(range(" bytes=10 - 19 ", 1000), Some(10..20));
+    assert_eq!(range("bytes=900-", 1000), Some(900..1000));
+    assert_eq!(range("bytes=-100", 1000), Some(900..1000));
+  }
+
+  #[test]
+  fn clamps_ranges_to_the_file() {
+    assert_eq!(range("bytes=990-2000", 1000), Some(990..1000));
+    assert_eq!(range("bytes=-5000", 1000), Some(0..1000));
+    assert_eq!(range(&format!("bytes=0-{}", u64::MAX), 10), Some(0..10));
+  }
+
+  #[test]
+  fn serves_the_whole_file_for_other_headers() {
+    for header in [
+      "",
+      "items=0-9",
+      "bytes=0-9,20-29",
+      "bytes=abc-9",
+      "bytes=9-0",
+      "bytes=0",
+      "bytes=-x",
+    ] {
+      assert_eq!(range(header, 1000), None, "{header}");
+    }
+  }
+
+  #[test]
+  fn rejects_ranges_past_the_end() {
+    assert!(unsatisfiable("bytes=1000-", 1000));
+    assert!(unsatisfiable("bytes=1000-1999", 1000));
+    assert!(unsatisfiable("bytes=-0", 1000));
+    assert!(unsatisfiable("bytes=0-", 0));
+    assert!(unsatisfiable("bytes=-10", 0));
+  }
+
+  #[test]
+  fn keeps_the_last_segment_of_file_names() {
+    assert_eq!(file_name("../../etc/passwd"), "passwd");
+    assert_eq!(file_name("C:\\Users\\ada\\report.pdf"), "report.pdf");
+    assert_eq!(file_name("a\u{0}b\nc.txt"), "abc.txt");
+    assert_eq!(file_name("dir/.."), "file");
+    assert_eq!(file_name("  "), "file");
+  }
+}
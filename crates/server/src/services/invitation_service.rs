@@ -0,0 +1,117 @@
+use crate::{
+  dtos::invitation_dto::CreateInvitationDto,
+  extractors::client_info::ClientInfo,
+  services::audit_service::{DynAuditService, audit_event},
+};
+use async_trait::async_trait;
+use database::{
+  audit::model::{AuditAction, AuditOutcome},
+  invitation::{model::Invitation, repository::DynInvitationRepository},
+};
+use mongodb::{
+  bson::{DateTime, oid::ObjectId},
+  results::DeleteResult,
+};
+use std::{sync::Arc, time::Duration};
+use tracing::info;
+use utils::{AppResult, config, random::generate_code};
+
+const INVITATION_CODE_LENGTH: usize = 24;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynInvitationService = Arc<dyn InvitationServiceTrait + Send + Sync>;
+
+#[async_trait]
+#[allow(clippy::module_name_repetitions)]
+pub trait InvitationServiceTrait {
+  async fn create_invitation(
+    &self,
+    request: CreateInvitationDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<Invitation>;
+
+  async fn get_all_invitations(&self) -> AppResult<Vec<Invitation>>;
+
+  async fn delete_invitation(
+    &self,
+    id: &str,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<DeleteResult>;
+}
+
+#[derive(Clone)]
+pub struct InvitationService {
+  repository: DynInvitationRepository,
+  audit: DynAuditService,
+}
+
+impl InvitationService {
+  pub fn new(repository: DynInvitationRepository, audit: DynAuditService) -> Self {
+    Self { repository, audit }
+  }
+}
+
+#[async_trait]
+impl InvitationServiceTrait for InvitationService {
+  async fn create_invitation(
+    &self,
+    request: CreateInvitationDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<Invitation> {
+    let cfg = config::get();
+    let expires_in = request.expires_in.unwrap_or(cfg.signup.invitation_expiry);
+    let created_at = DateTime::now();
+    let expires_at = created_at.saturating_add_duration(Duration::from_secs(expires_in as u64));
+
+    let invitation = Invitation {
+      id: Some(ObjectId::new()),
+      code: generate_code(INVITATION_CODE_LENGTH),
+      email: request.email.map(|email| email.to_lowercase()),
+      created_by: actor.to_string(),
+      created_at,
+      expires_at,
+      used_at: None,
+      used_by: None,
+      tenant_id: None,
+    };
+    self
+      .repository
+      .create_invitation(invitation.clone())
+      .await?;
+    info!("user {:?} created an invitation", actor);
+
+    let mut event =
+      audit_event(client, AuditAction::CreateInvitation, AuditOutcome::Success).actor(actor);
+    if let Some(email) = &invitation.email {
+      event = event.target(email);
+    }
+    self.audit.record(event).await;
+    Ok(invitation)
+  }
+
+  async fn get_all_invitations(&self) -> AppResult<Vec<Invitation>> {
+    let invitations = self.repository.get_all_invitations().await?;
+    Ok(invitations)
+  }
+
+  async fn delete_invitation(
+    &self,
+    id: &str,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<DeleteResult> {
+    let result = self.repository.delete_invitation(id).await?;
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::DeleteInvitation, AuditOutcome::Success)
+          .actor(actor)
+          .target(id),
+      )
+      .await;
+    Ok(result)
+  }
+}
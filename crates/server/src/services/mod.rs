@@ -1,20 +1,133 @@
+mod audit_service;
+mod bulk_user_service;
+mod file_service;
+mod invitation_service;
+mod organization_service;
+mod outbox_service;
+mod token_service;
 mod user_service;
+mod webauthn_service;
 
-use database::Database;
-use std::sync::Arc;
+use audit_service::{AuditService, DynAuditService};
+use bulk_user_service::{BulkUserService, DynBulkUserService};
+use database::{
+  Backend,
+  events::UserEvents,
+  storage::DynFileStorage,
+  user::{
+    cache::{CachedUserRepository, LruUserCache},
+    repository::DynUserRepository,
+    search::DynUserSearch,
+  },
+};
+use file_service::{DynFileService, FileService};
+use invitation_service::{DynInvitationService, InvitationService};
+use mongodb::bson::DateTime;
+use organization_service::{DynOrganizationService, OrganizationService};
+use outbox_service::{DynOutboxService, OutboxService};
+use std::{sync::Arc, time::Duration};
+use token_service::{DynTokenService, TokenService};
 use tracing::info;
 use user_service::{DynUserService, UserService};
+use utils::{AppError, AppResult, config};
+use webauthn_service::{DynWebauthnService, WebauthnService};
 
 #[derive(Clone)]
 pub struct Services {
   pub user: DynUserService,
+  /// Imports and exports users, see `POST /users/import` and `GET /users/export`.
+  pub bulk_user: DynBulkUserService,
+  pub audit: DynAuditService,
+  pub invitation: DynInvitationService,
+  pub organization: DynOrganizationService,
+  pub token: DynTokenService,
+  pub webauthn: DynWebauthnService,
+  /// Uploaded files, their content being kept by the storage of `storage.backend`.
+  pub file: DynFileService,
+  /// Relays the outbox, see `outbox.sinks`.
+  pub outbox: DynOutboxService,
+  /// Changes made to users, published when `events.enabled` is set.
+  pub events: UserEvents,
 }
 
 impl Services {
-  pub fn new(db: Database) -> Self {
+  pub fn new<B: Backend>(repository: Arc<B>, storage: DynFileStorage) -> Self {
+    Self::with_users(repository.clone(), repository.clone(), repository, storage)
+  }
+
+  /// Like `new`, but with users stored in `users` and searched with `search` rather than in
+  /// `repository`.
+  pub fn with_users<B: Backend>(
+    repository: Arc<B>,
+    users: DynUserRepository,
+    search: DynUserSearch,
+    storage: DynFileStorage,
+  ) -> Self {
     info!("initializing services...");
-    let repository = Arc::new(db);
-    let user = Arc::new(UserService::new(repository.clone())) as DynUserService;
-    Self { user }
+    let cache = &config::get().cache;
+    let users = if cache.enabled {
+      let lru = LruUserCache::new(cache.capacity, Duration::from_secs(cache.ttl));
+      Arc::new(CachedUserRepository::new(users, Arc::new(lru))) as DynUserRepository
+    } else {
+      users
+    };
+    let audit = Arc::new(AuditService::new(repository.clone())) as DynAuditService;
+    let invitation =
+      Arc::new(InvitationService::new(repository.clone(), audit.clone())) as DynInvitationService;
+    let token = Arc::new(TokenService::new(repository.clone(), audit.clone())) as DynTokenService;
+    let organization = Arc::new(OrganizationService::new(
+      repository.clone(),
+      users.clone(),
+      repository.clone(),
+      audit.clone(),
+    )) as DynOrganizationService;
+    let user = Arc::new(UserService::new(
+      users.clone(),
+      search,
+      repository.clone(),
+      repository.clone(),
+      repository.clone(),
+      token.clone(),
+      audit.clone(),
+    )) as DynUserService;
+    let bulk_user = Arc::new(BulkUserService::new(
+      user.clone(),
+      invitation.clone(),
+      users.clone(),
+      repository.clone(),
+      repository.clone(),
+      audit.clone(),
+    )) as DynBulkUserService;
+    let outbox = Arc::new(OutboxService::new(repository.clone())) as DynOutboxService;
+    let file = Arc::new(FileService::new(
+      repository.clone(),
+      users.clone(),
+      storage,
+      audit.clone(),
+    )) as DynFileService;
+    let webauthn =
+      Arc::new(WebauthnService::new(repository, users, audit.clone())) as DynWebauthnService;
+    Self {
+      user,
+      bulk_user,
+      audit,
+      invitation,
+      organization,
+      token,
+      webauthn,
+      file,
+      outbox,
+      events: UserEvents::new(config::get().events.capacity),
+    }
   }
 }
+
+/// Parses an optional RFC 3339 query parameter named `field`.
+pub(crate) fn parse_date(value: Option<String>, field: &str) -> AppResult<Option<DateTime>> {
+  value
+    .map(|value| {
+      DateTime::parse_rfc3339_str(&value)
+        .map_err(|_| AppError::BadRequest(format!("{field} must be an RFC 3339 date")))
+    })
+    .transpose()
+}
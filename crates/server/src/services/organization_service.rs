@@ -0,0 +1,665 @@
+use crate::{
+  dtos::organization_dto::{ChangeMemberRoleDto, CreateOrganizationDto, InviteMemberDto},
+  extractors::client_info::ClientInfo,
+  services::audit_service::{DynAuditService, audit_event},
+};
+use async_trait::async_trait;
+use database::{
+  audit::model::{AuditAction, AuditOutcome},
+  organization::{
+    model::{Membership, Organization, OrganizationInvitation, OrganizationRole},
+    repository::DynOrganizationRepository,
+  },
+  transaction::{DynUnitOfWork, transaction},
+  user::{model::User, repository::DynUserRepository},
+};
+use mongodb::bson::{DateTime, oid::ObjectId};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tracing::{error, info};
+use utils::{AppError, AppResult, config};
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynOrganizationService = Arc<dyn OrganizationServiceTrait + Send + Sync>;
+
+#[async_trait]
+#[allow(clippy::module_name_repetitions)]
+pub trait OrganizationServiceTrait {
+  /// Creates an organization owned by `actor`.
+  async fn create_organization(
+    &self,
+    request: CreateOrganizationDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<Organization>;
+
+  /// Returns the organizations `actor` belongs to, with their role in each.
+  async fn get_organizations(
+    &self,
+    actor: &str,
+  ) -> AppResult<Vec<(Organization, OrganizationRole)>>;
+
+  async fn get_members(
+    &self,
+    organization_id: &str,
+    actor: &str,
+  ) -> AppResult<Vec<(Membership, Option<User>)>>;
+
+  async fn change_member_role(
+    &self,
+    organization_id: &str,
+    request: ChangeMemberRoleDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<()>;
+
+  async fn leave_organization(
+    &self,
+    organization_id: &str,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<()>;
+
+  async fn invite_member(
+    &self,
+    organization_id: &str,
+    request: InviteMemberDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<OrganizationInvitation>;
+
+  async fn get_pending_invitations(&self, actor: &str) -> AppResult<Vec<OrganizationInvitation>>;
+
+  async fn accept_invitation(
+    &self,
+    invitation_id: &str,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<Membership>;
+}
+
+#[derive(Clone)]
+pub struct OrganizationService {
+  repository: DynOrganizationRepository,
+  users: DynUserRepository,
+  unit_of_work: DynUnitOfWork,
+  audit: DynAuditService,
+}
+
+impl OrganizationService {
+  pub fn new(
+    repository: DynOrganizationRepository,
+    users: DynUserRepository,
+    unit_of_work: DynUnitOfWork,
+    audit: DynAuditService,
+  ) -> Self {
+    Self {
+      repository,
+      users,
+      unit_of_work,
+      audit,
+    }
+  }
+
+  /// Resolves the authenticated principal to its `User`.
+  async fn current_user(&self, email: &str) -> AppResult<(User, ObjectId)> {
+    let user = self
+      .users
+      .get_user_by_email(email)
+      .await?
+      .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let id = user.id.ok_or(AppError::InternalServerErrorWithContext(
+      "user has no id".to_string(),
+    ))?;
+    Ok((user, id))
+  }
+
+  /// Returns the membership of `user_id`, failing unless its role is at least `role`.
+  ///
+  /// Non-members get a 404 so that organization ids cannot be probed.
+  async fn require_role(
+    &self,
+    organization_id: &ObjectId,
+    user_id: &ObjectId,
+    role: OrganizationRole,
+  ) -> AppResult<Membership> {
+    let membership = self
+      .repository
+      .get_membership(organization_id, user_id)
+      .await?
+      .ok_or_else(|| AppError::NotFound("Organization not found".to_string()))?;
+    if membership.role < role {
+      error!(
+        "user {:?} is not {:?} of organization {:?}",
+        user_id, role, organization_id
+      );
+      return Err(AppError::Forbidden);
+    }
+    Ok(membership)
+  }
+
+  /// Adds `delta` to the owner count, failing when that leaves the organization without owners.
+  ///
+  /// Called in the unit of work changing the memberships, the count being what concurrent
+  /// removals of the last owners conflict on.
+  async fn adjust_owners(&self, organization_id: &ObjectId, delta: i64) -> AppResult<()> {
+    if !self
+      .repository
+      .adjust_owners(organization_id, delta)
+      .await?
+    {
+      return Err(AppError::Conflict(
+        "organization must keep at least one owner".to_string(),
+      ));
+    }
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl OrganizationServiceTrait for OrganizationService {
+  async fn create_organization(
+    &self,
+    request: CreateOrganizationDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<Organization> {
+    let (_, user_id) = self.current_user(actor).await?;
+    let now = DateTime::now();
+    let organization_id = ObjectId::new();
+    let organization = Organization {
+      id: Some(organization_id),
+      name: request.name.unwrap(),
+      created_by: user_id,
+      created_at: now,
+      owners: 1,
+      tenant_id: None,
+    };
+    // The organization must never exist without its owner.
+    transaction(&*self.unit_of_work, || {
+      let organization = organization.clone();
+      async move {
+        self.repository.create_organization(organization).await?;
+        self
+          .repository
+          .create_membership(Membership {
+            id: Some(ObjectId::new()),
+            organization_id,
+            user_id,
+            role: OrganizationRole::Owner,
+            created_at: now,
+            tenant_id: None,
+          })
+          .await?;
+        Ok(())
+      }
+    })
+    .await?;
+    info!(
+      "user {:?} created organization {:?}",
+      actor, organization_id
+    );
+    // Audited once committed, a failed transaction having created nothing.
+    self
+      .audit
+      .record(
+        audit_event(
+          client,
+          AuditAction::CreateOrganization,
+          AuditOutcome::Success,
+        )
+        .actor(actor)
+        .target(organization_id.to_hex()),
+      )
+      .await;
+    Ok(organization)
+  }
+
+  async fn get_organizations(
+    &self,
+    actor: &str,
+  ) -> AppResult<Vec<(Organization, OrganizationRole)>> {
+    let (_, user_id) = self.current_user(actor).await?;
+    let memberships = self.repository.get_memberships_by_user(&user_id).await?;
+    let ids: Vec<ObjectId> = memberships.iter().map(|m| m.organization_id).collect();
+    let organizations = self.repository.get_organizations_by_ids(&ids).await?;
+
+    Ok(
+      organizations
+        .into_iter()
+        .filter_map(|organization| {
+          let role = memberships
+            .iter()
+            .find(|m| Some(m.organization_id) == organization.id)?
+            .role;
+          Some((organization, role))
+        })
+        .collect(),
+    )
+  }
+
+  async fn get_members(
+    &self,
+    organization_id: &str,
+    actor: &str,
+  ) -> AppResult<Vec<(Membership, Option<User>)>> {
+    let organization_id = ObjectId::from_str(organization_id)?;
+    let (_, user_id) = self.current_user(actor).await?;
+    self
+      .require_role(&organization_id, &user_id, OrganizationRole::Member)
+      .await?;
+
+    let memberships = self
+      .repository
+      .get_memberships_by_organization(&organization_id)
+      .await?;
+    let ids: Vec<ObjectId> = memberships.iter().map(|m| m.user_id).collect();
+    let users = self.users.get_users_by_ids(&ids).await?;
+    Ok(
+      memberships
+        .into_iter()
+        .map(|membership| {
+          let user = users
+            .iter()
+            .find(|user| user.id == Some(membership.user_id))
+            .cloned();
+          (membership, user)
+        })
+        .collect(),
+    )
+  }
+
+  async fn change_member_role(
+    &self,
+    organization_id: &str,
+    request: ChangeMemberRoleDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<()> {
+    let organization_id = ObjectId::from_str(organization_id)?;
+    let target_id = ObjectId::from_str(&request.user_id)?;
+    let (_, user_id) = self.current_user(actor).await?;
+    let own = self
+      .require_role(&organization_id, &user_id, OrganizationRole::Admin)
+      .await?;
+    let target = self
+      .repository
+      .get_membership(&organization_id, &target_id)
+      .await?
+      .ok_or_else(|| AppError::NotFound("Member not found".to_string()))?;
+
+    // Only owners may touch owners or hand out ownership.
+    if own.role != OrganizationRole::Owner
+      && (target.role == OrganizationRole::Owner || request.role == OrganizationRole::Owner)
+    {
+      self
+        .audit
+        .record(
+          audit_event(client, AuditAction::ChangeMemberRole, AuditOutcome::Failure)
+            .actor(actor)
+            .target(target_id.to_hex())
+            .reason("only owners can change ownership"),
+        )
+        .await;
+      return Err(AppError::Forbidden);
+    }
+    let owners = match (
+      target.role == OrganizationRole::Owner,
+      request.role == OrganizationRole::Owner,
+    ) {
+      (true, false) => -1,
+      (false, true) => 1,
+      _ => 0,
+    };
+
+    transaction(&*self.unit_of_work, || async move {
+      if owners != 0 {
+        self.adjust_owners(&organization_id, owners).await?;
+      }
+      self
+        .repository
+        .update_membership_role(&organization_id, &target_id, request.role)
+        .await?;
+      Ok(())
+    })
+    .await?;
+    info!(
+      "user {:?} changed role of {:?} in organization {:?} to {:?}",
+      actor, target_id, organization_id, request.role
+    );
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::ChangeMemberRole, AuditOutcome::Success)
+          .actor(actor)
+          .target(target_id.to_hex()),
+      )
+      .await;
+    Ok(())
+  }
+
+  async fn leave_organization(
+    &self,
+    organization_id: &str,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<()> {
+    let organization_id = ObjectId::from_str(organization_id)?;
+    let (_, user_id) = self.current_user(actor).await?;
+    let membership = self
+      .require_role(&organization_id, &user_id, OrganizationRole::Member)
+      .await?;
+
+    transaction(&*self.unit_of_work, || async move {
+      if membership.role == OrganizationRole::Owner {
+        self.adjust_owners(&organization_id, -1).await?;
+      }
+      self
+        .repository
+        .delete_membership(&organization_id, &user_id)
+        .await?;
+      Ok(())
+    })
+    .await?;
+    info!("user {:?} left organization {:?}", actor, organization_id);
+    self
+      .audit
+      .record(
+        audit_event(
+          client,
+          AuditAction::LeaveOrganization,
+          AuditOutcome::Success,
+        )
+        .actor(actor)
+        .target(organization_id.to_hex()),
+      )
+      .await;
+    Ok(())
+  }
+
+  async fn invite_member(
+    &self,
+    organization_id: &str,
+    request: InviteMemberDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<OrganizationInvitation> {
+    let cfg = config::get();
+    let organization_id = ObjectId::from_str(organization_id)?;
+    let (_, user_id) = self.current_user(actor).await?;
+    let own = self
+      .require_role(&organization_id, &user_id, OrganizationRole::Admin)
+      .await?;
+    if request.role > own.role {
+      return Err(AppError::Forbidden);
+    }
+
+    let email = request.email.to_lowercase();
+    let invitee_id = self
+      .users
+      .get_user_by_email(&email)
+      .await?
+      .and_then(|invitee| invitee.id);
+    if let Some(invitee_id) = invitee_id
+      && self
+        .repository
+        .get_membership(&organization_id, &invitee_id)
+        .await?
+        .is_some()
+    {
+      return Err(AppError::Conflict(format!("{email} is already a member")));
+    }
+
+    let created_at = DateTime::now();
+    let invitation = OrganizationInvitation {
+      id: Some(ObjectId::new()),
+      organization_id,
+      email,
+      role: request.role,
+      invited_by: user_id,
+      created_at,
+      expires_at: created_at
+        .saturating_add_duration(Duration::from_secs(cfg.signup.invitation_expiry as u64)),
+      accepted_at: None,
+      tenant_id: None,
+    };
+    self
+      .repository
+      .create_organization_invitation(invitation.clone())
+      .await?;
+    info!(
+      "user {:?} invited {:?} to organization {:?}",
+      actor, invitation.email, organization_id
+    );
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::InviteMember, AuditOutcome::Success)
+          .actor(actor)
+          .target(&invitation.email),
+      )
+      .await;
+    Ok(invitation)
+  }
+
+  async fn get_pending_invitations(&self, actor: &str) -> AppResult<Vec<OrganizationInvitation>> {
+    let invitations = self
+      .repository
+      .get_pending_invitations_by_email(actor)
+      .await?;
+    Ok(invitations)
+  }
+
+  async fn accept_invitation(
+    &self,
+    invitation_id: &str,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<Membership> {
+    let invitation_id = ObjectId::from_str(invitation_id)?;
+    let (_, user_id) = self.current_user(actor).await?;
+    // Accepting the invitation and joining the organization succeed or fail together.
+    let (membership, organization_id, joined) = transaction(&*self.unit_of_work, || async move {
+      let invitation = self
+        .repository
+        .accept_organization_invitation(&invitation_id, actor)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Invitation not found or expired".to_string()))?;
+
+      if let Some(membership) = self
+        .repository
+        .get_membership(&invitation.organization_id, &user_id)
+        .await?
+      {
+        return Ok((membership, invitation.organization_id, false));
+      }
+
+      let membership = Membership {
+        id: Some(ObjectId::new()),
+        organization_id: invitation.organization_id,
+        user_id,
+        role: invitation.role,
+        created_at: DateTime::now(),
+        tenant_id: None,
+      };
+      self
+        .repository
+        .create_membership(membership.clone())
+        .await?;
+      if membership.role == OrganizationRole::Owner {
+        self.adjust_owners(&membership.organization_id, 1).await?;
+      }
+      Ok((membership, invitation.organization_id, true))
+    })
+    .await?;
+    if joined {
+      info!("user {:?} joined organization {:?}", actor, organization_id);
+      self
+        .audit
+        .record(
+          audit_event(client, AuditAction::JoinOrganization, AuditOutcome::Success)
+            .actor(actor)
+            .target(organization_id.to_hex()),
+        )
+        .await;
+    }
+    Ok(membership)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::audit_service::AuditService;
+  use database::{
+    memory::MemoryDatabase, organization::repository::OrganizationRepositoryTrait,
+    user::repository::UserRepositoryTrait,
+  };
+
+  const OWNER: &str = "owner@example.com";
+  const OTHER: &str = "other@example.com";
+
+  /// A service over an in-memory database, and the id of an organization owned by `OWNER`.
+  async fn service() -> (OrganizationService, Arc<MemoryDatabase>, String) {
+    config::init_for_tests();
+    let database = Arc::new(MemoryDatabase::new());
+    for email in [OWNER, OTHER] {
+      database.create_user("Member", email, "hash").await.unwrap();
+    }
+    let audit = Arc::new(AuditService::new(database.clone()));
+    let service =
+      OrganizationService::new(database.clone(), database.clone(), database.clone(), audit);
+    let request = CreateOrganizationDto {
+      name: Some("Acme".to_string()),
+    };
+    let organization = service
+      .create_organization(request, OWNER, &ClientInfo::default())
+      .await
+      .unwrap();
+    (service, database, organization.id.unwrap().to_hex())
+  }
+
+  /// Makes `OTHER` a member of the organization with `role`.
+  async fn join(service: &OrganizationService, organization_id: &str, role: OrganizationRole) {
+    let client = ClientInfo::default();
+    let request = InviteMemberDto {
+      email: OTHER.to_string(),
+      role,
+    };
+    let invitation = service
+      .invite_member(organization_id, request, OWNER, &client)
+      .await
+      .unwrap();
+    service
+      .accept_invitation(&invitation.id.unwrap().to_hex(), OTHER, &client)
+      .await
+      .unwrap();
+  }
+
+  async fn owners(database: &MemoryDatabase, organization_id: &str) -> (i64, u64) {
+    let id = ObjectId::from_str(organization_id).unwrap();
+    let organization = database.get_organization_by_id(&id).await.unwrap().unwrap();
+    let members = database
+      .count_members_with_role(&id, OrganizationRole::Owner)
+      .await
+      .unwrap();
+    (organization.owners, members)
+  }
+
+  #[tokio::test]
+  async fn members_come_with_their_users() {
+    let (service, database, organization_id) = service().await;
+    join(&service, &organization_id, OrganizationRole::Member).await;
+    let members = service.get_members(&organization_id, OWNER).await.unwrap();
+    let mut emails: Vec<&str> = members
+      .iter()
+      .map(|(_, user)| user.as_ref().unwrap().email.as_str())
+      .collect();
+    emails.sort_unstable();
+    assert_eq!(emails, [OTHER, OWNER]);
+
+    // Deleted users are left out, their memberships remaining.
+    let (other, _) = service.current_user(OTHER).await.unwrap();
+    let other_id = other.id.unwrap();
+    database
+      .delete_user(&other_id.to_hex(), None)
+      .await
+      .unwrap();
+    let members = service.get_members(&organization_id, OWNER).await.unwrap();
+    assert_eq!(members.len(), 2);
+    for (membership, user) in members {
+      assert_eq!(user.is_none(), membership.user_id == other_id);
+    }
+  }
+
+  #[tokio::test]
+  async fn the_last_owner_cannot_leave_or_be_demoted() {
+    let (service, database, organization_id) = service().await;
+    let client = ClientInfo::default();
+    let result = service
+      .leave_organization(&organization_id, OWNER, &client)
+      .await;
+    assert!(matches!(result, Err(AppError::Conflict(_))));
+
+    let (owner, _) = service.current_user(OWNER).await.unwrap();
+    let request = ChangeMemberRoleDto {
+      user_id: owner.id.unwrap().to_hex(),
+      role: OrganizationRole::Admin,
+    };
+    let result = service
+      .change_member_role(&organization_id, request, OWNER, &client)
+      .await;
+    assert!(matches!(result, Err(AppError::Conflict(_))));
+    assert_eq!(owners(&database, &organization_id).await, (1, 1));
+  }
+
+  #[tokio::test]
+  async fn owner_changes_are_counted() {
+    let (service, database, organization_id) = service().await;
+    let client = ClientInfo::default();
+    join(&service, &organization_id, OrganizationRole::Member).await;
+    assert_eq!(owners(&database, &organization_id).await, (1, 1));
+
+    let (other, _) = service.current_user(OTHER).await.unwrap();
+    let promote = |role| ChangeMemberRoleDto {
+      user_id: other.id.unwrap().to_hex(),
+      role,
+    };
+    service
+      .change_member_role(
+        &organization_id,
+        promote(OrganizationRole::Owner),
+        OWNER,
+        &client,
+      )
+      .await
+      .unwrap();
+    assert_eq!(owners(&database, &organization_id).await, (2, 2));
+    service
+      .change_member_role(
+        &organization_id,
+        promote(OrganizationRole::Admin),
+        OWNER,
+        &client,
+      )
+      .await
+      .unwrap();
+    assert_eq!(owners(&database, &organization_id).await, (1, 1));
+  }
+
+  #[tokio::test]
+  async fn concurrent_owners_cannot_all_leave() {
+    let (service, database, organization_id) = service().await;
+    let client = ClientInfo::default();
+    join(&service, &organization_id, OrganizationRole::Owner).await;
+    assert_eq!(owners(&database, &organization_id).await, (2, 2));
+
+    let (first, second) = tokio::join!(
+      service.leave_organization(&organization_id, OWNER, &client),
+      service.leave_organization(&organization_id, OTHER, &client),
+    );
+    // Exactly one of them left, the other one being the last owner.
+    let failed = if first.is_ok() { second } else { first };
+    assert!(matches!(failed, Err(AppError::Conflict(_))));
+    assert_eq!(owners(&database, &organization_id).await, (1, 1));
+  }
+}
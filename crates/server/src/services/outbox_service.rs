@@ -0,0 +1,500 @@
+use async_trait::async_trait;
+use database::outbox::{model::OutboxMessage, repository::DynOutboxRepository};
+use hmac::{Hmac, Mac};
+use mongodb::bson::{Bson, DateTime, Document, oid::ObjectId};
+use serde_json::json;
+use sha2::Sha256;
+use std::{
+  fmt::Write,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+use utils::{
+  AppError, AppResult,
+  config::{self, outbox_config::OutboxSinkKind},
+};
+
+/// Longest wait between two attempts to deliver a message.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// Part of `outbox.lease` a webhook request may take. The relay renews the lease of a batch
+/// once half of it has passed, so that a delivery started before that ends within the lease.
+const WEBHOOK_TIMEOUT_PER_LEASE: u32 = 4;
+
+/// Messages kept for in-process subscribers that fall behind.
+const IN_PROCESS_CAPACITY: usize = 1024;
+
+/// Records a message in the outbox when `outbox.enabled` is set, within the current unit of
+/// work so that it is only delivered if the change it describes is committed.
+pub async fn enqueue(
+  outbox: &DynOutboxRepository,
+  topic: &str,
+  payload: Document,
+) -> AppResult<()> {
+  if config::get().outbox.enabled {
+    outbox
+      .enqueue_message(OutboxMessage::new(topic, payload))
+      .await?;
+  }
+  Ok(())
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynOutboxSink = Arc<dyn OutboxSinkTrait + Send + Sync>;
+
+/// Where the relay delivers outbox messages.
+#[async_trait]
+pub trait OutboxSinkTrait {
+  fn name(&self) -> &'static str;
+
+  /// Delivers `message`, which may already have been delivered by a previous attempt.
+  async fn deliver(&self, message: &OutboxMessage) -> AppResult<()>;
+}
+
+/// Logs each message.
+pub struct LogSink;
+
+#[async_trait]
+impl OutboxSinkTrait for LogSink {
+  fn name(&self) -> &'static str {
+    "log"
+  }
+
+  async fn deliver(&self, message: &OutboxMessage) -> AppResult<()> {
+    info!(
+      "outbox message {:?} {}: {}",
+      message.id, message.topic, message.payload
+    );
+    Ok(())
+  }
+}
+
+/// Posts each message as JSON, see `outbox.webhook_url`.
+///
+/// The body is signed when `outbox.webhook_secret` is set: `X-Outbox-Signature` holds
+/// `sha256=` and the hex encoded HMAC-SHA256 of the body. Any status but 2xx fails the delivery.
+pub struct WebhookSink {
+  client: reqwest::Client,
+  url: String,
+  secret: Option<String>,
+}
+
+impl WebhookSink {
+  pub fn new(url: String, secret: Option<String>, timeout: Duration) -> Self {
+    let client = reqwest::Client::builder()
+      .timeout(timeout)
+      .build()
+      .expect("the webhook client should build");
+    Self {
+      client,
+      url,
+      secret,
+    }
+  }
+
+  fn signature(&self, body: &[u8]) -> AppResult<Option<String>> {
+    let Some(secret) = &self.secret else {
+      return Ok(None);
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+      .map_err(|e| AppError::InternalServerErrorWithContext(e.to_string()))?;
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex = digest.iter().fold(String::new(), |mut hex, byte| {
+      let _ = write!(hex, "{byte:02x}");
+      hex
+    });
+    Ok(Some(format!("sha256={hex}")))
+  }
+}
+
+#[async_trait]
+impl OutboxSinkTrait for WebhookSink {
+  fn name(&self) -> &'static str {
+    "webhook"
+  }
+
+  async fn deliver(&self, message: &OutboxMessage) -> AppResult<()> {
+    let id = message.id.map(|id| id.to_hex()).unwrap_or_default();
+    let body = json!({
+      "id": id,
+      "topic": message.topic,
+      "payload": Bson::Document(message.payload.clone()).into_relaxed_extjson(),
+      "tenant_id": message.tenant_id,
+      "created_at": message.created_at.try_to_rfc3339_string().ok(),
+    })
+    .to_string();
+    let mut request = self
+      .client
+      .post(&self.url)
+      .header("content-type", "application/json")
+      .header("x-outbox-message-id", &id);
+    if let Some(signature) = self.signature(body.as_bytes())? {
+      request = request.header("x-outbox-signature", signature);
+    }
+    let response = request
+      .body(body)
+      .send()
+      .await
+      .map_err(|e| AppError::InternalServerErrorWithContext(format!("webhook failed: {e}")))?;
+    if !response.status().is_success() {
+      return Err(AppError::InternalServerErrorWithContext(format!(
+        "webhook answered {}",
+        response.status()
+      )));
+    }
+    Ok(())
+  }
+}
+
+/// Publishes each message to the subscribers of `OutboxServiceTrait::subscribe`.
+///
+/// Subscribers only receive the messages published while they are subscribed: with none, the
+/// message is dropped rather than failed, which would retry it to the other sinks as well.
+pub struct InProcessSink {
+  sender: broadcast::Sender<OutboxMessage>,
+}
+
+#[async_trait]
+impl OutboxSinkTrait for InProcessSink {
+  fn name(&self) -> &'static str {
+    "in_process"
+  }
+
+  async fn deliver(&self, message: &OutboxMessage) -> AppResult<()> {
+    if self.sender.send(message.clone()).is_err() {
+      debug!(
+        "no in-process subscriber for outbox message {:?}",
+        message.id
+      );
+    }
+    Ok(())
+  }
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynOutboxService = Arc<dyn OutboxServiceTrait + Send + Sync>;
+
+#[async_trait]
+#[allow(clippy::module_name_repetitions)]
+pub trait OutboxServiceTrait {
+  /// Delivers one batch of due messages to every sink, then marks each one delivered or
+  /// schedules its retry. Returns the number of messages claimed.
+  ///
+  /// A message is retried until every sink accepted it, each attempt skipping the sinks that
+  /// already did. Sinks still receive each message at least once, e.g. when the relay stops
+  /// before recording a delivery, and should ignore the ids they already handled.
+  async fn relay(&self) -> AppResult<usize>;
+
+  /// Receives the messages delivered by the `in_process` sink, for consumers living in this
+  /// process.
+  #[allow(dead_code)]
+  fn subscribe(&self) -> broadcast::Receiver<OutboxMessage>;
+}
+
+pub struct OutboxService {
+  repository: DynOutboxRepository,
+  sinks: Vec<DynOutboxSink>,
+  sender: broadcast::Sender<OutboxMessage>,
+}
+
+impl OutboxService {
+  /// Creates the service with the sinks listed in `outbox.sinks`.
+  pub fn new(repository: DynOutboxRepository) -> Self {
+    let cfg = &config::get().outbox;
+    let (sender, _) = broadcast::channel(IN_PROCESS_CAPACITY);
+    let mut sinks = Vec::new();
+    for kind in &cfg.sinks {
+      let sink = match kind {
+        OutboxSinkKind::Log => Arc::new(LogSink) as DynOutboxSink,
+        OutboxSinkKind::Webhook => Arc::new(WebhookSink::new(
+          cfg.webhook_url.clone().unwrap_or_default(),
+          cfg.webhook_secret.clone(),
+          Duration::from_secs(cfg.lease) / WEBHOOK_TIMEOUT_PER_LEASE,
+        )),
+        OutboxSinkKind::InProcess => Arc::new(InProcessSink {
+          sender: sender.clone(),
+        }),
+      };
+      sinks.push(sink);
+    }
+    Self {
+      repository,
+      sinks,
+      sender,
+    }
+  }
+
+  /// Delivers `message` to the sinks it was not delivered to yet, adding those that accept it
+  /// to `delivered_to`.
+  async fn deliver(
+    &self,
+    message: &OutboxMessage,
+    delivered_to: &mut Vec<String>,
+  ) -> Result<(), String> {
+    let mut errors = Vec::new();
+    for sink in &self.sinks {
+      if delivered_to.iter().any(|name| name == sink.name()) {
+        continue;
+      }
+      match sink.deliver(message).await {
+        Ok(()) => delivered_to.push(sink.name().to_string()),
+        Err(e) => errors.push(format!("{}: {e}", sink.name())),
+      }
+    }
+    if errors.is_empty() {
+      Ok(())
+    } else {
+      Err(errors.join("; "))
+    }
+  }
+}
+
+/// When to retry a message after its `attempts`-th failed delivery, doubling the delay each time.
+fn retry_at(attempts: u32, retry_delay: u64) -> DateTime {
+  let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+  let delay = Duration::from_secs(retry_delay)
+    .saturating_mul(factor)
+    .min(MAX_RETRY_DELAY);
+  DateTime::from_system_time(DateTime::now().to_system_time() + delay)
+}
+
+#[async_trait]
+impl OutboxServiceTrait for OutboxService {
+  async fn relay(&self) -> AppResult<usize> {
+    let cfg = &config::get().outbox;
+    let lease = Duration::from_secs(cfg.lease);
+    let messages = self
+      .repository
+      .claim_messages(cfg.batch_size, lease)
+      .await?;
+    let mut leased_at = Instant::now();
+    for (i, message) in messages.iter().enumerate() {
+      // A batch may take longer than the lease, whose end would let another relay claim the
+      // messages left: renew it for them before it is too late.
+      if leased_at.elapsed() >= lease / 2 {
+        let ids: Vec<ObjectId> = messages[i..].iter().filter_map(|m| m.id).collect();
+        self.repository.renew_lease(&ids, lease).await?;
+        leased_at = Instant::now();
+      }
+      let Some(id) = message.id else {
+        continue;
+      };
+      let mut delivered_to = message.delivered_to.clone();
+      match self.deliver(message, &mut delivered_to).await {
+        Ok(()) => {
+          self.repository.complete_message(&id).await?;
+        }
+        Err(e) if message.attempts >= cfg.max_attempts => {
+          error!(
+            "giving up on outbox message {id} after {} attempts: {e}",
+            message.attempts
+          );
+          self
+            .repository
+            .fail_message(&id, &e, &delivered_to, None)
+            .await?;
+        }
+        Err(e) => {
+          warn!(
+            "failed to deliver outbox message {id}, attempt {}: {e}",
+            message.attempts
+          );
+          let retry_at = retry_at(message.attempts, cfg.retry_delay);
+          self
+            .repository
+            .fail_message(&id, &e, &delivered_to, Some(retry_at))
+            .await?;
+        }
+      }
+    }
+    Ok(messages.len())
+  }
+
+  fn subscribe(&self) -> broadcast::Receiver<OutboxMessage> {
+    self.sender.subscribe()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use database::{memory::MemoryDatabase, outbox::repository::OutboxRepositoryTrait};
+  use mongodb::{
+    bson::doc,
+    results::{InsertOneResult, UpdateResult},
+  };
+  use std::sync::Mutex;
+
+  /// An in-memory outbox making failed messages due again right away, which records when they
+  /// were to be retried and which ones were given up on.
+  #[derive(Default)]
+  struct ImpatientOutbox {
+    inner: MemoryDatabase,
+    retries: Mutex<Vec<DateTime>>,
+    given_up: Mutex<Vec<ObjectId>>,
+  }
+
+  #[async_trait]
+  impl OutboxRepositoryTrait for ImpatientOutbox {
+    async fn enqueue_message(&self, message: OutboxMessage) -> AppResult<InsertOneResult> {
+      self.inner.enqueue_message(message).await
+    }
+
+    async fn claim_messages(&self, limit: usize, lease: Duration) -> AppResult<Vec<OutboxMessage>> {
+      self.inner.claim_messages(limit, lease).await
+    }
+
+    async fn renew_lease(&self, ids: &[ObjectId], lease: Duration) -> AppResult<UpdateResult> {
+      self.inner.renew_lease(ids, lease).await
+    }
+
+    async fn complete_message(&self, id: &ObjectId) -> AppResult<UpdateResult> {
+      self.inner.complete_message(id).await
+    }
+
+    async fn fail_message(
+      &self,
+      id: &ObjectId,
+      error: &str,
+      delivered_to: &[String],
+      retry_at: Option<DateTime>,
+    ) -> AppResult<UpdateResult> {
+      match retry_at {
+        Some(retry_at) => self.retries.lock().unwrap().push(retry_at),
+        None => self.given_up.lock().unwrap().push(*id),
+      }
+      let retry_at = retry_at.map(|_| DateTime::MIN);
+      self
+        .inner
+        .fail_message(id, error, delivered_to, retry_at)
+        .await
+    }
+  }
+
+  /// Records the topics it receives.
+  #[derive(Default)]
+  struct RecordingSink {
+    received: Mutex<Vec<String>>,
+  }
+
+  #[async_trait]
+  impl OutboxSinkTrait for RecordingSink {
+    fn name(&self) -> &'static str {
+      "recording"
+    }
+
+    async fn deliver(&self, message: &OutboxMessage) -> AppResult<()> {
+      self.received.lock().unwrap().push(message.topic.clone());
+      Ok(())
+    }
+  }
+
+  struct FailingSink;
+
+  #[async_trait]
+  impl OutboxSinkTrait for FailingSink {
+    fn name(&self) -> &'static str {
+      "failing"
+    }
+
+    async fn deliver(&self, _: &OutboxMessage) -> AppResult<()> {
+      Err(AppError::InternalServerErrorWithContext(
+        "unavailable".into(),
+      ))
+    }
+  }
+
+  /// A service delivering to `sinks` the messages of `outbox`, which holds one `user.created`.
+  async fn service(sinks: Vec<DynOutboxSink>) -> (OutboxService, Arc<ImpatientOutbox>) {
+    config::init_for_tests();
+    let outbox = Arc::new(ImpatientOutbox::default());
+    outbox
+      .enqueue_message(OutboxMessage::new("user.created", doc! {}))
+      .await
+      .unwrap();
+    let (sender, _) = broadcast::channel(IN_PROCESS_CAPACITY);
+    let service = OutboxService {
+      repository: outbox.clone(),
+      sinks,
+      sender,
+    };
+    (service, outbox)
+  }
+
+  #[tokio::test]
+  async fn sinks_that_accepted_a_message_do_not_get_it_again() {
+    let accepting = Arc::new(RecordingSink::default());
+    let (service, outbox) = service(vec![accepting.clone(), Arc::new(FailingSink)]).await;
+    assert_eq!(service.relay().await.unwrap(), 1);
+    assert_eq!(service.relay().await.unwrap(), 1);
+    assert_eq!(outbox.retries.lock().unwrap().len(), 2);
+    assert_eq!(*accepting.received.lock().unwrap(), ["user.created"]);
+  }
+
+  #[tokio::test]
+  async fn messages_without_in_process_subscribers_are_delivered() {
+    let (mut service, outbox) = service(Vec::new()).await;
+    service.sinks = vec![Arc::new(InProcessSink {
+      sender: service.sender.clone(),
+    })];
+    assert_eq!(service.relay().await.unwrap(), 1);
+    assert!(outbox.retries.lock().unwrap().is_empty());
+
+    let mut subscriber = service.subscribe();
+    outbox
+      .enqueue_message(OutboxMessage::new("user.updated", doc! {}))
+      .await
+      .unwrap();
+    assert_eq!(service.relay().await.unwrap(), 1);
+    assert_eq!(subscriber.recv().await.unwrap().topic, "user.updated");
+  }
+
+  /// Seconds from now until `date`.
+  fn seconds_until(date: DateTime) -> i64 {
+    (date.timestamp_millis() - DateTime::now().timestamp_millis() + 500) / 1000
+  }
+
+  #[test]
+  fn retries_back_off_exponentially_up_to_an_hour() {
+    assert_eq!(seconds_until(retry_at(1, 10)), 10);
+    assert_eq!(seconds_until(retry_at(2, 10)), 20);
+    assert_eq!(seconds_until(retry_at(4, 10)), 80);
+    assert_eq!(seconds_until(retry_at(12, 10)), 3600);
+    assert_eq!(seconds_until(retry_at(u32::MAX, 10)), 3600);
+  }
+
+  #[tokio::test]
+  async fn messages_are_given_up_on_after_max_attempts() {
+    let (service, outbox) = service(vec![Arc::new(FailingSink)]).await;
+    let max_attempts = config::get().outbox.max_attempts;
+    for _ in 1..max_attempts {
+      assert_eq!(service.relay().await.unwrap(), 1);
+    }
+    assert_eq!(
+      outbox.retries.lock().unwrap().len(),
+      max_attempts as usize - 1
+    );
+    assert!(outbox.given_up.lock().unwrap().is_empty());
+
+    assert_eq!(service.relay().await.unwrap(), 1);
+    assert_eq!(outbox.given_up.lock().unwrap().len(), 1);
+    // Failed messages are not claimed anymore.
+    assert_eq!(service.relay().await.unwrap(), 0);
+  }
+
+  #[test]
+  fn webhook_bodies_are_signed_with_the_secret() {
+    let body = b"The quick brown fox jumps over the lazy dog";
+    let timeout = Duration::from_secs(1);
+    let sink = WebhookSink::new("http://localhost".into(), Some("key".into()), timeout);
+    assert_eq!(
+      sink.signature(body).unwrap().as_deref(),
+      Some("sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8")
+    );
+    let unsigned = WebhookSink::new("http://localhost".into(), None, timeout);
+    assert_eq!(unsigned.signature(body).unwrap(), None);
+  }
+}
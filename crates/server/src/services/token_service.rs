@@ -0,0 +1,217 @@
+use crate::{
+  extractors::client_info::ClientInfo,
+  services::audit_service::{DynAuditService, audit_event},
+};
+use async_trait::async_trait;
+use database::{
+  audit::model::{AuditAction, AuditOutcome},
+  token::{model::RevokedToken, repository::DynTokenRepository},
+};
+use mongodb::bson::{DateTime, oid::ObjectId};
+use std::sync::Arc;
+use tracing::info;
+use utils::{
+  AppResult, config,
+  jwt::{Claims, decode_token},
+  tenant,
+};
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynTokenService = Arc<dyn TokenServiceTrait + Send + Sync>;
+
+#[async_trait]
+#[allow(clippy::module_name_repetitions)]
+pub trait TokenServiceTrait {
+  /// Returns the claims of `token` if it is valid, unexpired and not revoked.
+  async fn introspect(
+    &self,
+    token: &str,
+    token_type_hint: Option<&str>,
+  ) -> AppResult<Option<Claims>>;
+
+  /// Revokes `token`. Invalid or already expired tokens are ignored, as per RFC 7009.
+  async fn revoke(
+    &self,
+    token: &str,
+    token_type_hint: Option<&str>,
+    client_id: &str,
+    client: &ClientInfo,
+  ) -> AppResult<()>;
+
+  async fn is_revoked(&self, claims: &Claims) -> AppResult<bool>;
+}
+
+#[derive(Clone)]
+pub struct TokenService {
+  repository: DynTokenRepository,
+  audit: DynAuditService,
+}
+
+impl TokenService {
+  pub fn new(repository: DynTokenRepository, audit: DynAuditService) -> Self {
+    Self { repository, audit }
+  }
+
+  /// Decodes `token` with the access or refresh secret, trying the hinted one first.
+  fn decode(token: &str, token_type_hint: Option<&str>) -> Option<Claims> {
+    let cfg = config::get();
+    let access = cfg.jwt.access_token_secret.as_str();
+    let refresh = cfg.jwt.refresh_token_secret.as_str();
+    let secrets = match token_type_hint {
+      Some("refresh_token") => [refresh, access],
+      _ => [access, refresh],
+    };
+    secrets
+      .iter()
+      .find_map(|secret| decode_token(token, secret).ok())
+      .filter(|claims| claims.tenant == tenant::current())
+  }
+}
+
+#[async_trait]
+impl TokenServiceTrait for TokenService {
+  async fn introspect(
+    &self,
+    token: &str,
+    token_type_hint: Option<&str>,
+  ) -> AppResult<Option<Claims>> {
+    let Some(claims) = Self::decode(token, token_type_hint) else {
+      return Ok(None);
+    };
+    if self.is_revoked(&claims).await? {
+      return Ok(None);
+    }
+    Ok(Some(claims))
+  }
+
+  async fn revoke(
+    &self,
+    token: &str,
+    token_type_hint: Option<&str>,
+    client_id: &str,
+    client: &ClientInfo,
+  ) -> AppResult<()> {
+    let Some(claims) = Self::decode(token, token_type_hint) else {
+      return Ok(());
+    };
+    let Some(jti) = claims.jti else {
+      return Ok(());
+    };
+
+    let expires_at = DateTime::from_millis(claims.exp as i64 * 1000);
+    self
+      .repository
+      .revoke_token(RevokedToken {
+        id: Some(ObjectId::new()),
+        jti: jti.clone(),
+        subject: claims.sub.clone(),
+        revoked_at: DateTime::now(),
+        expires_at,
+        tenant_id: None,
+      })
+      .await?;
+    info!("client {:?} revoked token {:?}", client_id, jti);
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::RevokeToken, AuditOutcome::Success)
+          .actor(client_id)
+          .target(claims.sub),
+      )
+      .await;
+    Ok(())
+  }
+
+  async fn is_revoked(&self, claims: &Claims) -> AppResult<bool> {
+    match &claims.jti {
+      Some(jti) => self.repository.is_token_revoked(jti).await,
+      None => Ok(false),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::services::audit_service::AuditService;
+  use database::memory::MemoryDatabase;
+  use utils::jwt::{TokenType, create_token, now};
+
+  fn service() -> TokenService {
+    config::init_for_tests();
+    let database = Arc::new(MemoryDatabase::new());
+    let audit = Arc::new(AuditService::new(database.clone()));
+    TokenService::new(database, audit)
+  }
+
+  fn token(token_type: TokenType) -> String {
+    let cfg = config::get();
+    let secret = match token_type {
+      TokenType::Access => &cfg.jwt.access_token_secret,
+      TokenType::Refresh => &cfg.jwt.refresh_token_secret,
+    };
+    create_token(secret, "ada@example.com", 60, token_type, "user", now())
+  }
+
+  #[tokio::test]
+  async fn introspection_accepts_both_token_types_whatever_the_hint() {
+    let service = service();
+    for (token_type, hint) in [
+      (TokenType::Access, None),
+      (TokenType::Access, Some("refresh_token")),
+      (TokenType::Refresh, None),
+      (TokenType::Refresh, Some("refresh_token")),
+    ] {
+      let claims = service
+        .introspect(&token(token_type), hint)
+        .await
+        .unwrap()
+        .unwrap();
+      assert_eq!(claims.sub, "ada@example.com");
+      assert_eq!(claims.token_type, token_type);
+    }
+  }
+
+  #[tokio::test]
+  async fn introspection_rejects_unusable_tokens() {
+    let service = service();
+    assert!(service.introspect("garbage", None).await.unwrap().is_none());
+    let forged = create_token(
+      "other-secret",
+      "ada@example.com",
+      60,
+      TokenType::Access,
+      "user",
+      0,
+    );
+    assert!(service.introspect(&forged, None).await.unwrap().is_none());
+    // A token issued to another tenant is not usable here.
+    let foreign = tenant::scope("acme".to_string(), async { token(TokenType::Access) }).await;
+    assert!(service.introspect(&foreign, None).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn revoked_tokens_are_no_longer_active() {
+    let service = service();
+    let client = ClientInfo::default();
+    let access = token(TokenType::Access);
+    let refresh = token(TokenType::Refresh);
+
+    service
+      .revoke(&refresh, Some("access_token"), "billing", &client)
+      .await
+      .unwrap();
+    assert!(service.introspect(&refresh, None).await.unwrap().is_none());
+    assert!(service.introspect(&access, None).await.unwrap().is_some());
+
+    // Revoking twice, or revoking garbage, succeeds without doing anything.
+    service
+      .revoke(&refresh, None, "billing", &client)
+      .await
+      .unwrap();
+    service
+      .revoke("garbage", None, "billing", &client)
+      .await
+      .unwrap();
+  }
+}
@@ -1,26 +1,81 @@
-use crate::dtos::user_dto::{ChangePasswordDto, LoginInDto, SignUpUserDto, UpdateUserDto};
+use crate::{
+  dtos::user_dto::{
+    ChangePasswordDto, LoginInDto, ReauthenticateDto, SignUpUserDto, UpdateUserDto, UserQueryDto,
+    UserSearchDto,
+  },
+  extractors::{client_info::ClientInfo, pagination::PaginationQuery},
+  services::{
+    audit_service::{DynAuditService, audit_event},
+    outbox_service::enqueue,
+    parse_date,
+    token_service::DynTokenService,
+  },
+};
 use async_trait::async_trait;
-use database::user::{model::User, repository::DynUserRepository};
-use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
-use std::sync::Arc;
+use database::{
+  audit::model::{AuditAction, AuditOutcome},
+  invitation::repository::DynInvitationRepository,
+  outbox::repository::DynOutboxRepository,
+  pagination::Page,
+  transaction::{DynUnitOfWork, transaction},
+  user::{
+    model::{User, UserFilter},
+    repository::DynUserRepository,
+    search::{DynUserSearch, MIN_TERM_LENGTH, UserSearchQuery, UserSearchResults, query_terms},
+  },
+};
+use mongodb::{
+  bson::{DateTime, Document, doc},
+  results::{InsertOneResult, UpdateResult},
+};
+use std::{sync::Arc, time::Duration};
 use tracing::{error, info};
 use utils::{
-  AppError, AppResult, config, cookie,
-  jwt::{TokenType, create_token},
+  AppError, AppResult,
+  config::{
+    self,
+    signup_config::{SignupConfig, SignupMode},
+  },
+  cookie,
+  jwt::{self, TokenType, create_token},
   password::{hash_password, verify_password},
 };
 
+/// Accepted `sort` values of the user listing and the fields they sort by.
+const SORTABLE_FIELDS: &[(&str, &str)] = &[
+  ("name", "name"),
+  ("email", "email"),
+  ("role", "role"),
+  ("created_at", "created_at"),
+  ("updated_at", "updated_at"),
+  ("last_login_at", "last_login_at"),
+];
+
 #[allow(clippy::module_name_repetitions)]
 pub type DynUserService = Arc<dyn UserServiceTrait + Send + Sync>;
 
 #[async_trait]
 #[allow(clippy::module_name_repetitions)]
 pub trait UserServiceTrait {
-  async fn signup_user(&self, request: SignUpUserDto) -> AppResult<InsertOneResult>;
+  async fn signup_user(
+    &self,
+    request: SignUpUserDto,
+    client: &ClientInfo,
+  ) -> AppResult<InsertOneResult>;
+
+  /// Creates a user on behalf of an administrator, regardless of `signup.mode` and the allowed
+  /// domains. Auditing is left to the caller.
+  async fn create_user(
+    &self,
+    name: &str,
+    email: &str,
+    password: &str,
+  ) -> AppResult<InsertOneResult>;
 
   async fn login_user(
     &self,
     request: LoginInDto,
+    client: &ClientInfo,
   ) -> AppResult<(String, cookie::Cookie, String, cookie::Cookie)>;
 
   async fn refresh_access_token(
@@ -28,65 +83,362 @@ pub trait UserServiceTrait {
     refresh_token: String,
   ) -> AppResult<(String, cookie::Cookie, String, cookie::Cookie)>;
 
-  async fn get_all_users(&self) -> AppResult<Vec<User>>;
+  /// Verifies the current password of `actor` and issues tokens with a fresh `auth_time`.
+  async fn reauthenticate(
+    &self,
+    request: ReauthenticateDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<(String, cookie::Cookie, String, cookie::Cookie)>;
+
+  /// Returns one page of matching users and the total count when requested.
+  async fn find_users(
+    &self,
+    query: UserQueryDto,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<User>, Option<u64>)>;
+
+  /// Returns one page of the users matching `query`, most relevant first, see
+  /// `database::user::search`.
+  async fn search_users(
+    &self,
+    query: UserSearchDto,
+    page: &PaginationQuery,
+  ) -> AppResult<UserSearchResults>;
 
   async fn get_user_by_id(&self, user_id: &str) -> AppResult<Option<User>>;
 
   async fn get_user_by_email(&self, user_id: &str) -> AppResult<Option<User>>;
 
-  async fn update_user(&self, request: UpdateUserDto) -> AppResult<UpdateResult>;
+  /// Updates the user, provided it is still at `expected_version` when set.
+  async fn update_user(
+    &self,
+    request: UpdateUserDto,
+    expected_version: Option<i64>,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<User>;
+
+  async fn change_password(
+    &self,
+    request: ChangePasswordDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<UpdateResult>;
+
+  /// Soft deletes the user, provided it is still at `expected_version` when set. It can be
+  /// restored until the retention period has passed.
+  ///
+  /// Fails with `NotFound` when there is no such user, and with `PreconditionFailed` when it is
+  /// at another version.
+  async fn delete_user(
+    &self,
+    user_id: &str,
+    expected_version: Option<i64>,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<()>;
+
+  /// Returns one page of soft deleted users and the total count when requested.
+  async fn find_deleted_users(
+    &self,
+    query: UserQueryDto,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<User>, Option<u64>)>;
 
-  async fn change_password(&self, request: ChangePasswordDto) -> AppResult<UpdateResult>;
+  async fn restore_user(&self, user_id: &str, actor: &str, client: &ClientInfo) -> AppResult<()>;
 
-  async fn delete_user(&self, user_id: &str) -> AppResult<DeleteResult>;
+  /// Permanently removes users deleted longer than `retention.deleted_user_retention` ago.
+  ///
+  /// Returns the number of purged users.
+  async fn purge_deleted_users(&self) -> AppResult<u64>;
 
-  async fn logout_user(&self) -> AppResult<(cookie::Cookie, cookie::Cookie)>;
+  async fn logout_user(
+    &self,
+    actor: Option<String>,
+    client: &ClientInfo,
+  ) -> AppResult<(cookie::Cookie, cookie::Cookie)>;
 }
 
 #[derive(Clone)]
 pub struct UserService {
   repository: DynUserRepository,
+  search: DynUserSearch,
+  invitations: DynInvitationRepository,
+  outbox: DynOutboxRepository,
+  unit_of_work: DynUnitOfWork,
+  tokens: DynTokenService,
+  audit: DynAuditService,
 }
 
 impl UserService {
-  pub fn new(repository: DynUserRepository) -> Self {
-    Self { repository }
+  pub fn new(
+    repository: DynUserRepository,
+    search: DynUserSearch,
+    invitations: DynInvitationRepository,
+    outbox: DynOutboxRepository,
+    unit_of_work: DynUnitOfWork,
+    tokens: DynTokenService,
+    audit: DynAuditService,
+  ) -> Self {
+    Self {
+      repository,
+      search,
+      invitations,
+      outbox,
+      unit_of_work,
+      tokens,
+      audit,
+    }
   }
-}
 
-#[async_trait]
-impl UserServiceTrait for UserService {
-  async fn signup_user(&self, request: SignUpUserDto) -> AppResult<InsertOneResult> {
+  async fn find_page(
+    &self,
+    filter: UserFilter,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<User>, Option<u64>)> {
+    let pagination = page.pagination(SORTABLE_FIELDS, "created_at")?;
+    let users = self.repository.find_users(&filter, &pagination).await?;
+    let total = if page.include_total() {
+      Some(self.repository.count_users(&filter).await?)
+    } else {
+      None
+    };
+    Ok((users, total))
+  }
+
+  /// Explains why a write to `user_id` matched nothing: the user is gone, or it is no longer at
+  /// `expected_version`.
+  async fn write_conflict(&self, user_id: &str, expected_version: Option<i64>) -> AppError {
+    match self.repository.get_user_by_id(user_id).await {
+      Ok(Some(user)) if expected_version.is_some() => {
+        error!(
+          "user {:?} is at version {}, not {:?}",
+          user_id, user.version, expected_version
+        );
+        AppError::PreconditionFailed(format!("user is at version {}", user.version))
+      }
+      Ok(_) => AppError::NotFound("User not found".to_string()),
+      Err(e) => e,
+    }
+  }
+
+  /// Records a notification about a user in the outbox, see `outbox_service::enqueue`.
+  async fn notify(&self, topic: &str, payload: Document) -> AppResult<()> {
+    enqueue(&self.outbox, topic, payload).await
+  }
+
+  /// Creates a user whose password is already hashed, and notifies `user.created`.
+  async fn insert_user(
+    &self,
+    name: &str,
+    email: &str,
+    password: &str,
+  ) -> AppResult<InsertOneResult> {
+    let result = transaction(&*self.unit_of_work, || async {
+      let result = self.repository.create_user(name, email, password).await?;
+      let id = result.inserted_id.as_object_id().map(|id| id.to_hex());
+      let payload = doc! { "id": id, "name": name, "email": email };
+      self.notify("user.created", payload).await?;
+      Ok(result)
+    })
+    .await?;
+    info!("created user {:?}", result);
+    Ok(result)
+  }
+
+  /// Signs a user up under the given `policy`.
+  async fn signup(
+    &self,
+    request: SignUpUserDto,
+    client: &ClientInfo,
+    policy: &SignupConfig,
+  ) -> AppResult<InsertOneResult> {
     let email = request.email.unwrap();
     let name = request.name.unwrap();
     let password = request.password.unwrap();
-    let password = hash_password(&password)?;
+
+    if policy.mode == SignupMode::Closed {
+      error!("signup is closed, rejected user {:?}", email);
+      self
+        .record_signup_failure(client, &email, "signup is closed")
+        .await;
+      return Err(AppError::ForbiddenWithContext(
+        "signup is closed".to_string(),
+      ));
+    }
+
+    if let Some(reason) = policy.domain_rejection(&email) {
+      error!("rejected signup for user {:?}: {reason}", email);
+      self.record_signup_failure(client, &email, reason).await;
+      return Err(AppError::ForbiddenWithContext(reason.to_string()));
+    }
 
     let existing_user = self.repository.get_user_by_email(&email).await?;
     if existing_user.is_some() {
       error!("user {:?} already exists", email);
+      self
+        .record_signup_failure(client, &email, "email is taken")
+        .await;
       return Err(AppError::Conflict(format!("email {email} is taken")));
     }
 
-    let result = self
-      .repository
-      .create_user(&name, &email, &password)
-      .await?;
-    info!("created user {:?}", result);
+    let invitation_code = if policy.mode == SignupMode::InviteOnly {
+      let Some(code) = request.invitation_code else {
+        error!("missing invitation code for user {:?}", email);
+        self
+          .record_signup_failure(client, &email, "invitation code is required")
+          .await;
+        return Err(AppError::ForbiddenWithContext(
+          "invitation code is required".to_string(),
+        ));
+      };
+      Some(code)
+    } else {
+      None
+    };
+
+    // Hashing is expensive, so it only happens once the request passed the cheap checks.
+    let password = hash_password(&password)?;
+    // The invitation is only spent if the user is created.
+    let created = transaction(&*self.unit_of_work, || async {
+      if let Some(code) = &invitation_code
+        && self
+          .invitations
+          .redeem_invitation(code, &email)
+          .await?
+          .is_none()
+      {
+        return Ok(None);
+      }
+      self.insert_user(&name, &email, &password).await.map(Some)
+    })
+    .await?;
+    let Some(result) = created else {
+      error!("invalid invitation code for user {:?}", email);
+      self
+        .record_signup_failure(client, &email, "invitation code is invalid")
+        .await;
+      return Err(AppError::ForbiddenWithContext(
+        "invitation code is invalid or expired".to_string(),
+      ));
+    };
+    let mut event = audit_event(client, AuditAction::Signup, AuditOutcome::Success).actor(&email);
+    if let Some(id) = result.inserted_id.as_object_id() {
+      event = event.target(id.to_hex());
+    }
+    self.audit.record(event).await;
     Ok(result)
   }
 
+  async fn record_signup_failure(&self, client: &ClientInfo, email: &str, reason: &str) {
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::Signup, AuditOutcome::Failure)
+          .actor(email)
+          .reason(reason),
+      )
+      .await;
+  }
+}
+
+pub(crate) fn user_filter(query: UserQueryDto, deleted: bool) -> AppResult<UserFilter> {
+  Ok(UserFilter {
+    name: query.name,
+    email: query.email,
+    role: query.role,
+    created_from: parse_date(query.created_from, "created_from")?,
+    created_to: parse_date(query.created_to, "created_to")?,
+    deleted,
+  })
+}
+
+/// Issues an access and refresh token pair for `user`, along with their cookies.
+///
+/// `auth_time` is when the user last authenticated interactively, see `Claims::auth_time`.
+pub(crate) fn issue_tokens(
+  user: &User,
+  auth_time: usize,
+) -> (
+  String,
+  cookie::Cookie<'static>,
+  String,
+  cookie::Cookie<'static>,
+) {
+  let cfg = config::get();
+  let access_token = create_token(
+    &cfg.jwt.access_token_secret,
+    &user.email,
+    cfg.jwt.access_token_expiry,
+    TokenType::Access,
+    user.role.scope(),
+    auth_time,
+  );
+
+  let access_cookie = cookie::create(
+    "access_token",
+    access_token.clone(),
+    cfg.jwt.access_token_expiry,
+  );
+
+  let refresh_token = create_token(
+    &cfg.jwt.refresh_token_secret,
+    &user.email,
+    cfg.jwt.refresh_token_expiry,
+    TokenType::Refresh,
+    user.role.scope(),
+    auth_time,
+  );
+
+  let refresh_cookie = cookie::create(
+    "refresh_token",
+    refresh_token.clone(),
+    cfg.jwt.refresh_token_expiry,
+  );
+
+  (access_token, access_cookie, refresh_token, refresh_cookie)
+}
+
+#[async_trait]
+impl UserServiceTrait for UserService {
+  async fn signup_user(
+    &self,
+    request: SignUpUserDto,
+    client: &ClientInfo,
+  ) -> AppResult<InsertOneResult> {
+    self.signup(request, client, &config::get().signup).await
+  }
+
+  async fn create_user(
+    &self,
+    name: &str,
+    email: &str,
+    password: &str,
+  ) -> AppResult<InsertOneResult> {
+    let password = hash_password(password)?;
+    self.insert_user(name, email, &password).await
+  }
+
   async fn login_user(
     &self,
     request: LoginInDto,
+    client: &ClientInfo,
   ) -> AppResult<(String, cookie::Cookie, String, cookie::Cookie)> {
-    let cfg = config::get();
     let email = request.email.unwrap();
     let password = request.password.unwrap();
 
     let existing_user = self.repository.get_user_by_email(&email).await?;
     if existing_user.is_none() {
       error!("user {:?} does not exist", email);
+      self
+        .audit
+        .record(
+          audit_event(client, AuditAction::Login, AuditOutcome::Failure)
+            .actor(&email)
+            .reason("user does not exist"),
+        )
+        .await;
       return Err(AppError::NotFound(format!(
         "user {:?} does not exist",
         email
@@ -96,36 +448,28 @@ impl UserServiceTrait for UserService {
     let user = existing_user.unwrap();
     if verify_password(&password, &user.password).is_err() {
       error!("invalid password for user {:?}", email);
+      self
+        .audit
+        .record(
+          audit_event(client, AuditAction::Login, AuditOutcome::Failure)
+            .actor(&email)
+            .reason("invalid password"),
+        )
+        .await;
       return Err(AppError::Unauthorized);
     }
 
-    let access_token = create_token(
-      &cfg.jwt.access_token_secret,
-      &user.email,
-      cfg.jwt.access_token_expiry,
-      TokenType::Access,
-    );
-
-    let access_cookie = cookie::create(
-      "access_token",
-      access_token.clone(),
-      cfg.jwt.access_token_expiry,
-    );
-
-    let refresh_token = create_token(
-      &cfg.jwt.refresh_token_secret,
-      &user.email,
-      cfg.jwt.refresh_token_expiry,
-      TokenType::Refresh,
-    );
-
-    let refresh_cookie = cookie::create(
-      "refresh_token",
-      refresh_token.clone(),
-      cfg.jwt.refresh_token_expiry,
-    );
+    let (access_token, access_cookie, refresh_token, refresh_cookie) =
+      issue_tokens(&user, jwt::now());
+    if let Some(id) = &user.id {
+      self.repository.record_login(id).await?;
+    }
 
     info!("user {:?} logged in", email);
+    self
+      .audit
+      .record(audit_event(client, AuditAction::Login, AuditOutcome::Success).actor(&email))
+      .await;
     Ok((access_token, access_cookie, refresh_token, refresh_cookie))
   }
 
@@ -138,6 +482,14 @@ impl UserServiceTrait for UserService {
     if claims.token_type != utils::jwt::TokenType::Refresh {
       return Err(AppError::InvalidToken("Not a refresh token".to_string()));
     }
+    if claims.tenant != utils::tenant::current() {
+      return Err(AppError::InvalidToken(
+        "Token was issued for another tenant".to_string(),
+      ));
+    }
+    if self.tokens.is_revoked(&claims).await? {
+      return Err(AppError::InvalidToken("Token has been revoked".to_string()));
+    }
 
     let user = self
       .repository
@@ -145,39 +497,125 @@ impl UserServiceTrait for UserService {
       .await?
       .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
-    let access_token = create_token(
-      &cfg.jwt.access_token_secret,
-      &user.email,
-      cfg.jwt.access_token_expiry,
-      TokenType::Access,
-    );
+    let (access_token, access_cookie, refresh_token, refresh_cookie) =
+      issue_tokens(&user, claims.auth_time);
 
-    let refresh_token = create_token(
-      &cfg.jwt.refresh_token_secret,
-      &user.email,
-      cfg.jwt.refresh_token_expiry,
-      TokenType::Refresh,
-    );
+    info!("user {:?} refreshed access token", user.email);
+    Ok((access_token, access_cookie, refresh_token, refresh_cookie))
+  }
 
-    let access_cookie = cookie::create(
-      "access_token",
-      access_token.clone(),
-      cfg.jwt.access_token_expiry,
-    );
+  async fn reauthenticate(
+    &self,
+    request: ReauthenticateDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<(String, cookie::Cookie, String, cookie::Cookie)> {
+    let password = request.password.unwrap();
+    let user = self
+      .repository
+      .get_user_by_email(actor)
+      .await?
+      .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
-    let refresh_cookie = cookie::create(
-      "refresh_token",
-      refresh_token.clone(),
-      cfg.jwt.refresh_token_expiry,
-    );
+    if verify_password(&password, &user.password).is_err() {
+      error!("invalid password for user {:?}", actor);
+      self
+        .audit
+        .record(
+          audit_event(client, AuditAction::Reauthenticate, AuditOutcome::Failure)
+            .actor(actor)
+            .reason("invalid password"),
+        )
+        .await;
+      return Err(AppError::Unauthorized);
+    }
 
-    info!("user {:?} refreshed access token", user.email);
-    Ok((access_token, access_cookie, refresh_token, refresh_cookie))
+    let tokens = issue_tokens(&user, jwt::now());
+    info!("user {:?} re-authenticated", actor);
+    self
+      .audit
+      .record(audit_event(client, AuditAction::Reauthenticate, AuditOutcome::Success).actor(actor))
+      .await;
+    Ok(tokens)
   }
 
-  async fn get_all_users(&self) -> AppResult<Vec<User>> {
-    let users = self.repository.get_all_users().await?;
-    Ok(users)
+  async fn find_users(
+    &self,
+    query: UserQueryDto,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<User>, Option<u64>)> {
+    self.find_page(user_filter(query, false)?, page).await
+  }
+
+  async fn find_deleted_users(
+    &self,
+    query: UserQueryDto,
+    page: &PaginationQuery,
+  ) -> AppResult<(Page<User>, Option<u64>)> {
+    self.find_page(user_filter(query, true)?, page).await
+  }
+
+  async fn restore_user(&self, user_id: &str, actor: &str, client: &ClientInfo) -> AppResult<()> {
+    transaction(&*self.unit_of_work, || async {
+      let result = self.repository.restore_user(user_id).await?;
+      if result.matched_count == 0 {
+        return Err(AppError::NotFound("Deleted user not found".to_string()));
+      }
+      self.notify("user.restored", doc! { "id": user_id }).await
+    })
+    .await?;
+    info!("user {:?} restored user {:?}", actor, user_id);
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::Restore, AuditOutcome::Success)
+          .actor(actor)
+          .target(user_id),
+      )
+      .await;
+    Ok(())
+  }
+
+  async fn purge_deleted_users(&self) -> AppResult<u64> {
+    let cfg = config::get();
+    let retention = Duration::from_secs(cfg.retention.deleted_user_retention as u64);
+    let deleted_before = DateTime::from_system_time(DateTime::now().to_system_time() - retention);
+    let result = self.repository.purge_deleted_users(deleted_before).await?;
+    if result.deleted_count > 0 {
+      info!("purged {} deleted users", result.deleted_count);
+      self
+        .audit
+        .record(
+          audit_event(
+            &ClientInfo::default(),
+            AuditAction::Purge,
+            AuditOutcome::Success,
+          )
+          .reason(format!("{} users", result.deleted_count)),
+        )
+        .await;
+    }
+    Ok(result.deleted_count)
+  }
+
+  async fn search_users(
+    &self,
+    query: UserSearchDto,
+    page: &PaginationQuery,
+  ) -> AppResult<UserSearchResults> {
+    let terms = query_terms(&query.q.unwrap());
+    if terms.is_empty() {
+      return Err(AppError::BadRequest(format!(
+        "q must contain a word of at least {MIN_TERM_LENGTH} letters or digits"
+      )));
+    }
+    let query = UserSearchQuery {
+      terms,
+      skip: page.offset()?,
+      limit: page.limit,
+      include_total: page.include_total(),
+    };
+    self.search.search_users(&query).await
   }
 
   async fn get_user_by_id(&self, user_id: &str) -> AppResult<Option<User>> {
@@ -190,7 +628,13 @@ impl UserServiceTrait for UserService {
     Ok(user)
   }
 
-  async fn update_user(&self, request: UpdateUserDto) -> AppResult<UpdateResult> {
+  async fn update_user(
+    &self,
+    request: UpdateUserDto,
+    expected_version: Option<i64>,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<User> {
     let id = request.id.unwrap();
     let email = request.email.unwrap();
     let name = request.name.unwrap();
@@ -200,33 +644,440 @@ impl UserServiceTrait for UserService {
       let existing_id = existing_user.unwrap().id.unwrap().to_hex();
       if existing_id != id {
         error!("user {:?} already exists", email);
+        self
+          .audit
+          .record(
+            audit_event(client, AuditAction::Update, AuditOutcome::Failure)
+              .actor(actor)
+              .target(&id)
+              .reason("email is taken"),
+          )
+          .await;
         return Err(AppError::Conflict(format!("email {email} is taken")));
       }
     }
 
-    let result = self.repository.update_user(&id, &name, &email).await?;
-    info!("updated user {:?}", result);
-    Ok(result)
+    let user = transaction(&*self.unit_of_work, || async {
+      let user = self
+        .repository
+        .update_user(&id, &name, &email, expected_version)
+        .await?;
+      if let Some(user) = &user {
+        let payload = doc! { "id": &id, "name": &user.name, "email": &user.email };
+        self.notify("user.updated", payload).await?;
+      }
+      Ok(user)
+    })
+    .await?;
+    let Some(user) = user else {
+      return Err(self.write_conflict(&id, expected_version).await);
+    };
+    info!("updated user {:?}", id);
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::Update, AuditOutcome::Success)
+          .actor(actor)
+          .target(&id),
+      )
+      .await;
+    Ok(user)
   }
 
-  async fn change_password(&self, request: ChangePasswordDto) -> AppResult<UpdateResult> {
+  async fn change_password(
+    &self,
+    request: ChangePasswordDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<UpdateResult> {
     let id = request.id.unwrap();
     let password = request.password.unwrap();
     let password = hash_password(&password)?;
-    let result = self.repository.change_password(&id, &password).await?;
+    let result = transaction(&*self.unit_of_work, || async {
+      let result = self.repository.change_password(&id, &password).await?;
+      if result.matched_count > 0 {
+        self
+          .notify("user.password_changed", doc! { "id": &id })
+          .await?;
+      }
+      Ok(result)
+    })
+    .await?;
     info!("updated user {:?}", result);
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::ChangePassword, AuditOutcome::Success)
+          .actor(actor)
+          .target(&id),
+      )
+      .await;
     Ok(result)
   }
 
-  async fn delete_user(&self, user_id: &str) -> AppResult<DeleteResult> {
-    let result = self.repository.delete_user(user_id).await?;
-    Ok(result)
+  async fn delete_user(
+    &self,
+    user_id: &str,
+    expected_version: Option<i64>,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<()> {
+    let result = transaction(&*self.unit_of_work, || async {
+      let result = self
+        .repository
+        .delete_user(user_id, expected_version)
+        .await?;
+      if result.matched_count > 0 {
+        self.notify("user.deleted", doc! { "id": user_id }).await?;
+      }
+      Ok(result)
+    })
+    .await?;
+    if result.matched_count == 0 {
+      return Err(self.write_conflict(user_id, expected_version).await);
+    }
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::Delete, AuditOutcome::Success)
+          .actor(actor)
+          .target(user_id),
+      )
+      .await;
+    Ok(())
   }
 
-  async fn logout_user(&self) -> AppResult<(cookie::Cookie, cookie::Cookie)> {
+  async fn logout_user(
+    &self,
+    actor: Option<String>,
+    client: &ClientInfo,
+  ) -> AppResult<(cookie::Cookie, cookie::Cookie)> {
     let access_cookie = cookie::delete("access_token");
     let refresh_cookie = cookie::delete("refresh_token");
     info!("user logged out");
+    let mut event = audit_event(client, AuditAction::Logout, AuditOutcome::Success);
+    if let Some(actor) = actor {
+      event = event.actor(actor);
+    }
+    self.audit.record(event).await;
     Ok((access_cookie, refresh_cookie))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    dtos::user_dto::UserResponse,
+    services::{audit_service::AuditService, token_service::TokenService},
+  };
+  use axum::{http::StatusCode, response::IntoResponse};
+  use database::{
+    invitation::model::Invitation, memory::MemoryDatabase, user::repository::UserRepositoryTrait,
+  };
+
+  /// A service over an empty in-memory database, and the id of its only user.
+  async fn service() -> (UserService, String) {
+    config::init_for_tests();
+    let database = Arc::new(MemoryDatabase::new());
+    let id = database
+      .create_user("Versioned", "versioned@example.com", "hash")
+      .await
+      .unwrap()
+      .inserted_id
+      .as_object_id()
+      .unwrap()
+      .to_hex();
+    let audit = Arc::new(AuditService::new(database.clone()));
+    let tokens = Arc::new(TokenService::new(database.clone(), audit.clone()));
+    let service = UserService::new(
+      database.clone(),
+      database.clone(),
+      database.clone(),
+      database.clone(),
+      database,
+      tokens,
+      audit,
+    );
+    (service, id)
+  }
+
+  fn status(error: AppError) -> StatusCode {
+    error.into_response().status()
+  }
+
+  #[tokio::test]
+  async fn deletes_users_at_the_expected_version() {
+    let (service, id) = service().await;
+    let client = ClientInfo::default();
+    service
+      .delete_user(&id, Some(0), "admin", &client)
+      .await
+      .unwrap();
+    assert!(service.get_user_by_id(&id).await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn stale_versions_fail_the_precondition() {
+    let (service, id) = service().await;
+    let client = ClientInfo::default();
+    service
+      .repository
+      .update_user(&id, "Renamed", "versioned@example.com", None)
+      .await
+      .unwrap();
+
+    let error = service
+      .delete_user(&id, Some(0), "admin", &client)
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::PRECONDITION_FAILED);
+    assert!(service.get_user_by_id(&id).await.unwrap().is_some());
+  }
+
+  #[tokio::test]
+  async fn deletes_without_a_version_are_unconditional() {
+    let (service, id) = service().await;
+    let client = ClientInfo::default();
+    service
+      .delete_user(&id, None, "admin", &client)
+      .await
+      .unwrap();
+
+    let error = service
+      .delete_user(&id, None, "admin", &client)
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::NOT_FOUND);
+  }
+
+  fn signup_request(email: &str, invitation_code: Option<&str>) -> SignUpUserDto {
+    SignUpUserDto {
+      name: Some("Ada".to_string()),
+      email: Some(email.to_string()),
+      password: Some("correct horse".to_string()),
+      invitation_code: invitation_code.map(str::to_string),
+    }
+  }
+
+  fn policy(mode: SignupMode) -> SignupConfig {
+    SignupConfig {
+      mode,
+      ..SignupConfig::default()
+    }
+  }
+
+  async fn invite(service: &UserService, code: &str, email: Option<&str>) {
+    service
+      .invitations
+      .create_invitation(Invitation {
+        id: None,
+        code: code.to_string(),
+        email: email.map(str::to_string),
+        created_by: "admin@example.com".to_string(),
+        created_at: DateTime::now(),
+        expires_at: DateTime::MAX,
+        used_at: None,
+        used_by: None,
+        tenant_id: None,
+      })
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn closed_signup_rejects_everyone() {
+    let (service, _) = service().await;
+    let client = ClientInfo::default();
+    let error = service
+      .signup(
+        signup_request("ada@example.com", None),
+        &client,
+        &policy(SignupMode::Closed),
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::FORBIDDEN);
+    let user = service.repository.get_user_by_email("ada@example.com");
+    assert!(user.await.unwrap().is_none());
+  }
+
+  #[tokio::test]
+  async fn open_signup_checks_the_domain_and_taken_emails() {
+    let (service, _) = service().await;
+    let client = ClientInfo::default();
+    let open = SignupConfig {
+      denied_domains: vec!["example.org".to_string()],
+      ..policy(SignupMode::Open)
+    };
+
+    let error = service
+      .signup(signup_request("ada@mail.example.org", None), &client, &open)
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::FORBIDDEN);
+    let error = service
+      .signup(
+        signup_request("versioned@example.com", None),
+        &client,
+        &open,
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::CONFLICT);
+    service
+      .signup(signup_request("ada@example.com", None), &client, &open)
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn invite_only_signup_needs_a_valid_code() {
+    let (service, _) = service().await;
+    let client = ClientInfo::default();
+    let invite_only = policy(SignupMode::InviteOnly);
+    invite(&service, "for-ada", Some("ada@example.com")).await;
+
+    let error = service
+      .signup(
+        signup_request("ada@example.com", None),
+        &client,
+        &invite_only,
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::FORBIDDEN);
+    let error = service
+      .signup(
+        signup_request("grace@example.com", Some("for-ada")),
+        &client,
+        &invite_only,
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::FORBIDDEN);
+    assert!(
+      service
+        .repository
+        .get_user_by_email("grace@example.com")
+        .await
+        .unwrap()
+        .is_none()
+    );
+
+    service
+      .signup(
+        signup_request("ada@example.com", Some("for-ada")),
+        &client,
+        &invite_only,
+      )
+      .await
+      .unwrap();
+    let invitations = service.invitations.get_all_invitations().await.unwrap();
+    assert_eq!(invitations[0].used_by.as_deref(), Some("ada@example.com"));
+  }
+
+  #[tokio::test]
+  async fn invitations_are_only_spent_when_the_user_is_created() {
+    let (service, _) = service().await;
+    let client = ClientInfo::default();
+    let invite_only = policy(SignupMode::InviteOnly);
+    invite(&service, "anyone", None).await;
+
+    // The email is taken, so the signup fails before the invitation is redeemed.
+    let error = service
+      .signup(
+        signup_request("versioned@example.com", Some("anyone")),
+        &client,
+        &invite_only,
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::CONFLICT);
+    service
+      .signup(
+        signup_request("ada@example.com", Some("anyone")),
+        &client,
+        &invite_only,
+      )
+      .await
+      .unwrap();
+
+    let error = service
+      .signup(
+        signup_request("grace@example.com", Some("anyone")),
+        &client,
+        &invite_only,
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::FORBIDDEN);
+  }
+
+  #[tokio::test]
+  async fn reauthentication_refreshes_auth_time_and_refreshing_keeps_it() {
+    let (service, _) = service().await;
+    let client = ClientInfo::default();
+    let password = hash_password("correct horse").unwrap();
+    service
+      .repository
+      .create_user("Ada", "ada@example.com", &password)
+      .await
+      .unwrap();
+    let cfg = config::get();
+    let refresh_token = create_token(
+      &cfg.jwt.refresh_token_secret,
+      "ada@example.com",
+      60,
+      TokenType::Refresh,
+      "user",
+      1,
+    );
+
+    let (access_token, ..) = service.refresh_access_token(refresh_token).await.unwrap();
+    let claims = jwt::decode_token(&access_token, &cfg.jwt.access_token_secret).unwrap();
+    assert_eq!(claims.auth_time, 1);
+
+    let reauthenticate = |password: &str| ReauthenticateDto {
+      password: Some(password.to_string()),
+    };
+    let error = service
+      .reauthenticate(reauthenticate("wrong"), "ada@example.com", &client)
+      .await
+      .unwrap_err();
+    assert_eq!(status(error), StatusCode::UNAUTHORIZED);
+    let (access_token, ..) = service
+      .reauthenticate(reauthenticate("correct horse"), "ada@example.com", &client)
+      .await
+      .unwrap();
+    let claims = jwt::decode_token(&access_token, &cfg.jwt.access_token_secret).unwrap();
+    assert!(claims.auth_time + 5 >= jwt::now());
+  }
+
+  #[tokio::test]
+  async fn logging_in_records_the_last_login() {
+    let (service, _) = service().await;
+    let client = ClientInfo::default();
+    let password = hash_password("correct horse").unwrap();
+    service
+      .repository
+      .create_user("Ada", "ada@example.com", &password)
+      .await
+      .unwrap();
+    let login = LoginInDto {
+      email: Some("ada@example.com".to_string()),
+      password: Some("correct horse".to_string()),
+    };
+
+    service.login_user(login, &client).await.unwrap();
+    let user = service
+      .get_user_by_email("ada@example.com")
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(user.last_login_at >= user.created_at);
+    let response = UserResponse::from(user);
+    assert!(response.last_login_at.is_some());
+    assert_eq!(response.updated_at, response.created_at);
+  }
+}
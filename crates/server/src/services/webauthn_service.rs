@@ -0,0 +1,590 @@
+use crate::{
+  dtos::webauthn_dto::{
+    AuthenticationOptions, AuthenticatorSelection, CredentialDescriptor, CredentialParameter,
+    PasskeyLoginDto, RegisterPasskeyDto, RegistrationOptions, RelyingParty, UserEntity,
+  },
+  extractors::client_info::ClientInfo,
+  services::{
+    audit_service::{DynAuditService, audit_event},
+    user_service::issue_tokens,
+  },
+};
+use async_trait::async_trait;
+use database::{
+  audit::model::{AuditAction, AuditOutcome},
+  user::{model::User, repository::DynUserRepository},
+  webauthn::{
+    model::{WebauthnChallenge, WebauthnChallengeKind, WebauthnCredential},
+    repository::DynWebauthnRepository,
+  },
+};
+use mongodb::bson::{DateTime, oid::ObjectId};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tracing::{error, info};
+use utils::{
+  AppError, AppResult, config, cookie, jwt, random,
+  webauthn::{self, AuthenticatorData, ClientData},
+};
+
+const CHALLENGE_LENGTH: usize = 32;
+
+#[allow(clippy::module_name_repetitions)]
+pub type DynWebauthnService = Arc<dyn WebauthnServiceTrait + Send + Sync>;
+
+#[async_trait]
+#[allow(clippy::module_name_repetitions)]
+pub trait WebauthnServiceTrait {
+  /// Starts registering a passkey for `actor`.
+  async fn start_registration(&self, actor: &str) -> AppResult<RegistrationOptions>;
+
+  /// Verifies the authenticator's response and stores the new passkey.
+  async fn finish_registration(
+    &self,
+    request: RegisterPasskeyDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<WebauthnCredential>;
+
+  /// Starts a login with a discoverable passkey.
+  async fn start_login(&self) -> AppResult<AuthenticationOptions>;
+
+  /// Verifies the assertion and issues tokens for the passkey's owner.
+  async fn finish_login(
+    &self,
+    request: PasskeyLoginDto,
+    client: &ClientInfo,
+  ) -> AppResult<(String, cookie::Cookie, String, cookie::Cookie)>;
+
+  async fn get_credentials(&self, actor: &str) -> AppResult<Vec<WebauthnCredential>>;
+
+  async fn rename_credential(&self, id: &str, name: &str, actor: &str) -> AppResult<()>;
+
+  async fn remove_credential(&self, id: &str, actor: &str, client: &ClientInfo) -> AppResult<()>;
+}
+
+#[derive(Clone)]
+pub struct WebauthnService {
+  repository: DynWebauthnRepository,
+  users: DynUserRepository,
+  audit: DynAuditService,
+}
+
+impl WebauthnService {
+  pub fn new(
+    repository: DynWebauthnRepository,
+    users: DynUserRepository,
+    audit: DynAuditService,
+  ) -> Self {
+    Self {
+      repository,
+      users,
+      audit,
+    }
+  }
+
+  /// Resolves the authenticated principal to its `User`.
+  async fn current_user(&self, email: &str) -> AppResult<(User, ObjectId)> {
+    let user = self
+      .users
+      .get_user_by_email(email)
+      .await?
+      .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    let id = user.id.ok_or(AppError::InternalServerErrorWithContext(
+      "user has no id".to_string(),
+    ))?;
+    Ok((user, id))
+  }
+
+  /// Issues and stores a fresh single-use challenge.
+  async fn create_challenge(
+    &self,
+    kind: WebauthnChallengeKind,
+    user_id: Option<ObjectId>,
+  ) -> AppResult<String> {
+    let cfg = config::get();
+    let challenge = webauthn::encode(&random::generate_bytes(CHALLENGE_LENGTH));
+    let expires_at = DateTime::from_system_time(
+      DateTime::now().to_system_time() + Duration::from_secs(cfg.webauthn.challenge_timeout as u64),
+    );
+    self
+      .repository
+      .create_challenge(WebauthnChallenge {
+        id: Some(ObjectId::new()),
+        challenge: challenge.clone(),
+        kind,
+        user_id,
+        expires_at,
+        tenant_id: None,
+      })
+      .await?;
+    Ok(challenge)
+  }
+
+  /// Checks the client data of a ceremony and consumes its challenge.
+  async fn verify_client_data(
+    &self,
+    client_data_json: &[u8],
+    kind: WebauthnChallengeKind,
+  ) -> AppResult<WebauthnChallenge> {
+    let cfg = config::get();
+    let client_data = ClientData::parse(client_data_json)?;
+    let expected_type = match kind {
+      WebauthnChallengeKind::Registration => "webauthn.create",
+      WebauthnChallengeKind::Authentication => "webauthn.get",
+    };
+    if client_data.type_ != expected_type {
+      return Err(AppError::BadRequest("unexpected ceremony type".to_string()));
+    }
+    if client_data.origin != cfg.webauthn.origin {
+      error!("unexpected webauthn origin {:?}", client_data.origin);
+      return Err(AppError::BadRequest("origin mismatch".to_string()));
+    }
+    self
+      .repository
+      .take_challenge(&client_data.challenge, kind)
+      .await?
+      .ok_or_else(|| AppError::BadRequest("challenge is invalid or has expired".to_string()))
+  }
+
+  fn user_verification() -> &'static str {
+    if config::get().webauthn.require_user_verification {
+      "required"
+    } else {
+      "preferred"
+    }
+  }
+}
+
+#[async_trait]
+impl WebauthnServiceTrait for WebauthnService {
+  async fn start_registration(&self, actor: &str) -> AppResult<RegistrationOptions> {
+    let cfg = config::get();
+    let (user, user_id) = self.current_user(actor).await?;
+    let existing = self.repository.get_credentials_by_user(&user_id).await?;
+    let challenge = self
+      .create_challenge(WebauthnChallengeKind::Registration, Some(user_id))
+      .await?;
+
+    Ok(RegistrationOptions {
+      challenge,
+      rp: RelyingParty {
+        id: cfg.webauthn.rp_id.clone(),
+        name: cfg.webauthn.rp_name.clone(),
+      },
+      user: UserEntity {
+        id: webauthn::encode(&user_id.bytes()),
+        name: user.email,
+        display_name: user.name,
+      },
+      pub_key_cred_params: vec![CredentialParameter {
+        type_: "public-key",
+        alg: webauthn::COSE_ALG_ES256,
+      }],
+      timeout: cfg.webauthn.challenge_timeout * 1000,
+      attestation: "none",
+      exclude_credentials: existing
+        .into_iter()
+        .map(|credential| CredentialDescriptor {
+          type_: "public-key",
+          id: credential.credential_id,
+        })
+        .collect(),
+      authenticator_selection: AuthenticatorSelection {
+        resident_key: "required",
+        user_verification: Self::user_verification(),
+      },
+    })
+  }
+
+  async fn finish_registration(
+    &self,
+    request: RegisterPasskeyDto,
+    actor: &str,
+    client: &ClientInfo,
+  ) -> AppResult<WebauthnCredential> {
+    let cfg = config::get();
+    let (_, user_id) = self.current_user(actor).await?;
+
+    let client_data_json = webauthn::decode(&request.response.client_data_json)?;
+    let challenge = self
+      .verify_client_data(&client_data_json, WebauthnChallengeKind::Registration)
+      .await?;
+    if challenge.user_id != Some(user_id) {
+      return Err(AppError::BadRequest(
+        "challenge was issued to another user".to_string(),
+      ));
+    }
+
+    let auth_data =
+      webauthn::parse_attestation_object(&webauthn::decode(&request.response.attestation_object)?)?;
+    let auth_data = AuthenticatorData::parse(&auth_data)?;
+    auth_data.check(&cfg.webauthn.rp_id, cfg.webauthn.require_user_verification)?;
+    let attested = auth_data
+      .attested_credential
+      .ok_or_else(|| AppError::BadRequest("missing attested credential data".to_string()))?;
+
+    let credential_id = webauthn::encode(&attested.credential_id);
+    if credential_id != request.id.trim_end_matches('=') {
+      return Err(AppError::BadRequest("credential id mismatch".to_string()));
+    }
+    if self
+      .repository
+      .get_credential_by_credential_id(&credential_id)
+      .await?
+      .is_some()
+    {
+      return Err(AppError::Conflict(
+        "passkey is already registered".to_string(),
+      ));
+    }
+
+    let credential = WebauthnCredential {
+      id: Some(ObjectId::new()),
+      user_id,
+      credential_id,
+      public_key: webauthn::encode(&attested.public_key),
+      sign_count: auth_data.sign_count,
+      name: request.name.unwrap_or_else(|| "Passkey".to_string()),
+      created_at: DateTime::now(),
+      last_used_at: None,
+      tenant_id: None,
+    };
+    self
+      .repository
+      .create_credential(credential.clone())
+      .await?;
+    info!("user {:?} registered a passkey", actor);
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::RegisterPasskey, AuditOutcome::Success)
+          .actor(actor)
+          .target(credential.id.map(|id| id.to_hex()).unwrap_or_default()),
+      )
+      .await;
+    Ok(credential)
+  }
+
+  async fn start_login(&self) -> AppResult<AuthenticationOptions> {
+    let cfg = config::get();
+    let challenge = self
+      .create_challenge(WebauthnChallengeKind::Authentication, None)
+      .await?;
+    Ok(AuthenticationOptions {
+      challenge,
+      timeout: cfg.webauthn.challenge_timeout * 1000,
+      rp_id: cfg.webauthn.rp_id.clone(),
+      allow_credentials: Vec::new(),
+      user_verification: Self::user_verification(),
+    })
+  }
+
+  async fn finish_login(
+    &self,
+    request: PasskeyLoginDto,
+    client: &ClientInfo,
+  ) -> AppResult<(String, cookie::Cookie, String, cookie::Cookie)> {
+    let cfg = config::get();
+    let client_data_json = webauthn::decode(&request.response.client_data_json)?;
+    self
+      .verify_client_data(&client_data_json, WebauthnChallengeKind::Authentication)
+      .await?;
+
+    let credential_id = request.id.trim_end_matches('=');
+    let Some(credential) = self
+      .repository
+      .get_credential_by_credential_id(credential_id)
+      .await?
+    else {
+      self
+        .audit
+        .record(
+          audit_event(client, AuditAction::PasskeyLogin, AuditOutcome::Failure)
+            .target(credential_id)
+            .reason("unknown passkey"),
+        )
+        .await;
+      return Err(AppError::Unauthorized);
+    };
+
+    let raw_auth_data = webauthn::decode(&request.response.authenticator_data)?;
+    let auth_data = AuthenticatorData::parse(&raw_auth_data)?;
+    auth_data.check(&cfg.webauthn.rp_id, cfg.webauthn.require_user_verification)?;
+    let verified = webauthn::verify_signature(
+      &webauthn::decode(&credential.public_key)?,
+      &raw_auth_data,
+      &client_data_json,
+      &webauthn::decode(&request.response.signature)?,
+    );
+
+    // A counter that does not increase hints at a cloned authenticator. Authenticators that do
+    // not implement counters always report 0.
+    let counter_ok = auth_data.sign_count > credential.sign_count
+      || (auth_data.sign_count == 0 && credential.sign_count == 0);
+    let failure = match (&verified, counter_ok) {
+      (Err(_), _) => Some("invalid signature"),
+      (Ok(()), false) => Some("signature counter did not increase"),
+      _ => None,
+    };
+    if let Some(reason) = failure {
+      error!("passkey login failed for {:?}: {reason}", credential_id);
+      self
+        .audit
+        .record(
+          audit_event(client, AuditAction::PasskeyLogin, AuditOutcome::Failure)
+            .target(credential_id)
+            .reason(reason),
+        )
+        .await;
+      return Err(AppError::Unauthorized);
+    }
+
+    let user = self
+      .users
+      .get_user_by_id(&credential.user_id.to_hex())
+      .await?
+      .ok_or(AppError::Unauthorized)?;
+    let credential_oid = credential
+      .id
+      .ok_or(AppError::InternalServerErrorWithContext(
+        "credential has no id".to_string(),
+      ))?;
+    self
+      .repository
+      .update_credential_usage(&credential_oid, auth_data.sign_count)
+      .await?;
+    self.users.record_login(&credential.user_id).await?;
+
+    info!("user {:?} logged in with a passkey", user.email);
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::PasskeyLogin, AuditOutcome::Success)
+          .actor(&user.email)
+          .target(credential_oid.to_hex()),
+      )
+      .await;
+    Ok(issue_tokens(&user, jwt::now()))
+  }
+
+  async fn get_credentials(&self, actor: &str) -> AppResult<Vec<WebauthnCredential>> {
+    let (_, user_id) = self.current_user(actor).await?;
+    self.repository.get_credentials_by_user(&user_id).await
+  }
+
+  async fn rename_credential(&self, id: &str, name: &str, actor: &str) -> AppResult<()> {
+    let (_, user_id) = self.current_user(actor).await?;
+    let id =
+      ObjectId::from_str(id).map_err(|_| AppError::BadRequest("invalid passkey id".to_string()))?;
+    let result = self
+      .repository
+      .rename_credential(&id, &user_id, name)
+      .await?;
+    if result.matched_count == 0 {
+      return Err(AppError::NotFound("Passkey not found".to_string()));
+    }
+    Ok(())
+  }
+
+  async fn remove_credential(&self, id: &str, actor: &str, client: &ClientInfo) -> AppResult<()> {
+    let (_, user_id) = self.current_user(actor).await?;
+    let id =
+      ObjectId::from_str(id).map_err(|_| AppError::BadRequest("invalid passkey id".to_string()))?;
+    let result = self.repository.delete_credential(&id, &user_id).await?;
+    if result.deleted_count == 0 {
+      return Err(AppError::NotFound("Passkey not found".to_string()));
+    }
+    info!("user {:?} removed passkey {:?}", actor, id);
+    self
+      .audit
+      .record(
+        audit_event(client, AuditAction::RemovePasskey, AuditOutcome::Success)
+          .actor(actor)
+          .target(id.to_hex()),
+      )
+      .await;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    dtos::webauthn_dto::{AssertionResponse, AttestationResponse},
+    services::audit_service::AuditService,
+  };
+  use ciborium::Value;
+  use database::{memory::MemoryDatabase, user::repository::UserRepositoryTrait};
+  use p256::ecdsa::{Signature, SigningKey, signature::Signer};
+
+  const EMAIL: &str = "passkey@example.com";
+  const CREDENTIAL_ID: &[u8] = b"software-credential";
+
+  /// An authenticator keeping one ES256 credential in memory, answering like a browser would.
+  struct SoftwareAuthenticator {
+    key: SigningKey,
+    sign_count: u32,
+  }
+
+  impl SoftwareAuthenticator {
+    fn new(seed: u8) -> Self {
+      Self {
+        key: SigningKey::from_slice(&[seed; 32]).unwrap(),
+        sign_count: 0,
+      }
+    }
+
+    fn client_data(type_: &str, challenge: &str) -> Vec<u8> {
+      let origin = &config::get().webauthn.origin;
+      serde_json::to_vec(&serde_json::json!({
+        "type": type_,
+        "challenge": challenge,
+        "origin": origin,
+      }))
+      .unwrap()
+    }
+
+    /// Authenticator data with the user present and verified, and the credential when `attested`.
+    fn authenticator_data(&mut self, attested: bool) -> Vec<u8> {
+      self.sign_count += 1;
+      let mut data = webauthn::sha256(config::get().webauthn.rp_id.as_bytes());
+      data.push(if attested { 0x45 } else { 0x05 });
+      data.extend_from_slice(&self.sign_count.to_be_bytes());
+      if attested {
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let cose_key = Value::Map(vec![
+          (Value::Integer(1.into()), Value::Integer(2.into())),
+          (
+            Value::Integer(3.into()),
+            Value::Integer(webauthn::COSE_ALG_ES256.into()),
+          ),
+          (Value::Integer((-1).into()), Value::Integer(1.into())),
+          (
+            Value::Integer((-2).into()),
+            Value::Bytes(point.x().unwrap().to_vec()),
+          ),
+          (
+            Value::Integer((-3).into()),
+            Value::Bytes(point.y().unwrap().to_vec()),
+          ),
+        ]);
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&u16::try_from(CREDENTIAL_ID.len()).unwrap().to_be_bytes());
+        data.extend_from_slice(CREDENTIAL_ID);
+        ciborium::into_writer(&cose_key, &mut data).unwrap();
+      }
+      data
+    }
+
+    fn register(&mut self, challenge: &str) -> RegisterPasskeyDto {
+      let attestation_object = Value::Map(vec![
+        (Value::Text("fmt".into()), Value::Text("none".into())),
+        (Value::Text("attStmt".into()), Value::Map(Vec::new())),
+        (
+          Value::Text("authData".into()),
+          Value::Bytes(self.authenticator_data(true)),
+        ),
+      ]);
+      let mut attestation = Vec::new();
+      ciborium::into_writer(&attestation_object, &mut attestation).unwrap();
+      RegisterPasskeyDto {
+        id: webauthn::encode(CREDENTIAL_ID),
+        response: AttestationResponse {
+          client_data_json: webauthn::encode(&Self::client_data("webauthn.create", challenge)),
+          attestation_object: webauthn::encode(&attestation),
+        },
+        name: Some("Software".to_string()),
+      }
+    }
+
+    fn login(&mut self, challenge: &str) -> PasskeyLoginDto {
+      let client_data = Self::client_data("webauthn.get", challenge);
+      let authenticator_data = self.authenticator_data(false);
+      let mut message = authenticator_data.clone();
+      message.extend_from_slice(&webauthn::sha256(&client_data));
+      let signature: Signature = self.key.sign(&message);
+      PasskeyLoginDto {
+        id: webauthn::encode(CREDENTIAL_ID),
+        response: AssertionResponse {
+          client_data_json: webauthn::encode(&client_data),
+          authenticator_data: webauthn::encode(&authenticator_data),
+          signature: webauthn::encode(signature.to_der().as_bytes()),
+        },
+      }
+    }
+  }
+
+  async fn service() -> WebauthnService {
+    config::init_for_tests();
+    let database = Arc::new(MemoryDatabase::new());
+    database
+      .create_user("Passkey", EMAIL, "hash")
+      .await
+      .unwrap();
+    let audit = Arc::new(AuditService::new(database.clone()));
+    WebauthnService::new(database.clone(), database, audit)
+  }
+
+  /// Registers the passkey of `authenticator` for the test user.
+  async fn register(service: &WebauthnService, authenticator: &mut SoftwareAuthenticator) {
+    let options = service.start_registration(EMAIL).await.unwrap();
+    let request = authenticator.register(&options.challenge);
+    let credential = service
+      .finish_registration(request, EMAIL, &ClientInfo::default())
+      .await
+      .unwrap();
+    assert_eq!(credential.credential_id, webauthn::encode(CREDENTIAL_ID));
+    assert_eq!(credential.sign_count, 1);
+  }
+
+  #[tokio::test]
+  async fn registers_and_logs_in_with_an_es256_passkey() {
+    let service = service().await;
+    let mut authenticator = SoftwareAuthenticator::new(7);
+    register(&service, &mut authenticator).await;
+
+    let options = service.start_login().await.unwrap();
+    let request = authenticator.login(&options.challenge);
+    let (access_token, _, _, _) = service
+      .finish_login(request, &ClientInfo::default())
+      .await
+      .unwrap();
+
+    let claims = jwt::decode_token(&access_token, &config::get().jwt.access_token_secret).unwrap();
+    assert_eq!(claims.sub, EMAIL);
+    let credentials = service.get_credentials(EMAIL).await.unwrap();
+    assert_eq!(credentials[0].sign_count, 2);
+    assert!(credentials[0].last_used_at.is_some());
+  }
+
+  #[tokio::test]
+  async fn rejects_a_replayed_assertion() {
+    let service = service().await;
+    let mut authenticator = SoftwareAuthenticator::new(7);
+    register(&service, &mut authenticator).await;
+
+    let options = service.start_login().await.unwrap();
+    let request = authenticator.login(&options.challenge);
+    service
+      .finish_login(request.clone(), &ClientInfo::default())
+      .await
+      .unwrap();
+    let replayed = service.finish_login(request, &ClientInfo::default()).await;
+    assert!(replayed.is_err());
+  }
+
+  #[tokio::test]
+  async fn rejects_an_assertion_signed_with_another_key() {
+    let service = service().await;
+    let mut authenticator = SoftwareAuthenticator::new(7);
+    register(&service, &mut authenticator).await;
+
+    let mut impostor = SoftwareAuthenticator::new(8);
+    impostor.sign_count = authenticator.sign_count;
+    let options = service.start_login().await.unwrap();
+    let request = impostor.login(&options.challenge);
+    let login = service.finish_login(request, &ClientInfo::default()).await;
+    assert!(matches!(login, Err(AppError::Unauthorized)));
+  }
+}
@@ -0,0 +1,72 @@
+use crate::services::Services;
+use database::{Database, events::ChangeStreamWatcher};
+use std::time::Duration;
+use tokio::time::{MissedTickBehavior, interval};
+use tracing::error;
+use utils::config;
+
+/// Periodically purges users whose soft deletion is older than the retention period.
+///
+/// `database` is the Mongo database, whose tenant databases are purged as well, see
+/// `Database::tenants`.
+pub fn spawn_user_purge(services: Services, database: Option<Database>) {
+  let cfg = config::get();
+  let period = Duration::from_secs(cfg.retention.purge_interval.max(1) as u64);
+  tokio::spawn(async move {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+      ticker.tick().await;
+      if let Err(e) = services.user.purge_deleted_users().await {
+        error!("failed to purge deleted users: {e}");
+      }
+      let Some(database) = &database else {
+        continue;
+      };
+      let tenants = match database.tenants().await {
+        Ok(tenants) => tenants,
+        Err(e) => {
+          error!("failed to list tenant databases: {e}");
+          continue;
+        }
+      };
+      for tenant in tenants {
+        let purge = services.user.purge_deleted_users();
+        if let Err(e) = utils::tenant::scope(tenant.clone(), purge).await {
+          error!("failed to purge deleted users of tenant {tenant:?}: {e}");
+        }
+      }
+    }
+  });
+}
+
+/// Publishes the changes made to users to `services.events`, see `ChangeStreamWatcher`.
+pub fn spawn_user_events(database: Database, services: &Services) {
+  let cfg = config::get();
+  let watcher = ChangeStreamWatcher::new(database, services.events.clone(), &cfg.events.stream);
+  tokio::spawn(watcher.run());
+}
+
+/// Delivers the outbox every `outbox.poll_interval`, without waiting while batches come back full.
+pub fn spawn_outbox_relay(services: Services) {
+  let cfg = config::get();
+  let period = Duration::from_secs(cfg.outbox.poll_interval.max(1));
+  let batch_size = cfg.outbox.batch_size;
+  tokio::spawn(async move {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+      ticker.tick().await;
+      loop {
+        match services.outbox.relay().await {
+          Ok(claimed) if claimed >= batch_size => continue,
+          Ok(_) => break,
+          Err(e) => {
+            error!("failed to relay the outbox: {e}");
+            break;
+          }
+        }
+      }
+    }
+  });
+}
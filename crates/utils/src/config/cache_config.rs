@@ -0,0 +1,34 @@
+use serde::Deserialize;
+use std::num::NonZeroUsize;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct CacheConfig {
+  /// Cache user lookups in process, off by default. Every server has its own cache, so a change
+  /// made through another server can be seen up to `ttl` seconds late.
+  #[serde(default)]
+  pub enabled: bool,
+  /// Users kept at most, the least recently used being evicted first.
+  #[serde(default = "default_capacity")]
+  pub capacity: NonZeroUsize,
+  /// Seconds a user stays cached.
+  #[serde(default = "default_ttl")]
+  pub ttl: u64,
+}
+
+fn default_capacity() -> NonZeroUsize {
+  NonZeroUsize::new(10_000).expect("capacity should be positive")
+}
+
+fn default_ttl() -> u64 {
+  60
+}
+
+impl Default for CacheConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      capacity: default_capacity(),
+      ttl: default_ttl(),
+    }
+  }
+}
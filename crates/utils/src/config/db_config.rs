@@ -1,21 +1,328 @@
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct DbConfig {
-  /// Settings for the primary database. This is usually writeable, but will be read-only in
-  /// some configurations.
-  /// An optional follower database. Always read-only.
-  #[serde(alias = "database_uri")]
+  /// Where repositories keep their data.
+  #[serde(default)]
+  pub backend: DbBackend,
+  /// The primary database, receiving every write.
+  #[serde(alias = "database_uri", default)]
   pub uri: String,
+  /// Where read-heavy queries are sent, the primary by default.
+  #[serde(default)]
+  pub reads: ReadsConfig,
   #[serde(default = "default_database")]
   pub database: String,
-  #[serde(default = "default_collection")]
-  pub collection: String,
+  /// Name of the user collection, shorthand for `collections.User`.
+  #[serde(default)]
+  pub collection: Option<String>,
+  /// Collection names to use instead of the default ones, by default name, e.g.
+  /// `Invitation = "invitations"`.
+  #[serde(default)]
+  pub collections: HashMap<String, String>,
+  /// Options of the Mongo client, overriding those given in `uri`.
+  #[serde(default)]
+  pub client: MongoClientConfig,
+  /// When tenancy is enabled, store each tenant in its own `{database}-{tenant}` database
+  /// instead of tagging documents with a `tenant_id`.
+  #[serde(default)]
+  pub database_per_tenant: bool,
+  /// Apply pending migrations when the server starts.
+  #[serde(default)]
+  pub auto_migrate: bool,
+  /// Run units of work in transactions. Requires a replica set or a sharded cluster.
+  ///
+  /// Without it units of work are best effort: a failure midway keeps the writes made before
+  /// it.
+  #[serde(default)]
+  pub transactions: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DbBackend {
+  #[default]
+  Mongo,
+  /// Keeps everything in process memory, lost on restart. For tests and local development.
+  Memory,
+  /// Stores users in SQLite or Postgres, named by `uri`. Needs the `sqlite` or `postgres`
+  /// feature.
+  Sql,
+}
+
+/// Options of the Mongo client. Unset options keep the value given in `uri`, if any, or the
+/// driver default.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct MongoClientConfig {
+  /// Name sent to the server, shown in its logs and `currentOp`.
+  pub app_name: Option<String>,
+  /// Maximum number of connections per server.
+  pub max_pool_size: Option<u32>,
+  /// Connections kept open per server, even when idle.
+  pub min_pool_size: Option<u32>,
+  /// Seconds an idle connection is kept before being closed.
+  pub max_idle_time: Option<u64>,
+  /// Seconds to wait for a connection to be established.
+  pub connect_timeout: Option<u64>,
+  /// Seconds to wait for a suitable server before an operation fails.
+  pub server_selection_timeout: Option<u64>,
+  /// Compressors offered to the server, in order of preference.
+  #[serde(default)]
+  pub compressors: Vec<DbCompressor>,
+  pub tls: Option<MongoTlsConfig>,
+  pub auth: Option<MongoAuthConfig>,
+}
+
+/// Routing of the queries that tolerate stale data, such as user listings and lookups by id.
+/// Writes, and reads within a unit of work, always go to the primary.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ReadsConfig {
+  /// An optional follower database, e.g. an analytics node. Always read-only.
+  pub follower_uri: Option<String>,
+  /// Which members may serve the reads, `secondary_preferred` when `follower_uri` is set and
+  /// `primary` otherwise.
+  pub read_preference: Option<ReadPreferenceMode>,
+  /// Seconds a secondary may lag behind the primary and still serve reads, at least 90.
+  pub max_staleness: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadPreferenceMode {
+  Primary,
+  PrimaryPreferred,
+  Secondary,
+  SecondaryPreferred,
+  Nearest,
+}
+
+impl ReadsConfig {
+  /// The configured read preference, or its default.
+  pub fn read_preference(&self) -> ReadPreferenceMode {
+    self
+      .read_preference
+      .unwrap_or(if self.follower_uri.is_some() {
+        ReadPreferenceMode::SecondaryPreferred
+      } else {
+        ReadPreferenceMode::Primary
+      })
+  }
+
+  fn validate(&self) -> Result<(), String> {
+    if self.follower_uri.as_deref().is_some_and(str::is_empty) {
+      return Err("reads.follower_uri must not be empty".into());
+    }
+    if let Some(max_staleness) = self.max_staleness {
+      if max_staleness < 90 {
+        return Err("reads.max_staleness must be at least 90 seconds".into());
+      }
+      if self.read_preference() == ReadPreferenceMode::Primary {
+        return Err("reads.max_staleness cannot be used with the primary read preference".into());
+      }
+    }
+    Ok(())
+  }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DbCompressor {
+  Zstd,
+  Zlib,
+  Snappy,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct MongoTlsConfig {
+  /// Set to false to disable TLS even when `uri` enables it.
+  #[serde(default = "crate::config::default_true")]
+  pub enabled: bool,
+  /// Certificate authorities to trust instead of the system ones.
+  pub ca_file: Option<PathBuf>,
+  /// PEM file holding the client certificate and its key, for mutual TLS and `x509`.
+  pub cert_key_file: Option<PathBuf>,
+  /// Accept any server certificate. Never set in production.
+  #[serde(default)]
+  pub allow_invalid_certificates: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct MongoAuthConfig {
+  pub mechanism: DbAuthMechanism,
+  /// Database holding the user, `admin` by default, `$external` for `x509` and `plain`.
+  pub source: Option<String>,
+  pub username: Option<String>,
+  pub password: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DbAuthMechanism {
+  ScramSha1,
+  ScramSha256,
+  X509,
+  /// LDAP, sending the password in clear: only use with TLS.
+  Plain,
+}
+
+impl DbConfig {
+  /// The name of the collection stored as `default` unless overridden.
+  pub fn collection_name<'a>(&'a self, default: &'a str) -> &'a str {
+    let shorthand = self.collection.as_deref().filter(|_| default == "User");
+    self
+      .collections
+      .get(default)
+      .map(String::as_str)
+      .or(shorthand)
+      .unwrap_or(default)
+  }
+
+  /// Checks the settings that would otherwise only fail on first use of the database.
+  ///
+  /// # Errors
+  ///
+  /// Returns a description of the first invalid setting.
+  pub fn validate(&self) -> Result<(), String> {
+    let names = self
+      .collections
+      .values()
+      .chain(&self.collection)
+      .map(String::as_str);
+    for name in names {
+      if name.is_empty() || name.contains(['$', '\0']) || name.starts_with("system.") {
+        return Err(format!("invalid collection name {name:?}"));
+      }
+    }
+    self.reads.validate()?;
+    self.client.validate()
+  }
+}
+
+impl MongoClientConfig {
+  fn validate(&self) -> Result<(), String> {
+    if self.app_name.as_deref().is_some_and(str::is_empty) {
+      return Err("app_name must not be empty".into());
+    }
+    if self.max_pool_size == Some(0) {
+      return Err("max_pool_size must be positive".into());
+    }
+    if let (Some(min), Some(max)) = (self.min_pool_size, self.max_pool_size)
+      && min > max
+    {
+      return Err(format!(
+        "min_pool_size ({min}) must not exceed max_pool_size ({max})"
+      ));
+    }
+    let timeouts = [
+      ("connect_timeout", self.connect_timeout),
+      ("server_selection_timeout", self.server_selection_timeout),
+    ];
+    if let Some((name, _)) = timeouts.iter().find(|(_, timeout)| *timeout == Some(0)) {
+      return Err(format!("{name} must be positive"));
+    }
+    for (index, compressor) in self.compressors.iter().enumerate() {
+      if self.compressors[..index].contains(compressor) {
+        return Err(format!("compressor {compressor:?} is listed twice"));
+      }
+    }
+    if let Some(tls) = &self.tls {
+      let files = [&tls.ca_file, &tls.cert_key_file];
+      if let Some(missing) = files.into_iter().flatten().find(|file| !file.is_file()) {
+        return Err(format!("tls file {} does not exist", missing.display()));
+      }
+    }
+    if let Some(auth) = &self.auth {
+      match auth.mechanism {
+        DbAuthMechanism::ScramSha1 | DbAuthMechanism::ScramSha256 | DbAuthMechanism::Plain => {
+          if auth.username.is_none() || auth.password.is_none() {
+            return Err(format!(
+              "auth mechanism {:?} needs a username and a password",
+              auth.mechanism
+            ));
+          }
+        }
+        DbAuthMechanism::X509 => {
+          let has_certificate = self
+            .tls
+            .as_ref()
+            .is_some_and(|tls| tls.enabled && tls.cert_key_file.is_some());
+          if !has_certificate || auth.password.is_some() {
+            return Err("auth mechanism X509 needs tls.cert_key_file and no password".into());
+          }
+        }
+      }
+    }
+    Ok(())
+  }
 }
 
 fn default_database() -> String {
   "rust-axum-boilerplate-db".into()
 }
-fn default_collection() -> String {
-  "User".into()
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn auth(mechanism: DbAuthMechanism, password: Option<&str>) -> Option<MongoAuthConfig> {
+    Some(MongoAuthConfig {
+      mechanism,
+      source: None,
+      username: Some("app".to_string()),
+      password: password.map(str::to_string),
+    })
+  }
+
+  #[test]
+  fn client_options_are_validated() {
+    let valid = MongoClientConfig {
+      min_pool_size: Some(2),
+      max_pool_size: Some(10),
+      compressors: vec![DbCompressor::Zstd, DbCompressor::Snappy],
+      auth: auth(DbAuthMechanism::ScramSha256, Some("secret")),
+      ..MongoClientConfig::default()
+    };
+    assert_eq!(valid.validate(), Ok(()));
+
+    let invalid = [
+      MongoClientConfig {
+        app_name: Some(String::new()),
+        ..valid.clone()
+      },
+      MongoClientConfig {
+        min_pool_size: Some(20),
+        ..valid.clone()
+      },
+      MongoClientConfig {
+        server_selection_timeout: Some(0),
+        ..valid.clone()
+      },
+      MongoClientConfig {
+        compressors: vec![DbCompressor::Zlib, DbCompressor::Zlib],
+        ..valid.clone()
+      },
+      MongoClientConfig {
+        auth: auth(DbAuthMechanism::Plain, None),
+        ..valid.clone()
+      },
+      // x509 authenticates with the client certificate, which is missing.
+      MongoClientConfig {
+        auth: auth(DbAuthMechanism::X509, None),
+        ..valid.clone()
+      },
+      MongoClientConfig {
+        tls: Some(MongoTlsConfig {
+          enabled: true,
+          ca_file: Some(PathBuf::from("/nonexistent/ca.pem")),
+          cert_key_file: None,
+          allow_invalid_certificates: false,
+        }),
+        ..valid.clone()
+      },
+    ];
+    for client in invalid {
+      assert!(client.validate().is_err(), "{client:?} should be invalid");
+    }
+  }
 }
@@ -0,0 +1,34 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct EventsConfig {
+  /// Watch the user collection and publish its changes as domain events. Needs the mongo
+  /// backend running as a replica set, a single node one being enough.
+  #[serde(default)]
+  pub enabled: bool,
+  /// Name the resume token is saved under. Servers sharing a name resume from where the last
+  /// of them stopped.
+  #[serde(default = "default_stream")]
+  pub stream: String,
+  /// Events kept for each subscriber, the oldest being dropped for those falling behind.
+  #[serde(default = "default_capacity")]
+  pub capacity: usize,
+}
+
+fn default_stream() -> String {
+  "users".into()
+}
+
+fn default_capacity() -> usize {
+  1024
+}
+
+impl Default for EventsConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      stream: default_stream(),
+      capacity: default_capacity(),
+    }
+  }
+}
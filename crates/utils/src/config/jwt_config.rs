@@ -1,9 +1,16 @@
 use serde::Deserialize;
 
+fn default_reauthentication_window() -> usize {
+  300
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct JwtConfig {
   pub access_token_secret: String,
   pub access_token_expiry: usize,
   pub refresh_token_secret: String,
   pub refresh_token_expiry: usize,
+  /// How long, in seconds, a password (re-)authentication allows access to sensitive routes.
+  #[serde(default = "default_reauthentication_window")]
+  pub reauthentication_window: usize,
 }
@@ -1,8 +1,18 @@
-mod db_config;
+pub mod cache_config;
+pub mod db_config;
+pub mod events_config;
 mod jwt_config;
 mod log_config;
+pub mod oauth_config;
+pub mod outbox_config;
+pub mod retention_config;
 pub mod server_config;
+pub mod signup_config;
+pub mod storage_config;
+pub mod tenant_config;
+pub mod webauthn_config;
 
+use db_config::DbBackend;
 use figment::{
   Figment,
   providers::{Env, Format, Toml},
@@ -31,14 +41,56 @@ pub fn init() {
     config.db.uri = std::env::var("DATABASE_URI").unwrap_or_default();
   }
 
-  if config.db.uri.is_empty() {
+  if config.db.uri.is_empty() && config.db.backend != DbBackend::Memory {
     eprintln!("DATABASE_URI is not set");
     exit(1);
   }
 
+  if let Err(e) = config.db.validate() {
+    eprintln!("It looks like your [db] config is invalid: {e}");
+    exit(1);
+  }
+
+  if let Err(e) = config.outbox.validate() {
+    eprintln!("It looks like your [outbox] config is invalid: {e}");
+    exit(1);
+  }
+
+  // Without transactions a change could be saved and its message lost, or the other way around.
+  if config.outbox.enabled && config.db.backend == DbBackend::Mongo && !config.db.transactions {
+    eprintln!("The outbox needs db.transactions with the mongo backend");
+    exit(1);
+  }
+
+  if let Err(e) = config.tenant.validate() {
+    eprintln!("It looks like your [tenant] config is invalid: {e}");
+    exit(1);
+  }
+
+  if let Err(e) = config.storage.validate() {
+    eprintln!("It looks like your [storage] config is invalid: {e}");
+    exit(1);
+  }
+
   CONFIG.set(config).expect("config should be set");
 }
 
+/// Sets the configuration to `config.toml.template`, unless it is already set.
+///
+/// For tests, which run without a `config.toml` and share the configuration of their process.
+#[doc(hidden)]
+pub fn init_for_tests() -> &'static ServerConfig {
+  CONFIG.get_or_init(|| {
+    Figment::new()
+      .merge(Toml::file(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../config.toml.template"
+      )))
+      .extract()
+      .expect("config.toml.template should be valid")
+  })
+}
+
 pub fn get() -> &'static ServerConfig {
   CONFIG.get().expect("config should be set")
 }
@@ -0,0 +1,50 @@
+use serde::Deserialize;
+
+/// A service allowed to call the token introspection and revocation endpoints.
+#[derive(Deserialize, Clone, Debug)]
+pub struct OAuthClient {
+  pub client_id: String,
+  pub client_secret: String,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct OAuthConfig {
+  #[serde(default)]
+  pub clients: Vec<OAuthClient>,
+}
+
+impl OAuthConfig {
+  /// Returns the client matching the given credentials.
+  pub fn authenticate(&self, client_id: &str, client_secret: &str) -> Option<&OAuthClient> {
+    self.clients.iter().find(|client| {
+      client.client_id == client_id
+        && constant_time_eq(client.client_secret.as_bytes(), client_secret.as_bytes())
+    })
+  }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn clients_need_their_own_secret() {
+    let client = |client_id: &str, client_secret: &str| OAuthClient {
+      client_id: client_id.to_string(),
+      client_secret: client_secret.to_string(),
+    };
+    let oauth = OAuthConfig {
+      clients: vec![client("billing", "s3cret"), client("mailer", "other")],
+    };
+    let found = oauth.authenticate("billing", "s3cret").unwrap();
+    assert_eq!(found.client_id, "billing");
+    assert!(oauth.authenticate("billing", "other").is_none());
+    assert!(oauth.authenticate("billing", "s3cret ").is_none());
+    assert!(oauth.authenticate("unknown", "s3cret").is_none());
+    assert!(OAuthConfig::default().authenticate("", "").is_none());
+  }
+}
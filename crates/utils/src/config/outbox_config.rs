@@ -0,0 +1,109 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct OutboxConfig {
+  /// Record user changes in the outbox and run the relay delivering them. The message is
+  /// written atomically with the change, which requires `db.transactions` with the mongo
+  /// backend.
+  #[serde(default)]
+  pub enabled: bool,
+  /// Where messages are delivered. Each message goes to every sink, at least once, retries
+  /// skipping the sinks that already accepted it.
+  #[serde(default = "default_sinks")]
+  pub sinks: Vec<OutboxSinkKind>,
+  /// Receives every message as a JSON `POST`, with the `webhook` sink.
+  pub webhook_url: Option<String>,
+  /// Signs webhook bodies with HMAC-SHA256 in the `X-Outbox-Signature` header when set.
+  pub webhook_secret: Option<String>,
+  /// Seconds between two polls of the outbox.
+  #[serde(default = "default_poll_interval")]
+  pub poll_interval: u64,
+  /// Messages claimed per poll.
+  #[serde(default = "default_batch_size")]
+  pub batch_size: usize,
+  /// Seconds a claimed message is hidden from other relays, renewed while its batch is
+  /// delivered. Webhook requests time out after a quarter of it.
+  #[serde(default = "default_lease")]
+  pub lease: u64,
+  /// Attempts before a message is marked as failed.
+  #[serde(default = "default_max_attempts")]
+  pub max_attempts: u32,
+  /// Seconds before the first retry, doubled after each further failure.
+  #[serde(default = "default_retry_delay")]
+  pub retry_delay: u64,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxSinkKind {
+  /// Logs each message.
+  Log,
+  /// Posts each message to `webhook_url`.
+  Webhook,
+  /// Publishes each message to `services.outbox.subscribe()`. Messages published while nobody
+  /// is subscribed are dropped.
+  InProcess,
+}
+
+fn default_sinks() -> Vec<OutboxSinkKind> {
+  vec![OutboxSinkKind::Log]
+}
+
+fn default_poll_interval() -> u64 {
+  5
+}
+
+fn default_batch_size() -> usize {
+  100
+}
+
+fn default_lease() -> u64 {
+  60
+}
+
+fn default_max_attempts() -> u32 {
+  10
+}
+
+fn default_retry_delay() -> u64 {
+  10
+}
+
+impl Default for OutboxConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      sinks: default_sinks(),
+      webhook_url: None,
+      webhook_secret: None,
+      poll_interval: default_poll_interval(),
+      batch_size: default_batch_size(),
+      lease: default_lease(),
+      max_attempts: default_max_attempts(),
+      retry_delay: default_retry_delay(),
+    }
+  }
+}
+
+impl OutboxConfig {
+  /// Checks that the settings of the configured sinks are present.
+  ///
+  /// # Errors
+  ///
+  /// Returns a description of the first invalid setting.
+  pub fn validate(&self) -> Result<(), String> {
+    if !self.enabled {
+      return Ok(());
+    }
+    if self.sinks.is_empty() {
+      return Err("sinks must not be empty".into());
+    }
+    if self.sinks.contains(&OutboxSinkKind::Webhook) && self.webhook_url.is_none() {
+      return Err("the webhook sink needs webhook_url".into());
+    }
+    if self.batch_size == 0 || self.max_attempts == 0 || self.lease == 0 {
+      return Err("batch_size, max_attempts and lease must be positive".into());
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,28 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RetentionConfig {
+  /// Seconds a soft deleted user can be restored before it is purged.
+  #[serde(default = "default_deleted_user_retention")]
+  pub deleted_user_retention: usize,
+  /// Seconds between two runs of the purge task.
+  #[serde(default = "default_purge_interval")]
+  pub purge_interval: usize,
+}
+
+fn default_deleted_user_retention() -> usize {
+  2_592_000
+}
+
+fn default_purge_interval() -> usize {
+  3600
+}
+
+impl Default for RetentionConfig {
+  fn default() -> Self {
+    Self {
+      deleted_user_retention: default_deleted_user_retention(),
+      purge_interval: default_purge_interval(),
+    }
+  }
+}
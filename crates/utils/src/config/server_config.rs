@@ -1,7 +1,18 @@
+use crate::config::cache_config::CacheConfig;
 use crate::config::db_config::DbConfig;
+use crate::config::events_config::EventsConfig;
 use crate::config::jwt_config::JwtConfig;
 use crate::config::log_config::LogConfig;
-use serde::Deserialize;
+use crate::config::oauth_config::OAuthConfig;
+use crate::config::outbox_config::OutboxConfig;
+use crate::config::retention_config::RetentionConfig;
+use crate::config::signup_config::SignupConfig;
+use crate::config::storage_config::StorageConfig;
+use crate::config::tenant_config::TenantConfig;
+use crate::config::webauthn_config::WebauthnConfig;
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer, de::Error};
+use std::net::IpAddr;
 
 #[derive(clap::ValueEnum, Deserialize, Clone, Debug, Copy)]
 pub enum CargoEnv {
@@ -17,9 +28,57 @@ pub struct ServerConfig {
   pub app_host: String,
   #[serde(default = "default_app_port")]
   pub app_port: u16,
+  /// Peers allowed to report the client address with `X-Forwarded-For` or `X-Real-IP`, as
+  /// addresses or CIDR ranges. Requests from any other peer are attributed to the peer itself.
+  #[serde(default, deserialize_with = "deserialize_networks")]
+  pub trusted_proxies: Vec<IpNet>,
   pub db: DbConfig,
   pub log: LogConfig,
   pub jwt: JwtConfig,
+  #[serde(default)]
+  pub signup: SignupConfig,
+  #[serde(default)]
+  pub tenant: TenantConfig,
+  #[serde(default)]
+  pub oauth: OAuthConfig,
+  #[serde(default)]
+  pub webauthn: WebauthnConfig,
+  #[serde(default)]
+  pub retention: RetentionConfig,
+  #[serde(default)]
+  pub cache: CacheConfig,
+  #[serde(default)]
+  pub events: EventsConfig,
+  #[serde(default)]
+  pub outbox: OutboxConfig,
+  #[serde(default)]
+  pub storage: StorageConfig,
+}
+
+impl ServerConfig {
+  /// Whether `ip` is one of `trusted_proxies`.
+  #[must_use]
+  pub fn is_trusted_proxy(&self, ip: &IpAddr) -> bool {
+    self
+      .trusted_proxies
+      .iter()
+      .any(|network| network.contains(ip))
+  }
+}
+
+/// Reads addresses and CIDR ranges, an address being a range of its own.
+fn deserialize_networks<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Vec<IpNet>, D::Error> {
+  Vec::<String>::deserialize(deserializer)?
+    .iter()
+    .map(|network| {
+      network
+        .parse::<IpNet>()
+        .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| D::Error::custom(format!("invalid trusted proxy {network:?}")))
+    })
+    .collect()
 }
 
 fn default_app_host() -> String {
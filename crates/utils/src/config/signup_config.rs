@@ -0,0 +1,170 @@
+use serde::Deserialize;
+
+use super::default_false;
+
+/// Well-known throwaway mailbox providers, rejected when `block_disposable_domains` is set.
+const DISPOSABLE_DOMAINS: &[&str] = &[
+  "10minutemail.com",
+  "dispostable.com",
+  "getnada.com",
+  "guerrillamail.com",
+  "maildrop.cc",
+  "mailinator.com",
+  "mintemail.com",
+  "sharklasers.com",
+  "temp-mail.org",
+  "tempmail.com",
+  "throwawaymail.com",
+  "trashmail.com",
+  "yopmail.com",
+];
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignupMode {
+  /// Anyone may sign up.
+  #[default]
+  Open,
+  /// Sign up requires a single-use invitation code created by an admin.
+  InviteOnly,
+  /// Sign up is disabled.
+  Closed,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SignupConfig {
+  #[serde(default)]
+  pub mode: SignupMode,
+  /// When not empty, only emails from these domains (or their subdomains) may sign up.
+  #[serde(default)]
+  pub allowed_domains: Vec<String>,
+  /// Emails from these domains (or their subdomains) may never sign up.
+  #[serde(default)]
+  pub denied_domains: Vec<String>,
+  #[serde(default = "default_false")]
+  pub block_disposable_domains: bool,
+  /// Extra disposable domains, on top of the built-in list.
+  #[serde(default)]
+  pub disposable_domains: Vec<String>,
+  /// Lifetime of an invitation code in seconds.
+  #[serde(default = "default_invitation_expiry")]
+  pub invitation_expiry: usize,
+}
+
+fn default_invitation_expiry() -> usize {
+  604_800
+}
+
+impl Default for SignupConfig {
+  fn default() -> Self {
+    Self {
+      mode: SignupMode::default(),
+      allowed_domains: Vec::new(),
+      denied_domains: Vec::new(),
+      block_disposable_domains: false,
+      disposable_domains: Vec::new(),
+      invitation_expiry: default_invitation_expiry(),
+    }
+  }
+}
+
+fn matches_domain(domain: &str, rule: &str) -> bool {
+  let rule = rule.trim().trim_start_matches('@').to_lowercase();
+  domain == rule || domain.ends_with(&format!(".{rule}"))
+}
+
+impl SignupConfig {
+  /// Checks the domain of `email` against the allow/deny lists.
+  ///
+  /// Returns the reason the email is rejected, or `None` when it may sign up.
+  pub fn domain_rejection(&self, email: &str) -> Option<&'static str> {
+    let domain = email
+      .rsplit_once('@')
+      .map(|(_, domain)| domain.to_lowercase())
+      .unwrap_or_default();
+
+    if !self.allowed_domains.is_empty()
+      && !self
+        .allowed_domains
+        .iter()
+        .any(|rule| matches_domain(&domain, rule))
+    {
+      return Some("email domain is not allowed");
+    }
+    if self
+      .denied_domains
+      .iter()
+      .any(|rule| matches_domain(&domain, rule))
+    {
+      return Some("email domain is not allowed");
+    }
+    if self.block_disposable_domains
+      && DISPOSABLE_DOMAINS
+        .iter()
+        .copied()
+        .chain(self.disposable_domains.iter().map(String::as_str))
+        .any(|rule| matches_domain(&domain, rule))
+    {
+      return Some("disposable email addresses are not allowed");
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn domains(domains: &[&str]) -> Vec<String> {
+    domains.iter().map(|domain| domain.to_string()).collect()
+  }
+
+  #[test]
+  fn allowed_domains_include_their_subdomains() {
+    let signup = SignupConfig {
+      allowed_domains: domains(&["@Example.com"]),
+      ..SignupConfig::default()
+    };
+    assert_eq!(signup.domain_rejection("ada@example.com"), None);
+    assert_eq!(signup.domain_rejection("ada@Mail.EXAMPLE.com"), None);
+    assert!(signup.domain_rejection("ada@notexample.com").is_some());
+    assert!(
+      signup
+        .domain_rejection("ada@example.com.evil.org")
+        .is_some()
+    );
+    assert!(signup.domain_rejection("no-domain").is_some());
+  }
+
+  #[test]
+  fn denied_domains_win_over_allowed_ones() {
+    let signup = SignupConfig {
+      allowed_domains: domains(&["example.com"]),
+      denied_domains: domains(&["contractors.example.com"]),
+      ..SignupConfig::default()
+    };
+    assert_eq!(signup.domain_rejection("ada@example.com"), None);
+    assert!(
+      signup
+        .domain_rejection("ada@eu.contractors.example.com")
+        .is_some()
+    );
+  }
+
+  #[test]
+  fn disposable_domains_are_only_blocked_on_request() {
+    let mut signup = SignupConfig {
+      disposable_domains: domains(&["burner.dev"]),
+      ..SignupConfig::default()
+    };
+    assert_eq!(signup.domain_rejection("ada@mailinator.com"), None);
+
+    signup.block_disposable_domains = true;
+    assert_eq!(
+      signup.domain_rejection("ada@MAILINATOR.com"),
+      Some("disposable email addresses are not allowed")
+    );
+    assert!(signup.domain_rejection("ada@burner.dev").is_some());
+    assert_eq!(signup.domain_rejection("ada@example.com"), None);
+  }
+}
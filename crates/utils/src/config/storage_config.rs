@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct StorageConfig {
+  /// Where the content of uploaded files is kept. Their metadata always lives in the database.
+  #[serde(default)]
+  pub backend: StorageBackend,
+  /// Largest accepted upload, in bytes. Uploads are held in memory until they are stored.
+  #[serde(default = "default_max_file_size")]
+  pub max_file_size: u64,
+  /// Accepted content types, such as `application/pdf`, or `image/*` for every image type.
+  #[serde(default = "default_allowed_content_types")]
+  pub allowed_content_types: Vec<String>,
+  #[serde(default)]
+  pub local: LocalStorageConfig,
+  #[serde(default)]
+  pub gridfs: GridFsStorageConfig,
+  pub s3: Option<S3StorageConfig>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+  /// Files in a directory of the server, see `[storage.local]`.
+  #[default]
+  Local,
+  /// GridFS buckets of the Mongo database, with `db.backend = "mongo"` only.
+  Gridfs,
+  /// A bucket of S3 or an S3-compatible service such as MinIO, see `[storage.s3]`.
+  S3,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LocalStorageConfig {
+  /// Directory holding the files, created if missing.
+  #[serde(default = "default_local_path")]
+  pub path: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct GridFsStorageConfig {
+  /// Prefix of the `.files` and `.chunks` collections.
+  #[serde(default = "default_bucket")]
+  pub bucket: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct S3StorageConfig {
+  /// Base URL of the service, e.g. `https://s3.eu-west-1.amazonaws.com` or
+  /// `http://localhost:9000` for MinIO.
+  pub endpoint: String,
+  pub bucket: String,
+  #[serde(default = "default_region")]
+  pub region: String,
+  pub access_key: String,
+  pub secret_key: String,
+  /// Address objects as `endpoint/bucket/key` rather than `bucket.endpoint/key`. MinIO and
+  /// most self-hosted services need it.
+  #[serde(default = "crate::config::default_true")]
+  pub path_style: bool,
+}
+
+fn default_max_file_size() -> u64 {
+  10 * 1024 * 1024
+}
+
+fn default_allowed_content_types() -> Vec<String> {
+  ["image/*", "application/pdf", "text/plain"]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+fn default_local_path() -> String {
+  "uploads".into()
+}
+
+fn default_bucket() -> String {
+  "fs".into()
+}
+
+fn default_region() -> String {
+  "us-east-1".into()
+}
+
+impl Default for StorageConfig {
+  fn default() -> Self {
+    Self {
+      backend: StorageBackend::default(),
+      max_file_size: default_max_file_size(),
+      allowed_content_types: default_allowed_content_types(),
+      local: LocalStorageConfig::default(),
+      gridfs: GridFsStorageConfig::default(),
+      s3: None,
+    }
+  }
+}
+
+impl Default for LocalStorageConfig {
+  fn default() -> Self {
+    Self {
+      path: default_local_path(),
+    }
+  }
+}
+
+impl Default for GridFsStorageConfig {
+  fn default() -> Self {
+    Self {
+      bucket: default_bucket(),
+    }
+  }
+}
+
+impl StorageConfig {
+  /// Whether uploads of `content_type` are accepted, ignoring its parameters and case.
+  pub fn allows(&self, content_type: &str) -> bool {
+    let essence = content_type
+      .split(';')
+      .next()
+      .unwrap_or_default()
+      .trim()
+      .to_lowercase();
+    self.allowed_content_types.iter().any(|allowed| {
+      let allowed = allowed.to_lowercase();
+      match allowed.strip_suffix("/*") {
+        Some(kind) => essence
+          .split_once('/')
+          .is_some_and(|(essence_kind, _)| essence_kind == kind),
+        None => allowed == essence,
+      }
+    })
+  }
+
+  /// Checks that the settings of the configured backend are present.
+  ///
+  /// # Errors
+  ///
+  /// Returns a description of the first invalid setting.
+  pub fn validate(&self) -> Result<(), String> {
+    if self.max_file_size == 0 {
+      return Err("max_file_size must be positive".into());
+    }
+    if self.backend == StorageBackend::S3 && self.s3.is_none() {
+      return Err("the s3 backend needs [storage.s3]".into());
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,85 @@
+use serde::Deserialize;
+
+use super::default_false;
+use crate::tenant;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TenantSource {
+  /// The header named by `tenant.header`.
+  Header,
+  /// The first label of the `Host` header below `tenant.base_domain`.
+  Subdomain,
+  /// The `tenant` claim of the access token cookie.
+  Claim,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TenantConfig {
+  #[serde(default = "default_false")]
+  pub enabled: bool,
+  /// Sources tried in order to resolve the tenant of a request.
+  #[serde(default = "default_sources")]
+  pub sources: Vec<TenantSource>,
+  #[serde(default = "default_header")]
+  pub header: String,
+  /// e.g. `example.com`, so that `acme.example.com` resolves to the `acme` tenant.
+  pub base_domain: Option<String>,
+  /// Used when no source yields a tenant. Requests are rejected when unset.
+  pub default_tenant: Option<String>,
+  /// The accepted tenants. Requests resolving to any other tenant are rejected, so that clients
+  /// cannot create tenants, or tenant databases, at will.
+  #[serde(default)]
+  pub tenants: Vec<String>,
+}
+
+fn default_sources() -> Vec<TenantSource> {
+  vec![
+    TenantSource::Header,
+    TenantSource::Subdomain,
+    TenantSource::Claim,
+  ]
+}
+
+fn default_header() -> String {
+  "x-tenant-id".into()
+}
+
+impl Default for TenantConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      sources: default_sources(),
+      header: default_header(),
+      base_domain: None,
+      default_tenant: None,
+      tenants: Vec::new(),
+    }
+  }
+}
+
+impl TenantConfig {
+  pub fn is_known(&self, tenant: &str) -> bool {
+    self.tenants.iter().any(|known| known == tenant)
+  }
+
+  pub fn validate(&self) -> Result<(), String> {
+    if !self.enabled {
+      return Ok(());
+    }
+    if self.tenants.is_empty() {
+      return Err("tenants must list the accepted tenants".into());
+    }
+    if let Some(invalid) = self.tenants.iter().find(|tenant| !tenant::is_valid(tenant)) {
+      return Err(format!(
+        "invalid tenant {invalid:?}, use lowercase letters, digits and dashes"
+      ));
+    }
+    if let Some(default_tenant) = &self.default_tenant
+      && !self.is_known(default_tenant)
+    {
+      return Err("default_tenant must be one of tenants".into());
+    }
+    Ok(())
+  }
+}
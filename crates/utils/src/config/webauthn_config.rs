@@ -0,0 +1,48 @@
+use serde::Deserialize;
+
+use super::default_false;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct WebauthnConfig {
+  /// The relying party id, usually the registrable domain of `origin`.
+  #[serde(default = "default_rp_id")]
+  pub rp_id: String,
+  #[serde(default = "default_rp_name")]
+  pub rp_name: String,
+  /// The exact origin the browser reports in the client data.
+  #[serde(default = "default_origin")]
+  pub origin: String,
+  /// Lifetime of a registration or login challenge in seconds.
+  #[serde(default = "default_challenge_timeout")]
+  pub challenge_timeout: usize,
+  #[serde(default = "default_false")]
+  pub require_user_verification: bool,
+}
+
+fn default_rp_id() -> String {
+  "localhost".into()
+}
+
+fn default_rp_name() -> String {
+  "rust-axum-boilerplate".into()
+}
+
+fn default_origin() -> String {
+  "http://localhost:5000".into()
+}
+
+fn default_challenge_timeout() -> usize {
+  300
+}
+
+impl Default for WebauthnConfig {
+  fn default() -> Self {
+    Self {
+      rp_id: default_rp_id(),
+      rp_name: default_rp_name(),
+      origin: default_origin(),
+      challenge_timeout: default_challenge_timeout(),
+      require_user_verification: false,
+    }
+  }
+}
@@ -1,8 +1,12 @@
 #![allow(dead_code)]
 use axum::{
-  Json, extract::rejection::JsonRejection, http::StatusCode, response::IntoResponse,
+  Json,
+  extract::rejection::JsonRejection,
+  http::{StatusCode, header::CONTENT_RANGE},
+  response::IntoResponse,
   response::Response,
 };
+use mongodb::error::{ErrorKind, WriteFailure};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{borrow::Cow, collections::HashMap, fmt::Debug};
@@ -14,15 +18,21 @@ pub type AppResult<T> = Result<T, AppError>;
 
 pub type ErrorMap = HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>;
 
+/// Mongo's error code for a unique index violation.
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct HttpError {
   pub error: String,
+  /// The field the error relates to, e.g. the one holding a duplicate value.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub field: Option<String>,
 }
 
 impl HttpError {
   #[must_use]
   pub fn new(error: String) -> Self {
-    Self { error }
+    Self { error, field: None }
   }
 }
 
@@ -34,8 +44,12 @@ pub enum AppError {
   BadRequest(String),
   #[error("authentication is required to access this resource")]
   Unauthorized,
+  #[error("recent authentication is required to access this resource")]
+  ReauthenticationRequired,
   #[error("user does not have privilege to access this resource")]
   Forbidden,
+  #[error("{0}")]
+  ForbiddenWithContext(String),
   #[error("unexpected error has occurred")]
   InternalServerError,
   #[error("{0}")]
@@ -46,6 +60,16 @@ pub enum AppError {
   InvalidToken(String),
   #[error("{0}")]
   PreconditionFailed(String),
+  #[error("{0}")]
+  PayloadTooLarge(String),
+  #[error("{0}")]
+  UnsupportedMediaType(String),
+  /// The requested byte range lies outside of a resource of `size` bytes.
+  #[error("range is not satisfiable")]
+  RangeNotSatisfiable { size: u64 },
+  /// A unique constraint on the named field was violated by a backend other than Mongo.
+  #[error("{0} already exists")]
+  DuplicateKey(String),
   #[error(transparent)]
   AxumJsonRejection(#[from] JsonRejection),
   #[error(transparent)]
@@ -56,6 +80,8 @@ pub enum AppError {
   SerdeJsonError(#[from] serde_json::Error),
   #[error(transparent)]
   AnyhowError(#[from] anyhow::Error),
+  #[error(transparent)]
+  IoError(#[from] std::io::Error),
   #[error("{0}")]
   MongoError(#[from] mongodb::error::Error),
   #[error("{0}")]
@@ -74,6 +100,16 @@ impl AppError {
   #[must_use]
   /// Maps `validator`'s `ValidationErrors` to a simple map of property name/error messages structure.
   pub fn unprocessable_entity(errors: ValidationErrors) -> Response {
+    let body = Json(json!({
+        "errors": Self::error_map(errors),
+    }));
+
+    (StatusCode::BAD_REQUEST, body).into_response()
+  }
+
+  /// The error messages of `errors` by property name, as reported by `unprocessable_entity`.
+  #[must_use]
+  pub fn error_map(errors: ValidationErrors) -> ErrorMap {
     let mut validation_errors = ErrorMap::new();
 
     for (field_property, error_kind) in errors.into_errors() {
@@ -100,12 +136,35 @@ impl AppError {
         }
       }
     }
+    validation_errors
+  }
+}
 
-    let body = Json(json!({
-        "errors": validation_errors,
-    }));
-
-    (StatusCode::BAD_REQUEST, body).into_response()
+impl AppError {
+  /// Returns the field violating a unique index if this is a duplicate-key error.
+  ///
+  /// Unique indexes are named after the field they guard, and the server reports the index
+  /// name in the message, e.g. `E11000 duplicate key error collection: db.User index: email dup
+  /// key: { ... }`.
+  pub fn duplicate_key_field(&self) -> Option<String> {
+    let error = match self {
+      Self::DuplicateKey(field) => return Some(field.clone()),
+      Self::MongoError(error) => error,
+      _ => return None,
+    };
+    let (code, message) = match error.kind.as_ref() {
+      ErrorKind::Write(WriteFailure::WriteError(e)) => (e.code, &e.message),
+      ErrorKind::Command(e) => (e.code, &e.message),
+      _ => return None,
+    };
+    if code != DUPLICATE_KEY_CODE {
+      return None;
+    }
+    message
+      .split(" index: ")
+      .nth(1)
+      .and_then(|rest| rest.split_whitespace().next())
+      .map(str::to_string)
   }
 }
 
@@ -115,16 +174,35 @@ impl IntoResponse for AppError {
     if let Self::ValidationError(e) = self {
       return Self::unprocessable_entity(e);
     }
+    if let Self::RangeNotSatisfiable { size } = self {
+      let body = Json(HttpError::new(self.to_string()));
+      let headers = [(CONTENT_RANGE, format!("bytes */{size}"))];
+      return (StatusCode::RANGE_NOT_SATISFIABLE, headers, body).into_response();
+    }
+    if let Some(field) = self.duplicate_key_field() {
+      let body = Json(HttpError {
+        error: format!("{field} already exists"),
+        field: Some(field),
+      });
+      return (StatusCode::CONFLICT, body).into_response();
+    }
 
     let (status, error_message) = match self {
       Self::InternalServerErrorWithContext(err) => (StatusCode::INTERNAL_SERVER_ERROR, err),
       Self::NotFound(err) => (StatusCode::NOT_FOUND, err),
       Self::Conflict(err) => (StatusCode::CONFLICT, err),
       Self::PreconditionFailed(err) => (StatusCode::PRECONDITION_FAILED, err),
+      Self::PayloadTooLarge(err) => (StatusCode::PAYLOAD_TOO_LARGE, err),
+      Self::UnsupportedMediaType(err) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, err),
       Self::BadRequest(err) => (StatusCode::BAD_REQUEST, err),
       Self::InvalidToken(err) => (StatusCode::UNAUTHORIZED, err), // Changed to return message directly
       Self::Unauthorized => (StatusCode::UNAUTHORIZED, Self::Unauthorized.to_string()),
+      Self::ReauthenticationRequired => (
+        StatusCode::UNAUTHORIZED,
+        Self::ReauthenticationRequired.to_string(),
+      ),
       Self::Forbidden => (StatusCode::FORBIDDEN, Self::Forbidden.to_string()),
+      Self::ForbiddenWithContext(err) => (StatusCode::FORBIDDEN, err),
       Self::AxumJsonRejection(err) => (StatusCode::BAD_REQUEST, err.body_text()),
       _ => (
         StatusCode::INTERNAL_SERVER_ERROR,
@@ -137,3 +215,72 @@ impl IntoResponse for AppError {
     (status, body).into_response()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mongodb::{bson, error::CommandError};
+
+  fn write_error(code: i32, message: &str) -> AppError {
+    let error = bson::from_document(bson::doc! { "code": code, "errmsg": message }).unwrap();
+    let kind = ErrorKind::Write(WriteFailure::WriteError(error));
+    AppError::MongoError(mongodb::error::Error::from(kind))
+  }
+
+  async fn body(response: Response) -> serde_json::Value {
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+      .await
+      .unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+  }
+
+  #[test]
+  fn duplicate_keys_are_reported_against_their_index() {
+    let error = write_error(
+      DUPLICATE_KEY_CODE,
+      "E11000 duplicate key error collection: db.User index: email dup key: { email: \"a\" }",
+    );
+    assert_eq!(error.duplicate_key_field().as_deref(), Some("email"));
+    let command: CommandError = bson::from_document(bson::doc! {
+      "code": DUPLICATE_KEY_CODE,
+      "codeName": "DuplicateKey",
+      "errmsg": "E11000 duplicate key error collection: db.Invitation index: code dup key: {}",
+    })
+    .unwrap();
+    let error = AppError::MongoError(ErrorKind::Command(command).into());
+    assert_eq!(error.duplicate_key_field().as_deref(), Some("code"));
+
+    let other = write_error(121, "Document failed validation index: email");
+    assert_eq!(other.duplicate_key_field(), None);
+    assert_eq!(
+      AppError::Conflict("taken".to_string()).duplicate_key_field(),
+      None
+    );
+  }
+
+  #[tokio::test]
+  async fn duplicate_keys_are_conflicts() {
+    for error in [
+      AppError::DuplicateKey("email".to_string()),
+      write_error(
+        DUPLICATE_KEY_CODE,
+        "E11000 duplicate key error collection: db.User index: email dup key: {}",
+      ),
+    ] {
+      let response = error.into_response();
+      assert_eq!(response.status(), StatusCode::CONFLICT);
+      assert_eq!(
+        body(response).await,
+        json!({ "error": "email already exists", "field": "email" })
+      );
+    }
+
+    // Other write errors stay internal, without leaking the server's message.
+    let response = write_error(121, "Document failed validation").into_response();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+      body(response).await,
+      json!({ "error": "unexpected error has occurred" })
+    );
+  }
+}
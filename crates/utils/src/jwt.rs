@@ -1,4 +1,4 @@
-use crate::{AppError, AppResult};
+use crate::{AppError, AppResult, random::generate_code, tenant};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -10,24 +10,60 @@ pub enum TokenType {
 }
 
 /// Our claims struct, it needs to derive `Serialize` and/or `Deserialize`
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
   pub sub: String, // usually email or username
   pub exp: usize,
+  /// Issued at, in seconds since the epoch.
+  #[serde(default)]
+  pub iat: usize,
+  /// Time the user last authenticated interactively (e.g. with a password), in seconds since
+  /// the epoch. Refreshing a token keeps the original value.
+  #[serde(default)]
+  pub auth_time: usize,
   pub token_type: TokenType,
+  /// Unique token id, used to revoke the token before it expires.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub jti: Option<String>,
+  /// Space-separated list of scopes granted to the token.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+  /// OAuth client the token was issued to. Tokens of the login routes belong to no client.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub client_id: Option<String>,
+  /// Tenant the token was issued for, when tenancy is enabled.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenant: Option<String>,
 }
 
-pub fn create_token(secret: &str, email: &str, jwt_expiry: usize, token_type: TokenType) -> String {
-  let expiration = SystemTime::now()
+/// Returns the current time in seconds since the epoch.
+pub fn now() -> usize {
+  SystemTime::now()
     .duration_since(UNIX_EPOCH)
     .expect("Time went backwards")
     .as_secs() as usize
-    + jwt_expiry;
+}
+
+pub fn create_token(
+  secret: &str,
+  email: &str,
+  jwt_expiry: usize,
+  token_type: TokenType,
+  scope: &str,
+  auth_time: usize,
+) -> String {
+  let issued_at = now();
 
   let claims = Claims {
     sub: email.into(),
-    exp: expiration,
+    exp: issued_at + jwt_expiry,
+    iat: issued_at,
+    auth_time,
     token_type,
+    jti: Some(generate_code(32)),
+    scope: Some(scope.into()),
+    client_id: None,
+    tenant: tenant::current(),
   };
 
   encode(
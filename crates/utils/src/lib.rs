@@ -3,5 +3,8 @@ pub mod cookie;
 pub mod errors;
 pub mod jwt;
 pub mod password;
+pub mod random;
+pub mod tenant;
+pub mod webauthn;
 
 pub use errors::*;
@@ -0,0 +1,17 @@
+use rand::{Rng, distr::Alphanumeric};
+
+/// Generates a random alphanumeric string suitable for single-use codes.
+pub fn generate_code(len: usize) -> String {
+  rand::rng()
+    .sample_iter(&Alphanumeric)
+    .take(len)
+    .map(char::from)
+    .collect()
+}
+
+/// Generates `len` random bytes, e.g. for WebAuthn challenges.
+pub fn generate_bytes(len: usize) -> Vec<u8> {
+  let mut bytes = vec![0u8; len];
+  rand::rng().fill(&mut bytes[..]);
+  bytes
+}
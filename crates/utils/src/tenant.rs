@@ -0,0 +1,28 @@
+use std::future::Future;
+
+tokio::task_local! {
+  static CURRENT_TENANT: String;
+}
+
+/// Returns the tenant of the request being handled, if any.
+///
+/// This is `None` when tenancy is disabled and outside of a request, e.g. in background tasks.
+pub fn current() -> Option<String> {
+  CURRENT_TENANT.try_with(Clone::clone).ok()
+}
+
+/// Runs `f` with `tenant` as the current tenant.
+pub async fn scope<F: Future>(tenant: String, f: F) -> F::Output {
+  CURRENT_TENANT.scope(tenant, f).await
+}
+
+/// Tenant ids end up in database names and hostnames, so only lowercase alphanumerics and
+/// dashes are accepted.
+pub fn is_valid(tenant: &str) -> bool {
+  !tenant.is_empty()
+    && tenant.len() <= 63
+    && !tenant.starts_with('-')
+    && tenant
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
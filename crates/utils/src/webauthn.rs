@@ -0,0 +1,200 @@
+//! Minimal WebAuthn Level 2 verification helpers.
+//!
+//! Only ES256 (ECDSA P-256 with SHA-256) credentials are supported, which every platform and
+//! roaming authenticator implements. Attestation statements are not verified: registration
+//! requests `none` attestation, so the credential public key is trusted on first use.
+use crate::AppError;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ciborium::Value;
+use p256::ecdsa::{Signature, VerifyingKey, signature::Verifier};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// COSE algorithm identifier of ES256.
+pub const COSE_ALG_ES256: i64 = -7;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// A malformed or unverifiable WebAuthn value, answered with `400 Bad Request`.
+///
+/// Kept apart from `AppError`, which is too large to be returned cheaply by these helpers.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct WebauthnError(&'static str);
+
+impl From<WebauthnError> for AppError {
+  fn from(error: WebauthnError) -> Self {
+    Self::BadRequest(error.to_string())
+  }
+}
+
+pub type WebauthnResult<T> = Result<T, WebauthnError>;
+
+pub fn encode(bytes: &[u8]) -> String {
+  URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn decode(value: &str) -> WebauthnResult<Vec<u8>> {
+  URL_SAFE_NO_PAD
+    .decode(value.trim_end_matches('='))
+    .map_err(|_| WebauthnError("invalid base64url value"))
+}
+
+pub fn sha256(bytes: &[u8]) -> Vec<u8> {
+  Sha256::digest(bytes).to_vec()
+}
+
+/// The `CollectedClientData` serialized by the browser.
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+  #[serde(rename = "type")]
+  pub type_: String,
+  /// Base64url encoded challenge.
+  pub challenge: String,
+  pub origin: String,
+}
+
+impl ClientData {
+  pub fn parse(client_data_json: &[u8]) -> WebauthnResult<Self> {
+    serde_json::from_slice(client_data_json).map_err(|_| WebauthnError("invalid client data"))
+  }
+}
+
+#[derive(Debug)]
+pub struct AttestedCredential {
+  pub credential_id: Vec<u8>,
+  /// Uncompressed SEC1 encoded P-256 public key.
+  pub public_key: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct AuthenticatorData {
+  pub rp_id_hash: Vec<u8>,
+  pub flags: u8,
+  pub sign_count: u32,
+  pub attested_credential: Option<AttestedCredential>,
+}
+
+impl AuthenticatorData {
+  pub fn parse(data: &[u8]) -> WebauthnResult<Self> {
+    let invalid = || WebauthnError("invalid authenticator data");
+    if data.len() < 37 {
+      return Err(invalid());
+    }
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA == 0 {
+      None
+    } else {
+      // aaguid (16 bytes), credential id length (2 bytes), credential id, COSE public key
+      let rest = data.get(37 + 16..).ok_or_else(invalid)?;
+      let id_len = usize::from(u16::from_be_bytes([
+        *rest.first().ok_or_else(invalid)?,
+        *rest.get(1).ok_or_else(invalid)?,
+      ]));
+      let credential_id = rest.get(2..2 + id_len).ok_or_else(invalid)?.to_vec();
+      let cose_key: Value = ciborium::from_reader(rest.get(2 + id_len..).ok_or_else(invalid)?)
+        .map_err(|_| invalid())?;
+      Some(AttestedCredential {
+        credential_id,
+        public_key: cose_key_to_sec1(&cose_key)?,
+      })
+    };
+
+    Ok(Self {
+      rp_id_hash: data[..32].to_vec(),
+      flags,
+      sign_count,
+      attested_credential,
+    })
+  }
+
+  pub fn user_present(&self) -> bool {
+    self.flags & FLAG_USER_PRESENT != 0
+  }
+
+  pub fn user_verified(&self) -> bool {
+    self.flags & FLAG_USER_VERIFIED != 0
+  }
+
+  /// Checks the relying party id hash and the user presence/verification flags.
+  pub fn check(&self, rp_id: &str, require_user_verification: bool) -> WebauthnResult<()> {
+    if self.rp_id_hash != sha256(rp_id.as_bytes()) {
+      return Err(WebauthnError("relying party id mismatch"));
+    }
+    if !self.user_present() {
+      return Err(WebauthnError("user presence is required"));
+    }
+    if require_user_verification && !self.user_verified() {
+      return Err(WebauthnError("user verification is required"));
+    }
+    Ok(())
+  }
+}
+
+/// Extracts the authenticator data from a CBOR encoded attestation object.
+pub fn parse_attestation_object(attestation_object: &[u8]) -> WebauthnResult<Vec<u8>> {
+  let invalid = || WebauthnError("invalid attestation object");
+  let value: Value = ciborium::from_reader(attestation_object).map_err(|_| invalid())?;
+  value
+    .as_map()
+    .and_then(|entries| {
+      entries
+        .iter()
+        .find(|(key, _)| key.as_text() == Some("authData"))
+    })
+    .and_then(|(_, auth_data)| auth_data.as_bytes().cloned())
+    .ok_or_else(invalid)
+}
+
+fn cose_key_to_sec1(key: &Value) -> WebauthnResult<Vec<u8>> {
+  let unsupported = || WebauthnError("unsupported credential public key");
+  let entries = key.as_map().ok_or_else(unsupported)?;
+  let get = |label: i64| {
+    entries
+      .iter()
+      .find(|(key, _)| key.as_integer() == Some(label.into()))
+      .map(|(_, value)| value)
+  };
+  let int = |label: i64| {
+    get(label)
+      .and_then(Value::as_integer)
+      .and_then(|value| i64::try_from(value).ok())
+  };
+
+  // kty: EC2, alg: ES256, crv: P-256
+  if int(1) != Some(2) || int(3) != Some(COSE_ALG_ES256) || int(-1) != Some(1) {
+    return Err(unsupported());
+  }
+  let x = get(-2).and_then(Value::as_bytes).ok_or_else(unsupported)?;
+  let y = get(-3).and_then(Value::as_bytes).ok_or_else(unsupported)?;
+  if x.len() != 32 || y.len() != 32 {
+    return Err(unsupported());
+  }
+
+  let mut sec1 = Vec::with_capacity(65);
+  sec1.push(0x04);
+  sec1.extend_from_slice(x);
+  sec1.extend_from_slice(y);
+  Ok(sec1)
+}
+
+/// Verifies an assertion signature over `authenticator_data || sha256(client_data_json)`.
+pub fn verify_signature(
+  public_key: &[u8],
+  authenticator_data: &[u8],
+  client_data_json: &[u8],
+  signature: &[u8],
+) -> WebauthnResult<()> {
+  let invalid = || WebauthnError("invalid signature");
+  let key = VerifyingKey::from_sec1_bytes(public_key).map_err(|_| invalid())?;
+  let signature = Signature::from_der(signature).map_err(|_| invalid())?;
+
+  let mut message = authenticator_data.to_vec();
+  message.extend_from_slice(&sha256(client_data_json));
+  key.verify(&message, &signature).map_err(|_| invalid())
+}